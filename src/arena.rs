@@ -0,0 +1,85 @@
+//! Bump allocation of the short-lived data of an evaluation.
+//!
+//! Policy instances are long-lived: the same Wasm module evaluates many
+//! requests. The small temporary allocations performed by the helpers of
+//! this crate (e.g. the tokens of a JSON pointer) fragment the memory of the
+//! Wasm allocator over time. When the `arena` feature is enabled, these
+//! structures are allocated inside of a per-instance bump arena instead.
+//!
+//! The arena is reset when the response is serialized (see
+//! [`ValidationResponse::to_vec`](crate::response::ValidationResponse::to_vec)),
+//! all the memory it holds is then reused by the next evaluation. Policies
+//! can place their own scratch data inside of the arena via [`with_arena`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::arena;
+//!
+//! let longest = arena::with_arena(|bump| {
+//!     let mut names = bumpalo::collections::Vec::new_in(bump);
+//!     names.extend(["nginx", "sidecar"].iter().map(|name| bump.alloc_str(name)));
+//!     names.iter().map(|name| name.len()).max()
+//! });
+//! assert_eq!(longest, Some(7));
+//! ```
+use bumpalo::Bump;
+use std::cell::RefCell;
+
+thread_local! {
+    static ARENA: RefCell<Bump> = RefCell::new(Bump::new());
+}
+
+/// Run `f` with access to the arena of the current evaluation. The data
+/// allocated inside of the arena cannot outlive the closure
+pub fn with_arena<R>(f: impl FnOnce(&Bump) -> R) -> R {
+    ARENA.with(|arena| f(&arena.borrow()))
+}
+
+/// Release all the data allocated inside of the arena, keeping the memory
+/// around for the next evaluation. Nothing happens when invoked from inside
+/// of [`with_arena`]
+pub fn reset() {
+    ARENA.with(|arena| {
+        if let Ok(mut arena) = arena.try_borrow_mut() {
+            arena.reset();
+        }
+    })
+}
+
+/// Number of bytes allocated inside of the arena since the last reset
+pub fn allocated_bytes() -> usize {
+    ARENA.with(|arena| arena.borrow().allocated_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_releases_allocations() {
+        reset();
+        let capacity = allocated_bytes();
+        with_arena(|bump| {
+            bump.alloc_slice_fill_copy(64 * 1024, 0u8);
+        });
+        assert!(allocated_bytes() > capacity);
+
+        reset();
+        let capacity = allocated_bytes();
+        with_arena(|bump| {
+            bump.alloc_slice_fill_copy(64 * 1024, 0u8);
+        });
+        assert_eq!(allocated_bytes(), capacity);
+    }
+
+    #[test]
+    fn reset_inside_of_arena_is_ignored() {
+        let value = with_arena(|bump| {
+            let value = bump.alloc(42);
+            reset();
+            *value
+        });
+        assert_eq!(value, 42);
+    }
+}
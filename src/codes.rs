@@ -0,0 +1,217 @@
+//! Stable registry of rejection codes.
+//!
+//! Fleets of policies should reject requests using the same codes for the
+//! same category of problems, this way monitoring systems can aggregate the
+//! rejections regardless of the policy that produced them. The `code` of
+//! the response is an HTTP status and is returned to the API client, hence
+//! the helpers of this module set it to the
+//! [`http_status`](RejectionCode::http_status) of the rejection code. The
+//! rejection code itself is carried by the [`AUDIT_ANNOTATION_KEY`] audit
+//! annotation, which holds its name (e.g. `image-not-signed`), and by the
+//! [`AUDIT_ANNOTATION_ID_KEY`] one, which holds its numeric value (e.g.
+//! `4401`).
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::codes::{self, RejectionCode};
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     codes::reject(
+//!         RejectionCode::RegistryNotAllowed,
+//!         "images must be pulled from registry.example.com",
+//!     )
+//! }
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::response::ValidationResponse;
+
+/// Key of the audit annotation holding the name of the rejection code
+pub const AUDIT_ANNOTATION_KEY: &str = "rejection-code";
+
+/// Key of the audit annotation holding the numeric value of the rejection
+/// code
+pub const AUDIT_ANNOTATION_ID_KEY: &str = "rejection-code-id";
+
+/// The category of a rejection. The numeric values are stable and are never
+/// reused
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum RejectionCode {
+    /// The image is not signed, or its signatures cannot be verified
+    ImageNotSigned = 4401,
+    /// The image is pulled from a registry that is not allowed
+    RegistryNotAllowed = 4402,
+    /// The image is not pinned to a digest
+    ImageNotPinned = 4403,
+    /// The workload requests privileges that are not allowed
+    PrivilegeNotAllowed = 4404,
+    /// The workload uses host namespaces, ports or paths
+    HostAccessNotAllowed = 4405,
+    /// A required label or annotation is missing
+    MissingMetadata = 4406,
+    /// The resource requests or limits are missing or out of range
+    InvalidResources = 4407,
+    /// A quota or another cluster-wide limit would be exceeded
+    LimitExceeded = 4408,
+    /// The resource conflicts with another resource of the cluster
+    Conflict = 4409,
+    /// The object is not allowed inside of the namespace
+    NamespaceNotAllowed = 4410,
+    /// A host capability failed and the policy fails closed
+    HostCapabilityFailure = 4501,
+}
+
+impl RejectionCode {
+    /// All the registered codes
+    pub const ALL: [RejectionCode; 11] = [
+        RejectionCode::ImageNotSigned,
+        RejectionCode::RegistryNotAllowed,
+        RejectionCode::ImageNotPinned,
+        RejectionCode::PrivilegeNotAllowed,
+        RejectionCode::HostAccessNotAllowed,
+        RejectionCode::MissingMetadata,
+        RejectionCode::InvalidResources,
+        RejectionCode::LimitExceeded,
+        RejectionCode::Conflict,
+        RejectionCode::NamespaceNotAllowed,
+        RejectionCode::HostCapabilityFailure,
+    ];
+
+    /// The numeric code, stored inside of the [`AUDIT_ANNOTATION_ID_KEY`]
+    /// audit annotation. This is not a valid HTTP status
+    pub fn code(&self) -> u16 {
+        *self as u16
+    }
+
+    /// The HTTP status set as `code` of the response
+    pub fn http_status(&self) -> u16 {
+        match self {
+            RejectionCode::Conflict => 409,
+            _ => 403,
+        }
+    }
+
+    /// The machine-readable name of the code, e.g. `image-not-signed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            RejectionCode::ImageNotSigned => "image-not-signed",
+            RejectionCode::RegistryNotAllowed => "registry-not-allowed",
+            RejectionCode::ImageNotPinned => "image-not-pinned",
+            RejectionCode::PrivilegeNotAllowed => "privilege-not-allowed",
+            RejectionCode::HostAccessNotAllowed => "host-access-not-allowed",
+            RejectionCode::MissingMetadata => "missing-metadata",
+            RejectionCode::InvalidResources => "invalid-resources",
+            RejectionCode::LimitExceeded => "limit-exceeded",
+            RejectionCode::Conflict => "conflict",
+            RejectionCode::NamespaceNotAllowed => "namespace-not-allowed",
+            RejectionCode::HostCapabilityFailure => "host-capability-failure",
+        }
+    }
+
+    /// Find the registered code with the given numeric value
+    pub fn from_code(code: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.code() == code)
+    }
+
+    /// The audit annotations describing the code
+    pub fn audit_annotations(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (AUDIT_ANNOTATION_KEY.to_string(), self.name().to_string()),
+            (AUDIT_ANNOTATION_ID_KEY.to_string(), self.code().to_string()),
+        ])
+    }
+}
+
+impl std::fmt::Display for RejectionCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name(), self.code())
+    }
+}
+
+/// Set the `code` of a rejection response and add the matching audit
+/// annotations, preserving the existing ones. Accepted responses are left
+/// untouched
+pub fn set_code(response: &mut ValidationResponse, code: RejectionCode) {
+    if response.accepted {
+        return;
+    }
+    response.code = Some(code.http_status());
+    response
+        .audit_annotations
+        .get_or_insert_with(HashMap::new)
+        .extend(code.audit_annotations());
+}
+
+/// Create a rejection response carrying the given code
+pub fn reject(code: RejectionCode, message: &str) -> wapc_guest::CallResult {
+    crate::reject_request(
+        Some(message.to_string()),
+        Some(code.http_status()),
+        Some(code.audit_annotations()),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_is_consistent() {
+        for code in RejectionCode::ALL {
+            assert_eq!(RejectionCode::from_code(code.code()), Some(code));
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::json!(code.name())
+            );
+        }
+        assert_eq!(RejectionCode::ImageNotSigned.code(), 4401);
+        assert_eq!(RejectionCode::from_code(400), None);
+        assert_eq!(RejectionCode::ImageNotSigned.http_status(), 403);
+        assert_eq!(RejectionCode::Conflict.http_status(), 409);
+        assert_eq!(
+            RejectionCode::RegistryNotAllowed.to_string(),
+            "registry-not-allowed (4402)"
+        );
+    }
+
+    #[test]
+    fn rejection_with_code() {
+        let raw = reject(RejectionCode::ImageNotSigned, "not signed").unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw).unwrap();
+        assert!(!response.accepted);
+        assert_eq!(response.code, Some(403));
+        let annotations = response.audit_annotations.unwrap();
+        assert_eq!(annotations[AUDIT_ANNOTATION_KEY], "image-not-signed");
+        assert_eq!(annotations[AUDIT_ANNOTATION_ID_KEY], "4401");
+    }
+
+    #[test]
+    fn set_code_on_existing_response() {
+        let mut response = ValidationResponse {
+            accepted: false,
+            message: Some("denied".to_string()),
+            code: None,
+            mutated_object: None,
+            audit_annotations: Some(HashMap::from([(
+                "violations".to_string(),
+                "[]".to_string(),
+            )])),
+            warnings: None,
+        };
+        set_code(&mut response, RejectionCode::LimitExceeded);
+        assert_eq!(response.code, Some(403));
+        let annotations = response.audit_annotations.as_ref().unwrap();
+        assert_eq!(annotations.len(), 3);
+        assert_eq!(annotations[AUDIT_ANNOTATION_KEY], "limit-exceeded");
+        assert_eq!(annotations[AUDIT_ANNOTATION_ID_KEY], "4408");
+
+        response.accepted = true;
+        response.code = None;
+        set_code(&mut response, RejectionCode::Conflict);
+        assert_eq!(response.code, None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Compressed payloads exchanged between the host and the policy.
+//!
+//! Evaluating large objects (e.g. CRDs embedding whole manifests) or large
+//! lists of Kubernetes resources requires moving megabytes of JSON across the
+//! Wasm boundary. When the `compression` feature is enabled, the policy
+//! advertises the encodings it understands through the
+//! [`GuestMetadata`](crate::metadata::GuestMetadata) returned by the
+//! `guest_metadata_guest` function. The host can then send gzip or zstd
+//! compressed payloads.
+//!
+//! The encoding of a payload is detected from its first bytes: a JSON
+//! document can never start with the magic numbers of gzip and zstd.
+//! Decompression is transparent for [`ValidationRequest::new`](crate::request::ValidationRequest::new),
+//! [`RawValidationRequest::new`](crate::request::RawValidationRequest::new)
+//! and the list functions of the Kubernetes host capability.
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// First bytes of a zstd frame
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Maximum size of a decompressed payload, in bytes. This protects the
+/// policy from decompression bombs
+pub const MAX_DECOMPRESSED_SIZE: u64 = 128 * 1024 * 1024;
+
+/// The encoding of a payload
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentEncoding {
+    /// Plain JSON
+    Identity,
+    /// gzip compressed JSON
+    Gzip,
+    /// zstd compressed JSON
+    Zstd,
+}
+
+impl ContentEncoding {
+    /// Detect the encoding of `payload` from its first bytes
+    pub fn detect(payload: &[u8]) -> Self {
+        if payload.starts_with(GZIP_MAGIC) {
+            ContentEncoding::Gzip
+        } else if payload.starts_with(ZSTD_MAGIC) {
+            ContentEncoding::Zstd
+        } else {
+            ContentEncoding::Identity
+        }
+    }
+
+    /// Returns `true` when the policy is able to decode payloads using this
+    /// encoding
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_ENCODINGS.contains(self)
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Zstd => "zstd",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The encodings understood by the policy, in order of preference
+pub const SUPPORTED_ENCODINGS: &[ContentEncoding] = &[
+    #[cfg(feature = "compression")]
+    ContentEncoding::Zstd,
+    #[cfg(feature = "compression")]
+    ContentEncoding::Gzip,
+    ContentEncoding::Identity,
+];
+
+/// Decode a payload sent by the host. Plain JSON payloads are returned as
+/// they are, without being copied
+pub fn decode(payload: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let encoding = ContentEncoding::detect(payload);
+    if encoding == ContentEncoding::Identity {
+        return Ok(Cow::Borrowed(payload));
+    }
+    if !encoding.is_supported() {
+        return Err(anyhow!(
+            "received a {} compressed payload, the policy must be built with the `compression` feature of the SDK",
+            encoding
+        ));
+    }
+    decompress(encoding, payload).map(Cow::Owned)
+}
+
+#[cfg(feature = "compression")]
+fn decompress(encoding: ContentEncoding, payload: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let reader: Box<dyn Read + '_> = match encoding {
+        ContentEncoding::Gzip => Box::new(flate2::read::GzDecoder::new(payload)),
+        ContentEncoding::Zstd => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(payload)
+                .map_err(|e| anyhow!("cannot decode zstd payload: {}", e))?,
+        ),
+        ContentEncoding::Identity => return Ok(payload.to_vec()),
+    };
+
+    let mut decoded = Vec::new();
+    reader
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|e| anyhow!("cannot decode {} payload: {}", encoding, e))?;
+    if decoded.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(anyhow!(
+            "decompressed {} payload exceeds {} bytes",
+            encoding,
+            MAX_DECOMPRESSED_SIZE
+        ));
+    }
+    Ok(decoded)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(encoding: ContentEncoding, _payload: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("{} payloads are not supported", encoding))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "compression")]
+    pub(crate) fn zstd(data: &[u8]) -> Vec<u8> {
+        ruzstd::encoding::compress_to_vec(data, ruzstd::encoding::CompressionLevel::Fastest)
+    }
+
+    #[test]
+    fn plain_payload_is_borrowed() {
+        let payload = br#"{"request": {}}"#;
+        assert_eq!(ContentEncoding::detect(payload), ContentEncoding::Identity);
+        assert!(matches!(decode(payload).unwrap(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn supported_encodings() {
+        assert!(ContentEncoding::Identity.is_supported());
+        assert_eq!(
+            ContentEncoding::Gzip.is_supported(),
+            cfg!(feature = "compression")
+        );
+        assert_eq!(
+            serde_json::to_value(SUPPORTED_ENCODINGS).unwrap()[0],
+            if cfg!(feature = "compression") {
+                "zstd"
+            } else {
+                "identity"
+            }
+        );
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn compressed_payload_requires_feature() {
+        let error = decode(&[0x1f, 0x8b, 0x08]).unwrap_err();
+        assert!(error.to_string().contains("`compression` feature"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decode_compressed_payloads() {
+        let payload = br#"{"request": {"uid": "abc"}}"#;
+        for (encoding, compressed) in [
+            (ContentEncoding::Gzip, gzip(payload)),
+            (ContentEncoding::Zstd, zstd(payload)),
+        ] {
+            assert_eq!(ContentEncoding::detect(&compressed), encoding);
+            assert_eq!(decode(&compressed).unwrap().as_ref(), payload);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decode_corrupted_payload() {
+        let mut compressed = gzip(b"{}");
+        compressed.truncate(compressed.len() / 2);
+        assert!(decode(&compressed).is_err());
+    }
+}
@@ -1 +1,4 @@
+pub mod conditions;
 pub mod policies;
+pub mod reports;
+pub mod webhook;
@@ -0,0 +1,202 @@
+//! Helpers to handle the `status.conditions` of Kubernetes resources.
+//!
+//! The semantics match the ones of the `meta.SetStatusCondition` family of
+//! functions provided by the Go `k8s.io/apimachinery` module. These helpers
+//! can be used by controllers managing any of the Kubewarden policy CRDs.
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono::{DateTime, Utc};
+
+/// The condition is met
+pub const CONDITION_TRUE: &str = "True";
+/// The condition is not met
+pub const CONDITION_FALSE: &str = "False";
+/// It is not possible to decide whether the condition is met
+pub const CONDITION_UNKNOWN: &str = "Unknown";
+
+fn now() -> Time {
+    let elapsed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Time(
+        DateTime::<Utc>::from_timestamp(elapsed.as_secs() as i64, elapsed.subsec_nanos())
+            .unwrap_or_default(),
+    )
+}
+
+/// A transition time that has not been set. Go's zero time, and the Unix
+/// epoch, are both considered unset.
+fn is_unset(time: &Time) -> bool {
+    time.0.timestamp() <= 0
+}
+
+/// Set the given condition inside of `conditions`.
+///
+/// 1. if the condition of the specified type already exists, all the fields
+///    of the existing condition are updated to the values of `new_condition`.
+///    `last_transition_time` is changed only when the status changes: it is
+///    set to the one of `new_condition`, or to the current time when unset.
+/// 2. otherwise `new_condition` is appended. `last_transition_time` is set
+///    to the current time when unset.
+///
+/// Returns `true` when `conditions` has been changed.
+pub fn set_condition(conditions: &mut Vec<Condition>, mut new_condition: Condition) -> bool {
+    let existing = match conditions
+        .iter_mut()
+        .find(|c| c.type_ == new_condition.type_)
+    {
+        Some(existing) => existing,
+        None => {
+            if is_unset(&new_condition.last_transition_time) {
+                new_condition.last_transition_time = now();
+            }
+            conditions.push(new_condition);
+            return true;
+        }
+    };
+
+    let mut changed = false;
+    if existing.status != new_condition.status {
+        existing.status = new_condition.status;
+        existing.last_transition_time = if is_unset(&new_condition.last_transition_time) {
+            now()
+        } else {
+            new_condition.last_transition_time
+        };
+        changed = true;
+    }
+    if existing.reason != new_condition.reason {
+        existing.reason = new_condition.reason;
+        changed = true;
+    }
+    if existing.message != new_condition.message {
+        existing.message = new_condition.message;
+        changed = true;
+    }
+    if existing.observed_generation != new_condition.observed_generation {
+        existing.observed_generation = new_condition.observed_generation;
+        changed = true;
+    }
+
+    changed
+}
+
+/// Remove the condition of the given type. Returns `true` when a condition
+/// has been removed.
+pub fn remove_condition(conditions: &mut Vec<Condition>, condition_type: &str) -> bool {
+    let len = conditions.len();
+    conditions.retain(|c| c.type_ != condition_type);
+    conditions.len() != len
+}
+
+/// Find the condition of the given type
+pub fn find_condition<'a>(
+    conditions: &'a [Condition],
+    condition_type: &str,
+) -> Option<&'a Condition> {
+    conditions.iter().find(|c| c.type_ == condition_type)
+}
+
+/// Returns `true` when the condition of the given type is present and its
+/// status is equal to `status`
+pub fn is_condition_present_and_equal(
+    conditions: &[Condition],
+    condition_type: &str,
+    status: &str,
+) -> bool {
+    find_condition(conditions, condition_type).is_some_and(|c| c.status == status)
+}
+
+/// Returns `true` when the condition of the given type has status `True`
+pub fn is_condition_true(conditions: &[Condition], condition_type: &str) -> bool {
+    is_condition_present_and_equal(conditions, condition_type, CONDITION_TRUE)
+}
+
+/// Returns `true` when the condition of the given type has status `False`
+pub fn is_condition_false(conditions: &[Condition], condition_type: &str) -> bool {
+    is_condition_present_and_equal(conditions, condition_type, CONDITION_FALSE)
+}
+
+/// Returns the last time the condition of the given type changed its status
+pub fn last_transition_time<'a>(
+    conditions: &'a [Condition],
+    condition_type: &str,
+) -> Option<&'a Time> {
+    find_condition(conditions, condition_type).map(|c| &c.last_transition_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(type_: &str, status: &str, reason: &str, seconds: i64) -> Condition {
+        Condition {
+            type_: type_.to_string(),
+            status: status.to_string(),
+            reason: reason.to_string(),
+            message: String::new(),
+            observed_generation: None,
+            last_transition_time: Time(DateTime::<Utc>::from_timestamp(seconds, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn set_new_condition() {
+        let mut conditions = vec![];
+
+        assert!(set_condition(
+            &mut conditions,
+            condition("Ready", CONDITION_FALSE, "Pending", 0)
+        ));
+        assert!(is_condition_false(&conditions, "Ready"));
+        assert!(!is_unset(
+            last_transition_time(&conditions, "Ready").unwrap()
+        ));
+    }
+
+    #[test]
+    fn update_existing_condition() {
+        let mut conditions = vec![condition("Ready", CONDITION_FALSE, "Pending", 100)];
+
+        // same status: the transition time is not changed
+        assert!(set_condition(
+            &mut conditions,
+            condition("Ready", CONDITION_FALSE, "StillPending", 200)
+        ));
+        assert_eq!(conditions[0].reason, "StillPending");
+        assert_eq!(conditions[0].last_transition_time.0.timestamp(), 100);
+
+        // nothing changed
+        assert!(!set_condition(
+            &mut conditions,
+            condition("Ready", CONDITION_FALSE, "StillPending", 300)
+        ));
+
+        // status change: the transition time is updated
+        assert!(set_condition(
+            &mut conditions,
+            condition("Ready", CONDITION_TRUE, "Active", 400)
+        ));
+        assert!(is_condition_true(&conditions, "Ready"));
+        assert_eq!(
+            last_transition_time(&conditions, "Ready")
+                .unwrap()
+                .0
+                .timestamp(),
+            400
+        );
+    }
+
+    #[test]
+    fn remove_conditions() {
+        let mut conditions = vec![
+            condition("Ready", CONDITION_TRUE, "Active", 100),
+            condition("Degraded", CONDITION_FALSE, "Healthy", 100),
+        ];
+
+        assert!(remove_condition(&mut conditions, "Degraded"));
+        assert!(!remove_condition(&mut conditions, "Degraded"));
+        assert!(find_condition(&conditions, "Degraded").is_none());
+        assert!(!is_condition_true(&conditions, "Degraded"));
+        assert_eq!(conditions.len(), 1);
+    }
+}
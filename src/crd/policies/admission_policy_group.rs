@@ -1,6 +1,6 @@
 /// This module contains all the definitions of all Kubewarden policy CRDs
 /// that are used to define the policy groups.
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use k8s_openapi::{
     api::admissionregistration::v1::{MatchCondition, RuleWithOperations},
@@ -8,14 +8,15 @@ use k8s_openapi::{
 };
 
 use crate::crd::policies::common::{
-    default_policy_server, default_settings, BackgroundAudit, FailurePolicy, MatchPolicy,
-    PolicyMode, SideEffects, TimeoutSeconds,
+    default_policy_server, default_settings, expression_mismatches, policy_names_in_expression,
+    BackgroundAudit, FailurePolicy, MatchPolicy, PolicyMode, SideEffects, TimeoutSeconds,
 };
 
 #[derive(
     Clone, Default, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema,
 )]
 #[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
 pub struct PolicyGroupMember {
     /// Module is the location of the WASM module to be loaded. Can be a
     /// local file (file://), a remote file served by an HTTP server
@@ -54,6 +55,7 @@ pub struct AdmissionPolicyGroupSpec {
     /// performing audit checks. If false, the policy cannot produce meaningful
     /// evaluation results during audit checks and will be skipped.
     /// The default is "true".
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub background_audit: Option<BackgroundAudit>,
 
     /// Expression is the evaluation expression to accept or reject the
@@ -71,6 +73,7 @@ pub struct AdmissionPolicyGroupSpec {
 
     /// FailurePolicy defines how unrecognized errors and timeout errors from the
     /// policy are handled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub failure_policy: Option<FailurePolicy>,
 
     /// MatchConditions are a list of conditions that must be met for a request to be
@@ -87,9 +90,11 @@ pub struct AdmissionPolicyGroupSpec {
     ///     - If failurePolicy=Ignore, the policy is skipped.
     ///
     /// Only available if the feature gate AdmissionWebhookMatchConditions is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_conditions: Option<Vec<MatchCondition>>,
 
     /// matchPolicy defines how the "rules" list is used to match incoming requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_policy: Option<MatchPolicy>,
 
     /// Message is used to specify the message that will be returned when
@@ -104,6 +109,7 @@ pub struct AdmissionPolicyGroupSpec {
     /// allowed, but is disallowed to transition from "protect" to
     /// "monitor". To perform this transition, the policy should be
     /// recreated in "monitor" mode instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<PolicyMode>,
 
     /// ObjectSelector decides whether to run the webhook based on if the
@@ -117,6 +123,7 @@ pub struct AdmissionPolicyGroupSpec {
     /// Use the object selector only if the webhook is opt-in, because end
     /// users may skip the admission webhook by setting the labels.
     /// Default to the empty LabelSelector, which matches everything.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub object_selector: Option<LabelSelector>,
 
     /// Policies is a list of policies that are part of the group that will
@@ -130,12 +137,14 @@ pub struct AdmissionPolicyGroupSpec {
 
     /// Rules describes what operations on what resources/subresources the webhook cares about.
     /// The webhook cares about an operation if it matches any Rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rules: Option<Vec<RuleWithOperations>>,
 
     /// SideEffects states whether this webhook has side effects.
     /// Acceptable values are: None, NoneOnDryRun.
     /// Webhooks with side effects MUST implement a reconciliation system, since a request may be
     /// rejected by a future step in the admission change and the side effects therefore need to be undone.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub side_effects: Option<SideEffects>,
 
     /// TimeoutSeconds specifies the timeout for this webhook. After the timeout passes,
@@ -143,9 +152,29 @@ pub struct AdmissionPolicyGroupSpec {
     /// failure policy.
     /// The timeout value must be between 1 and 30 seconds.
     /// Default to 10 seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_seconds: Option<TimeoutSeconds>,
 }
 
+impl AdmissionPolicyGroupSpec {
+    /// Names of the policies called by the group `expression`
+    pub fn referenced_policies(&self) -> BTreeSet<String> {
+        policy_names_in_expression(&self.expression)
+    }
+
+    /// Policies called by the group `expression` that are not defined inside
+    /// of `policies`. These are usually typos.
+    pub fn undefined_policies(&self) -> Vec<String> {
+        expression_mismatches(&self.expression, self.policies.keys()).0
+    }
+
+    /// Policies defined inside of `policies` that are never called by the
+    /// group `expression`
+    pub fn unused_policies(&self) -> Vec<String> {
+        expression_mismatches(&self.expression, self.policies.keys()).1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,4 +423,72 @@ spec:
             "the image is using the latest tag or is not signed by Alice and Bob"
         );
     }
+
+    #[test]
+    fn test_admission_policy_group_expression_introspection() {
+        let policy: AdmissionPolicyGroup = serde_yaml::from_str(YAML_NO_DEFAULTS)
+            .expect("cannot deserialize AdmissionPolicyGroup");
+        let mut spec = policy.spec.expect("should have spec");
+
+        assert_eq!(
+            spec.referenced_policies().into_iter().collect::<Vec<_>>(),
+            vec!["reject_latest", "signed_by_alice", "signed_by_bob"]
+        );
+        assert!(spec.undefined_policies().is_empty());
+        assert!(spec.unused_policies().is_empty());
+
+        spec.expression = "reject_lastest() || signed_by_alice()".to_string();
+        assert_eq!(spec.undefined_policies(), vec!["reject_lastest"]);
+        assert_eq!(
+            spec.unused_policies(),
+            vec!["reject_latest", "signed_by_bob"]
+        );
+    }
+
+    #[test]
+    fn test_admission_policy_group_serialization_roundtrip() {
+        for yaml in [YAML_NO_DEFAULTS, YAML_WITH_DEFAULTS] {
+            let policy: AdmissionPolicyGroup =
+                serde_yaml::from_str(yaml).expect("cannot deserialize AdmissionPolicyGroup");
+            let value =
+                serde_json::to_value(&policy).expect("cannot serialize AdmissionPolicyGroup");
+            crate::crd::policies::common::assert_no_null_fields(&value, "");
+
+            let policy_again: AdmissionPolicyGroup =
+                serde_json::from_value(value).expect("cannot deserialize AdmissionPolicyGroup");
+            assert_eq!(policy, policy_again);
+        }
+    }
+
+    #[test]
+    // make sure serde fails with an error
+    fn test_admission_policy_group_member_does_not_have_ctx_aware() {
+        let yaml = r#"
+apiVersion: policies.kubewarden.io/v1
+kind: AdmissionPolicyGroup
+metadata:
+  name: demo
+  namespace: default
+spec:
+  rules:
+    - apiGroups: [""]
+      apiVersions: ["v1"]
+      resources: ["pods"]
+      operations:
+        - CREATE
+  policies:
+    reject_latest:
+      module: registry://ghcr.io/kubewarden/policies/trusted-repos:v0.2.0
+      contextAwareResources:
+        - apiVersion: "v1"
+          kind: "pod"
+  expression: "reject_latest()"
+  message: "the image is using the latest tag"
+"#;
+
+        let err = serde_yaml::from_str::<AdmissionPolicyGroup>(yaml).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unknown field `contextAwareResources`"));
+    }
 }
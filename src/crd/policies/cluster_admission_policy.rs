@@ -34,10 +34,12 @@ pub struct ClusterAdmissionPolicySpec {
     /// performing audit checks. If false, the policy cannot produce meaningful
     /// evaluation results during audit checks and will be skipped.
     /// The default is "true".
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub background_audit: Option<BackgroundAudit>,
 
     /// FailurePolicy defines how unrecognized errors and timeout errors from the
     /// policy are handled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub failure_policy: Option<FailurePolicy>,
 
     /// MatchConditions are a list of conditions that must be met for a request to be
@@ -54,9 +56,11 @@ pub struct ClusterAdmissionPolicySpec {
     ///     - If failurePolicy=Ignore, the policy is skipped.
     ///
     /// Only available if the feature gate AdmissionWebhookMatchConditions is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_conditions: Option<Vec<MatchCondition>>,
 
     /// matchPolicy defines how the "rules" list is used to match incoming requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_policy: Option<MatchPolicy>,
 
     /// Mode defines the execution mode of this policy. Can be set to
@@ -66,6 +70,7 @@ pub struct ClusterAdmissionPolicySpec {
     /// allowed, but is disallowed to transition from "protect" to
     /// "monitor". To perform this transition, the policy should be
     /// recreated in "monitor" mode instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<PolicyMode>,
 
     /// Module is the location of the WASM module to be loaded. Can be a
@@ -92,6 +97,7 @@ pub struct ClusterAdmissionPolicySpec {
     /// Use the object selector only if the webhook is opt-in, because end
     /// users may skip the admission webhook by setting the labels.
     /// Default to the empty LabelSelector, which matches everything.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub object_selector: Option<LabelSelector>,
 
     /// identifies an existing PolicyServer resource
@@ -100,6 +106,7 @@ pub struct ClusterAdmissionPolicySpec {
 
     /// Rules describes what operations on what resources/subresources the webhook cares about.
     /// The webhook cares about an operation if it matches any Rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rules: Option<Vec<RuleWithOperations>>,
 
     /// Settings is a free-form object that contains the policy configuration
@@ -111,6 +118,7 @@ pub struct ClusterAdmissionPolicySpec {
     /// Acceptable values are: None, NoneOnDryRun.
     /// Webhooks with side effects MUST implement a reconciliation system, since a request may be
     /// rejected by a future step in the admission change and the side effects therefore need to be undone.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub side_effects: Option<SideEffects>,
 
     /// TimeoutSeconds specifies the timeout for this webhook. After the timeout passes,
@@ -118,18 +126,20 @@ pub struct ClusterAdmissionPolicySpec {
     /// failure policy.
     /// The timeout value must be between 1 and 30 seconds.
     /// Default to 10 seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_seconds: Option<TimeoutSeconds>,
 
     /// NamespaceSelector decides whether to run the webhook on an object based on whether the namespace
     /// for that object matches the selector. If the object itself is a namespace, the matching is
     /// performed on object.metadata.labels.
     /// If the object is another cluster scoped resource, it never skips the webhook.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub namespace_selector: Option<LabelSelector>,
 
     /// List of Kubernetes resources the policy is allowed to access at evaluation time.
     /// Access to these resources is done using the ServiceAccount of the PolicyServer
     /// the policy is assigned to.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub context_aware_resources: Vec<ContextAwareResource>,
 }
 
@@ -253,4 +263,19 @@ spec:
         assert_eq!(spec.settings.0, serde_json::json!({}));
         assert!(!spec.mutating);
     }
+
+    #[test]
+    fn test_cluster_admission_policy_serialization_roundtrip() {
+        for yaml in [YAML_NO_DEFAULTS, YAML_WITH_DEFAULTS] {
+            let policy: ClusterAdmissionPolicy =
+                serde_yaml::from_str(yaml).expect("cannot deserialize ClusterAdmissionPolicy");
+            let value =
+                serde_json::to_value(&policy).expect("cannot serialize ClusterAdmissionPolicy");
+            crate::crd::policies::common::assert_no_null_fields(&value, "");
+
+            let policy_again: ClusterAdmissionPolicy =
+                serde_json::from_value(value).expect("cannot deserialize ClusterAdmissionPolicy");
+            assert_eq!(policy, policy_again);
+        }
+    }
 }
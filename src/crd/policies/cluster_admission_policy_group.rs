@@ -1,6 +1,6 @@
 /// This module contains all the definitions of all Kubewarden policy CRDs
 /// that are used to define the policy groups.
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use k8s_openapi::{
     api::admissionregistration::v1::{MatchCondition, RuleWithOperations},
@@ -8,8 +8,9 @@ use k8s_openapi::{
 };
 
 use crate::crd::policies::common::{
-    default_policy_server, default_settings, BackgroundAudit, ContextAwareResource, FailurePolicy,
-    MatchPolicy, PolicyMode, SideEffects, TimeoutSeconds,
+    default_policy_server, default_settings, expression_mismatches, policy_names_in_expression,
+    BackgroundAudit, ContextAwareResource, FailurePolicy, MatchPolicy, PolicyMode, SideEffects,
+    TimeoutSeconds,
 };
 
 #[derive(
@@ -32,7 +33,7 @@ pub struct PolicyGroupMemberWithContext {
     /// List of Kubernetes resources the policy is allowed to access at evaluation time.
     /// Access to these resources is done using the ServiceAccount of the PolicyServer
     /// the policy is assigned to.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub context_aware_resources: Vec<ContextAwareResource>,
 }
 
@@ -60,6 +61,7 @@ pub struct ClusterAdmissionPolicyGroupSpec {
     /// performing audit checks. If false, the policy cannot produce meaningful
     /// evaluation results during audit checks and will be skipped.
     /// The default is "true".
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub background_audit: Option<BackgroundAudit>,
 
     /// Expression is the evaluation expression to accept or reject the
@@ -77,6 +79,7 @@ pub struct ClusterAdmissionPolicyGroupSpec {
 
     /// FailurePolicy defines how unrecognized errors and timeout errors from the
     /// policy are handled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub failure_policy: Option<FailurePolicy>,
 
     /// MatchConditions are a list of conditions that must be met for a request to be
@@ -93,9 +96,11 @@ pub struct ClusterAdmissionPolicyGroupSpec {
     ///     - If failurePolicy=Ignore, the policy is skipped.
     ///
     /// Only available if the feature gate AdmissionWebhookMatchConditions is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_conditions: Option<Vec<MatchCondition>>,
 
     /// matchPolicy defines how the "rules" list is used to match incoming requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub match_policy: Option<MatchPolicy>,
 
     /// Message is used to specify the message that will be returned when
@@ -110,6 +115,7 @@ pub struct ClusterAdmissionPolicyGroupSpec {
     /// allowed, but is disallowed to transition from "protect" to
     /// "monitor". To perform this transition, the policy should be
     /// recreated in "monitor" mode instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<PolicyMode>,
 
     /// ObjectSelector decides whether to run the webhook based on if the
@@ -123,6 +129,7 @@ pub struct ClusterAdmissionPolicyGroupSpec {
     /// Use the object selector only if the webhook is opt-in, because end
     /// users may skip the admission webhook by setting the labels.
     /// Default to the empty LabelSelector, which matches everything.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub object_selector: Option<LabelSelector>,
 
     /// Policies is a list of policies that are part of the group that will
@@ -136,12 +143,14 @@ pub struct ClusterAdmissionPolicyGroupSpec {
 
     /// Rules describes what operations on what resources/subresources the webhook cares about.
     /// The webhook cares about an operation if it matches any Rule.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub rules: Option<Vec<RuleWithOperations>>,
 
     /// SideEffects states whether this webhook has side effects.
     /// Acceptable values are: None, NoneOnDryRun.
     /// Webhooks with side effects MUST implement a reconciliation system, since a request may be
     /// rejected by a future step in the admission change and the side effects therefore need to be undone.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub side_effects: Option<SideEffects>,
 
     /// TimeoutSeconds specifies the timeout for this webhook. After the timeout passes,
@@ -149,15 +158,36 @@ pub struct ClusterAdmissionPolicyGroupSpec {
     /// failure policy.
     /// The timeout value must be between 1 and 30 seconds.
     /// Default to 10 seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_seconds: Option<TimeoutSeconds>,
 
     /// NamespaceSelector decides whether to run the webhook on an object based on whether the namespace
     /// for that object matches the selector. If the object itself is a namespace, the matching is
     /// performed on object.metadata.labels.
     /// If the object is another cluster scoped resource, it never skips the webhook.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub namespace_selector: Option<LabelSelector>,
 }
 
+impl ClusterAdmissionPolicyGroupSpec {
+    /// Names of the policies called by the group `expression`
+    pub fn referenced_policies(&self) -> BTreeSet<String> {
+        policy_names_in_expression(&self.expression)
+    }
+
+    /// Policies called by the group `expression` that are not defined inside
+    /// of `policies`. These are usually typos.
+    pub fn undefined_policies(&self) -> Vec<String> {
+        expression_mismatches(&self.expression, self.policies.keys()).0
+    }
+
+    /// Policies defined inside of `policies` that are never called by the
+    /// group `expression`
+    pub fn unused_policies(&self) -> Vec<String> {
+        expression_mismatches(&self.expression, self.policies.keys()).1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
@@ -373,4 +403,19 @@ spec:
             "the image is using the latest tag or is not signed by Alice and Bob"
         );
     }
+
+    #[test]
+    fn test_cluster_admission_policy_group_serialization_roundtrip() {
+        for yaml in [YAML_NO_DEFAULTS, YAML_WITH_DEFAULTS] {
+            let policy: ClusterAdmissionPolicyGroup =
+                serde_yaml::from_str(yaml).expect("cannot deserialize ClusterAdmissionPolicyGroup");
+            let value = serde_json::to_value(&policy)
+                .expect("cannot serialize ClusterAdmissionPolicyGroup");
+            crate::crd::policies::common::assert_no_null_fields(&value, "");
+
+            let policy_again: ClusterAdmissionPolicyGroup = serde_json::from_value(value)
+                .expect("cannot deserialize ClusterAdmissionPolicyGroup");
+            assert_eq!(policy, policy_again);
+        }
+    }
 }
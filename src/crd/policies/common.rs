@@ -1,5 +1,7 @@
 /// This module contains a list of common types and functions that are used across the different
 /// policy types.
+use std::collections::BTreeSet;
+
 use k8s_openapi::apimachinery::pkg::runtime::RawExtension;
 
 #[derive(
@@ -82,3 +84,117 @@ pub(crate) fn default_policy_server() -> String {
 pub(crate) fn default_settings() -> RawExtension {
     RawExtension(serde_json::json!({}))
 }
+
+/// CEL functions that can be used inside of a policy group expression and
+/// are not calls to policies of the group
+const CEL_BUILTIN_FUNCTIONS: &[&str] = &[
+    "bool",
+    "bytes",
+    "double",
+    "duration",
+    "dyn",
+    "has",
+    "int",
+    "matches",
+    "size",
+    "string",
+    "timestamp",
+    "type",
+    "uint",
+];
+
+/// Return the names of the policies called inside of a policy group
+/// expression. Each policy of the group is represented as a function call
+/// (e.g. `signed_by_alice() && !is_latest()`). Method calls, string literals
+/// and CEL builtin functions are ignored.
+pub fn policy_names_in_expression(expression: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut names = BTreeSet::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' || c == '\'' {
+            // skip string literals
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let ident: String = chars[start..i].iter().collect();
+            let is_method = chars[..start]
+                .iter()
+                .rev()
+                .find(|c| !c.is_whitespace())
+                .is_some_and(|c| *c == '.');
+            let is_call = chars[i..]
+                .iter()
+                .find(|c| !c.is_whitespace())
+                .is_some_and(|c| *c == '(');
+            if is_call && !is_method && !CEL_BUILTIN_FUNCTIONS.contains(&ident.as_str()) {
+                names.insert(ident);
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    names
+}
+
+/// Compare the policies referenced by a group expression with the policies
+/// defined by the group. Returns the policies called by the expression that
+/// are not defined, and the defined policies that are never called.
+pub(crate) fn expression_mismatches<'a>(
+    expression: &str,
+    defined: impl Iterator<Item = &'a String>,
+) -> (Vec<String>, Vec<String>) {
+    let referenced = policy_names_in_expression(expression);
+    let defined: BTreeSet<String> = defined.cloned().collect();
+
+    (
+        referenced.difference(&defined).cloned().collect(),
+        defined.difference(&referenced).cloned().collect(),
+    )
+}
+
+/// Ensure a serialized resource does not contain `null` values, which would
+/// wipe server-defaulted fields when used inside of a patch
+#[cfg(test)]
+pub(crate) fn assert_no_null_fields(value: &serde_json::Value, path: &str) {
+    match value {
+        serde_json::Value::Null => panic!("found null value at '{}'", path),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .for_each(|(k, v)| assert_no_null_fields(v, &format!("{}/{}", path, k))),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .for_each(|(i, v)| assert_no_null_fields(v, &format!("{}/{}", path, i))),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_names_in_expression() {
+        let names = policy_names_in_expression(
+            r#"reject_latest() || (signed_by_alice() && signed_by_bob ()) && "fake()".size() > 0 && size(x) > 1"#,
+        );
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            vec!["reject_latest", "signed_by_alice", "signed_by_bob"]
+        );
+    }
+}
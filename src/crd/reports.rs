@@ -0,0 +1,6 @@
+pub mod cluster_policy_report;
+pub mod common;
+pub mod policy_report;
+
+pub use cluster_policy_report::ClusterPolicyReport;
+pub use policy_report::PolicyReport;
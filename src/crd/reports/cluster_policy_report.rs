@@ -0,0 +1,77 @@
+use k8s_openapi::{
+    api::core::v1::ObjectReference,
+    apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta},
+};
+
+use crate::crd::reports::common::{impl_report_resource, PolicyReportResult, PolicyReportSummary};
+
+/// ClusterPolicyReport is the Schema for the cluster-wide policy reports API
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterPolicyReport {
+    /// Standard object's metadata
+    #[serde(default)]
+    pub metadata: ObjectMeta,
+
+    /// Scope is an optional reference to the report scope (e.g. a Deployment,
+    /// Namespace, or Node)
+    pub scope: Option<ObjectReference>,
+
+    /// ScopeSelector is an optional selector for multiple scopes (e.g. Pods).
+    /// Either one of, or none of, but not both of, Scope or ScopeSelector
+    /// should be specified.
+    pub scope_selector: Option<LabelSelector>,
+
+    /// PolicyReportSummary provides a summary of results
+    pub summary: Option<PolicyReportSummary>,
+
+    /// PolicyReportResult provides result details
+    pub results: Option<Vec<PolicyReportResult>>,
+}
+
+impl_report_resource!(
+    ClusterPolicyReport,
+    "ClusterPolicyReport",
+    "ClusterPolicyReportList",
+    "clusterpolicyreports",
+    k8s_openapi::ClusterResourceScope
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::Resource;
+
+    const YAML: &str = r#"
+apiVersion: wgpolicyk8s.io/v1alpha2
+kind: ClusterPolicyReport
+metadata:
+  name: 3ac2f4ad-76ad-4c2c-9b09-3e3c21ea1dc5
+scope:
+  apiVersion: v1
+  kind: Namespace
+  name: kube-system
+summary:
+  pass: 2
+results:
+  - source: kubewarden
+    policy: clusterwide-safe-labels
+    result: pass
+"#;
+
+    #[test]
+    fn test_cluster_policy_report_roundtrip() {
+        let report: ClusterPolicyReport =
+            serde_yaml::from_str(YAML).expect("cannot deserialize ClusterPolicyReport");
+        let summary = report.summary.as_ref().unwrap();
+        assert_eq!(summary.pass, 2);
+        assert_eq!(summary.fail, 0);
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["kind"], ClusterPolicyReport::KIND);
+        assert_eq!(value["apiVersion"], ClusterPolicyReport::API_VERSION);
+
+        let report_again: ClusterPolicyReport = serde_json::from_value(value).unwrap();
+        assert_eq!(report, report_again);
+    }
+}
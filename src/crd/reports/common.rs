@@ -0,0 +1,194 @@
+/// This module contains the types shared by the `wgpolicyk8s.io` PolicyReport
+/// and ClusterPolicyReport resources. These are the resources written by the
+/// Kubewarden audit scanner.
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::core::v1::ObjectReference, apimachinery::pkg::apis::meta::v1::LabelSelector,
+};
+
+pub(crate) const GROUP: &str = "wgpolicyk8s.io";
+pub(crate) const VERSION: &str = "v1alpha2";
+
+/// The outcome of the evaluation of a policy rule
+#[derive(
+    Clone, Default, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyResult {
+    /// The policy requirements are met
+    #[default]
+    Pass,
+    /// The policy requirements are not met
+    Fail,
+    /// The policy requirements are not met and the policy is not scored
+    Warn,
+    /// The policy could not be evaluated
+    Error,
+    /// The policy was not selected based on user inputs or applicability
+    Skip,
+}
+
+/// The severity of a policy violation
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyResultSeverity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Info,
+}
+
+/// A point in time independent of any time zone or calendar, represented as
+/// seconds and fractions of seconds at nanosecond resolution in UTC epoch time
+#[derive(
+    Clone, Default, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema,
+)]
+pub struct Timestamp {
+    /// Represents seconds of UTC time since Unix epoch
+    pub seconds: i64,
+    /// Non-negative fractions of a second at nanosecond resolution
+    pub nanos: i32,
+}
+
+/// Summary provides a status count summary
+#[derive(
+    Clone, Default, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema,
+)]
+#[serde(default)]
+pub struct PolicyReportSummary {
+    /// Number of policy checks with a pass result
+    pub pass: i32,
+    /// Number of policy checks with a fail result
+    pub fail: i32,
+    /// Number of policy checks with a warn result
+    pub warn: i32,
+    /// Number of policy checks with an error result
+    pub error: i32,
+    /// Number of policy checks with a skip result
+    pub skip: i32,
+}
+
+/// PolicyReportResult provides the result for an individual policy
+#[derive(
+    Clone, Default, Debug, serde::Deserialize, serde::Serialize, PartialEq, schemars::JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyReportResult {
+    /// Source is an identifier for the policy engine that manages this report
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    /// Policy is the name or identifier of the policy
+    pub policy: String,
+
+    /// Rule is the name or identifier of the rule within the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+
+    /// Category indicates policy category
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+
+    /// Severity indicates policy check result criticality
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<PolicyResultSeverity>,
+
+    /// Timestamp indicates the time the result was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+
+    /// Result indicates the outcome of the policy rule execution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<PolicyResult>,
+
+    /// Scored indicates if this result is scored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scored: Option<bool>,
+
+    /// Resources is an optional reference to the checked Kubernetes resources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Vec<ObjectReference>>,
+
+    /// ResourceSelector is an optional label selector for checked Kubernetes
+    /// resources. Use it when a policy check applies to multiple resources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_selector: Option<LabelSelector>,
+
+    /// Description is a short user friendly message for the policy rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// Properties provides additional information for the policy rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<BTreeMap<String, String>>,
+}
+
+/// Implement the Kubernetes resource traits, and the serialization including
+/// `apiVersion` and `kind`, for the report types. The report types do not
+/// have a `spec` field, hence they cannot rely on the CRD derive macro.
+macro_rules! impl_report_resource {
+    ($ty:ident, $kind:literal, $list_kind:literal, $plural:literal, $scope:ty) => {
+        impl k8s_openapi::Resource for $ty {
+            const API_VERSION: &'static str = "wgpolicyk8s.io/v1alpha2";
+            const GROUP: &'static str = $crate::crd::reports::common::GROUP;
+            const KIND: &'static str = $kind;
+            const VERSION: &'static str = $crate::crd::reports::common::VERSION;
+            const URL_PATH_SEGMENT: &'static str = $plural;
+            type Scope = $scope;
+        }
+
+        impl k8s_openapi::ListableResource for $ty {
+            const LIST_KIND: &'static str = $list_kind;
+        }
+
+        impl k8s_openapi::Metadata for $ty {
+            type Ty = k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+            fn metadata(&self) -> &Self::Ty {
+                &self.metadata
+            }
+
+            fn metadata_mut(&mut self) -> &mut Self::Ty {
+                &mut self.metadata
+            }
+        }
+
+        impl serde::Serialize for $ty {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use k8s_openapi::Resource;
+                use serde::ser::SerializeStruct;
+
+                let mut state = serializer.serialize_struct(
+                    Self::KIND,
+                    3 + self.scope.as_ref().map_or(0, |_| 1)
+                        + self.scope_selector.as_ref().map_or(0, |_| 1)
+                        + self.summary.as_ref().map_or(0, |_| 1)
+                        + self.results.as_ref().map_or(0, |_| 1),
+                )?;
+                state.serialize_field("apiVersion", Self::API_VERSION)?;
+                state.serialize_field("kind", Self::KIND)?;
+                state.serialize_field("metadata", &self.metadata)?;
+                if let Some(value) = &self.scope {
+                    state.serialize_field("scope", value)?;
+                }
+                if let Some(value) = &self.scope_selector {
+                    state.serialize_field("scopeSelector", value)?;
+                }
+                if let Some(value) = &self.summary {
+                    state.serialize_field("summary", value)?;
+                }
+                if let Some(value) = &self.results {
+                    state.serialize_field("results", value)?;
+                }
+                state.end()
+            }
+        }
+    };
+}
+
+pub(crate) use impl_report_resource;
@@ -0,0 +1,105 @@
+use k8s_openapi::{
+    api::core::v1::ObjectReference,
+    apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta},
+};
+
+use crate::crd::reports::common::{impl_report_resource, PolicyReportResult, PolicyReportSummary};
+
+/// PolicyReport is the Schema for the namespaced policy reports API
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyReport {
+    /// Standard object's metadata
+    #[serde(default)]
+    pub metadata: ObjectMeta,
+
+    /// Scope is an optional reference to the report scope (e.g. a Deployment,
+    /// Namespace, or Node)
+    pub scope: Option<ObjectReference>,
+
+    /// ScopeSelector is an optional selector for multiple scopes (e.g. Pods).
+    /// Either one of, or none of, but not both of, Scope or ScopeSelector
+    /// should be specified.
+    pub scope_selector: Option<LabelSelector>,
+
+    /// PolicyReportSummary provides a summary of results
+    pub summary: Option<PolicyReportSummary>,
+
+    /// PolicyReportResult provides result details
+    pub results: Option<Vec<PolicyReportResult>>,
+}
+
+impl_report_resource!(
+    PolicyReport,
+    "PolicyReport",
+    "PolicyReportList",
+    "policyreports",
+    k8s_openapi::NamespaceResourceScope
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::reports::common::{PolicyResult, PolicyResultSeverity};
+
+    const YAML: &str = r#"
+apiVersion: wgpolicyk8s.io/v1alpha2
+kind: PolicyReport
+metadata:
+  name: 0b8d4b6b-8ee6-4a0f-8a47-4bb5e3b8d5f2
+  namespace: default
+  labels:
+    app.kubernetes.io/managed-by: kubewarden
+scope:
+  apiVersion: v1
+  kind: Pod
+  name: nginx
+  namespace: default
+  uid: 0b8d4b6b-8ee6-4a0f-8a47-4bb5e3b8d5f2
+summary:
+  pass: 1
+  fail: 1
+  warn: 0
+  error: 0
+  skip: 0
+results:
+  - source: kubewarden
+    policy: namespaced-default-safe-labels
+    rule: safe-labels
+    category: validate
+    severity: low
+    timestamp:
+      seconds: 1711635611
+      nanos: 0
+    result: pass
+    scored: true
+  - source: kubewarden
+    policy: cap-no-privilege-escalation
+    result: fail
+    message: "privilege escalation is not allowed"
+    properties:
+      policy-resource-version: "123"
+"#;
+
+    #[test]
+    fn test_policy_report_roundtrip() {
+        let report: PolicyReport =
+            serde_yaml::from_str(YAML).expect("cannot deserialize PolicyReport");
+        assert_eq!(report.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(report.summary.as_ref().unwrap().fail, 1);
+
+        let results = report.results.as_ref().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].severity, Some(PolicyResultSeverity::Low));
+        assert_eq!(results[1].result, Some(PolicyResult::Fail));
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["apiVersion"], "wgpolicyk8s.io/v1alpha2");
+        assert_eq!(value["kind"], "PolicyReport");
+        assert!(value.get("scopeSelector").is_none());
+        assert!(value["results"][1].get("rule").is_none());
+
+        let report_again: PolicyReport = serde_json::from_value(value).unwrap();
+        assert_eq!(report, report_again);
+    }
+}
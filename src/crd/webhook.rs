@@ -0,0 +1,354 @@
+//! Typed `AdmissionReview` payloads, to build validating and defaulting
+//! webhooks for the Kubewarden CRDs.
+//!
+//! The [`PolicyDefaults`] trait mirrors the defaulting performed by the
+//! Kubewarden controller.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::crd::policies::common::{default_policy_server, PolicyMode};
+use crate::crd::policies::{
+    AdmissionPolicy, AdmissionPolicyGroup, ClusterAdmissionPolicy, ClusterAdmissionPolicyGroup,
+};
+use crate::request::{GroupVersionKind, GroupVersionResource, UserInfo};
+
+/// Finalizer added by the Kubewarden controller to all the policies
+pub const KUBEWARDEN_FINALIZER: &str = "kubewarden.io/finalizer";
+
+/// Kubernetes' `admission.k8s.io/v1` AdmissionReview, where the objects
+/// are deserialized into `T`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(bound(deserialize = "T: DeserializeOwned", serialize = "T: Serialize"))]
+pub struct AdmissionReview<T> {
+    /// Always `admission.k8s.io/v1`
+    pub api_version: String,
+    /// Always `AdmissionReview`
+    pub kind: String,
+    /// Request sent by the API server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<AdmissionRequest<T>>,
+    /// Response returned by the webhook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<AdmissionResponse>,
+}
+
+/// The request part of an [`AdmissionReview`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[serde(bound(deserialize = "T: DeserializeOwned", serialize = "T: Serialize"))]
+pub struct AdmissionRequest<T> {
+    /// Identifier of the request, must be copied into the response
+    pub uid: String,
+    /// Fully-qualified type of object being submitted
+    pub kind: GroupVersionKind,
+    /// Fully-qualified resource being requested
+    pub resource: GroupVersionResource,
+    /// Subresource being requested, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_resource: Option<String>,
+    /// Name of the object as presented in the request
+    #[serde(default)]
+    pub name: String,
+    /// Namespace associated with the request (if any)
+    #[serde(default)]
+    pub namespace: String,
+    /// Operation being performed
+    pub operation: String,
+    /// Information about the requesting user
+    #[serde(default)]
+    pub user_info: UserInfo,
+    /// Object from the incoming request. Not set for DELETE operations
+    #[serde(default)]
+    pub object: Option<T>,
+    /// Existing object. Only populated for DELETE and UPDATE requests
+    #[serde(default)]
+    pub old_object: Option<T>,
+    /// Modifications will not be persisted for this request
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The response part of an [`AdmissionReview`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionResponse {
+    /// Identifier of the request this response refers to
+    pub uid: String,
+    /// Whether the request is allowed
+    pub allowed: bool,
+    /// Details about the rejection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    /// Base64 encoded JSON Patch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+    /// Type of patch, always `JSONPatch` when a patch is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch_type: Option<String>,
+    /// Annotations added to the audit log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_annotations: Option<HashMap<String, String>>,
+    /// Warnings returned to the API client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<String>>,
+}
+
+impl AdmissionResponse {
+    /// Allow the request identified by `uid`
+    pub fn allow(uid: &str) -> Self {
+        AdmissionResponse {
+            uid: uid.to_string(),
+            allowed: true,
+            ..Default::default()
+        }
+    }
+
+    /// Deny the request identified by `uid`
+    pub fn deny(uid: &str, message: &str, code: i32) -> Self {
+        AdmissionResponse {
+            uid: uid.to_string(),
+            allowed: false,
+            status: Some(Status {
+                code: Some(code),
+                message: Some(message.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Attach a JSON Patch to the response
+    pub fn with_patch(mut self, patch: Vec<Value>) -> anyhow::Result<Self> {
+        if patch.is_empty() {
+            return Ok(self);
+        }
+        self.patch = Some(STANDARD.encode(serde_json::to_vec(&patch)?));
+        self.patch_type = Some("JSONPatch".to_string());
+        Ok(self)
+    }
+}
+
+impl<T> AdmissionReview<T> {
+    /// Wrap the given response into an `AdmissionReview`
+    pub fn from_response(response: AdmissionResponse) -> Self {
+        AdmissionReview {
+            api_version: "admission.k8s.io/v1".to_string(),
+            kind: "AdmissionReview".to_string(),
+            request: None,
+            response: Some(response),
+        }
+    }
+}
+
+impl<T> AdmissionReview<T>
+where
+    T: PolicyDefaults + Serialize + Clone,
+{
+    /// Build the response of a defaulting webhook: the object of the request
+    /// is defaulted and the changes are returned as a JSON Patch
+    pub fn defaulting_response(&self) -> anyhow::Result<AdmissionReview<T>> {
+        let request = self
+            .request
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("AdmissionReview does not contain a request"))?;
+        let response = AdmissionResponse::allow(&request.uid);
+        let object = match &request.object {
+            Some(object) => object,
+            None => return Ok(AdmissionReview::from_response(response)),
+        };
+
+        let mut defaulted = object.clone();
+        defaulted.apply_defaults();
+        let patch = json_patch(
+            &serde_json::to_value(object)?,
+            &serde_json::to_value(&defaulted)?,
+        );
+
+        Ok(AdmissionReview::from_response(response.with_patch(patch)?))
+    }
+}
+
+/// Compute the JSON Patch operations required to turn `original` into
+/// `modified`. Objects are compared key by key, all the other values are
+/// replaced as a whole.
+pub fn json_patch(original: &Value, modified: &Value) -> Vec<Value> {
+    let mut ops = Vec::new();
+    diff("", original, modified, &mut ops);
+    ops
+}
+
+fn diff(path: &str, original: &Value, modified: &Value, ops: &mut Vec<Value>) {
+    match (original, modified) {
+        (Value::Object(original), Value::Object(modified)) => {
+            for (key, value) in modified {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match original.get(key) {
+                    Some(current) => diff(&child, current, value, ops),
+                    None => ops.push(json!({"op": "add", "path": child, "value": value})),
+                }
+            }
+            for key in original.keys().filter(|key| !modified.contains_key(*key)) {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                ops.push(json!({"op": "remove", "path": child}));
+            }
+        }
+        (original, modified) if original != modified => {
+            ops.push(json!({"op": "replace", "path": path, "value": modified}));
+        }
+        _ => {}
+    }
+}
+
+/// Defaulting performed by the Kubewarden controller on its resources
+pub trait PolicyDefaults {
+    /// Set the default values of the fields that have not been provided
+    fn apply_defaults(&mut self);
+}
+
+macro_rules! impl_policy_defaults {
+    ($($ty:ty),*) => {
+        $(
+            impl PolicyDefaults for $ty {
+                fn apply_defaults(&mut self) {
+                    if let Some(spec) = self.spec.as_mut() {
+                        if spec.policy_server.is_empty() {
+                            spec.policy_server = default_policy_server();
+                        }
+                        if spec.mode.is_none() {
+                            spec.mode = Some(PolicyMode::default());
+                        }
+                        default_settings_of(&mut spec.settings);
+                    }
+                    add_finalizer(&mut self.metadata);
+                }
+            }
+        )*
+    };
+}
+
+impl_policy_defaults!(AdmissionPolicy, ClusterAdmissionPolicy);
+
+macro_rules! impl_policy_group_defaults {
+    ($($ty:ty),*) => {
+        $(
+            impl PolicyDefaults for $ty {
+                fn apply_defaults(&mut self) {
+                    if let Some(spec) = self.spec.as_mut() {
+                        if spec.policy_server.is_empty() {
+                            spec.policy_server = default_policy_server();
+                        }
+                        if spec.mode.is_none() {
+                            spec.mode = Some(PolicyMode::default());
+                        }
+                        for member in spec.policies.values_mut() {
+                            default_settings_of(&mut member.settings);
+                        }
+                    }
+                    add_finalizer(&mut self.metadata);
+                }
+            }
+        )*
+    };
+}
+
+impl_policy_group_defaults!(AdmissionPolicyGroup, ClusterAdmissionPolicyGroup);
+
+fn default_settings_of(settings: &mut k8s_openapi::apimachinery::pkg::runtime::RawExtension) {
+    if settings.0.is_null() {
+        *settings = crate::crd::policies::common::default_settings();
+    }
+}
+
+fn add_finalizer(metadata: &mut k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta) {
+    let finalizers = metadata.finalizers.get_or_insert_with(Vec::new);
+    if !finalizers.iter().any(|f| f == KUBEWARDEN_FINALIZER) {
+        finalizers.push(KUBEWARDEN_FINALIZER.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REVIEW: &str = r#"{
+        "apiVersion": "admission.k8s.io/v1",
+        "kind": "AdmissionReview",
+        "request": {
+            "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+            "kind": {"group": "policies.kubewarden.io", "version": "v1", "kind": "AdmissionPolicy"},
+            "resource": {"group": "policies.kubewarden.io", "version": "v1", "resource": "admissionpolicies"},
+            "name": "psp-capabilities",
+            "namespace": "default",
+            "operation": "CREATE",
+            "userInfo": {"username": "admin"},
+            "object": {
+                "apiVersion": "policies.kubewarden.io/v1",
+                "kind": "AdmissionPolicy",
+                "metadata": {"name": "psp-capabilities", "namespace": "default"},
+                "spec": {
+                    "module": "registry://ghcr.io/kubewarden/policies/psp-capabilities:v0.1.9",
+                    "policyServer": ""
+                }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn apply_defaults() {
+        let review: AdmissionReview<AdmissionPolicy> = serde_json::from_str(REVIEW).unwrap();
+        let mut policy = review.request.unwrap().object.unwrap();
+        policy.apply_defaults();
+
+        let spec = policy.spec.as_ref().unwrap();
+        assert_eq!(spec.policy_server, "default");
+        assert_eq!(spec.mode, Some(PolicyMode::Protect));
+        assert_eq!(spec.settings.0, json!({}));
+        assert_eq!(
+            policy.metadata.finalizers,
+            Some(vec![KUBEWARDEN_FINALIZER.to_string()])
+        );
+
+        // defaulting is idempotent
+        let defaulted = policy.clone();
+        policy.apply_defaults();
+        assert_eq!(policy, defaulted);
+    }
+
+    #[test]
+    fn defaulting_response_contains_patch() {
+        let review: AdmissionReview<AdmissionPolicy> = serde_json::from_str(REVIEW).unwrap();
+        let response = review.defaulting_response().unwrap().response.unwrap();
+
+        assert!(response.allowed);
+        assert_eq!(response.uid, "705ab4f5-6393-11e8-b7cc-42010a800002");
+        assert_eq!(response.patch_type.as_deref(), Some("JSONPatch"));
+
+        let patch: Vec<Value> =
+            serde_json::from_slice(&STANDARD.decode(response.patch.unwrap()).unwrap()).unwrap();
+        assert!(patch.contains(&json!({
+            "op": "replace",
+            "path": "/spec/policyServer",
+            "value": "default"
+        })));
+        assert!(patch.contains(&json!({
+            "op": "add",
+            "path": "/metadata/finalizers",
+            "value": [KUBEWARDEN_FINALIZER]
+        })));
+    }
+
+    #[test]
+    fn deny_response() {
+        let review = AdmissionReview::<AdmissionPolicy>::from_response(AdmissionResponse::deny(
+            "uid", "invalid", 400,
+        ));
+        let value = serde_json::to_value(review).unwrap();
+        assert_eq!(value["response"]["allowed"], false);
+        assert_eq!(value["response"]["status"]["message"], "invalid");
+        assert!(value.get("request").is_none());
+    }
+}
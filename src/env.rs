@@ -0,0 +1,259 @@
+//! Inspection of the environment variables of the containers.
+//!
+//! [`collect`] walks all the containers of a Pod, including the init and the
+//! ephemeral ones, and returns their environment variables together with the
+//! place their value comes from. Policies can then look for the references to
+//! Secrets, or for credentials written in clear text.
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::PodSpec;
+//! use kubewarden_policy_sdk::env::{collect, EnvSource};
+//! use serde_json::json;
+//!
+//! let pod_spec: PodSpec = serde_json::from_value(json!({
+//!     "containers": [{
+//!         "name": "app",
+//!         "env": [
+//!             {"name": "TOKEN", "value": "ghp_0123456789"},
+//!             {"name": "PASSWORD", "valueFrom": {"secretKeyRef": {"name": "db", "key": "password"}}}
+//!         ]
+//!     }]
+//! }))
+//! .unwrap();
+//!
+//! let vars = collect(&pod_spec);
+//! assert!(vars.iter().any(|var| var.references_secret("db")));
+//!
+//! let leaked: Vec<&str> = vars
+//!     .iter()
+//!     .filter(|var| matches!(&var.source, EnvSource::Literal(value) if value.starts_with("ghp_")))
+//!     .map(|var| var.path.as_str())
+//!     .collect();
+//! assert_eq!(leaked, vec!["containers[0].env[0]"]);
+//! ```
+use k8s_openapi::api::core::v1::{EnvFromSource, EnvVar, PodSpec};
+#[cfg(feature = "regex-patterns")]
+use regex::Regex;
+
+/// Where the value of an environment variable comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvSource {
+    /// The value is written inside of the PodSpec
+    Literal(String),
+    /// A key of a ConfigMap, through `valueFrom.configMapKeyRef`
+    ConfigMapKey { name: String, key: String },
+    /// A key of a Secret, through `valueFrom.secretKeyRef`
+    SecretKey { name: String, key: String },
+    /// A field of the Pod, through `valueFrom.fieldRef`
+    Field(String),
+    /// A resource of the container, through `valueFrom.resourceFieldRef`
+    ResourceField(String),
+    /// All the keys of a ConfigMap, through `envFrom.configMapRef`
+    ConfigMap(String),
+    /// All the keys of a Secret, through `envFrom.secretRef`
+    Secret(String),
+}
+
+/// An environment variable of a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvVarRef {
+    /// Name of the container
+    pub container: String,
+    /// Location of the variable inside of the PodSpec (e.g.
+    /// `containers[0].env[1]` or `initContainers[0].envFrom[0]`)
+    pub path: String,
+    /// Name of the variable. For `envFrom` entries, the prefix added to the
+    /// imported keys, which is usually empty
+    pub name: String,
+    /// Where the value of the variable comes from
+    pub source: EnvSource,
+}
+
+impl EnvVarRef {
+    /// Returns `true` when the value is read from the Secret named `name`
+    pub fn references_secret(&self, name: &str) -> bool {
+        match &self.source {
+            EnvSource::SecretKey { name: secret, .. } | EnvSource::Secret(secret) => secret == name,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` when the value is written inside of the PodSpec and
+    /// matches `regex`
+    #[cfg(feature = "regex-patterns")]
+    pub fn literal_matches(&self, regex: &Regex) -> bool {
+        matches!(&self.source, EnvSource::Literal(value) if regex.is_match(value))
+    }
+}
+
+/// Collect the environment variables of all the containers of the Pod: the
+/// init containers first, then the containers and the ephemeral containers.
+///
+/// Variables without a value nor a source are reported as empty literals.
+pub fn collect(pod_spec: &PodSpec) -> Vec<EnvVarRef> {
+    let init_containers = pod_spec
+        .init_containers
+        .iter()
+        .flatten()
+        .map(|c| (&c.name, &c.env, &c.env_from));
+    let containers = pod_spec
+        .containers
+        .iter()
+        .map(|c| (&c.name, &c.env, &c.env_from));
+    let ephemeral_containers = pod_spec
+        .ephemeral_containers
+        .iter()
+        .flatten()
+        .map(|c| (&c.name, &c.env, &c.env_from));
+
+    let mut vars = Vec::new();
+    for (field, containers) in [
+        ("initContainers", init_containers.collect::<Vec<_>>()),
+        ("containers", containers.collect()),
+        ("ephemeralContainers", ephemeral_containers.collect()),
+    ] {
+        for (index, (name, env, env_from)) in containers.into_iter().enumerate() {
+            for (var_index, var) in env.iter().flatten().enumerate() {
+                vars.push(EnvVarRef {
+                    container: name.clone(),
+                    path: format!("{}[{}].env[{}]", field, index, var_index),
+                    name: var.name.clone(),
+                    source: env_var_source(var),
+                });
+            }
+            for (source_index, source) in env_from.iter().flatten().enumerate() {
+                if let Some(env_source) = env_from_source(source) {
+                    vars.push(EnvVarRef {
+                        container: name.clone(),
+                        path: format!("{}[{}].envFrom[{}]", field, index, source_index),
+                        name: source.prefix.clone().unwrap_or_default(),
+                        source: env_source,
+                    });
+                }
+            }
+        }
+    }
+    vars
+}
+
+fn env_var_source(var: &EnvVar) -> EnvSource {
+    let Some(value_from) = &var.value_from else {
+        return EnvSource::Literal(var.value.clone().unwrap_or_default());
+    };
+    if let Some(selector) = &value_from.secret_key_ref {
+        EnvSource::SecretKey {
+            name: selector.name.clone(),
+            key: selector.key.clone(),
+        }
+    } else if let Some(selector) = &value_from.config_map_key_ref {
+        EnvSource::ConfigMapKey {
+            name: selector.name.clone(),
+            key: selector.key.clone(),
+        }
+    } else if let Some(selector) = &value_from.field_ref {
+        EnvSource::Field(selector.field_path.clone())
+    } else if let Some(selector) = &value_from.resource_field_ref {
+        EnvSource::ResourceField(selector.resource.clone())
+    } else {
+        EnvSource::Literal(var.value.clone().unwrap_or_default())
+    }
+}
+
+fn env_from_source(source: &EnvFromSource) -> Option<EnvSource> {
+    if let Some(secret) = &source.secret_ref {
+        Some(EnvSource::Secret(secret.name.clone()))
+    } else {
+        source
+            .config_map_ref
+            .as_ref()
+            .map(|config_map| EnvSource::ConfigMap(config_map.name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod_spec() -> PodSpec {
+        serde_json::from_value(json!({
+            "initContainers": [{"name": "init", "envFrom": [
+                {"secretRef": {"name": "bootstrap"}},
+                {"prefix": "CFG_", "configMapRef": {"name": "settings"}}
+            ]}],
+            "containers": [{"name": "app", "env": [
+                {"name": "MODE", "value": "prod"},
+                {"name": "EMPTY"},
+                {"name": "URL", "valueFrom": {"configMapKeyRef": {"name": "settings", "key": "url"}}},
+                {"name": "NODE", "valueFrom": {"fieldRef": {"fieldPath": "spec.nodeName"}}},
+                {"name": "CPU", "valueFrom": {"resourceFieldRef": {"resource": "limits.cpu"}}}
+            ]}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn collect_all_sources() {
+        let vars = collect(&pod_spec());
+        let summary: Vec<(&str, &str, &EnvSource)> = vars
+            .iter()
+            .map(|var| (var.path.as_str(), var.name.as_str(), &var.source))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "initContainers[0].envFrom[0]",
+                    "",
+                    &EnvSource::Secret("bootstrap".to_string())
+                ),
+                (
+                    "initContainers[0].envFrom[1]",
+                    "CFG_",
+                    &EnvSource::ConfigMap("settings".to_string())
+                ),
+                (
+                    "containers[0].env[0]",
+                    "MODE",
+                    &EnvSource::Literal("prod".to_string())
+                ),
+                (
+                    "containers[0].env[1]",
+                    "EMPTY",
+                    &EnvSource::Literal(String::new())
+                ),
+                (
+                    "containers[0].env[2]",
+                    "URL",
+                    &EnvSource::ConfigMapKey {
+                        name: "settings".to_string(),
+                        key: "url".to_string()
+                    }
+                ),
+                (
+                    "containers[0].env[3]",
+                    "NODE",
+                    &EnvSource::Field("spec.nodeName".to_string())
+                ),
+                (
+                    "containers[0].env[4]",
+                    "CPU",
+                    &EnvSource::ResourceField("limits.cpu".to_string())
+                ),
+            ]
+        );
+        assert_eq!(vars[0].container, "init");
+        assert!(vars[0].references_secret("bootstrap"));
+        assert!(!vars[1].references_secret("settings"));
+    }
+
+    #[cfg(feature = "regex-patterns")]
+    #[test]
+    fn match_literals() {
+        let vars = collect(&pod_spec());
+        assert!(vars[2].literal_matches(&Regex::new("^pr").unwrap()));
+        assert!(!vars[4].literal_matches(&Regex::new("").unwrap()));
+    }
+}
@@ -0,0 +1,199 @@
+//! Helpers to find out whether a request should be exempted from the policy
+//! evaluation.
+//!
+//! Namespaces are commonly used to opt-out workloads from some checks, by
+//! labelling them (e.g. `pod-security.kubernetes.io/enforce=privileged`).
+//! The helpers of this module look up the namespace of the object being
+//! evaluated through the Kubernetes host capability, and check its labels.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::Namespace;
+use k8s_openapi::Resource;
+
+pub use crate::host_capabilities::error::FailureMode;
+use crate::host_capabilities::kubernetes::{get_resource, GetResourceRequest};
+use crate::request::ValidationRequest;
+
+thread_local! {
+    static NAMESPACE_LABELS: RefCell<HashMap<String, BTreeMap<String, String>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Returns `true` when the namespace of the object being evaluated has the
+/// `label_key` label set to one of the `expected_values`. When
+/// `expected_values` is empty, the presence of the label is enough.
+///
+/// Cluster-wide objects are never exempted. When the object being evaluated
+/// is a Namespace, its own labels are checked.
+///
+/// The labels of the namespaces are cached for the lifetime of the policy
+/// instance, use [`clear_namespace_cache`] to drop them. When the namespace
+/// cannot be looked up, the result is decided by the `failure_mode`:
+/// failing open means the namespace is considered exempted.
+pub fn namespace_is_exempt<T: Default>(
+    validation_request: &ValidationRequest<T>,
+    label_key: &str,
+    expected_values: &[&str],
+    failure_mode: FailureMode,
+) -> bool {
+    let request = &validation_request.request;
+
+    let labels = if request.kind.group.is_empty() && request.kind.kind == Namespace::KIND {
+        serde_json::from_value::<Namespace>(request.object.clone())
+            .map(|namespace| namespace.metadata.labels.unwrap_or_default())
+            .map_err(anyhow::Error::from)
+    } else if request.namespace.is_empty() {
+        return false;
+    } else {
+        namespace_labels(&request.namespace)
+    };
+
+    match labels {
+        Ok(labels) => labels.get(label_key).is_some_and(|value| {
+            expected_values.is_empty() || expected_values.contains(&value.as_str())
+        }),
+        Err(_) => failure_mode == FailureMode::Open,
+    }
+}
+
+/// Drop all the namespace labels cached by [`namespace_is_exempt`]
+pub fn clear_namespace_cache() {
+    NAMESPACE_LABELS.with(|cache| cache.borrow_mut().clear());
+}
+
+fn namespace_labels(name: &str) -> Result<BTreeMap<String, String>> {
+    if let Some(labels) = NAMESPACE_LABELS.with(|cache| cache.borrow().get(name).cloned()) {
+        return Ok(labels);
+    }
+
+    let namespace: Namespace = get_resource(&GetResourceRequest {
+        api_version: Namespace::API_VERSION.to_string(),
+        kind: Namespace::KIND.to_string(),
+        name: name.to_string(),
+        namespace: None,
+        disable_cache: false,
+        max_age_seconds: None,
+    })?;
+    let labels = namespace.metadata.labels.unwrap_or_default();
+
+    NAMESPACE_LABELS.with(|cache| cache.borrow_mut().insert(name.to_string(), labels.clone()));
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_capabilities::kubernetes::tests::mock_wapc;
+    use crate::request::{GroupVersionKind, KubernetesAdmissionRequest};
+    use serial_test::serial;
+
+    const ENFORCE_LABEL: &str = "pod-security.kubernetes.io/enforce";
+
+    fn pod_request(namespace: &str) -> ValidationRequest<()> {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
+                kind: GroupVersionKind::new("", "v1", "Pod"),
+                namespace: namespace.to_string(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn namespace_response(labels: serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "ns", "labels": labels }
+        }))
+        .unwrap()
+    }
+
+    #[serial]
+    #[test]
+    fn namespace_with_expected_label_is_exempt() {
+        clear_namespace_cache();
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(1).returning(|_, _, _, _| {
+            Ok(namespace_response(
+                serde_json::json!({ ENFORCE_LABEL: "privileged" }),
+            ))
+        });
+
+        let request = pod_request("ns");
+        assert!(namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &["privileged"],
+            FailureMode::Closed
+        ));
+        // served from the cache, the host is not contacted again
+        assert!(!namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &["baseline"],
+            FailureMode::Closed
+        ));
+        assert!(namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &[],
+            FailureMode::Closed
+        ));
+    }
+
+    #[serial]
+    #[test]
+    fn lookup_errors_follow_failure_mode() {
+        clear_namespace_cache();
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(2)
+            .returning(|_, _, _, _| Err("forbidden".into()));
+
+        let request = pod_request("ns");
+        assert!(namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &[],
+            FailureMode::Open
+        ));
+        assert!(!namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &[],
+            FailureMode::Closed
+        ));
+    }
+
+    #[serial]
+    #[test]
+    fn cluster_wide_and_namespace_objects() {
+        clear_namespace_cache();
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(0);
+
+        assert!(!namespace_is_exempt(
+            &pod_request(""),
+            ENFORCE_LABEL,
+            &[],
+            FailureMode::Open
+        ));
+
+        let mut request = pod_request("");
+        request.request.kind = GroupVersionKind::new("", "v1", "Namespace");
+        request.request.object = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "ns", "labels": { ENFORCE_LABEL: "privileged" } }
+        });
+        assert!(namespace_is_exempt(
+            &request,
+            ENFORCE_LABEL,
+            &["privileged"],
+            FailureMode::Closed
+        ));
+    }
+}
@@ -0,0 +1,627 @@
+//! Abstraction over the channel used to reach the host, with retries.
+//!
+//! [`HostClient`] is implemented by [`WapcClient`], which performs the waPC
+//! host calls, and by [`RetryingClient`], which retries the calls failing
+//! because of transient problems (e.g. a registry hiccup) with an exponential
+//! backoff. Errors are classified by [`classify_error`]: only the
+//! [`ErrorClass::Retryable`] ones are retried. The operations that must not
+//! be performed twice, listed by [`NON_IDEMPOTENT_OPERATIONS`], are never
+//! retried.
+//!
+//! The backoff waits through [`Clock::sleep`]: the time spent waiting counts
+//! against the execution timeout of the policy enforced by the host, keep
+//! `max_attempts` and `max_backoff` low.
+//!
+//! Once a [`RetryPolicy`] is installed with [`set_retry_policy`], all the
+//! host capabilities of the SDK retry their calls.
+//!
+//! A [`CircuitBreaker`] can be installed too, with [`set_circuit_breaker`]:
+//! after too many consecutive transient failures of a capability (e.g.
+//! `oci`), its calls fail immediately for the rest of the evaluation, instead
+//! of waiting for the host once again. The circuits are closed when a new
+//! admission request is decoded by
+//! [`ValidationRequest::new`](crate::request::ValidationRequest::new), and
+//! when the `validate` function registered by
+//! [`register_policy`](crate::register_policy) is invoked.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::host_capabilities::client::{set_retry_policy, RetryPolicy};
+//! use std::time::Duration;
+//!
+//! set_retry_policy(Some(RetryPolicy {
+//!     max_attempts: 3,
+//!     initial_backoff: Duration::from_millis(200),
+//!     ..Default::default()
+//! }));
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::host_capabilities::error::{handle_host_failure, FailureMode};
+use crate::host_capabilities::ops;
+use crate::host_capabilities::telemetry::record_host_call;
+use crate::host_capabilities::time::{Clock, HostClock};
+
+/// Fragments of the error messages of the host identifying transient
+/// failures, compared case-insensitively
+pub const RETRYABLE_ERROR_PATTERNS: &[&str] = &[
+    "timeout",
+    "timed out",
+    "connection refused",
+    "connection reset",
+    "temporarily unavailable",
+    "service unavailable",
+    "too many requests",
+    "bad gateway",
+    "gateway timeout",
+];
+
+/// HTTP status codes identifying transient failures, see [`status_code`]
+pub const RETRYABLE_STATUS_CODES: &[u16] = &[429, 502, 503, 504];
+
+/// Operations, identified by their namespace and name, that have side
+/// effects and are never retried
+pub const NON_IDEMPOTENT_OPERATIONS: &[(&str, &str)] = &[
+    (ops::NAMESPACE_EVENTS, ops::EVENTS_V1_EMIT),
+    (ops::NAMESPACE_KUBERNETES, ops::KUBERNETES_DRY_RUN_APPLY),
+];
+
+/// Beginning of the error returned by the capabilities whose circuit is open
+pub const CIRCUIT_OPEN_MESSAGE: &str = "circuit open for capability";
+
+thread_local! {
+    static RETRY_POLICY: Cell<Option<RetryPolicy>> = const { Cell::new(None) };
+    static CIRCUIT_BREAKER: Cell<Option<CircuitBreaker>> = const { Cell::new(None) };
+    static CONSECUTIVE_FAILURES: RefCell<BTreeMap<String, u32>> = const { RefCell::new(BTreeMap::new()) };
+    static EVALUATED_REQUEST_UID: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// A channel to the host
+pub trait HostClient {
+    /// Invoke the operation `op` of the namespace `ns`, see
+    /// [`wapc_guest::host_call`]
+    fn host_call(&self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> wapc_guest::CallResult;
+}
+
+/// A [`HostClient`] performing waPC host calls
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WapcClient;
+
+impl HostClient for WapcClient {
+    fn host_call(&self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> wapc_guest::CallResult {
+        record_host_call(ns, op);
+        wapc_guest::host_call(binding, ns, op, msg)
+    }
+}
+
+/// Whether a failed host call can be retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The failure is transient, the call can succeed if retried
+    Retryable,
+    /// Retrying the call would fail again (e.g. the resource does not exist)
+    Fatal,
+}
+
+/// Classify the error returned by the host, looking for the
+/// [`RETRYABLE_ERROR_PATTERNS`] inside of its message, and for one of the
+/// [`RETRYABLE_STATUS_CODES`] reported as [`status_code`]
+pub fn classify_error(error: &str) -> ErrorClass {
+    let lowercase = error.to_lowercase();
+    let retryable = RETRYABLE_ERROR_PATTERNS
+        .iter()
+        .any(|pattern| lowercase.contains(pattern))
+        || status_code(error).is_some_and(|code| RETRYABLE_STATUS_CODES.contains(&code));
+    if retryable {
+        ErrorClass::Retryable
+    } else {
+        ErrorClass::Fatal
+    }
+}
+
+/// Returns `true` when the operation `op` of the namespace `ns` can be
+/// retried, see [`NON_IDEMPOTENT_OPERATIONS`]
+pub fn is_idempotent(ns: &str, op: &str) -> bool {
+    !NON_IDEMPOTENT_OPERATIONS.contains(&(ns, op))
+}
+
+/// The HTTP status code reported inside of an error message, e.g.
+/// `status: 503`, `status code 429` or `HTTP 502`. Numbers appearing
+/// elsewhere (resource names, ports, UIDs,...) are ignored
+pub fn status_code(error: &str) -> Option<u16> {
+    let lowercase = error.to_lowercase();
+    for keyword in ["status code", "status", "http"] {
+        for (start, _) in lowercase.match_indices(keyword) {
+            let rest = lowercase[start + keyword.len()..].trim_start_matches([' ', ':', '=']);
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 3 {
+                return rest[..3].parse().ok();
+            }
+        }
+    }
+    None
+}
+
+/// How many times, and how often, a host call is attempted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    /// Time waited before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the time waited between two attempts
+    pub max_backoff: Duration,
+    /// Factor applied to the backoff after each retry
+    pub multiplier: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Time waited before the given retry, starting from `1`
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .checked_pow(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Install the retry policy applied to all the host capabilities of the SDK,
+/// `None` disables the retries
+pub fn set_retry_policy(policy: Option<RetryPolicy>) {
+    RETRY_POLICY.set(policy);
+}
+
+/// The retry policy applied to the host capabilities
+pub fn retry_policy() -> Option<RetryPolicy> {
+    RETRY_POLICY.get()
+}
+
+/// A [`HostClient`] retrying the calls of the `inner` client that fail with
+/// a [`ErrorClass::Retryable`] error, unless the operation is listed by
+/// [`NON_IDEMPOTENT_OPERATIONS`]
+pub struct RetryingClient<C: HostClient = WapcClient, K: Clock = HostClock> {
+    inner: C,
+    policy: RetryPolicy,
+    clock: K,
+}
+
+impl RetryingClient {
+    /// Retry the waPC host calls according to `policy`
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self::with_client(WapcClient, policy, HostClock)
+    }
+}
+
+impl<C: HostClient, K: Clock> RetryingClient<C, K> {
+    /// Retry the calls of `inner` according to `policy`, using `clock` to
+    /// wait between the attempts
+    pub fn with_client(inner: C, policy: RetryPolicy, clock: K) -> Self {
+        RetryingClient {
+            inner,
+            policy,
+            clock,
+        }
+    }
+}
+
+impl<C: HostClient, K: Clock> HostClient for RetryingClient<C, K> {
+    fn host_call(&self, binding: &str, ns: &str, op: &str, msg: &[u8]) -> wapc_guest::CallResult {
+        let max_attempts = if is_idempotent(ns, op) {
+            self.policy.max_attempts
+        } else {
+            1
+        };
+        let mut retry = 0;
+        loop {
+            match self.inner.host_call(binding, ns, op, msg) {
+                Err(error)
+                    if retry + 1 < max_attempts
+                        && classify_error(&error.to_string()) == ErrorClass::Retryable =>
+                {
+                    retry += 1;
+                    self.clock.sleep(self.policy.backoff(retry));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Stop calling a capability of the host after too many consecutive
+/// failures within an evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Number of consecutive transient failures, counted after the retries,
+    /// opening the circuit of a capability
+    pub failure_threshold: u32,
+    /// How [`respond_to_failure`] handles the failures
+    pub failure_mode: FailureMode,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            failure_threshold: 3,
+            failure_mode: FailureMode::Closed,
+        }
+    }
+}
+
+/// Install the circuit breaker applied to all the capabilities of the host,
+/// identified by their namespace. `None` disables the circuit breaker
+pub fn set_circuit_breaker(breaker: Option<CircuitBreaker>) {
+    CIRCUIT_BREAKER.set(breaker);
+    reset_circuit_breakers();
+}
+
+/// The installed circuit breaker
+pub fn circuit_breaker() -> Option<CircuitBreaker> {
+    CIRCUIT_BREAKER.get()
+}
+
+/// Close all the circuits. This is done automatically at the beginning of
+/// each evaluation, see the [module documentation](self), since the
+/// circuits are meant to stay open only during one evaluation
+pub fn reset_circuit_breakers() {
+    CONSECUTIVE_FAILURES.with(|failures| failures.borrow_mut().clear());
+}
+
+/// Close all the circuits when `uid` identifies a different admission
+/// request than the one evaluated so far. Decoding the same request again,
+/// in the middle of its evaluation, keeps the circuits. Requests without
+/// `uid` always start a new evaluation
+pub(crate) fn begin_evaluation(uid: &str) {
+    EVALUATED_REQUEST_UID.with(|current| {
+        let mut current = current.borrow_mut();
+        if uid.is_empty() || *current != uid {
+            uid.clone_into(&mut current);
+            reset_circuit_breakers();
+        }
+    });
+}
+
+/// The capabilities whose circuit is open
+pub fn open_circuits() -> Vec<String> {
+    let Some(breaker) = circuit_breaker() else {
+        return Vec::new();
+    };
+    CONSECUTIVE_FAILURES.with(|failures| {
+        failures
+            .borrow()
+            .iter()
+            .filter(|(_, count)| **count >= breaker.failure_threshold)
+            .map(|(ns, _)| ns.clone())
+            .collect()
+    })
+}
+
+/// Returns `true` when `error` has been caused by an open circuit
+pub fn is_circuit_open_error(error: &anyhow::Error) -> bool {
+    error.to_string().contains(CIRCUIT_OPEN_MESSAGE)
+}
+
+/// Translate a host capability failure into a response, according to the
+/// [`FailureMode`] of the installed [`CircuitBreaker`]. The request is
+/// rejected when no circuit breaker is installed, see [`handle_host_failure`]
+pub fn respond_to_failure(error: &anyhow::Error) -> wapc_guest::CallResult {
+    let failure_mode = circuit_breaker().map_or(FailureMode::Closed, |b| b.failure_mode);
+    handle_host_failure(failure_mode, error)
+}
+
+/// Perform a host call on behalf of the host capabilities, applying the
+/// installed [`RetryPolicy`] and [`CircuitBreaker`]
+pub(crate) fn dispatch(binding: &str, ns: &str, op: &str, msg: &[u8]) -> wapc_guest::CallResult {
+    let call = || match retry_policy() {
+        Some(policy) => RetryingClient::new(policy).host_call(binding, ns, op, msg),
+        None => WapcClient.host_call(binding, ns, op, msg),
+    };
+    match circuit_breaker() {
+        Some(breaker) => with_circuit_breaker(&breaker, ns, call),
+        None => call(),
+    }
+}
+
+fn with_circuit_breaker<F>(breaker: &CircuitBreaker, ns: &str, call: F) -> wapc_guest::CallResult
+where
+    F: FnOnce() -> wapc_guest::CallResult,
+{
+    let failures = CONSECUTIVE_FAILURES.with(|f| f.borrow().get(ns).copied().unwrap_or(0));
+    if failures >= breaker.failure_threshold {
+        return Err(format!(
+            "{} '{}': {} consecutive failures",
+            CIRCUIT_OPEN_MESSAGE, ns, failures
+        )
+        .into());
+    }
+
+    let result = call();
+    let failed =
+        matches!(&result, Err(e) if classify_error(&e.to_string()) == ErrorClass::Retryable);
+    CONSECUTIVE_FAILURES.with(|f| {
+        let mut f = f.borrow_mut();
+        if failed {
+            *f.entry(ns.to_string()).or_default() += 1;
+        } else {
+            f.remove(ns);
+        }
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Returns the given results, one per call
+    struct ScriptedClient {
+        results: RefCell<Vec<Result<Vec<u8>, String>>>,
+        calls: Cell<u32>,
+    }
+
+    impl ScriptedClient {
+        fn new(results: Vec<Result<Vec<u8>, &str>>) -> Self {
+            ScriptedClient {
+                results: RefCell::new(
+                    results
+                        .into_iter()
+                        .rev()
+                        .map(|r| r.map_err(str::to_string))
+                        .collect(),
+                ),
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl HostClient for &ScriptedClient {
+        fn host_call(&self, _: &str, _: &str, _: &str, _: &[u8]) -> wapc_guest::CallResult {
+            self.calls.set(self.calls.get() + 1);
+            self.results
+                .borrow_mut()
+                .pop()
+                .unwrap()
+                .map_err(|e| e.into())
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingClock {
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl Clock for &RecordingClock {
+        fn now(&self) -> anyhow::Result<String> {
+            Ok("2024-01-01T10:00:00Z".to_string())
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+            multiplier: 2,
+        }
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = policy();
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert_eq!(policy.backoff(40), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn classify_errors() {
+        assert_eq!(
+            classify_error("registry returned status code 503 Service Unavailable"),
+            ErrorClass::Retryable
+        );
+        assert_eq!(classify_error("Request Timed Out"), ErrorClass::Retryable);
+        assert_eq!(classify_error("HTTP 429"), ErrorClass::Retryable);
+        assert_eq!(classify_error("manifest unknown"), ErrorClass::Fatal);
+        assert_eq!(classify_error("Unauthorized"), ErrorClass::Fatal);
+        // digits outside of a status code do not make an error transient
+        assert_eq!(
+            classify_error("deployment web-5039 not found"),
+            ErrorClass::Fatal
+        );
+        assert_eq!(
+            classify_error("cannot reach 10.0.0.1:5030: no route to host"),
+            ErrorClass::Fatal
+        );
+        assert_eq!(
+            classify_error("pod with uid 429b0e1c is gone, status: 404"),
+            ErrorClass::Fatal
+        );
+    }
+
+    #[test]
+    fn parse_status_codes() {
+        assert_eq!(status_code("status: 503"), Some(503));
+        assert_eq!(status_code("Status Code=502 Bad Gateway"), Some(502));
+        assert_eq!(status_code("unexpected HTTP 504"), Some(504));
+        assert_eq!(status_code("status 5030"), None);
+        assert_eq!(status_code("image v503 not found"), None);
+    }
+
+    #[test]
+    fn retry_transient_failures() {
+        let inner = ScriptedClient::new(vec![
+            Err("connection reset by peer"),
+            Err("status: 503"),
+            Ok(b"ok".to_vec()),
+        ]);
+        let clock = RecordingClock::default();
+        let client = RetryingClient::with_client(&inner, policy(), &clock);
+
+        assert_eq!(
+            client
+                .host_call("kubewarden", "oci", "v2/verify", &[])
+                .unwrap(),
+            b"ok"
+        );
+        assert_eq!(inner.calls.get(), 3);
+        assert_eq!(
+            *clock.sleeps.borrow(),
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn give_up() {
+        let inner = ScriptedClient::new(vec![Err("timeout"); 4]);
+        let clock = RecordingClock::default();
+        let client = RetryingClient::with_client(&inner, policy(), &clock);
+        assert!(client
+            .host_call("kubewarden", "oci", "v2/verify", &[])
+            .is_err());
+        assert_eq!(inner.calls.get(), 4);
+        assert_eq!(clock.sleeps.borrow().len(), 3);
+
+        let inner = ScriptedClient::new(vec![Err("not found")]);
+        let clock = RecordingClock::default();
+        let client = RetryingClient::with_client(&inner, policy(), &clock);
+        assert!(client
+            .host_call("kubewarden", "oci", "v2/verify", &[])
+            .is_err());
+        assert_eq!(inner.calls.get(), 1);
+        assert!(clock.sleeps.borrow().is_empty());
+    }
+
+    #[test]
+    fn do_not_retry_non_idempotent_operations() {
+        for (ns, op) in NON_IDEMPOTENT_OPERATIONS {
+            let inner = ScriptedClient::new(vec![Err("timeout")]);
+            let clock = RecordingClock::default();
+            let client = RetryingClient::with_client(&inner, policy(), &clock);
+            assert!(client.host_call("kubewarden", ns, op, &[]).is_err());
+            assert_eq!(inner.calls.get(), 1, "{ns} {op} has been retried");
+            assert!(clock.sleeps.borrow().is_empty());
+        }
+        assert!(is_idempotent(ops::NAMESPACE_OCI, ops::OCI_V2_VERIFY));
+    }
+
+    #[test]
+    fn circuit_breaker_opens() {
+        let breaker = CircuitBreaker {
+            failure_threshold: 2,
+            failure_mode: FailureMode::Open,
+        };
+        set_circuit_breaker(Some(breaker));
+        let inner = ScriptedClient::new(vec![
+            Err("status: 503"),
+            Ok(Vec::new()),
+            Err("not found"),
+            Err("timeout"),
+            Err("timeout"),
+        ]);
+        let call =
+            |ns: &str| with_circuit_breaker(&breaker, ns, || (&inner).host_call("", ns, "", &[]));
+
+        // a success, or a fatal error, resets the count
+        assert!(call("oci").is_err());
+        assert!(call("oci").is_ok());
+        assert!(call("oci").is_err());
+        assert!(call("oci").is_err());
+        assert!(open_circuits().is_empty());
+        assert!(call("oci").is_err());
+        assert_eq!(open_circuits(), vec!["oci".to_string()]);
+
+        // the host is not called anymore
+        let error = anyhow::anyhow!("{}", call("oci").unwrap_err());
+        assert_eq!(inner.calls.get(), 5);
+        assert!(is_circuit_open_error(&error));
+        let response: crate::response::ValidationResponse =
+            serde_json::from_slice(&respond_to_failure(&error).unwrap()).unwrap();
+        assert!(response.accepted);
+
+        reset_circuit_breakers();
+        assert!(open_circuits().is_empty());
+        set_circuit_breaker(None);
+    }
+
+    #[test]
+    fn circuits_reset_once_per_evaluation() {
+        let breaker = CircuitBreaker {
+            failure_threshold: 1,
+            failure_mode: FailureMode::Closed,
+        };
+        set_circuit_breaker(Some(breaker));
+        begin_evaluation("1234");
+        let inner = ScriptedClient::new(vec![Err("timeout")]);
+        assert!(
+            with_circuit_breaker(&breaker, "oci", || (&inner).host_call("", "oci", "", &[]))
+                .is_err()
+        );
+        assert_eq!(open_circuits(), vec!["oci".to_string()]);
+
+        // decoding the request again, in the middle of its evaluation, keeps
+        // the circuits
+        crate::request::ValidationRequest::<()>::new(
+            br#"{"settings": null, "request": {"uid": "1234"}}"#,
+        )
+        .unwrap();
+        assert_eq!(open_circuits(), vec!["oci".to_string()]);
+
+        crate::middleware::set_validate_function(|_| {
+            assert!(open_circuits().is_empty());
+            crate::accept_request()
+        });
+        let response: crate::response::ValidationResponse =
+            serde_json::from_slice(&crate::middleware::validate_guest(b"{}").unwrap()).unwrap();
+        assert!(response.accepted);
+        set_circuit_breaker(None);
+    }
+
+    #[test]
+    fn circuits_reset_without_register_policy() {
+        let breaker = CircuitBreaker {
+            failure_threshold: 1,
+            failure_mode: FailureMode::Closed,
+        };
+        set_circuit_breaker(Some(breaker));
+        let inner = ScriptedClient::new(vec![Err("timeout"), Ok(Vec::new())]);
+        let call =
+            || with_circuit_breaker(&breaker, "oci", || (&inner).host_call("", "oci", "", &[]));
+
+        // the policy registers its validate function with wapc_guest directly,
+        // each admission request is decoded with ValidationRequest::new
+        let first = br#"{"settings": null, "request": {"uid": "first"}}"#;
+        crate::request::ValidationRequest::<()>::new(first).unwrap();
+        assert!(call().is_err());
+        assert!(call().is_err());
+        assert_eq!(inner.calls.get(), 1);
+
+        let second = br#"{"settings": null, "request": {"uid": "second"}}"#;
+        crate::request::ValidationRequest::<()>::new(second).unwrap();
+        assert!(open_circuits().is_empty());
+        assert!(call().is_ok());
+        assert_eq!(inner.calls.get(), 2);
+        set_circuit_breaker(None);
+    }
+}
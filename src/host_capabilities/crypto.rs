@@ -1,34 +1,238 @@
-use crate::host_capabilities::crypto_v1::{
-    CertificateVerificationRequest, CertificateVerificationResponse,
-};
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
-
-/// A x509 certificate
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-pub struct Certificate {
-    /// Which encoding is used by the certificate
-    pub encoding: CertificateEncoding,
-    /// Actual certificate
-    pub data: Vec<u8>,
+#[cfg(test)]
+use tests::mock_wapc as host;
+use v1::{CertificateVerificationRequest, CertificateVerificationResponse};
+
+/// Payloads of the `v1/is_certificate_trusted` operation
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    /// A x509 certificate
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct Certificate {
+        /// Which encoding is used by the certificate
+        pub encoding: CertificateEncoding,
+        /// Actual certificate
+        pub data: Vec<u8>,
+    }
+
+    /// The encoding of the certificate
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub enum CertificateEncoding {
+        #[allow(missing_docs)]
+        // Be explicit about how the name should be handled
+        // see https://github.com/kubewarden/policy-sdk-rust/issues/105
+        #[serde(rename = "Der")]
+        Der,
+
+        #[allow(missing_docs)]
+        // Be explicit about how the name should be handled
+        // see https://github.com/kubewarden/policy-sdk-rust/issues/105
+        #[serde(rename = "Pem")]
+        Pem,
+    }
+
+    /// CertificateVerificationRequest holds information about a certificate and
+    /// a chain to validate it with.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct CertificateVerificationRequest {
+        /// PEM-encoded certificate
+        pub cert: Certificate,
+        /// list of PEM-encoded certs, ordered by trust usage (intermediates first, root last)
+        /// If empty, certificate is assumed trusted
+        pub cert_chain: Option<Vec<Certificate>>,
+        /// RFC 3339 time format string, to check expiration against. If None,
+        /// certificate is assumed never expired
+        #[serde(with = "optional_string_as_none")]
+        pub not_after: Option<String>,
+    }
+
+    /// Custom serialization and deserialization method. Ensure Some("") is serialized/deserialized
+    /// as None
+    pub(super) mod optional_string_as_none {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Ok(Option::<String>::deserialize(deserializer)?.and_then(|s| {
+                if s.is_empty() {
+                    None
+                } else {
+                    Some(s)
+                }
+            }))
+        }
+
+        pub fn serialize<S>(
+            optional_string: &Option<String>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match optional_string {
+                Some(s) => {
+                    if s.is_empty() {
+                        serializer.serialize_none()
+                    } else {
+                        serializer.serialize_some(s)
+                    }
+                }
+                None => serializer.serialize_none(),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct CertificateVerificationResponse {
+        pub trusted: bool,
+        /// empty when trusted is true
+        #[serde(default)]
+        pub reason: String,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn certificate_verification_request_handle_serialization_with_empty_not_after() {
+            let data = "hello world".as_bytes().to_owned();
+            let request = CertificateVerificationRequest {
+                cert: Certificate {
+                    encoding: crate::host_capabilities::crypto::CertificateEncoding::Pem,
+                    data,
+                },
+                cert_chain: None,
+                not_after: Some("".to_owned()),
+            };
+
+            let request_json = serde_json::to_value(request).unwrap();
+            let request_obj = request_json
+                .as_object()
+                .expect("cannot convert json data back to an object");
+            assert_eq!(
+                Some(&serde_json::Value::Null),
+                request_obj.get(&"not_after".to_owned())
+            );
+        }
+
+        #[test]
+        fn certificate_verification_request_handle_deserialization_with_empty_not_after() {
+            let data = "hello world".as_bytes().to_owned();
+            let input = json!({
+                "cert": {
+                    "encoding": "Pem",
+                    "data": data
+                },
+                "not_after": ""
+            });
+
+            let request: CertificateVerificationRequest = serde_json::from_value(input).unwrap();
+            assert!(request.not_after.is_none());
+        }
+    }
 }
 
-/// The encoding of the certificate
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-pub enum CertificateEncoding {
-    #[allow(missing_docs)]
-    // Be explicit about how the name should be handled
-    // see https://github.com/kubewarden/policy-sdk-rust/issues/105
-    #[serde(rename = "Der")]
-    Der,
-
-    #[allow(missing_docs)]
-    // Be explicit about how the name should be handled
-    // see https://github.com/kubewarden/policy-sdk-rust/issues/105
-    #[serde(rename = "Pem")]
-    Pem,
+/// Payloads of the `v2/is_certificate_trusted` operation
+pub mod v2 {
+    use super::v1::optional_string_as_none;
+    use serde::{Deserialize, Serialize};
+
+    pub use super::v1::{Certificate, CertificateEncoding, CertificateVerificationResponse};
+
+    /// CertificateVerificationRequest holds information about a certificate and
+    /// how to validate it. The certificate can be validated either with a
+    /// chain of certificates, or with a trust store configured on the
+    /// policy-server.
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct CertificateVerificationRequest {
+        /// PEM-encoded certificate
+        pub cert: Certificate,
+        /// list of PEM-encoded certs, ordered by trust usage (intermediates first, root last)
+        /// If empty and no `trust_store` is given, certificate is assumed trusted
+        pub cert_chain: Option<Vec<Certificate>>,
+        /// Name of a trust store configured on the policy-server (e.g.
+        /// `corporate-ca`). The certificates of the trust store are used
+        /// together with the ones of `cert_chain`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub trust_store: Option<String>,
+        /// RFC 3339 time format string, to check expiration against. If None,
+        /// certificate is assumed never expired
+        #[serde(with = "optional_string_as_none")]
+        pub not_after: Option<String>,
+    }
+
+    /// Every `v1/is_certificate_trusted` request can be expressed as a
+    /// `v2/is_certificate_trusted` one, without a trust store
+    impl From<super::v1::CertificateVerificationRequest> for CertificateVerificationRequest {
+        fn from(req: super::v1::CertificateVerificationRequest) -> Self {
+            CertificateVerificationRequest {
+                cert: req.cert,
+                cert_chain: req.cert_chain,
+                trust_store: None,
+                not_after: req.not_after,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn certificate_verification_request_with_trust_store() {
+            let request = CertificateVerificationRequest {
+                cert: Certificate {
+                    encoding: CertificateEncoding::Pem,
+                    data: "hello world".as_bytes().to_owned(),
+                },
+                cert_chain: None,
+                trust_store: Some("corporate-ca".to_string()),
+                not_after: None,
+            };
+
+            let request_json = serde_json::to_value(request).unwrap();
+            assert_eq!(request_json["trust_store"], json!("corporate-ca"));
+
+            let request: CertificateVerificationRequest = serde_json::from_value(json!({
+                "cert": { "encoding": "Pem", "data": [] },
+                "cert_chain": null,
+                "not_after": null
+            }))
+            .unwrap();
+            assert!(request.trust_store.is_none());
+        }
+
+        #[test]
+        fn convert_v1_request() {
+            let request: CertificateVerificationRequest =
+                super::super::v1::CertificateVerificationRequest {
+                    cert: Certificate {
+                        encoding: CertificateEncoding::Der,
+                        data: vec![1, 2, 3],
+                    },
+                    cert_chain: None,
+                    not_after: Some("2030-01-01T00:00:00Z".to_string()),
+                }
+                .into();
+
+            assert_eq!(request.cert.data, vec![1, 2, 3]);
+            assert!(request.trust_store.is_none());
+            assert_eq!(request.not_after.as_deref(), Some("2030-01-01T00:00:00Z"));
+        }
+    }
 }
 
+pub use v1::{Certificate, CertificateEncoding};
+
 /// Used as return of verify_cert()
 #[derive(Debug)]
 pub enum BoolWithReason {
@@ -75,11 +279,57 @@ pub fn verify_cert(
             e
         )
     })?;
-    let response_raw =
-        wapc_guest::host_call("kubewarden", "crypto", "v1/is_certificate_trusted", &msg)
-            .map_err(|e| anyhow!("{}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_CRYPTO,
+        ops::CRYPTO_V1_IS_CERTIFICATE_TRUSTED,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
+
+    parse_verification_response(&response_raw)
+}
+
+/// Verify_cert_with_named_store verifies cert's trust against a trust store
+/// configured on the policy-server, instead of a chain of certificates
+/// embedded inside of the policy settings. The expiration and validation time
+/// of the certificate are checked too.
+/// Accepts 3 arguments:
+/// * cert: PEM-encoded certificate to verify.
+/// * trust_store: name of the trust store configured on the policy-server
+///   (e.g. `corporate-ca`).
+/// * not_after: string in RFC 3339 time format, to check expiration against.
+///   If None, certificate is assumed never expired.
+pub fn verify_cert_with_named_store(
+    cert: Certificate,
+    trust_store: &str,
+    not_after: Option<String>,
+) -> Result<BoolWithReason> {
+    let req = v2::CertificateVerificationRequest {
+        cert,
+        cert_chain: None,
+        trust_store: Some(trust_store.to_string()),
+        not_after,
+    };
+    let msg = serde_json::to_vec(&req).map_err(|e| {
+        anyhow!(
+            "error serializing the certificate verification request: {}",
+            e
+        )
+    })?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_CRYPTO,
+        ops::CRYPTO_V2_IS_CERTIFICATE_TRUSTED,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
+
+    parse_verification_response(&response_raw)
+}
 
-    let response: CertificateVerificationResponse = serde_json::from_slice(&response_raw)?;
+fn parse_verification_response(response_raw: &[u8]) -> Result<BoolWithReason> {
+    let response: CertificateVerificationResponse = serde_json::from_slice(response_raw)?;
     match response.trusted {
         true => Ok(BoolWithReason::True),
         false => Ok(BoolWithReason::False(format!(
@@ -88,3 +338,72 @@ pub fn verify_cert(
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::automock;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    fn certificate() -> Certificate {
+        Certificate {
+            encoding: CertificateEncoding::Pem,
+            data: "hello world".as_bytes().to_owned(),
+        }
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn verify_cert_with_named_store_trusted() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                ns == "crypto"
+                    && op == "v2/is_certificate_trusted"
+                    && req["trust_store"] == "corporate-ca"
+            })
+            .returning(|_, _, _, _| {
+                Ok(
+                    serde_json::to_vec(&CertificateVerificationResponse::from(
+                        BoolWithReason::True,
+                    ))
+                    .unwrap(),
+                )
+            });
+
+        let res = verify_cert_with_named_store(certificate(), "corporate-ca", None);
+        assert!(matches!(res.unwrap(), BoolWithReason::True));
+    }
+
+    #[serial]
+    #[test]
+    fn verify_cert_with_named_store_not_trusted() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(1).returning(|_, _, _, _| {
+            Ok(serde_json::to_vec(&CertificateVerificationResponse::from(
+                BoolWithReason::False("unknown trust store".to_string()),
+            ))
+            .unwrap())
+        });
+
+        let res = verify_cert_with_named_store(certificate(), "missing", None);
+        match res.unwrap() {
+            BoolWithReason::False(reason) => assert!(reason.contains("unknown trust store")),
+            BoolWithReason::True => panic!("certificate should not be trusted"),
+        }
+    }
+}
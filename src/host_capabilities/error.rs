@@ -0,0 +1,228 @@
+//! Turn host capability failures into policy responses.
+//!
+//! Host calls can fail for many reasons: the registry cannot be reached, the
+//! Kubernetes API server is down,... The [`HostResultExt`] trait and the
+//! [`or_reject!`](crate::or_reject) macro convert these failures into a
+//! consistent response: a rejection, or an acceptance with a warning when
+//! the policy is configured to fail open. In both cases the original error is
+//! attached to the response as an audit annotation, and logged.
+//!
+//! Policies can expose a [`FailureMode`] inside of their settings, and use
+//! [`handle_host_failure`] to translate outages in a uniform way.
+use serde::{Deserialize, Serialize};
+use slog::{o, warn, Logger};
+use std::collections::HashMap;
+
+use crate::logging::KubewardenDrain;
+use crate::response::ValidationResponse;
+
+/// Key of the audit annotation holding the original host error
+pub const HOST_ERROR_ANNOTATION: &str = "host-error";
+/// Key of the audit annotation holding the [`FailureMode`] that has been applied
+pub const FAILURE_MODE_ANNOTATION: &str = "host-failure-mode";
+/// Message used by [`handle_host_failure`]
+pub const HOST_FAILURE_MESSAGE: &str =
+    "the request cannot be evaluated because of a host capability failure";
+
+/// How to behave when a host capability fails. It can be used inside of the
+/// policy settings, where it is spelled `open` or `closed`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureMode {
+    /// Accept the request with a warning, the policy is not enforced
+    Open,
+    /// Reject the request, the policy is enforced
+    #[default]
+    Closed,
+}
+
+/// Extension trait for the results of the host capabilities
+pub trait HostResultExt<T> {
+    /// Return the value, or a rejection response built with `message` when
+    /// the host call failed
+    fn or_reject(self, message: &str) -> Result<T, wapc_guest::CallResult>;
+
+    /// Return the value, or a response built according to the
+    /// `failure_mode` when the host call failed
+    fn or_respond(
+        self,
+        message: &str,
+        failure_mode: FailureMode,
+    ) -> Result<T, wapc_guest::CallResult>;
+}
+
+impl<T> HostResultExt<T> for anyhow::Result<T> {
+    fn or_reject(self, message: &str) -> Result<T, wapc_guest::CallResult> {
+        self.or_respond(message, FailureMode::Closed)
+    }
+
+    fn or_respond(
+        self,
+        message: &str,
+        failure_mode: FailureMode,
+    ) -> Result<T, wapc_guest::CallResult> {
+        self.map_err(|error| failure_response(failure_mode, message, &error))
+    }
+}
+
+/// Translate a host capability failure into a response, according to the
+/// `failure_mode`. The request is rejected with a standard message, or
+/// accepted with a warning. The error is logged and attached to the response
+/// as an audit annotation.
+pub fn handle_host_failure(
+    failure_mode: FailureMode,
+    error: &anyhow::Error,
+) -> wapc_guest::CallResult {
+    failure_response(failure_mode, HOST_FAILURE_MESSAGE, error)
+}
+
+fn failure_response(
+    failure_mode: FailureMode,
+    message: &str,
+    error: &anyhow::Error,
+) -> wapc_guest::CallResult {
+    let mode = match failure_mode {
+        FailureMode::Open => "open",
+        FailureMode::Closed => "closed",
+    };
+    let logger = Logger::root(KubewardenDrain::new(), o!());
+    warn!(logger, "host capability failure";
+        "message" => message,
+        "error" => error.to_string(),
+        "failure_mode" => mode);
+
+    let accepted = failure_mode == FailureMode::Open;
+    Ok(ValidationResponse {
+        accepted,
+        message: (!accepted).then(|| message.to_string()),
+        code: None,
+        mutated_object: None,
+        audit_annotations: Some(HashMap::from([
+            (
+                HOST_ERROR_ANNOTATION.to_string(),
+                format!("{}: {}", message, error),
+            ),
+            (FAILURE_MODE_ANNOTATION.to_string(), mode.to_string()),
+        ])),
+        warnings: accepted.then(|| {
+            vec![format!(
+                "{}, the request has been accepted because the policy fails open",
+                message
+            )]
+        }),
+    }
+    .to_vec()?)
+}
+
+/// Evaluate a host capability call, returning early from the `validate`
+/// function with a rejection response when it fails. An optional
+/// [`FailureMode`] can be given as third argument.
+///
+/// ```rust
+/// use kubewarden_policy_sdk::or_reject;
+/// use kubewarden_policy_sdk::host_capabilities::net::lookup_host;
+///
+/// fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+///     let addresses = or_reject!(lookup_host("example.com"), "cannot resolve example.com");
+///     // use the addresses...
+///     kubewarden_policy_sdk::accept_request()
+/// }
+/// ```
+#[macro_export]
+macro_rules! or_reject {
+    ($expr:expr, $message:expr) => {
+        match $crate::host_capabilities::error::HostResultExt::or_reject($expr, $message) {
+            Ok(value) => value,
+            Err(response) => return response,
+        }
+    };
+    ($expr:expr, $message:expr, $failure_mode:expr) => {
+        match $crate::host_capabilities::error::HostResultExt::or_respond(
+            $expr,
+            $message,
+            $failure_mode,
+        ) {
+            Ok(value) => value,
+            Err(response) => return response,
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn lookup(fail: bool) -> anyhow::Result<u32> {
+        if fail {
+            Err(anyhow!("connection refused"))
+        } else {
+            Ok(42)
+        }
+    }
+
+    fn validate(fail: bool, failure_mode: FailureMode) -> wapc_guest::CallResult {
+        let value = crate::or_reject!(lookup(fail), "lookup failed", failure_mode);
+        assert_eq!(value, 42);
+        crate::reject_request(Some("evaluated".to_string()), None, None, None)
+    }
+
+    fn response(raw: wapc_guest::CallResult) -> ValidationResponse {
+        serde_json::from_slice(&raw.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn success_returns_value() {
+        assert_eq!(lookup(false).or_reject("lookup failed").unwrap(), 42);
+        let response = response(validate(false, FailureMode::Closed));
+        assert_eq!(response.message.as_deref(), Some("evaluated"));
+    }
+
+    #[test]
+    fn failure_rejects() {
+        let response = response(validate(true, FailureMode::Closed));
+        assert!(!response.accepted);
+        assert_eq!(response.message.as_deref(), Some("lookup failed"));
+        assert_eq!(
+            response.audit_annotations.unwrap()[HOST_ERROR_ANNOTATION],
+            "lookup failed: connection refused"
+        );
+    }
+
+    #[test]
+    fn failure_accepts_when_fail_open() {
+        let response = response(validate(true, FailureMode::Open));
+        assert!(response.accepted);
+        assert!(response.message.is_none());
+        assert_eq!(
+            response.warnings.unwrap(),
+            vec!["lookup failed, the request has been accepted because the policy fails open"]
+        );
+        let annotations = response.audit_annotations.unwrap();
+        assert!(annotations.contains_key(HOST_ERROR_ANNOTATION));
+        assert_eq!(annotations[FAILURE_MODE_ANNOTATION], "open");
+    }
+
+    #[test]
+    fn handle_host_failure_with_mode_from_settings() {
+        #[derive(Deserialize)]
+        struct Settings {
+            failure_mode: FailureMode,
+        }
+
+        let settings: Settings = serde_json::from_str(r#"{"failure_mode": "open"}"#).unwrap();
+        let error = anyhow!("registry unreachable");
+
+        let accepted = response(handle_host_failure(settings.failure_mode, &error));
+        assert!(accepted.accepted);
+        assert_eq!(accepted.warnings.unwrap().len(), 1);
+
+        let rejected = response(handle_host_failure(FailureMode::default(), &error));
+        assert!(!rejected.accepted);
+        assert_eq!(rejected.message.as_deref(), Some(HOST_FAILURE_MESSAGE));
+        assert_eq!(
+            rejected.audit_annotations.unwrap()[HOST_ERROR_ANNOTATION],
+            format!("{}: registry unreachable", HOST_FAILURE_MESSAGE)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
+use anyhow::{anyhow, Result};
+#[cfg(test)]
+use tests::mock_wapc as host;
+
+/// Payloads of the `v1/emit` operation
+pub mod v1 {
+    use crate::request::KubernetesAdmissionRequest;
+    use serde::{Deserialize, Serialize};
+
+    /// The type of a Kubernetes Event
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum EventType {
+        /// An informational event
+        #[default]
+        Normal,
+        /// An event describing a potential problem
+        Warning,
+    }
+
+    /// The object an Event is about
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+    pub struct RegardingObject {
+        /// apiVersion of the object (v1 for core group, groupName/groupVersions for other)
+        pub api_version: String,
+        /// Singular PascalCase name of the resource
+        pub kind: String,
+        /// The name of the object
+        pub name: String,
+        /// Optional - the namespace of the object. Cluster level objects must
+        /// set this parameter to `None`
+        pub namespace: Option<String>,
+    }
+
+    impl From<&KubernetesAdmissionRequest> for RegardingObject {
+        /// The object being evaluated by the admission request
+        fn from(request: &KubernetesAdmissionRequest) -> Self {
+            RegardingObject {
+                api_version: request.kind.api_version(),
+                kind: request.kind.kind.clone(),
+                name: request.name.clone(),
+                namespace: (!request.namespace.is_empty()).then(|| request.namespace.clone()),
+            }
+        }
+    }
+
+    /// Describe the set of parameters used by the `emit` functions
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct EmitEventRequest {
+        /// The type of the Event
+        pub event_type: EventType,
+        /// Short, UpperCamelCase, reason of the Event (e.g. `LatestTagUsed`)
+        pub reason: String,
+        /// Human readable description of the Event
+        pub message: String,
+        /// The object the Event is about
+        pub regarding: RegardingObject,
+    }
+}
+
+pub use v1::{EmitEventRequest, EventType, RegardingObject};
+
+/// Maximum length of the message of a Kubernetes Event. Longer messages are
+/// truncated before being sent to the host
+pub const MAX_MESSAGE_LENGTH: usize = 1024;
+
+/// Create a `Normal` Kubernetes Event about the `regarding` object. The
+/// Event is attributed to the policy by the host.
+///
+/// Events are visible through `kubectl describe` and `kubectl events`,
+/// which allows policies to surface advisory findings even when accepting
+/// the request.
+/// # Arguments
+/// * `reason` - short, UpperCamelCase, reason of the Event (e.g. `LatestTagUsed`)
+/// * `message` - human readable description of the Event
+/// * `regarding` - the object the Event is about
+pub fn emit(reason: &str, message: &str, regarding: &RegardingObject) -> Result<()> {
+    emit_event(EventType::Normal, reason, message, regarding)
+}
+
+/// Create a `Warning` Kubernetes Event about the `regarding` object,
+/// see [`emit`]
+pub fn emit_warning(reason: &str, message: &str, regarding: &RegardingObject) -> Result<()> {
+    emit_event(EventType::Warning, reason, message, regarding)
+}
+
+fn emit_event(
+    event_type: EventType,
+    reason: &str,
+    message: &str,
+    regarding: &RegardingObject,
+) -> Result<()> {
+    if reason.is_empty() || reason.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        return Err(anyhow!(
+            "invalid event reason '{}': must be a non-empty UpperCamelCase string",
+            reason
+        ));
+    }
+
+    let message = match message.char_indices().nth(MAX_MESSAGE_LENGTH) {
+        Some((end, _)) => &message[..end],
+        None => message,
+    };
+    let req = EmitEventRequest {
+        event_type,
+        reason: reason.to_string(),
+        message: message.to_string(),
+        regarding: regarding.clone(),
+    };
+    let msg = serde_json::to_vec(&req)
+        .map_err(|e| anyhow!("error serializing the emit event request: {}", e))?;
+    host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_EVENTS,
+        ops::EVENTS_V1_EMIT,
+        &msg,
+    )
+    .map_err(|e| anyhow!("error invoking wapc events.emit: {:?}", e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{GroupVersionKind, KubernetesAdmissionRequest};
+    use mockall::automock;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    fn regarding() -> RegardingObject {
+        RegardingObject::from(&KubernetesAdmissionRequest {
+            kind: GroupVersionKind::new("apps", "v1", "Deployment"),
+            name: "web".to_string(),
+            namespace: "team-a".to_string(),
+            ..Default::default()
+        })
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn emit_warning_event() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|binding, ns, op, msg| {
+                let req: EmitEventRequest = serde_json::from_slice(msg).unwrap();
+                binding == "kubewarden"
+                    && ns == "events"
+                    && op == "v1/emit"
+                    && req.event_type == EventType::Warning
+                    && req.reason == "LatestTagUsed"
+                    && req.regarding.api_version == "apps/v1"
+                    && req.regarding.namespace.as_deref() == Some("team-a")
+            })
+            .returning(|_, _, _, _| Ok(vec![]));
+
+        emit_warning("LatestTagUsed", "image uses the latest tag", &regarding()).unwrap();
+    }
+
+    #[serial]
+    #[test]
+    fn long_messages_are_truncated() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, _, msg| {
+                let req: EmitEventRequest = serde_json::from_slice(msg).unwrap();
+                req.event_type == EventType::Normal
+                    && req.message.chars().count() == MAX_MESSAGE_LENGTH
+            })
+            .returning(|_, _, _, _| Ok(vec![]));
+
+        emit("Audited", &"é".repeat(2000), &regarding()).unwrap();
+    }
+
+    #[test]
+    fn invalid_reason() {
+        assert!(emit("", "message", &regarding()).is_err());
+        assert!(emit("not valid", "message", &regarding()).is_err());
+    }
+}
@@ -1,24 +1,190 @@
+//! Access the resources of the Kubernetes cluster through the host.
+//!
+//! # Migrating from `cluster_context`
+//!
+//! Older versions of the SDK provided a `cluster_context` module, built on
+//! top of dedicated waPC operations. That module is no longer shipped; all
+//! its calls map to the functions of this module:
+//!
+//! | `cluster_context`                    | `host_capabilities::kubernetes`          |
+//! |--------------------------------------|------------------------------------------|
+//! | `ClusterContext::namespaces()`       | [`list_namespaces`]`(None)`              |
+//! | `ClusterContext::services()`         | [`list_services`]`(None, None)`          |
+//! | `ClusterContext::ingresses()`        | [`list_ingresses`]`(None, None)`         |
+//! | filtering the results by label       | pass a label selector to the functions above, the filtering is done by the host |
+//! | any other kind of resource           | [`list`], [`get_resource`], [`list_metadata_only`] |
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
-
-/// Describe the set of parameters used by the `list_resources_by_namespace`
-/// function.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ListResourcesByNamespaceRequest {
-    /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
-    pub api_version: String,
-    /// Singular PascalCase name of the resource
-    pub kind: String,
-    /// Namespace scoping the search
-    pub namespace: String,
-    /// A selector to restrict the list of returned objects by their labels.
-    /// Defaults to everything if `None`
-    pub label_selector: Option<String>,
-    /// A selector to restrict the list of returned objects by their fields.
-    /// Defaults to everything if `None`
-    pub field_selector: Option<String>,
+use k8s_openapi::api::scheduling::v1::PriorityClass;
+use k8s_openapi::Resource;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+#[cfg(test)]
+use tests::mock_wapc as host;
+
+/// Payloads of the `list_resources_by_namespace`, `list_resources_all`,
+/// `list_resources_metadata` and `get_resource` operations. These operations
+/// predate the versioned operation names, they are the first version of the
+/// `kubernetes` namespace
+pub mod v1 {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ListMeta, ObjectMeta};
+    use serde::{Deserialize, Serialize};
+
+    /// Describe the set of parameters used by the `list_resources_by_namespace`
+    /// function.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListResourcesByNamespaceRequest {
+        /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
+        pub api_version: String,
+        /// Singular PascalCase name of the resource
+        pub kind: String,
+        /// Namespace scoping the search
+        pub namespace: String,
+        /// A selector to restrict the list of returned objects by their labels.
+        /// Defaults to everything if `None`
+        pub label_selector: Option<String>,
+        /// A selector to restrict the list of returned objects by their fields.
+        /// Defaults to everything if `None`
+        pub field_selector: Option<String>,
+        /// Disable caching of results obtained from Kubernetes API Server,
+        /// see [`GetResourceRequest::disable_cache`]
+        #[serde(default)]
+        pub disable_cache: bool,
+        /// Optional - maximum age, in seconds, of the cached results the host
+        /// is allowed to return. Ignored when `disable_cache` is set.
+        /// Defaults to the caching policy of the host if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_age_seconds: Option<u64>,
+    }
+
+    /// Describe the set of parameters used by the `list_all_resources` function.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListAllResourcesRequest {
+        /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
+        pub api_version: String,
+        /// Singular PascalCase name of the resource
+        pub kind: String,
+        /// A selector to restrict the list of returned objects by their labels.
+        /// Defaults to everything if `None`
+        pub label_selector: Option<String>,
+        /// A selector to restrict the list of returned objects by their fields.
+        /// Defaults to everything if `None`
+        pub field_selector: Option<String>,
+        /// Disable caching of results obtained from Kubernetes API Server,
+        /// see [`GetResourceRequest::disable_cache`]
+        #[serde(default)]
+        pub disable_cache: bool,
+        /// Optional - maximum age, in seconds, of the cached results the host
+        /// is allowed to return. Ignored when `disable_cache` is set.
+        /// Defaults to the caching policy of the host if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_age_seconds: Option<u64>,
+    }
+
+    /// The metadata of a Kubernetes resource, without its contents
+    /// (`meta.k8s.io/v1 PartialObjectMetadata`)
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct PartialObjectMetadata {
+        /// apiVersion of the object, usually `meta.k8s.io/v1`
+        #[serde(default)]
+        pub api_version: String,
+        /// Kind of the object, usually `PartialObjectMetadata`
+        #[serde(default)]
+        pub kind: String,
+        /// The metadata of the resource
+        #[serde(default)]
+        pub metadata: ObjectMeta,
+    }
+
+    /// A list of [`PartialObjectMetadata`]
+    /// (`meta.k8s.io/v1 PartialObjectMetadataList`)
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct PartialObjectMetadataList {
+        /// apiVersion of the list, usually `meta.k8s.io/v1`
+        #[serde(default)]
+        pub api_version: String,
+        /// Kind of the list, usually `PartialObjectMetadataList`
+        #[serde(default)]
+        pub kind: String,
+        /// Standard list metadata
+        #[serde(default)]
+        pub metadata: ListMeta,
+        /// The metadata of the listed resources
+        #[serde(default)]
+        pub items: Vec<PartialObjectMetadata>,
+    }
+
+    impl PartialObjectMetadataList {
+        /// apiVersion of the `PartialObjectMetadataList` type
+        pub const API_VERSION: &'static str = "meta.k8s.io/v1";
+        /// Kind of the `PartialObjectMetadataList` type
+        pub const KIND: &'static str = "PartialObjectMetadataList";
+    }
+
+    /// Describe the set of parameters used by the `list_metadata_only` function.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct ListMetadataRequest {
+        /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
+        pub api_version: String,
+        /// Singular PascalCase name of the resource
+        pub kind: String,
+        /// Optional - namespace scoping the search. All the resources of the
+        /// cluster are listed when `None`
+        pub namespace: Option<String>,
+        /// A selector to restrict the list of returned objects by their labels.
+        /// Defaults to everything if `None`
+        pub label_selector: Option<String>,
+        /// A selector to restrict the list of returned objects by their fields.
+        /// Defaults to everything if `None`
+        pub field_selector: Option<String>,
+        /// Disable caching of results obtained from Kubernetes API Server,
+        /// see [`GetResourceRequest::disable_cache`]
+        #[serde(default)]
+        pub disable_cache: bool,
+        /// Optional - maximum age, in seconds, of the cached results the host
+        /// is allowed to return. Ignored when `disable_cache` is set.
+        /// Defaults to the caching policy of the host if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_age_seconds: Option<u64>,
+    }
+
+    /// Describe the set of parameters used by the `get_resource` function.
+    #[derive(Serialize, Deserialize, Debug, Default)]
+    pub struct GetResourceRequest {
+        /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
+        pub api_version: String,
+        /// Singular PascalCase name of the resource
+        pub kind: String,
+        /// The name of the resource
+        pub name: String,
+        /// The namespace used to search namespaced resources. Cluster level resources
+        /// must set this parameter to `None`
+        pub namespace: Option<String>,
+        /// Disable caching of results obtained from Kubernetes API Server
+        /// By default query results are cached for 5 seconds, that might cause
+        /// stale data to be returned.
+        /// However, making too many requests against the Kubernetes API Server
+        /// might cause issues to the cluster
+        #[serde(default)]
+        pub disable_cache: bool,
+        /// Optional - maximum age, in seconds, of the cached result the host
+        /// is allowed to return. Ignored when `disable_cache` is set.
+        /// Defaults to the caching policy of the host if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub max_age_seconds: Option<u64>,
+    }
 }
 
+pub use v1::{
+    GetResourceRequest, ListAllResourcesRequest, ListMetadataRequest,
+    ListResourcesByNamespaceRequest, PartialObjectMetadata, PartialObjectMetadataList,
+};
+
 /// Get all the Kubernetes resources defined inside of the given
 /// namespace
 /// Note: cannot be used for cluster-wide resources
@@ -34,14 +200,15 @@ where
             e
         )
     })?;
-    let response_raw = wapc_guest::host_call(
-        "kubewarden",
-        "kubernetes",
-        "list_resources_by_namespace",
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_KUBERNETES,
+        ops::KUBERNETES_LIST_RESOURCES_BY_NAMESPACE,
         &msg,
     )
     .map_err(|e| anyhow!("{}", e))?;
 
+    let response_raw = crate::compression::decode(&response_raw)?;
     serde_json::from_slice(&response_raw).map_err(|e| {
         anyhow!(
             "error deserializing list resources by namespace response into Kubernetes resource: {:?}",
@@ -50,21 +217,6 @@ where
     })
 }
 
-/// Describe the set of parameters used by the `list_all_resources` function.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ListAllResourcesRequest {
-    /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
-    pub api_version: String,
-    /// Singular PascalCase name of the resource
-    pub kind: String,
-    /// A selector to restrict the list of returned objects by their labels.
-    /// Defaults to everything if `None`
-    pub label_selector: Option<String>,
-    /// A selector to restrict the list of returned objects by their fields.
-    /// Defaults to everything if `None`
-    pub field_selector: Option<String>,
-}
-
 /// Get all the Kubernetes resources defined inside of the cluster.
 /// Note: this has be used for cluster-wide resources
 pub fn list_all_resources<T>(req: &ListAllResourcesRequest) -> Result<k8s_openapi::List<T>>
@@ -73,10 +225,15 @@ where
 {
     let msg = serde_json::to_vec(req)
         .map_err(|e| anyhow!("error serializing the list all resources request: {}", e))?;
-    let response_raw =
-        wapc_guest::host_call("kubewarden", "kubernetes", "list_resources_all", &msg)
-            .map_err(|e| anyhow!("{}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_KUBERNETES,
+        ops::KUBERNETES_LIST_RESOURCES_ALL,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
 
+    let response_raw = crate::compression::decode(&response_raw)?;
     serde_json::from_slice(&response_raw).map_err(|e| {
         anyhow!(
             "error deserializing list all resources response into Kubernetes resource: {:?}",
@@ -85,24 +242,81 @@ where
     })
 }
 
-/// Describe the set of parameters used by the `get_resource` function.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GetResourceRequest {
-    /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
-    pub api_version: String,
-    /// Singular PascalCase name of the resource
-    pub kind: String,
-    /// The name of the resource
-    pub name: String,
-    /// The namespace used to search namespaced resources. Cluster level resources
-    /// must set this parameter to `None`
-    pub namespace: Option<String>,
-    /// Disable caching of results obtained from Kubernetes API Server
-    /// By default query results are cached for 5 seconds, that might cause
-    /// stale data to be returned.
-    /// However, making too many requests against the Kubernetes API Server
-    /// might cause issues to the cluster
-    pub disable_cache: bool,
+/// List only the metadata (names, labels, annotations,...) of Kubernetes
+/// resources. The payload exchanged with the host is way smaller than the
+/// one of [`list_resources_by_namespace`] and [`list_all_resources`], which
+/// makes this function the best choice for policies that do not need the
+/// contents of the resources.
+pub fn list_metadata_only(req: &ListMetadataRequest) -> Result<PartialObjectMetadataList> {
+    let msg = serde_json::to_vec(req)
+        .map_err(|e| anyhow!("error serializing the list metadata request: {}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_KUBERNETES,
+        ops::KUBERNETES_LIST_RESOURCES_METADATA,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
+
+    let response_raw = crate::compression::decode(&response_raw)?;
+    serde_json::from_slice(&response_raw).map_err(|e| {
+        anyhow!(
+            "error deserializing list metadata response into PartialObjectMetadataList: {:?}",
+            e
+        )
+    })
+}
+
+/// Get the resources of type `T` matching the optional `label_selector`.
+/// The resources defined inside of `namespace` are returned when it is set,
+/// all the resources of the cluster otherwise.
+///
+/// The label selector (e.g. `app=web,tier!=frontend`) is evaluated by the
+/// host, only the matching resources are sent to the policy.
+pub fn list<T>(namespace: Option<&str>, label_selector: Option<&str>) -> Result<Vec<T>>
+where
+    T: k8s_openapi::ListableResource + serde::de::DeserializeOwned + Clone,
+{
+    let label_selector = label_selector.map(str::to_string);
+    let list = match namespace {
+        Some(namespace) => list_resources_by_namespace::<T>(&ListResourcesByNamespaceRequest {
+            api_version: T::API_VERSION.to_string(),
+            kind: T::KIND.to_string(),
+            namespace: namespace.to_string(),
+            label_selector,
+            ..Default::default()
+        })?,
+        None => list_all_resources::<T>(&ListAllResourcesRequest {
+            api_version: T::API_VERSION.to_string(),
+            kind: T::KIND.to_string(),
+            label_selector,
+            ..Default::default()
+        })?,
+    };
+    Ok(list.items)
+}
+
+/// Get the Namespaces matching the optional `label_selector`, see [`list`]
+pub fn list_namespaces(
+    label_selector: Option<&str>,
+) -> Result<Vec<k8s_openapi::api::core::v1::Namespace>> {
+    list(None, label_selector)
+}
+
+/// Get the Services matching the optional `label_selector`, see [`list`]
+pub fn list_services(
+    namespace: Option<&str>,
+    label_selector: Option<&str>,
+) -> Result<Vec<k8s_openapi::api::core::v1::Service>> {
+    list(namespace, label_selector)
+}
+
+/// Get the Ingresses matching the optional `label_selector`, see [`list`]
+pub fn list_ingresses(
+    namespace: Option<&str>,
+    label_selector: Option<&str>,
+) -> Result<Vec<k8s_openapi::api::networking::v1::Ingress>> {
+    list(namespace, label_selector)
 }
 
 /// Get a specific Kubernetes resource.
@@ -112,8 +326,13 @@ where
 {
     let msg = serde_json::to_vec(req)
         .map_err(|e| anyhow!("error serializing the get resource request: {}", e))?;
-    let response_raw = wapc_guest::host_call("kubewarden", "kubernetes", "get_resource", &msg)
-        .map_err(|e| anyhow!("{}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_KUBERNETES,
+        ops::KUBERNETES_GET_RESOURCE,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
 
     serde_json::from_slice(&response_raw).map_err(|e| {
         anyhow!(
@@ -122,3 +341,1234 @@ where
         )
     })
 }
+
+/// Get all the `ResourceQuota` objects defined inside of the given namespace.
+/// See [`crate::quota::would_exceed`] to evaluate a Pod against them
+pub fn get_resource_quotas(
+    namespace: &str,
+) -> Result<Vec<k8s_openapi::api::core::v1::ResourceQuota>> {
+    let quotas = list_resources_by_namespace::<k8s_openapi::api::core::v1::ResourceQuota>(
+        &ListResourcesByNamespaceRequest {
+            api_version: k8s_openapi::api::core::v1::ResourceQuota::API_VERSION.to_string(),
+            kind: k8s_openapi::api::core::v1::ResourceQuota::KIND.to_string(),
+            namespace: namespace.to_string(),
+            ..Default::default()
+        },
+    )?;
+    Ok(quotas.items)
+}
+
+thread_local! {
+    static PRIORITY_CLASSES: RefCell<HashMap<String, PriorityClass>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Get the `PriorityClass` with the given name.
+///
+/// PriorityClasses rarely change, hence they are cached for the lifetime of
+/// the policy instance. Use [`clear_priority_class_cache`] to drop them.
+/// See [`crate::priority`] to evaluate the priority of a Pod
+pub fn get_priority_class(name: &str) -> Result<PriorityClass> {
+    if let Some(priority_class) = PRIORITY_CLASSES.with(|cache| cache.borrow().get(name).cloned()) {
+        return Ok(priority_class);
+    }
+
+    let priority_class: PriorityClass = get_resource(&GetResourceRequest {
+        api_version: PriorityClass::API_VERSION.to_string(),
+        kind: PriorityClass::KIND.to_string(),
+        name: name.to_string(),
+        namespace: None,
+        disable_cache: false,
+        max_age_seconds: None,
+    })?;
+
+    PRIORITY_CLASSES.with(|cache| {
+        cache
+            .borrow_mut()
+            .insert(name.to_string(), priority_class.clone())
+    });
+    Ok(priority_class)
+}
+
+/// Store `priority_class` inside of the cache of [`get_priority_class`],
+/// under the given name. Used by [`crate::test::mock_priority_class`]
+pub(crate) fn cache_priority_class(name: &str, priority_class: PriorityClass) {
+    PRIORITY_CLASSES.with(|cache| cache.borrow_mut().insert(name.to_string(), priority_class));
+}
+
+/// Drop all the PriorityClasses cached by [`get_priority_class`]
+pub fn clear_priority_class_cache() {
+    PRIORITY_CLASSES.with(|cache| cache.borrow_mut().clear());
+}
+
+/// Submit `object` to the Kubernetes API server as a server-side dry-run
+/// (`dryRun=All`) create or update, and return the object as persisted by
+/// the API server.
+///
+/// Nothing is stored inside of the cluster. Mutating policies can use this
+/// function to ensure their output passes the schema validation and the
+/// other admission webhooks before emitting it. An error is returned when
+/// the API server rejects the object.
+pub fn dry_run_apply<T>(object: &T) -> Result<T>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    let msg = serde_json::to_vec(&serde_json::json!({ "object": object }))
+        .map_err(|e| anyhow!("error serializing the dry-run apply request: {}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_KUBERNETES,
+        ops::KUBERNETES_DRY_RUN_APPLY,
+        &msg,
+    )
+    .map_err(|e| anyhow!("{}", e))?;
+
+    serde_json::from_slice(&response_raw).map_err(|e| {
+        anyhow!(
+            "error deserializing dry-run apply response into Kubernetes resource: {:?}",
+            e
+        )
+    })
+}
+
+/// Returns `true` when the resource of type `T` with the given `name` exists.
+/// Namespaced resources must provide their `namespace`, cluster level
+/// resources must set it to `None`.
+///
+/// The resource is looked up with a field selector on its name, only its
+/// metadata is transferred, see [`list_metadata_only`].
+pub fn exists<T>(name: &str, namespace: Option<&str>) -> Result<bool>
+where
+    T: k8s_openapi::Resource,
+{
+    let field_selector = format!("metadata.name={}", name);
+    Ok(count_items::<T>(namespace, None, Some(field_selector))? > 0)
+}
+
+/// Returns the number of resources of type `T` matching the given label
+/// selector. The resources defined inside of `namespace` are counted when it
+/// is set, all the resources of the cluster otherwise.
+///
+/// Only the metadata of the resources is transferred, see
+/// [`list_metadata_only`].
+pub fn count<T>(namespace: Option<&str>, label_selector: Option<&str>) -> Result<usize>
+where
+    T: k8s_openapi::Resource,
+{
+    count_items::<T>(namespace, label_selector.map(str::to_string), None)
+}
+
+fn count_items<T>(
+    namespace: Option<&str>,
+    label_selector: Option<String>,
+    field_selector: Option<String>,
+) -> Result<usize>
+where
+    T: k8s_openapi::Resource,
+{
+    let list = list_metadata_only(&ListMetadataRequest {
+        api_version: T::API_VERSION.to_string(),
+        kind: T::KIND.to_string(),
+        namespace: namespace.map(str::to_string),
+        label_selector,
+        field_selector,
+        ..Default::default()
+    })?;
+    Ok(list.items.len())
+}
+
+/// Type of the Secrets holding the credentials used to pull images
+pub const DOCKER_CONFIG_JSON_SECRET_TYPE: &str = "kubernetes.io/dockerconfigjson";
+/// Key of the Secret data holding the registry credentials
+pub const DOCKER_CONFIG_JSON_KEY: &str = ".dockerconfigjson";
+
+/// The problem affecting a Secret referenced by `imagePullSecrets`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImagePullSecretProblem {
+    /// The Secret does not exist
+    NotFound,
+    /// The Secret is not of type `kubernetes.io/dockerconfigjson`
+    WrongType,
+    /// The `.dockerconfigjson` of the Secret is missing or invalid
+    Malformed(String),
+}
+
+/// A Secret referenced by `imagePullSecrets` that cannot be used to pull
+/// images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImagePullSecretIssue {
+    /// Name of the Secret
+    pub name: String,
+    /// What is wrong with the Secret
+    pub problem: ImagePullSecretProblem,
+}
+
+impl std::fmt::Display for ImagePullSecretIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem {
+            ImagePullSecretProblem::NotFound => {
+                write!(f, "image pull secret '{}' not found", self.name)
+            }
+            ImagePullSecretProblem::WrongType => write!(
+                f,
+                "image pull secret '{}' is not of type {}",
+                self.name, DOCKER_CONFIG_JSON_SECRET_TYPE
+            ),
+            ImagePullSecretProblem::Malformed(reason) => write!(
+                f,
+                "image pull secret '{}' is malformed: {}",
+                self.name, reason
+            ),
+        }
+    }
+}
+
+/// Ensure all the Secrets referenced by the `imagePullSecrets` of
+/// `pod_spec` exist inside of `namespace` and are of type
+/// `kubernetes.io/dockerconfigjson`.
+///
+/// The Secrets are looked up through [`list_metadata_only`], filtering them
+/// by name and type on the host: their data is never transferred to the
+/// policy. Use [`resolve_image_pull_secrets_with_contents`] to validate the
+/// credentials too.
+///
+/// Returns the Secrets that cannot be used, an empty list when all of them
+/// are fine.
+pub fn resolve_image_pull_secrets(
+    pod_spec: &k8s_openapi::api::core::v1::PodSpec,
+    namespace: &str,
+) -> Result<Vec<ImagePullSecretIssue>> {
+    let mut issues = Vec::new();
+    for name in image_pull_secret_names(pod_spec) {
+        if let Some(problem) = image_pull_secret_problem(name, namespace)? {
+            issues.push(ImagePullSecretIssue {
+                name: name.to_string(),
+                problem,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Like [`resolve_image_pull_secrets`], but the Secrets are also fetched to
+/// ensure their `.dockerconfigjson` is a valid Docker configuration file.
+///
+/// **Warning:** the whole Secrets, including the registry credentials, are
+/// transferred into the memory of the policy. Prefer
+/// [`resolve_image_pull_secrets`] unless the credentials must be validated.
+pub fn resolve_image_pull_secrets_with_contents(
+    pod_spec: &k8s_openapi::api::core::v1::PodSpec,
+    namespace: &str,
+) -> Result<Vec<ImagePullSecretIssue>> {
+    use k8s_openapi::api::core::v1::Secret;
+
+    let mut issues = Vec::new();
+    for name in image_pull_secret_names(pod_spec) {
+        let mut problem = image_pull_secret_problem(name, namespace)?;
+        if problem.is_none() {
+            let secret: Secret = get_resource(&GetResourceRequest {
+                api_version: Secret::API_VERSION.to_string(),
+                kind: Secret::KIND.to_string(),
+                name: name.to_string(),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            })?;
+            problem = docker_config_problem(&secret).map(ImagePullSecretProblem::Malformed);
+        }
+        if let Some(problem) = problem {
+            issues.push(ImagePullSecretIssue {
+                name: name.to_string(),
+                problem,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// The names of the image pull secrets, without duplicates
+fn image_pull_secret_names(pod_spec: &k8s_openapi::api::core::v1::PodSpec) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for secret in pod_spec.image_pull_secrets.iter().flatten() {
+        let name = secret.name.as_str();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn image_pull_secret_problem(
+    name: &str,
+    namespace: &str,
+) -> Result<Option<ImagePullSecretProblem>> {
+    use k8s_openapi::api::core::v1::Secret;
+
+    let by_name = format!("metadata.name={}", name);
+    let by_name_and_type = format!("{},type={}", by_name, DOCKER_CONFIG_JSON_SECRET_TYPE);
+    if count_items::<Secret>(Some(namespace), None, Some(by_name_and_type))? > 0 {
+        return Ok(None);
+    }
+    if count_items::<Secret>(Some(namespace), None, Some(by_name))? > 0 {
+        return Ok(Some(ImagePullSecretProblem::WrongType));
+    }
+    Ok(Some(ImagePullSecretProblem::NotFound))
+}
+
+fn docker_config_problem(secret: &k8s_openapi::api::core::v1::Secret) -> Option<String> {
+    let data = match secret
+        .data
+        .as_ref()
+        .and_then(|data| data.get(DOCKER_CONFIG_JSON_KEY))
+    {
+        Some(data) => data,
+        None => return Some(format!("{} not found", DOCKER_CONFIG_JSON_KEY)),
+    };
+    match serde_json::from_slice::<serde_json::Value>(&data.0) {
+        Ok(config) if config["auths"].is_object() => None,
+        Ok(_) => Some(format!("{} has no auths", DOCKER_CONFIG_JSON_KEY)),
+        Err(e) => Some(format!(
+            "{} is not valid JSON: {}",
+            DOCKER_CONFIG_JSON_KEY, e
+        )),
+    }
+}
+
+/// A problem found by [`check_pdb_coverage`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PdbIssue {
+    /// No PodDisruptionBudget selects the Pods of the workload
+    NotCovered,
+    /// More than one PodDisruptionBudget selects the Pods of the workload,
+    /// the eviction of these Pods is refused by the API server
+    MultipleBudgets(Vec<String>),
+    /// The PodDisruptionBudget does not allow any voluntary disruption with
+    /// the given number of replicas, draining a node would be blocked
+    BlocksDisruptions(String),
+    /// The `minAvailable` or `maxUnavailable` value of the
+    /// PodDisruptionBudget cannot be understood
+    Malformed {
+        /// Name of the PodDisruptionBudget
+        name: String,
+        /// Why the budget is invalid
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for PdbIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PdbIssue::NotCovered => write!(f, "no PodDisruptionBudget covers the workload"),
+            PdbIssue::MultipleBudgets(names) => write!(
+                f,
+                "the workload is covered by multiple PodDisruptionBudgets: {}",
+                names.join(", ")
+            ),
+            PdbIssue::BlocksDisruptions(name) => write!(
+                f,
+                "PodDisruptionBudget '{}' does not allow any disruption",
+                name
+            ),
+            PdbIssue::Malformed { name, reason } => {
+                write!(f, "PodDisruptionBudget '{}' is invalid: {}", name, reason)
+            }
+        }
+    }
+}
+
+/// The outcome of [`check_pdb_coverage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PdbCoverage {
+    /// Names of the PodDisruptionBudgets selecting the Pods of the workload
+    pub budgets: Vec<String>,
+    /// The problems found, empty when the workload is properly covered
+    pub issues: Vec<PdbIssue>,
+}
+
+impl PdbCoverage {
+    /// Returns `true` when at least one PodDisruptionBudget covers the workload
+    pub fn is_covered(&self) -> bool {
+        !self.budgets.is_empty()
+    }
+
+    /// Returns `true` when exactly one PodDisruptionBudget covers the
+    /// workload, and it allows voluntary disruptions
+    pub fn is_sane(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Find the PodDisruptionBudgets of `namespace` covering the Pods selected by
+/// `workload_selector`, and ensure their `minAvailable`/`maxUnavailable`
+/// allow some voluntary disruption when the workload runs `replicas` Pods.
+///
+/// A budget covers the workload when its selector matches the `matchLabels`
+/// of `workload_selector`, which are the labels every Pod of the workload
+/// carries. Percentages are scaled like the disruption controller does,
+/// rounding up.
+pub fn check_pdb_coverage(
+    workload_selector: &k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector,
+    namespace: &str,
+    replicas: i32,
+) -> Result<PdbCoverage> {
+    use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+
+    let pod_labels = workload_selector.match_labels.clone().unwrap_or_default();
+    let mut coverage = PdbCoverage::default();
+    let mut sanity_issues = Vec::new();
+    for pdb in list::<PodDisruptionBudget>(Some(namespace), None)? {
+        let spec = pdb.spec.unwrap_or_default();
+        let covers = spec
+            .selector
+            .as_ref()
+            .is_some_and(|selector| label_selector_matches(selector, &pod_labels));
+        if !covers {
+            continue;
+        }
+        let name = pdb.metadata.name.unwrap_or_default();
+        if let Some(issue) = pdb_budget_issue(
+            &name,
+            spec.min_available.as_ref(),
+            spec.max_unavailable.as_ref(),
+            replicas,
+        ) {
+            sanity_issues.push(issue);
+        }
+        coverage.budgets.push(name);
+    }
+
+    match coverage.budgets.len() {
+        0 => coverage.issues.push(PdbIssue::NotCovered),
+        1 => {}
+        _ => coverage
+            .issues
+            .push(PdbIssue::MultipleBudgets(coverage.budgets.clone())),
+    }
+    coverage.issues.extend(sanity_issues);
+    Ok(coverage)
+}
+
+fn pdb_budget_issue(
+    name: &str,
+    min_available: Option<&k8s_openapi::apimachinery::pkg::util::intstr::IntOrString>,
+    max_unavailable: Option<&k8s_openapi::apimachinery::pkg::util::intstr::IntOrString>,
+    replicas: i32,
+) -> Option<PdbIssue> {
+    let malformed = |reason: String| PdbIssue::Malformed {
+        name: name.to_string(),
+        reason,
+    };
+    let allowed_disruptions = match (min_available, max_unavailable) {
+        (Some(_), Some(_)) => {
+            return Some(malformed(
+                "minAvailable and maxUnavailable cannot be both set".to_string(),
+            ))
+        }
+        (Some(min_available), None) => match scale_int_or_percent(min_available, replicas) {
+            Ok(min_available) => replicas - min_available,
+            Err(e) => return Some(malformed(format!("minAvailable: {}", e))),
+        },
+        (None, Some(max_unavailable)) => match scale_int_or_percent(max_unavailable, replicas) {
+            Ok(max_unavailable) => max_unavailable,
+            Err(e) => return Some(malformed(format!("maxUnavailable: {}", e))),
+        },
+        // the API server defaults to `minAvailable: 1`
+        (None, None) => replicas - 1,
+    };
+    (allowed_disruptions <= 0).then(|| PdbIssue::BlocksDisruptions(name.to_string()))
+}
+
+fn scale_int_or_percent(
+    value: &k8s_openapi::apimachinery::pkg::util::intstr::IntOrString,
+    total: i32,
+) -> Result<i32> {
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+    match value {
+        IntOrString::Int(value) => Ok(*value),
+        IntOrString::String(value) => {
+            let percent: i64 = value
+                .strip_suffix('%')
+                .and_then(|percent| percent.parse().ok())
+                .ok_or_else(|| anyhow!("'{}' is not a number nor a percentage", value))?;
+            Ok(((percent * i64::from(total) + 99) / 100) as i32)
+        }
+    }
+}
+
+/// Evaluate a Kubernetes `LabelSelector` against the given labels, with the
+/// semantics of [`LabelOperator`](crate::settings::common::LabelOperator).
+/// The empty selector matches everything, unknown operators match nothing
+fn label_selector_matches(
+    selector: &k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector,
+    labels: &std::collections::BTreeMap<String, String>,
+) -> bool {
+    let match_labels = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+    let match_expressions = selector.match_expressions.iter().flatten().all(|req| {
+        req.operator
+            .parse::<crate::settings::common::LabelOperator>()
+            .is_ok_and(|operator| {
+                operator.matches(
+                    labels.get(&req.key).map(String::as_str),
+                    req.values.as_deref().unwrap_or_default(),
+                )
+            })
+    });
+    match_labels && match_expressions
+}
+
+/// The kind of traffic a NetworkPolicy applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyType {
+    /// Incoming traffic
+    Ingress,
+    /// Outgoing traffic
+    Egress,
+}
+
+/// Returns `true` when `namespace` has a NetworkPolicy denying all the
+/// incoming traffic by default, see [`namespace_has_default_deny_for`]
+pub fn namespace_has_default_deny(namespace: &str) -> Result<bool> {
+    namespace_has_default_deny_for(namespace, PolicyType::Ingress)
+}
+
+/// Returns `true` when `namespace` has a NetworkPolicy selecting all its Pods
+/// and allowing no traffic of the given type.
+///
+/// Like the API server does, a NetworkPolicy without `policyTypes` applies to
+/// the incoming traffic, and to the outgoing one only when it has egress rules.
+pub fn namespace_has_default_deny_for(namespace: &str, policy_type: PolicyType) -> Result<bool> {
+    use k8s_openapi::api::networking::v1::NetworkPolicy;
+
+    Ok(list::<NetworkPolicy>(Some(namespace), None)?
+        .into_iter()
+        .filter_map(|policy| policy.spec)
+        .any(|spec| {
+            let selects_all = spec
+                .pod_selector
+                .match_labels
+                .as_ref()
+                .is_none_or(|l| l.is_empty())
+                && spec
+                    .pod_selector
+                    .match_expressions
+                    .as_ref()
+                    .is_none_or(|e| e.is_empty());
+            let (applies, no_rules) = match policy_type {
+                PolicyType::Ingress => (
+                    spec.policy_types
+                        .as_ref()
+                        .is_none_or(|types| types.iter().any(|t| t == "Ingress")),
+                    spec.ingress.as_ref().is_none_or(|rules| rules.is_empty()),
+                ),
+                PolicyType::Egress => (
+                    spec.policy_types
+                        .as_ref()
+                        .is_some_and(|types| types.iter().any(|t| t == "Egress")),
+                    spec.egress.as_ref().is_none_or(|rules| rules.is_empty()),
+                ),
+            };
+            selects_all && applies && no_rules
+        }))
+}
+
+/// Get the names of the NetworkPolicies of `namespace` whose `podSelector`
+/// selects a Pod with the given labels. An empty list means the Pod is not
+/// isolated by any NetworkPolicy
+pub fn selector_covered_by_networkpolicies(
+    pod_labels: &std::collections::BTreeMap<String, String>,
+    namespace: &str,
+) -> Result<Vec<String>> {
+    use k8s_openapi::api::networking::v1::NetworkPolicy;
+
+    Ok(list::<NetworkPolicy>(Some(namespace), None)?
+        .into_iter()
+        .filter(|policy| {
+            policy
+                .spec
+                .as_ref()
+                .is_some_and(|spec| label_selector_matches(&spec.pod_selector, pod_labels))
+        })
+        .map(|policy| policy.metadata.name.unwrap_or_default())
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::Namespace;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use mockall::automock;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn get_resource_sends_request() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|binding, ns, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                binding == "kubewarden"
+                    && ns == "kubernetes"
+                    && op == "get_resource"
+                    && req["name"] == "default"
+                    && req["kind"] == "Namespace"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "Namespace",
+                    "metadata": { "name": "default" }
+                }))
+                .unwrap())
+            });
+
+        let namespace: Namespace = get_resource(&GetResourceRequest {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            name: "default".to_string(),
+            namespace: None,
+            disable_cache: false,
+            max_age_seconds: None,
+        })
+        .unwrap();
+        assert_eq!(namespace.metadata.name.as_deref(), Some("default"));
+    }
+
+    #[serial]
+    #[test]
+    fn get_resource_host_error() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err("not found".into()));
+
+        let res: Result<Namespace> = get_resource(&GetResourceRequest {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            name: "missing".to_string(),
+            namespace: None,
+            disable_cache: false,
+            max_age_seconds: None,
+        });
+        assert!(res.is_err());
+    }
+
+    #[serial]
+    #[test]
+    fn list_resources_sends_cache_hints() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                ns == "kubernetes"
+                    && op == "list_resources_by_namespace"
+                    && req["disable_cache"] == false
+                    && req["max_age_seconds"] == 60
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "PodList",
+                    "metadata": {},
+                    "items": []
+                }))
+                .unwrap())
+            });
+
+        let pods: k8s_openapi::List<k8s_openapi::api::core::v1::Pod> =
+            list_resources_by_namespace(&ListResourcesByNamespaceRequest {
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                namespace: "default".to_string(),
+                max_age_seconds: Some(60),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(pods.items.is_empty());
+    }
+
+    #[test]
+    fn cache_hints_are_optional() {
+        let req: ListAllResourcesRequest = serde_json::from_value(serde_json::json!({
+            "api_version": "v1",
+            "kind": "Namespace",
+            "label_selector": null,
+            "field_selector": null
+        }))
+        .unwrap();
+        assert!(!req.disable_cache);
+        assert_eq!(req.max_age_seconds, None);
+
+        let serialized = serde_json::to_value(&req).unwrap();
+        assert!(serialized.get("max_age_seconds").is_none());
+    }
+
+    #[serial]
+    #[test]
+    fn resource_exists() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(2)
+            .withf(|_, _, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                op == "list_resources_metadata"
+                    && req["kind"] == "Namespace"
+                    && req["namespace"].is_null()
+            })
+            .returning(|_, _, _, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                let items = if req["field_selector"] == "metadata.name=default" {
+                    serde_json::json!([{"metadata": {"name": "default"}}])
+                } else {
+                    serde_json::json!([])
+                };
+                Ok(serde_json::to_vec(&serde_json::json!({"items": items})).unwrap())
+            });
+
+        assert!(exists::<Namespace>("default", None).unwrap());
+        assert!(!exists::<Namespace>("missing", None).unwrap());
+    }
+
+    #[serial]
+    #[test]
+    fn count_resources() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                op == "list_resources_metadata"
+                    && req["namespace"] == "team-a"
+                    && req["label_selector"] == "app=web"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "meta.k8s.io/v1",
+                    "kind": "PartialObjectMetadataList",
+                    "items": [{"metadata": {"name": "web-1"}}, {"metadata": {"name": "web-2"}}]
+                }))
+                .unwrap())
+            });
+
+        let pods = count::<k8s_openapi::api::core::v1::Pod>(Some("team-a"), Some("app=web"));
+        assert_eq!(pods.unwrap(), 2);
+    }
+
+    #[serial]
+    #[test]
+    fn list_resources_metadata() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                ns == "kubernetes"
+                    && op == "list_resources_metadata"
+                    && req["kind"] == "Deployment"
+                    && req["namespace"] == "team-a"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "meta.k8s.io/v1",
+                    "kind": "PartialObjectMetadataList",
+                    "metadata": {"resourceVersion": "42"},
+                    "items": [{
+                        "apiVersion": "meta.k8s.io/v1",
+                        "kind": "PartialObjectMetadata",
+                        "metadata": {"name": "web", "labels": {"app": "web"}}
+                    }]
+                }))
+                .unwrap())
+            });
+
+        let list = list_metadata_only(&ListMetadataRequest {
+            api_version: "apps/v1".to_string(),
+            kind: "Deployment".to_string(),
+            namespace: Some("team-a".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(list.kind, PartialObjectMetadataList::KIND);
+        assert_eq!(list.metadata.resource_version.as_deref(), Some("42"));
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].metadata.name.as_deref(), Some("web"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[serial]
+    #[test]
+    fn list_resources_compressed_response() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(1).returning(|_, _, _, _| {
+            let list = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "NamespaceList",
+                "metadata": {},
+                "items": [{"metadata": {"name": "team-a"}}]
+            });
+            Ok(crate::compression::tests::zstd(
+                &serde_json::to_vec(&list).unwrap(),
+            ))
+        });
+
+        let namespaces = list_all_resources::<Namespace>(&ListAllResourcesRequest {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(namespaces.items.len(), 1);
+        assert_eq!(namespaces.items[0].metadata.name.as_deref(), Some("team-a"));
+    }
+
+    #[serial]
+    #[test]
+    fn dry_run_apply_returns_server_object() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                ns == "kubernetes"
+                    && op == "dry_run_apply"
+                    && req["object"]["metadata"]["name"] == "team-a"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "Namespace",
+                    "metadata": {
+                        "name": "team-a",
+                        "labels": {"kubernetes.io/metadata.name": "team-a"}
+                    }
+                }))
+                .unwrap())
+            });
+
+        let namespace = Namespace {
+            metadata: ObjectMeta {
+                name: Some("team-a".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let persisted = dry_run_apply(&namespace).unwrap();
+        assert_eq!(
+            persisted.metadata.labels.unwrap()["kubernetes.io/metadata.name"],
+            "team-a"
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn dry_run_apply_rejected() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err("admission webhook denied the request".into()));
+
+        let res = dry_run_apply(&Namespace::default());
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("admission webhook denied the request"));
+    }
+
+    #[serial]
+    #[test]
+    fn priority_classes_are_cached() {
+        clear_priority_class_cache();
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                op == "get_resource"
+                    && req["api_version"] == "scheduling.k8s.io/v1"
+                    && req["kind"] == "PriorityClass"
+                    && req["name"] == "high"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "scheduling.k8s.io/v1",
+                    "kind": "PriorityClass",
+                    "metadata": {"name": "high"},
+                    "value": 1000000
+                }))
+                .unwrap())
+            });
+
+        assert_eq!(get_priority_class("high").unwrap().value, 1_000_000);
+        assert_eq!(get_priority_class("high").unwrap().value, 1_000_000);
+        clear_priority_class_cache();
+    }
+
+    #[serial]
+    #[test]
+    fn list_with_label_selector() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                op == "list_resources_all"
+                    && req["api_version"] == "v1"
+                    && req["kind"] == "Namespace"
+                    && req["label_selector"] == "team=a"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "NamespaceList",
+                    "metadata": {},
+                    "items": [{"metadata": {"name": "team-a", "labels": {"team": "a"}}}]
+                }))
+                .unwrap())
+            });
+
+        let namespaces = list_namespaces(Some("team=a")).unwrap();
+        assert_eq!(namespaces.len(), 1);
+        assert_eq!(namespaces[0].metadata.name.as_deref(), Some("team-a"));
+    }
+
+    #[serial]
+    #[test]
+    fn list_namespaced_resources() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, op, msg| {
+                let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+                op == "list_resources_by_namespace"
+                    && req["api_version"] == "networking.k8s.io/v1"
+                    && req["kind"] == "Ingress"
+                    && req["namespace"] == "team-a"
+                    && req["label_selector"].is_null()
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "networking.k8s.io/v1",
+                    "kind": "IngressList",
+                    "metadata": {},
+                    "items": []
+                }))
+                .unwrap())
+            });
+
+        assert!(list_ingresses(Some("team-a"), None).unwrap().is_empty());
+    }
+
+    fn pod_spec_with_pull_secrets(names: &[&str]) -> k8s_openapi::api::core::v1::PodSpec {
+        use k8s_openapi::api::core::v1::{LocalObjectReference, PodSpec};
+
+        PodSpec {
+            image_pull_secrets: Some(
+                names
+                    .iter()
+                    .map(|name| LocalObjectReference {
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// `registry` is a valid pull secret, `opaque` has the wrong type,
+    /// `broken` has a malformed configuration
+    fn expect_secrets() -> mock_wapc::__host_call::Context {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().returning(|_, _, op, msg| {
+            let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            assert_eq!(req["namespace"], "team-a");
+            if op == "get_resource" {
+                let config = if req["name"] == "broken" {
+                    "{}"
+                } else {
+                    r#"{"auths": {}}"#
+                };
+                return Ok(serde_json::to_vec(&serde_json::json!({
+                    "apiVersion": "v1",
+                    "kind": "Secret",
+                    "metadata": {"name": req["name"]},
+                    "type": DOCKER_CONFIG_JSON_SECRET_TYPE,
+                    "data": {".dockerconfigjson": base64_encode(config)}
+                }))
+                .unwrap());
+            }
+            // the existence and the type are checked without fetching the data
+            assert_eq!(op, ops::KUBERNETES_LIST_RESOURCES_METADATA);
+            let selector = req["field_selector"].as_str().unwrap();
+            let found = match selector {
+                "metadata.name=registry,type=kubernetes.io/dockerconfigjson"
+                | "metadata.name=broken,type=kubernetes.io/dockerconfigjson"
+                | "metadata.name=opaque" => 1,
+                _ => 0,
+            };
+            Ok(serde_json::to_vec(&serde_json::json!({
+                "items": vec![serde_json::json!({}); found]
+            }))
+            .unwrap())
+        });
+        ctx
+    }
+
+    fn base64_encode(value: &str) -> String {
+        serde_json::to_value(k8s_openapi::ByteString(value.as_bytes().to_vec()))
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[serial]
+    #[test]
+    fn resolve_pull_secrets_metadata_only() {
+        let _ctx = expect_secrets();
+        let pod_spec = pod_spec_with_pull_secrets(&["registry", "opaque", "missing", "registry"]);
+
+        let issues = resolve_image_pull_secrets(&pod_spec, "team-a").unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                ImagePullSecretIssue {
+                    name: "opaque".to_string(),
+                    problem: ImagePullSecretProblem::WrongType,
+                },
+                ImagePullSecretIssue {
+                    name: "missing".to_string(),
+                    problem: ImagePullSecretProblem::NotFound,
+                },
+            ]
+        );
+        assert_eq!(
+            issues[1].to_string(),
+            "image pull secret 'missing' not found"
+        );
+        assert!(resolve_image_pull_secrets(&Default::default(), "team-a")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[serial]
+    #[test]
+    fn resolve_pull_secrets_with_contents() {
+        let _ctx = expect_secrets();
+        let pod_spec = pod_spec_with_pull_secrets(&["registry", "broken"]);
+
+        let issues = resolve_image_pull_secrets_with_contents(&pod_spec, "team-a").unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].name, "broken");
+        assert_eq!(
+            issues[0].problem,
+            ImagePullSecretProblem::Malformed(".dockerconfigjson has no auths".to_string())
+        );
+    }
+
+    fn expect_pdbs(pdbs: serde_json::Value) -> mock_wapc::__host_call::Context {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().returning(move |_, _, op, msg| {
+            assert_eq!(op, ops::KUBERNETES_LIST_RESOURCES_BY_NAMESPACE);
+            let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            assert_eq!(req["kind"], "PodDisruptionBudget");
+            assert_eq!(req["namespace"], "team-a");
+            Ok(serde_json::to_vec(&serde_json::json!({
+                "apiVersion": "policy/v1",
+                "kind": "PodDisruptionBudgetList",
+                "metadata": {},
+                "items": pdbs.clone(),
+            }))
+            .unwrap())
+        });
+        ctx
+    }
+
+    fn pdb(name: &str, spec: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "policy/v1",
+            "kind": "PodDisruptionBudget",
+            "metadata": {"name": name, "namespace": "team-a"},
+            "spec": spec,
+        })
+    }
+
+    fn web_selector() -> k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector {
+        serde_json::from_value(serde_json::json!({
+            "matchLabels": {"app": "web", "tier": "frontend"}
+        }))
+        .unwrap()
+    }
+
+    #[serial]
+    #[test]
+    fn pdb_coverage_sane() {
+        let _ctx = expect_pdbs(serde_json::json!([
+            pdb(
+                "web",
+                serde_json::json!({
+                    "selector": {
+                        "matchLabels": {"app": "web"},
+                        "matchExpressions": [{"key": "tier", "operator": "In", "values": ["frontend"]}]
+                    },
+                    "maxUnavailable": "25%"
+                })
+            ),
+            pdb(
+                "db",
+                serde_json::json!({
+                    "selector": {"matchLabels": {"app": "db"}},
+                    "minAvailable": 1
+                })
+            ),
+        ]));
+
+        let coverage = check_pdb_coverage(&web_selector(), "team-a", 3).unwrap();
+        assert_eq!(coverage.budgets, vec!["web".to_string()]);
+        assert!(coverage.is_covered());
+        assert!(coverage.is_sane());
+    }
+
+    #[serial]
+    #[test]
+    fn pdb_coverage_issues() {
+        let _ctx = expect_pdbs(serde_json::json!([
+            pdb(
+                "all",
+                serde_json::json!({"selector": {}, "minAvailable": "100%"})
+            ),
+            pdb(
+                "web",
+                serde_json::json!({
+                    "selector": {"matchLabels": {"app": "web"}},
+                    "maxUnavailable": "lots"
+                })
+            ),
+            pdb("none", serde_json::json!({"minAvailable": 0})),
+        ]));
+
+        let coverage = check_pdb_coverage(&web_selector(), "team-a", 3).unwrap();
+        assert_eq!(coverage.budgets, vec!["all".to_string(), "web".to_string()]);
+        assert_eq!(
+            coverage.issues,
+            vec![
+                PdbIssue::MultipleBudgets(vec!["all".to_string(), "web".to_string()]),
+                PdbIssue::BlocksDisruptions("all".to_string()),
+                PdbIssue::Malformed {
+                    name: "web".to_string(),
+                    reason: "maxUnavailable: 'lots' is not a number nor a percentage".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn pdb_coverage_missing() {
+        let _ctx = expect_pdbs(serde_json::json!([]));
+
+        let coverage = check_pdb_coverage(&web_selector(), "team-a", 1).unwrap();
+        assert!(!coverage.is_covered());
+        assert_eq!(coverage.issues, vec![PdbIssue::NotCovered]);
+        assert_eq!(
+            coverage.issues[0].to_string(),
+            "no PodDisruptionBudget covers the workload"
+        );
+    }
+
+    fn expect_network_policies(policies: serde_json::Value) -> mock_wapc::__host_call::Context {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().returning(move |_, _, op, msg| {
+            assert_eq!(op, ops::KUBERNETES_LIST_RESOURCES_BY_NAMESPACE);
+            let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            assert_eq!(req["kind"], "NetworkPolicy");
+            let items: Vec<serde_json::Value> = policies
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|spec| {
+                    serde_json::json!({
+                        "apiVersion": "networking.k8s.io/v1",
+                        "kind": "NetworkPolicy",
+                        "metadata": {"name": spec["name"], "namespace": req["namespace"]},
+                        "spec": spec["spec"],
+                    })
+                })
+                .collect();
+            Ok(serde_json::to_vec(&serde_json::json!({
+                "apiVersion": "networking.k8s.io/v1",
+                "kind": "NetworkPolicyList",
+                "metadata": {},
+                "items": items,
+            }))
+            .unwrap())
+        });
+        ctx
+    }
+
+    #[serial]
+    #[test]
+    fn default_deny_networkpolicy() {
+        let _ctx = expect_network_policies(serde_json::json!([
+            {"name": "allow-web", "spec": {
+                "podSelector": {"matchLabels": {"app": "web"}},
+                "policyTypes": ["Ingress"]
+            }},
+            {"name": "deny-egress", "spec": {
+                "podSelector": {},
+                "policyTypes": ["Egress"]
+            }},
+        ]));
+        assert!(!namespace_has_default_deny("team-a").unwrap());
+        assert!(namespace_has_default_deny_for("team-a", PolicyType::Egress).unwrap());
+    }
+
+    #[serial]
+    #[test]
+    fn default_deny_without_policy_types() {
+        let _ctx = expect_network_policies(serde_json::json!([
+            {"name": "allow-all", "spec": {"podSelector": {}, "ingress": [{}]}},
+            {"name": "default-deny", "spec": {"podSelector": {}}},
+        ]));
+        assert!(namespace_has_default_deny("team-a").unwrap());
+        assert!(!namespace_has_default_deny_for("team-a", PolicyType::Egress).unwrap());
+    }
+
+    #[serial]
+    #[test]
+    fn pod_covered_by_networkpolicies() {
+        let _ctx = expect_network_policies(serde_json::json!([
+            {"name": "web", "spec": {"podSelector": {"matchLabels": {"app": "web"}}}},
+            {"name": "not-db", "spec": {"podSelector": {
+                "matchExpressions": [{"key": "app", "operator": "NotIn", "values": ["db"]}]
+            }}},
+        ]));
+        let labels =
+            |app: &str| std::collections::BTreeMap::from([("app".to_string(), app.to_string())]);
+
+        assert_eq!(
+            selector_covered_by_networkpolicies(&labels("web"), "team-a").unwrap(),
+            vec!["web".to_string(), "not-db".to_string()]
+        );
+        assert!(selector_covered_by_networkpolicies(&labels("db"), "team-a")
+            .unwrap()
+            .is_empty());
+    }
+}
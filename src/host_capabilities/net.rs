@@ -1,21 +1,34 @@
+use crate::host_capabilities::ops;
+use crate::host_capabilities::telemetry::host;
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-/// Response to host lookup requests
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct LookupResponse {
-    /// list of Ips that have been resolved
-    pub ips: Vec<String>,
+/// Payloads of the `v1/dns_lookup_host` operation
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    /// Response to host lookup requests
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct LookupResponse {
+        /// list of Ips that have been resolved
+        pub ips: Vec<String>,
+    }
 }
 
+pub use v1::LookupResponse;
+
 /// Lookup the addresses for a given hostname via DNS
 pub fn lookup_host(host: &str) -> Result<LookupResponse> {
     let req = json!(host);
     let msg = serde_json::to_vec(&req)
         .map_err(|e| anyhow!("error serializing the validation request: {}", e))?;
-    let response_raw = wapc_guest::host_call("kubewarden", "net", "v1/dns_lookup_host", &msg)
-        .map_err(|e| anyhow!("error invoking wapc net.dns_lookup_host : {:?}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_NET,
+        ops::NET_V1_DNS_LOOKUP_HOST,
+        &msg,
+    )
+    .map_err(|e| anyhow!("error invoking wapc net.dns_lookup_host : {:?}", e))?;
 
     let response: LookupResponse = serde_json::from_slice(&response_raw)?;
 
@@ -1,44 +1,63 @@
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
 use anyhow::{anyhow, Result};
-use oci_spec::image::{ImageConfiguration, ImageIndex, ImageManifest};
-use serde::{Deserialize, Serialize};
 use serde_json::json;
 #[cfg(test)]
-use tests::mock_wapc as wapc_guest;
+use tests::mock_wapc as host;
 
-/// Response to manifest digest request
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ManifestDigestResponse {
-    pub digest: String,
-}
+/// Payloads of the `v1/manifest_digest`, `v1/oci_manifest` and
+/// `v1/oci_manifest_config` operations
+pub mod v1 {
+    use oci_spec::image::{ImageConfiguration, ImageIndex, ImageManifest};
+    use serde::{Deserialize, Serialize};
 
-/// An image, or image index, OCI manifest
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
-#[serde(untagged)]
-pub enum OciManifestResponse {
-    //Using  box here to make linter happy. It complains about the different sizes between the two
-    //enum elements. See more here:
-    //https://rust-lang.github.io/rust-clippy/master/index.html#/large_enum_variant
-    /// An OCI image manifest
-    Image(Box<ImageManifest>),
-    /// An OCI image index manifest
-    ImageIndex(Box<ImageIndex>),
-}
+    /// Response to manifest digest request
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct ManifestDigestResponse {
+        pub digest: String,
+    }
 
-/// Response to manifest and config request
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct OciManifestAndConfigResponse {
-    pub manifest: ImageManifest,
-    pub digest: String,
-    pub config: ImageConfiguration,
+    /// An image, or image index, OCI manifest
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+    #[serde(untagged)]
+    #[non_exhaustive]
+    pub enum OciManifestResponse {
+        //Using  box here to make linter happy. It complains about the different sizes between the two
+        //enum elements. See more here:
+        //https://rust-lang.github.io/rust-clippy/master/index.html#/large_enum_variant
+        /// An OCI image manifest
+        Image(Box<ImageManifest>),
+        /// An OCI image index manifest
+        ImageIndex(Box<ImageIndex>),
+        /// A manifest this version of the SDK cannot decode, for example one
+        /// returned by a newer policy-server. The raw document is kept as-is
+        Unknown(serde_json::Value),
+    }
+
+    /// Response to manifest and config request
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct OciManifestAndConfigResponse {
+        pub manifest: ImageManifest,
+        pub digest: String,
+        pub config: ImageConfiguration,
+    }
 }
 
+pub use v1::{ManifestDigestResponse, OciManifestAndConfigResponse, OciManifestResponse};
+
 /// Computes the digest of the OCI object referenced by `image`
 pub fn get_manifest_digest(image: &str) -> Result<ManifestDigestResponse> {
     let req = json!(image);
     let msg = serde_json::to_vec(&req)
         .map_err(|e| anyhow!("error serializing the validation request: {}", e))?;
-    let response_raw = wapc_guest::host_call("kubewarden", "oci", "v1/manifest_digest", &msg)
-        .map_err(|e| anyhow!("error invoking wapc oci.manifest_digest: {:?}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_OCI,
+        ops::OCI_V1_MANIFEST_DIGEST,
+        &msg,
+    )
+    .map_err(|e| anyhow!("error invoking wapc oci.manifest_digest: {:?}", e))?;
 
     let response: ManifestDigestResponse = serde_json::from_slice(&response_raw)?;
 
@@ -50,8 +69,9 @@ pub fn get_manifest(image: &str) -> Result<OciManifestResponse> {
     let req = json!(image);
     let msg = serde_json::to_vec(&req)
         .map_err(|e| anyhow!("error serializing the validation request: {}", e))?;
-    let response_raw = wapc_guest::host_call("kubewarden", "oci", "v1/oci_manifest", &msg)
-        .map_err(|e| anyhow!("error invoking wapc oci.manifest_digest: {:?}", e))?;
+    let response_raw =
+        host::host_call(ops::BINDING, ops::NAMESPACE_OCI, ops::OCI_V1_MANIFEST, &msg)
+            .map_err(|e| anyhow!("error invoking wapc oci.manifest_digest: {:?}", e))?;
     let response: OciManifestResponse = serde_json::from_slice(&response_raw)?;
     Ok(response)
 }
@@ -61,23 +81,146 @@ pub fn get_manifest_and_config(image: &str) -> Result<OciManifestAndConfigRespon
     let req = json!(image);
     let msg = serde_json::to_vec(&req)
         .map_err(|e| anyhow!("error serializing the validation request: {}", e))?;
-    let response_raw =
-        wapc_guest::host_call("kubewarden", "oci", "v1/oci_manifest_config", &msg)
-            .map_err(|e| anyhow!("error invoking wapc oci.manifest_and_config: {:?}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_OCI,
+        ops::OCI_V1_MANIFEST_CONFIG,
+        &msg,
+    )
+    .map_err(|e| anyhow!("error invoking wapc oci.manifest_and_config: {:?}", e))?;
 
     let response: OciManifestAndConfigResponse = serde_json::from_slice(&response_raw)?;
 
     Ok(response)
 }
 
+/// Registry used when an image reference does not specify one
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// The registry hosting `image`. Images without an explicit registry
+/// (e.g. `busybox` or `library/busybox`) are hosted on `docker.io`
+pub fn image_registry(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains('.') || host.contains(':') || host == "localhost" => host,
+        _ => DEFAULT_REGISTRY,
+    }
+}
+
+/// The verdict of [`enforce_registry_policy`] about a single image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageVerdict {
+    /// The image can be used as-is
+    Allowed,
+    /// The image is hosted on a registry that is not allowed
+    RegistryNotAllowed {
+        /// The registry hosting the image
+        registry: String,
+    },
+    /// The image is allowed, but it has to be replaced by the given
+    /// reference, pinned to the digest of the image
+    PinToDigest {
+        /// The image reference pinned to its digest
+        pinned: String,
+    },
+    /// The digest of the image could not be computed
+    DigestUnavailable {
+        /// The error reported by the host
+        error: String,
+    },
+}
+
+impl ImageVerdict {
+    /// Returns `true` when the image can be used, possibly after pinning it
+    /// to its digest
+    pub fn is_allowed(&self) -> bool {
+        matches!(
+            self,
+            ImageVerdict::Allowed | ImageVerdict::PinToDigest { .. }
+        )
+    }
+}
+
+/// The outcome of [`enforce_registry_policy`] about a single image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageEvaluation {
+    /// The image reference that has been evaluated
+    pub image: String,
+    /// The verdict about the image
+    pub verdict: ImageVerdict,
+}
+
+/// Evaluate `images` against a registry allow-list.
+///
+/// Each image must be hosted on one of the `allowed_registries`. When
+/// `require_digest` is set, images not pinned to a digest are resolved
+/// through [`get_manifest_digest`] and a [`ImageVerdict::PinToDigest`]
+/// verdict carries the reference the policy should mutate the image to.
+/// # Arguments
+/// * `images` - the image references to evaluate
+/// * `allowed_registries` - the registries images can be pulled from (e.g. `ghcr.io`)
+/// * `require_digest` - whether images have to be pinned to a digest
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use kubewarden_policy_sdk::host_capabilities::oci::{enforce_registry_policy, ImageVerdict};
+///
+/// let allowed = vec!["ghcr.io".to_string()];
+/// for evaluation in enforce_registry_policy(["ghcr.io/kubewarden/policy-server:v1.0.0"], &allowed, true) {
+///     match evaluation.verdict {
+///         ImageVerdict::PinToDigest { pinned } => println!("mutate {} to {}", evaluation.image, pinned),
+///         verdict if !verdict.is_allowed() => println!("reject {}: {:?}", evaluation.image, verdict),
+///         _ => {}
+///     }
+/// }
+/// ```
+pub fn enforce_registry_policy<I, S>(
+    images: I,
+    allowed_registries: &[String],
+    require_digest: bool,
+) -> Vec<ImageEvaluation>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    images
+        .into_iter()
+        .map(|image| {
+            let image = image.as_ref();
+            let registry = image_registry(image);
+            let verdict = if !allowed_registries.iter().any(|allowed| allowed == registry) {
+                ImageVerdict::RegistryNotAllowed {
+                    registry: registry.to_string(),
+                }
+            } else if !require_digest || image.contains('@') {
+                ImageVerdict::Allowed
+            } else {
+                match get_manifest_digest(image) {
+                    Ok(response) => ImageVerdict::PinToDigest {
+                        pinned: format!("{}@{}", image, response.digest),
+                    },
+                    Err(e) => ImageVerdict::DigestUnavailable {
+                        error: e.to_string(),
+                    },
+                }
+            };
+            ImageEvaluation {
+                image: image.to_string(),
+                verdict,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use mockall::automock;
     use oci_spec::image::{
         Arch, ConfigBuilder, Descriptor, DescriptorBuilder, Digest, History, HistoryBuilder,
-        ImageConfigurationBuilder, ImageIndexBuilder, ImageManifestBuilder, MediaType, Os,
-        PlatformBuilder, RootFsBuilder, SCHEMA_VERSION,
+        ImageConfiguration, ImageConfigurationBuilder, ImageIndex, ImageIndexBuilder,
+        ImageManifest, ImageManifestBuilder, MediaType, Os, PlatformBuilder, RootFsBuilder,
+        SCHEMA_VERSION,
     };
     use serial_test::serial;
     use std::str::FromStr;
@@ -289,7 +432,7 @@ mod tests {
             OciManifestResponse::Image(image) => {
                 assert_eq!(*image, create_oci_image_manifest());
             }
-            OciManifestResponse::ImageIndex(_) => panic!("Invalid oci manifest type returned"),
+            _ => panic!("Invalid oci manifest type returned"),
         }
     }
 
@@ -313,10 +456,23 @@ mod tests {
         let response = get_manifest("ghcr.io/kubewarden/policy-server:latest")
             .expect("failed to get oci manifest reponse");
         match response {
-            OciManifestResponse::Image(_) => panic!("Invalid oci manifest type returned"),
             OciManifestResponse::ImageIndex(image) => {
                 assert_eq!(*image, create_oci_index_image_manifest());
             }
+            _ => panic!("Invalid oci manifest type returned"),
+        }
+    }
+
+    #[test]
+    fn unknown_oci_manifest() {
+        let raw = json!({
+            "schemaVersion": 3,
+            "mediaType": "application/vnd.example.future.manifest.v1+json",
+        });
+        let response: OciManifestResponse = serde_json::from_value(raw.clone()).unwrap();
+        match response {
+            OciManifestResponse::Unknown(value) => assert_eq!(value, raw),
+            _ => panic!("manifest should not be decoded"),
         }
     }
 
@@ -349,4 +505,77 @@ mod tests {
         assert_eq!(response.manifest, create_oci_image_manifest());
         assert_eq!(response.digest, "sha256:983");
     }
+
+    #[test]
+    fn registry_of_image() {
+        assert_eq!(image_registry("busybox"), "docker.io");
+        assert_eq!(image_registry("library/busybox:1.36"), "docker.io");
+        assert_eq!(
+            image_registry("ghcr.io/kubewarden/policy-server"),
+            "ghcr.io"
+        );
+        assert_eq!(image_registry("localhost/app"), "localhost");
+        assert_eq!(
+            image_registry("registry.local:5000/app:v1"),
+            "registry.local:5000"
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn registry_policy() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(2)
+            .withf(|_, ns, op, _| ns == "oci" && op == "v1/manifest_digest")
+            .returning(|_, _, _, msg| {
+                if msg == b"\"ghcr.io/kubewarden/missing:v1\"" {
+                    Err("manifest unknown".into())
+                } else {
+                    Ok(serde_json::to_vec(&ManifestDigestResponse {
+                        digest: "sha256:123".to_string(),
+                    })
+                    .unwrap())
+                }
+            });
+
+        let allowed = vec!["ghcr.io".to_string()];
+        let evaluations = enforce_registry_policy(
+            [
+                "busybox",
+                "ghcr.io/kubewarden/policy-server@sha256:456",
+                "ghcr.io/kubewarden/policy-server:v1",
+                "ghcr.io/kubewarden/missing:v1",
+            ],
+            &allowed,
+            true,
+        );
+        let verdicts: Vec<ImageVerdict> = evaluations.into_iter().map(|e| e.verdict).collect();
+
+        assert_eq!(
+            verdicts[0],
+            ImageVerdict::RegistryNotAllowed {
+                registry: "docker.io".to_string()
+            }
+        );
+        assert_eq!(verdicts[1], ImageVerdict::Allowed);
+        assert_eq!(
+            verdicts[2],
+            ImageVerdict::PinToDigest {
+                pinned: "ghcr.io/kubewarden/policy-server:v1@sha256:123".to_string()
+            }
+        );
+        assert!(matches!(
+            verdicts[3],
+            ImageVerdict::DigestUnavailable { .. }
+        ));
+        assert!(!verdicts[3].is_allowed());
+    }
+
+    #[test]
+    fn registry_policy_without_digests() {
+        let allowed = vec!["docker.io".to_string()];
+        let evaluations = enforce_registry_policy(vec!["busybox".to_string()], &allowed, false);
+        assert_eq!(evaluations[0].verdict, ImageVerdict::Allowed);
+    }
 }
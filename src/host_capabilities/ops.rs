@@ -0,0 +1,157 @@
+//! Names of the waPC operations exposed by the Kubewarden hosts.
+//!
+//! Every host call is identified by a binding, a namespace and an operation
+//! name. These constants are the single source of truth used by the SDK, and
+//! can be used by hosts (e.g. policy-server, kwctl) to stay in sync with it.
+//!
+//! The [`OPERATIONS`] table lists all the known operations, tracking which
+//! ones have been superseded by newer versions.
+
+/// The waPC binding used by all the host calls
+pub const BINDING: &str = "kubewarden";
+
+/// Namespace of the OCI registry related operations
+pub const NAMESPACE_OCI: &str = "oci";
+/// Namespace of the cryptographic operations
+pub const NAMESPACE_CRYPTO: &str = "crypto";
+/// Namespace of the network related operations
+pub const NAMESPACE_NET: &str = "net";
+/// Namespace of the Kubernetes operations
+pub const NAMESPACE_KUBERNETES: &str = "kubernetes";
+/// Namespace of the logging operations
+pub const NAMESPACE_TRACING: &str = "tracing";
+/// Namespace of the time operations
+pub const NAMESPACE_TIME: &str = "time";
+/// Namespace of the random data operations
+pub const NAMESPACE_RAND: &str = "rand";
+/// Namespace of the operations describing the running policy
+pub const NAMESPACE_POLICY: &str = "policy";
+/// Namespace of the Kubernetes Events operations
+pub const NAMESPACE_EVENTS: &str = "events";
+
+/// Verify Sigstore signatures, using `verification::v1::SigstoreVerificationInput`
+pub const OCI_V1_VERIFY: &str = "v1/verify";
+/// Verify Sigstore signatures, using `verification::v2::SigstoreVerificationInput`
+pub const OCI_V2_VERIFY: &str = "v2/verify";
+/// Get the status of the Sigstore trust root
+pub const OCI_V1_SIGSTORE_TRUST_STORE_STATUS: &str = "v1/sigstore_trust_store_status";
+/// Get the digest of an OCI manifest
+pub const OCI_V1_MANIFEST_DIGEST: &str = "v1/manifest_digest";
+/// Get an OCI manifest
+pub const OCI_V1_MANIFEST: &str = "v1/oci_manifest";
+/// Get an OCI manifest and its configuration
+pub const OCI_V1_MANIFEST_CONFIG: &str = "v1/oci_manifest_config";
+/// Get the metadata of the Sigstore signatures of an OCI object, without
+/// verifying them
+pub const OCI_V1_SIGSTORE_SIGNATURES: &str = "v1/sigstore_signatures";
+/// Verify a certificate against a chain of certificates
+pub const CRYPTO_V1_IS_CERTIFICATE_TRUSTED: &str = "v1/is_certificate_trusted";
+/// Verify a certificate against a chain of certificates or a named trust store
+pub const CRYPTO_V2_IS_CERTIFICATE_TRUSTED: &str = "v2/is_certificate_trusted";
+/// Resolve a host name via DNS
+pub const NET_V1_DNS_LOOKUP_HOST: &str = "v1/dns_lookup_host";
+/// List the Kubernetes resources of a namespace
+pub const KUBERNETES_LIST_RESOURCES_BY_NAMESPACE: &str = "list_resources_by_namespace";
+/// List the Kubernetes resources of the whole cluster
+pub const KUBERNETES_LIST_RESOURCES_ALL: &str = "list_resources_all";
+/// List only the metadata of Kubernetes resources
+pub const KUBERNETES_LIST_RESOURCES_METADATA: &str = "list_resources_metadata";
+/// Get a single Kubernetes resource
+pub const KUBERNETES_GET_RESOURCE: &str = "get_resource";
+/// Submit a Kubernetes resource to the API server with `dryRun=All`
+pub const KUBERNETES_DRY_RUN_APPLY: &str = "dry_run_apply";
+/// Emit a log event
+pub const TRACING_LOG: &str = "log";
+/// Get the current time
+pub const TIME_V1_NOW: &str = "v1/now";
+/// Get random bytes
+pub const RAND_V1_BYTES: &str = "v1/bytes";
+/// Get information about the running policy
+pub const POLICY_V1_INFO: &str = "v1/info";
+/// Create a Kubernetes Event attributed to the policy
+pub const EVENTS_V1_EMIT: &str = "v1/emit";
+
+/// A waPC operation exposed by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostOperation {
+    /// Namespace of the operation
+    pub namespace: &'static str,
+    /// Name of the operation
+    pub operation: &'static str,
+    /// Optional - the newer operation, inside of the same namespace, that
+    /// should be used instead of this one
+    pub superseded_by: Option<&'static str>,
+}
+
+const fn op(namespace: &'static str, operation: &'static str) -> HostOperation {
+    HostOperation {
+        namespace,
+        operation,
+        superseded_by: None,
+    }
+}
+
+/// All the operations known by the SDK
+pub const OPERATIONS: &[HostOperation] = &[
+    HostOperation {
+        namespace: NAMESPACE_OCI,
+        operation: OCI_V1_VERIFY,
+        superseded_by: Some(OCI_V2_VERIFY),
+    },
+    op(NAMESPACE_OCI, OCI_V2_VERIFY),
+    op(NAMESPACE_OCI, OCI_V1_SIGSTORE_TRUST_STORE_STATUS),
+    op(NAMESPACE_OCI, OCI_V1_MANIFEST_DIGEST),
+    op(NAMESPACE_OCI, OCI_V1_MANIFEST),
+    op(NAMESPACE_OCI, OCI_V1_MANIFEST_CONFIG),
+    op(NAMESPACE_OCI, OCI_V1_SIGSTORE_SIGNATURES),
+    op(NAMESPACE_CRYPTO, CRYPTO_V1_IS_CERTIFICATE_TRUSTED),
+    op(NAMESPACE_CRYPTO, CRYPTO_V2_IS_CERTIFICATE_TRUSTED),
+    op(NAMESPACE_NET, NET_V1_DNS_LOOKUP_HOST),
+    op(NAMESPACE_KUBERNETES, KUBERNETES_LIST_RESOURCES_BY_NAMESPACE),
+    op(NAMESPACE_KUBERNETES, KUBERNETES_LIST_RESOURCES_ALL),
+    op(NAMESPACE_KUBERNETES, KUBERNETES_LIST_RESOURCES_METADATA),
+    op(NAMESPACE_KUBERNETES, KUBERNETES_GET_RESOURCE),
+    op(NAMESPACE_KUBERNETES, KUBERNETES_DRY_RUN_APPLY),
+    op(NAMESPACE_TRACING, TRACING_LOG),
+    op(NAMESPACE_TIME, TIME_V1_NOW),
+    op(NAMESPACE_RAND, RAND_V1_BYTES),
+    op(NAMESPACE_POLICY, POLICY_V1_INFO),
+    op(NAMESPACE_EVENTS, EVENTS_V1_EMIT),
+];
+
+/// Find an operation inside of the [`OPERATIONS`] table
+pub fn lookup(namespace: &str, operation: &str) -> Option<&'static HostOperation> {
+    OPERATIONS
+        .iter()
+        .find(|op| op.namespace == namespace && op.operation == operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn operations_are_unique() {
+        let unique: HashSet<(&str, &str)> = OPERATIONS
+            .iter()
+            .map(|op| (op.namespace, op.operation))
+            .collect();
+        assert_eq!(unique.len(), OPERATIONS.len());
+    }
+
+    #[test]
+    fn superseding_operations_exist() {
+        for op in OPERATIONS {
+            if let Some(newer) = op.superseded_by {
+                let newer = lookup(op.namespace, newer).expect("superseding operation not found");
+                assert!(newer.superseded_by.is_none());
+            }
+        }
+        assert_eq!(
+            lookup(NAMESPACE_OCI, OCI_V1_VERIFY).unwrap().superseded_by,
+            Some(OCI_V2_VERIFY)
+        );
+        assert!(lookup(NAMESPACE_OCI, "v0/unknown").is_none());
+    }
+}
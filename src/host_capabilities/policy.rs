@@ -0,0 +1,251 @@
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
+use anyhow::{anyhow, Result};
+use std::cell::Cell;
+#[cfg(test)]
+use tests::mock_wapc as host;
+
+/// Payloads of the `v1/info` operation
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    /// The execution mode of the policy
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Default)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PolicyMode {
+        /// Rejections are only logged, all the requests are accepted
+        Monitor,
+        /// Rejections are enforced. Modes unknown to this version of the SDK,
+        /// reported by newer hosts, are handled as `Protect`
+        #[default]
+        #[serde(other)]
+        Protect,
+    }
+
+    /// PolicyInfo describes the running policy, as seen by the policy-server
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct PolicyInfo {
+        /// the name of the policy
+        pub name: String,
+        /// the version of the policy-server evaluating the policy
+        pub policy_server_version: String,
+        /// the execution mode of the policy
+        pub mode: PolicyMode,
+        /// Optional - the namespace served by the policy. Set only for
+        /// namespaced policies (e.g. `AdmissionPolicy`)
+        pub namespace: Option<String>,
+        /// Optional - whether the policy is registered as mutating. Not set by
+        /// hosts that do not report it
+        #[serde(default)]
+        pub mutating: Option<bool>,
+    }
+
+    impl PolicyInfo {
+        /// Returns `true` when the policy runs in monitor mode. Policies can use
+        /// it to tailor their messages (e.g. "would have rejected")
+        pub fn is_monitor_mode(&self) -> bool {
+            self.mode == PolicyMode::Monitor
+        }
+    }
+}
+
+pub use v1::{PolicyInfo, PolicyMode};
+
+/// Get information about the running policy from the host
+pub fn policy_info() -> Result<PolicyInfo> {
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_POLICY,
+        ops::POLICY_V1_INFO,
+        &[],
+    )
+    .map_err(|e| anyhow!("error invoking wapc policy.info: {:?}", e))?;
+
+    let response: PolicyInfo = serde_json::from_slice(&response_raw)?;
+
+    Ok(response)
+}
+
+thread_local! {
+    static MUTATION_ALLOWED: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Ensure the policy is registered as mutating, returning a descriptive error
+/// otherwise. The outcome is cached for the lifetime of the policy instance.
+///
+/// When the host cannot tell whether the policy is mutating, either because
+/// it does not report it or because it does not implement the `policy`
+/// capability, the mutation is allowed: the policy-server remains the last
+/// line of defense. This outcome is cached too, the host is not asked again.
+pub fn ensure_mutation_allowed() -> Result<()> {
+    let allowed = match MUTATION_ALLOWED.get() {
+        Some(allowed) => allowed,
+        None => {
+            let allowed = policy_info()
+                .ok()
+                .and_then(|info| info.mutating)
+                .unwrap_or(true);
+            MUTATION_ALLOWED.set(Some(allowed));
+            allowed
+        }
+    };
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "the policy attempted to mutate the request, but it is not registered as mutating: set `mutating: true` inside of the policy definition"
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use mockall::automock;
+    use serde_json::json;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn get_policy_info() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, _| ns == "policy" && op == "v1/info")
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&json!({
+                    "name": "namespaced-privileged-pods",
+                    "policy_server_version": "v1.18.0",
+                    "mode": "monitor",
+                    "namespace": "team-a",
+                    "mutating": false
+                }))
+                .unwrap())
+            });
+
+        let info = policy_info().unwrap();
+        assert_eq!(info.name, "namespaced-privileged-pods");
+        assert_eq!(info.namespace.as_deref(), Some("team-a"));
+        assert!(info.is_monitor_mode());
+        assert_eq!(info.mutating, Some(false));
+    }
+
+    #[test]
+    fn policy_info_from_newer_host() {
+        let info: PolicyInfo = serde_json::from_value(json!({
+            "name": "privileged-pods",
+            "policy_server_version": "v9.0.0",
+            "mode": "dry-run",
+            "namespace": null,
+            "priority": 10
+        }))
+        .unwrap();
+        assert_eq!(info.mode, PolicyMode::Protect);
+        assert_eq!(info.mutating, None);
+        assert_eq!(
+            serde_json::to_value(PolicyMode::Monitor).unwrap(),
+            json!("monitor")
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn get_policy_info_error() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err("not supported".into()));
+
+        assert!(policy_info().is_err());
+    }
+
+    fn policy_info_response(mutating: Option<bool>) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "name": "policy",
+            "policy_server_version": "v1.18.0",
+            "mode": "protect",
+            "mutating": mutating
+        }))
+        .unwrap()
+    }
+
+    #[serial]
+    #[test]
+    fn mutation_not_allowed() {
+        MUTATION_ALLOWED.set(None);
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Ok(policy_info_response(Some(false))));
+
+        let err = ensure_mutation_allowed().unwrap_err();
+        assert!(err.to_string().contains("not registered as mutating"));
+        // cached, the host is not contacted again
+        assert!(ensure_mutation_allowed().is_err());
+    }
+
+    #[serial]
+    #[test]
+    fn mutation_allowed() {
+        MUTATION_ALLOWED.set(None);
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Ok(policy_info_response(Some(true))));
+
+        assert!(ensure_mutation_allowed().is_ok());
+        assert!(ensure_mutation_allowed().is_ok());
+    }
+
+    #[serial]
+    #[test]
+    fn mutation_allowed_when_host_cannot_tell() {
+        MUTATION_ALLOWED.set(None);
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Ok(policy_info_response(None)));
+
+        assert!(ensure_mutation_allowed().is_ok());
+        // cached, the host is not contacted again
+        assert!(ensure_mutation_allowed().is_ok());
+    }
+
+    #[serial]
+    #[test]
+    fn mutation_allowed_when_host_lacks_capability() {
+        MUTATION_ALLOWED.set(None);
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err("unknown operation".into()));
+
+        assert!(ensure_mutation_allowed().is_ok());
+        // cached, the host is not contacted again
+        assert!(ensure_mutation_allowed().is_ok());
+    }
+
+    /// Simulate a host reporting the policy as mutating. The tests producing
+    /// mutations must hold the returned context and be `#[serial]`
+    pub(crate) fn mock_mutating_policy() -> mock_wapc::__host_call::Context {
+        MUTATION_ALLOWED.set(None);
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .returning(|_, _, _, _| Ok(policy_info_response(Some(true))));
+        ctx
+    }
+}
@@ -0,0 +1,185 @@
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::cell::Cell;
+#[cfg(test)]
+use tests::mock_wapc as host;
+
+/// Get `n` random bytes from the host.
+///
+/// WebAssembly guests have no reliable source of entropy, policies that need
+/// random data (e.g. to generate identifiers for audit annotations) should
+/// use this function, or a [`RandomSource`] to make them testable.
+pub fn bytes(n: usize) -> Result<Vec<u8>> {
+    let msg = serde_json::to_vec(&json!(n))
+        .map_err(|e| anyhow!("error serializing the random bytes request: {}", e))?;
+    let response_raw = host::host_call(ops::BINDING, ops::NAMESPACE_RAND, ops::RAND_V1_BYTES, &msg)
+        .map_err(|e| anyhow!("error invoking wapc rand.bytes: {:?}", e))?;
+
+    let response: Vec<u8> = serde_json::from_slice(&response_raw)?;
+    if response.len() != n {
+        return Err(anyhow!(
+            "host returned {} random bytes, {} were requested",
+            response.len(),
+            n
+        ));
+    }
+
+    Ok(response)
+}
+
+/// Generate a random (version 4) UUID, using random bytes provided by the host
+pub fn uuid_v4() -> Result<String> {
+    HostRandom.uuid_v4()
+}
+
+/// A source of random data. Policies can depend on this trait instead of
+/// calling [`bytes`] and [`uuid_v4`] directly, so that a [`SeededRandom`]
+/// can be used by their tests.
+pub trait RandomSource {
+    /// Get `n` random bytes
+    fn bytes(&self, n: usize) -> Result<Vec<u8>>;
+
+    /// Generate a random (version 4) UUID. An error is returned when the
+    /// source provides less than 16 bytes
+    fn uuid_v4(&self) -> Result<String> {
+        let mut b = self.bytes(16)?;
+        if b.len() < 16 {
+            return Err(anyhow!(
+                "a UUID needs 16 random bytes, {} were provided",
+                b.len()
+            ));
+        }
+        b.truncate(16);
+        b[6] = (b[6] & 0x0f) | 0x40;
+        b[8] = (b[8] & 0x3f) | 0x80;
+
+        let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        ))
+    }
+}
+
+/// A [`RandomSource`] that asks the host for random data
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostRandom;
+
+impl RandomSource for HostRandom {
+    fn bytes(&self, n: usize) -> Result<Vec<u8>> {
+        bytes(n)
+    }
+}
+
+/// A deterministic [`RandomSource`]: the same seed always produces the same
+/// sequence of bytes. Useful for testing, never use it to generate secrets.
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: Cell<u64>,
+}
+
+impl SeededRandom {
+    /// Create a new source using the given seed
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            state: Cell::new(seed),
+        }
+    }
+
+    // splitmix64
+    fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn bytes(&self, n: usize) -> Result<Vec<u8>> {
+        let mut b = Vec::with_capacity(n + 8);
+        while b.len() < n {
+            b.extend_from_slice(&self.next_u64().to_le_bytes());
+        }
+        b.truncate(n);
+        Ok(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::automock;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn host_uuid_v4() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| ns == "rand" && op == "v1/bytes" && msg == b"16")
+            .returning(|_, _, _, _| Ok(serde_json::to_vec(&[0xffu8; 16]).unwrap()));
+
+        assert_eq!(uuid_v4().unwrap(), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    }
+
+    #[serial]
+    #[test]
+    fn host_bytes_wrong_length() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Ok(serde_json::to_vec(&[1u8, 2]).unwrap()));
+
+        assert!(bytes(4).is_err());
+    }
+
+    #[test]
+    fn seeded_random_is_deterministic() {
+        let a = SeededRandom::new(42);
+        let b = SeededRandom::new(42);
+
+        assert_eq!(a.bytes(13).unwrap(), b.bytes(13).unwrap());
+        assert_eq!(a.uuid_v4().unwrap(), b.uuid_v4().unwrap());
+        assert_ne!(a.bytes(8).unwrap(), SeededRandom::new(1).bytes(8).unwrap());
+
+        let uuid = a.uuid_v4().unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+
+    struct ShortRandom;
+
+    impl RandomSource for ShortRandom {
+        fn bytes(&self, _n: usize) -> Result<Vec<u8>> {
+            Ok(vec![0; 4])
+        }
+    }
+
+    #[test]
+    fn uuid_v4_needs_16_bytes() {
+        assert!(ShortRandom.uuid_v4().is_err());
+    }
+}
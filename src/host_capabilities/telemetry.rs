@@ -0,0 +1,176 @@
+//! Opt-in counters of the host calls made while evaluating a request.
+//!
+//! Policies relying on the context-aware capabilities can end up querying the
+//! Kubernetes API many times per evaluation. Once the telemetry is enabled,
+//! every host call made through the SDK is counted by namespace and
+//! operation, and the summary can be logged or attached to the response as an
+//! audit annotation.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::host_capabilities::telemetry;
+//! use kubewarden_policy_sdk::accept_request;
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     telemetry::enable_host_call_telemetry(true);
+//!     // perform the host calls...
+//!
+//!     let summary = telemetry::take_host_call_summary();
+//!     for (key, value) in summary.audit_annotations() {
+//!         kubewarden_policy_sdk::response::record_audit_annotation(&key, &value);
+//!     }
+//!     accept_request()
+//! }
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Key of the audit annotation holding the host calls summary
+pub const AUDIT_ANNOTATION_KEY: &str = "host-calls";
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static COUNTS: RefCell<BTreeMap<(String, String), u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Enable or disable the counting of the host calls. The telemetry is
+/// disabled by default
+pub fn enable_host_call_telemetry(enabled: bool) {
+    ENABLED.set(enabled);
+}
+
+/// Returns `true` when the host calls are being counted
+pub fn host_call_telemetry_enabled() -> bool {
+    ENABLED.get()
+}
+
+/// Count a host call, when the telemetry is enabled
+pub(crate) fn record_host_call(namespace: &str, operation: &str) {
+    if !host_call_telemetry_enabled() {
+        return;
+    }
+    COUNTS.with(|counts| {
+        *counts
+            .borrow_mut()
+            .entry((namespace.to_string(), operation.to_string()))
+            .or_default() += 1;
+    });
+}
+
+/// The number of host calls made to one operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostCallCount {
+    /// Namespace of the capability, e.g. `kubernetes`
+    pub namespace: String,
+    /// Operation invoked, e.g. `get_resource`
+    pub operation: String,
+    /// Number of calls
+    pub count: u64,
+}
+
+/// The host calls made since the summary was last taken, sorted by namespace
+/// and operation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostCallSummary {
+    pub calls: Vec<HostCallCount>,
+}
+
+impl HostCallSummary {
+    /// Total number of host calls
+    pub fn total(&self) -> u64 {
+        self.calls.iter().map(|call| call.count).sum()
+    }
+
+    /// The summary as an audit annotation stored under the
+    /// [`AUDIT_ANNOTATION_KEY`] key. Empty when no host call has been made
+    pub fn audit_annotations(&self) -> HashMap<String, String> {
+        if self.calls.is_empty() {
+            return HashMap::new();
+        }
+        HashMap::from([(AUDIT_ANNOTATION_KEY.to_string(), self.to_string())])
+    }
+
+    /// Emit the summary as a debug event through the given `logger`
+    pub fn log(&self, logger: &slog::Logger) {
+        slog::debug!(logger, "host calls";
+            "total" => self.total(),
+            "calls" => self.to_string());
+    }
+}
+
+impl fmt::Display for HostCallSummary {
+    /// Print the calls as `namespace/operation=count`, separated by commas
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|call| format!("{}/{}={}", call.namespace, call.operation, call.count))
+            .collect();
+        write!(f, "{}", calls.join(","))
+    }
+}
+
+/// Return the host calls counted so far, clearing the counters. Policies
+/// should call this function at the end of each evaluation
+pub fn take_host_call_summary() -> HostCallSummary {
+    let counts = COUNTS.with(|counts| std::mem::take(&mut *counts.borrow_mut()));
+    HostCallSummary {
+        calls: counts
+            .into_iter()
+            .map(|((namespace, operation), count)| HostCallCount {
+                namespace,
+                operation,
+                count,
+            })
+            .collect(),
+    }
+}
+
+/// The `host_call` function used by the host capabilities in place of the
+/// one of the `wapc_guest` crate: it counts the calls and applies the
+/// [`RetryPolicy`](crate::host_capabilities::client::RetryPolicy) before
+/// forwarding them to the host
+pub(crate) mod host {
+    use wapc_guest::CallResult;
+
+    pub fn host_call(binding: &str, ns: &str, op: &str, msg: &[u8]) -> CallResult {
+        crate::host_capabilities::client::dispatch(binding, ns, op, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_host_calls() {
+        take_host_call_summary();
+        enable_host_call_telemetry(true);
+        for op in [
+            "get_resource",
+            "get_resource",
+            "list_resources_by_namespace",
+        ] {
+            // there is no host when running the tests, the call fails
+            let _ = host::host_call("kubewarden", "kubernetes", op, &[]);
+        }
+        let _ = host::host_call("kubewarden", "oci", "v2/verify", &[]);
+        enable_host_call_telemetry(false);
+        let _ = host::host_call("kubewarden", "oci", "v2/verify", &[]);
+
+        let summary = take_host_call_summary();
+        assert_eq!(summary.total(), 4);
+        assert_eq!(
+            summary.to_string(),
+            "kubernetes/get_resource=2,kubernetes/list_resources_by_namespace=1,oci/v2/verify=1"
+        );
+        assert_eq!(
+            summary.audit_annotations()[AUDIT_ANNOTATION_KEY],
+            summary.to_string()
+        );
+        assert!(take_host_call_summary().calls.is_empty());
+        assert!(HostCallSummary::default().audit_annotations().is_empty());
+    }
+}
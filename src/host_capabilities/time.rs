@@ -0,0 +1,108 @@
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+#[cfg(test)]
+use tests::mock_wapc as host;
+
+/// Get the current time from the host, as a RFC 3339 formatted string
+/// (e.g. `2024-01-01T10:00:00Z`).
+///
+/// WebAssembly guests cannot rely on `std::time::SystemTime`, policies that
+/// need the current time (e.g. to check certificate expiration windows)
+/// should use this function, or a [`Clock`] to make them testable.
+pub fn now() -> Result<String> {
+    let response_raw = host::host_call(ops::BINDING, ops::NAMESPACE_TIME, ops::TIME_V1_NOW, &[])
+        .map_err(|e| anyhow!("error invoking wapc time.now: {:?}", e))?;
+
+    let response: String = serde_json::from_slice(&response_raw)?;
+
+    Ok(response)
+}
+
+/// A source of the current time. Policies can depend on this trait instead
+/// of calling [`now`] directly, so that a [`FixedClock`] can be used by
+/// their tests.
+pub trait Clock {
+    /// The current time, as a RFC 3339 formatted string
+    fn now(&self) -> Result<String>;
+
+    /// Pause the evaluation for `duration`. On `wasm32-wasi` the guest is
+    /// suspended through WASI, the wait counts against the execution timeout
+    /// of the policy enforced by the host
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A [`Clock`] that asks the host for the current time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostClock;
+
+impl Clock for HostClock {
+    fn now(&self) -> Result<String> {
+        now()
+    }
+}
+
+/// A [`Clock`] that always returns the same time, useful for testing
+#[derive(Debug, Clone)]
+pub struct FixedClock(pub String);
+
+impl Clock for FixedClock {
+    fn now(&self) -> Result<String> {
+        Ok(self.0.clone())
+    }
+
+    /// Time does not pass for a [`FixedClock`], it returns immediately
+    fn sleep(&self, _duration: Duration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::automock;
+    use serial_test::serial;
+
+    #[automock()]
+    pub mod wapc {
+        use wapc_guest::CallResult;
+
+        // needed for creating mocks
+        #[allow(dead_code)]
+        pub fn host_call(_binding: &str, _ns: &str, _op: &str, _msg: &[u8]) -> CallResult {
+            Ok(vec![u8::from(true)])
+        }
+    }
+
+    // these tests need to run sequentially because mockall creates a global context to create the mocks
+    #[serial]
+    #[test]
+    fn host_clock() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|binding, ns, op, _| binding == "kubewarden" && ns == "time" && op == "v1/now")
+            .returning(|_, _, _, _| Ok(serde_json::to_vec("2024-01-01T10:00:00Z").unwrap()));
+
+        assert_eq!(HostClock.now().unwrap(), "2024-01-01T10:00:00Z");
+    }
+
+    #[serial]
+    #[test]
+    fn host_clock_error() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err("not supported".into()));
+
+        assert!(now().is_err());
+    }
+
+    #[test]
+    fn fixed_clock() {
+        let clock = FixedClock("2024-01-01T10:00:00Z".to_string());
+        assert_eq!(clock.now().unwrap(), "2024-01-01T10:00:00Z");
+    }
+}
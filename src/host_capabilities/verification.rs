@@ -1,39 +1,257 @@
-use crate::host_capabilities::SigstoreVerificationInputV2;
+use crate::host_capabilities::ops;
+#[cfg(not(test))]
+use crate::host_capabilities::telemetry::host;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 #[cfg(test)]
-use tests::mock_wapc as wapc_guest;
-
-/// VerificationResponse holds the response of a sigstore signatures verification
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub struct VerificationResponse {
-    /// true if the image is trusted, which means verification was successfull
-    pub is_trusted: bool,
-    /// digest of the image that was verified
-    pub digest: String,
-}
+use tests::mock_wapc as host;
 
-/// KeylessInfo holds information about a keyless signature
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-pub struct KeylessInfo {
-    /// the issuer identifier
-    pub issuer: String,
-    /// contains the information of the user used to authenticate against the OIDC provider
-    pub subject: String,
+/// Payloads of the `v1/verify`, `v1/sigstore_trust_store_status` and
+/// `v1/sigstore_signatures` operations
+pub mod v1 {
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    /// VerificationResponse holds the response of a sigstore signatures verification
+    #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+    pub struct VerificationResponse {
+        /// true if the image is trusted, which means verification was successfull
+        pub is_trusted: bool,
+        /// digest of the image that was verified
+        pub digest: String,
+    }
+
+    /// TrustStoreStatus describes the state of the Sigstore trust root used by the
+    /// host to perform signature verifications. The trust root is fetched and
+    /// refreshed by the host through TUF.
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct TrustStoreStatus {
+        /// true if the TUF metadata of the trust root has not expired
+        pub fresh: bool,
+        /// Optional - RFC 3339 time of the last successful refresh of the trust root
+        pub last_refreshed: Option<String>,
+        /// Optional - RFC 3339 time at which the TUF timestamp metadata expires
+        pub expires: Option<String>,
+        /// Optional - version of the TUF root metadata in use
+        pub root_version: Option<u64>,
+    }
+
+    /// SignatureInfo describes one of the Sigstore signatures attached to an OCI
+    /// object. The signature has not been verified by the host
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+    pub struct SignatureInfo {
+        /// Optional - the OIDC issuer of the certificate of a keyless signature
+        #[serde(default)]
+        pub issuer: Option<String>,
+        /// Optional - the subject of the certificate of a keyless signature
+        #[serde(default)]
+        pub subject: Option<String>,
+        /// Optional - PEM encoded certificate embedded into the signature layer
+        #[serde(default)]
+        pub certificate: Option<String>,
+        /// Annotations provided by the signer when it signed the OCI object
+        #[serde(default)]
+        pub annotations: BTreeMap<String, String>,
+        /// true if the signature layer has a Rekor bundle
+        #[serde(default)]
+        pub has_rekor_bundle: bool,
+    }
+
+    impl SignatureInfo {
+        /// The issuer and the subject of a keyless signature, `None` when the
+        /// signature has been produced with a key
+        pub fn keyless_signer(&self) -> Option<KeylessInfo> {
+            match (&self.issuer, &self.subject) {
+                (Some(issuer), Some(subject)) => Some(KeylessInfo {
+                    issuer: issuer.clone(),
+                    subject: subject.clone(),
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    /// SignaturesResponse holds the metadata of all the Sigstore signatures
+    /// attached to an OCI object
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    pub struct SignaturesResponse {
+        /// digest of the OCI object the signatures refer to
+        pub digest: String,
+        /// the signatures found, empty when the object is not signed
+        #[serde(default)]
+        pub signatures: Vec<SignatureInfo>,
+    }
+
+    /// KeylessInfo holds information about a keyless signature
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+    pub struct KeylessInfo {
+        /// the issuer identifier
+        pub issuer: String,
+        /// contains the information of the user used to authenticate against the OIDC provider
+        pub subject: String,
+    }
+
+    /// KeylessPrefixInfo holds information about a keyless signature
+    #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+    #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+    pub struct KeylessPrefixInfo {
+        /// the issuer identifier
+        pub issuer: String,
+        /// Valid prefix of the Subject field in the signature used to authenticate
+        /// against the OIDC provider. It forms a valid URL on its own, and will get
+        /// sanitized by appending `/` to protect against typosquatting
+        pub url_prefix: String,
+    }
+
+    /// SigstoreVerificationInput is used for the v1/verify callback
+    #[derive(Serialize, Deserialize, Debug)]
+    #[deprecated(
+        note = "the v1/verify operation has been superseded by v2/verify, use verification::v2::SigstoreVerificationInput"
+    )]
+    pub enum SigstoreVerificationInput {
+        /// Require the verification of the manifest digest of an OCI object (be
+        /// it an image or anything else that can be stored into an OCI registry)
+        /// to be signed by Sigstore, using public keys mode
+        SigstorePubKeyVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// List of PEM encoded keys that must have been used to sign the OCI object
+            pub_keys: Vec<String>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+
+        /// Require the verification of the manifest digest of an OCI object to be
+        /// signed by Sigstore, using keyless mode
+        SigstoreKeylessVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// List of keyless signatures that must be found
+            keyless: Vec<KeylessInfo>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+    }
 }
 
-/// KeylessPrefixInfo holds information about a keyless signature
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-pub struct KeylessPrefixInfo {
-    /// the issuer identifier
-    pub issuer: String,
-    /// Valid prefix of the Subject field in the signature used to authenticate
-    /// against the OIDC provider. It forms a valid URL on its own, and will get
-    /// sanitized by appending `/` to protect against typosquatting
-    pub url_prefix: String,
+/// Payloads of the `v2/verify` operation
+pub mod v2 {
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    pub use super::v1::{KeylessInfo, KeylessPrefixInfo, VerificationResponse};
+
+    /// SigstoreVerificationInput is used for the v2/verify callback
+    /// From now on we use serde internally tagged.
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(tag = "type")]
+    pub enum SigstoreVerificationInput {
+        /// Require the verification of the manifest digest of an OCI object (be
+        /// it an image or anything else that can be stored into an OCI registry)
+        /// to be signed by Sigstore, using public keys mode
+        SigstorePubKeyVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// List of PEM encoded keys that must have been used to sign the OCI object
+            pub_keys: Vec<String>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+
+        /// Require the verification of the manifest digest of an OCI object to be
+        /// signed by Sigstore, using keyless mode
+        SigstoreKeylessVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// List of keyless signatures that must be found
+            keyless: Vec<KeylessInfo>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+
+        /// Require the verification of the manifest digest of an OCI object to be
+        /// signed by Sigstore using keyless mode, where the passed subject is a URL
+        /// prefix of the subject to match
+        SigstoreKeylessPrefixVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// List of keyless signatures that must be found
+            keyless_prefix: Vec<KeylessPrefixInfo>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+
+        /// Require the verification of the manifest digest of an OCI object to be
+        /// signed by Sigstore using keyless mode and performed in GitHub Actions
+        SigstoreGithubActionsVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// owner of the repository. E.g: octocat
+            owner: String,
+            /// Optional - Repo of the GH Action workflow that signed the artifact. E.g: example-repo
+            repo: Option<String>,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+
+        /// Require the verification of the manifest digest of an OCI object
+        /// using the user provided certificate
+        SigstoreCertificateVerify {
+            /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+            image: String,
+            /// PEM encoded certificate used to verify the signature
+            certificate: Vec<u8>,
+            /// Optional - the certificate chain that is used to verify the provided
+            /// certificate. When not specified, the certificate is assumed to be trusted
+            certificate_chain: Option<Vec<Vec<u8>>>,
+            /// Require the  signature layer to have a Rekor bundle.
+            /// Having a Rekor bundle allows further checks to be performed,
+            /// like ensuring the signature has been produced during the validity
+            /// time frame of the certificate.
+            ///
+            /// It is recommended to set this value to `true` to have a more secure
+            /// verification process.
+            require_rekor_bundle: bool,
+            /// Optional - Annotations that must have been provided by all signers when they signed the OCI artifact
+            annotations: Option<BTreeMap<String, String>>,
+        },
+    }
+
+    /// Every `v1/verify` request can be expressed as a `v2/verify` one
+    #[allow(deprecated)]
+    impl From<super::v1::SigstoreVerificationInput> for SigstoreVerificationInput {
+        fn from(input: super::v1::SigstoreVerificationInput) -> Self {
+            match input {
+                super::v1::SigstoreVerificationInput::SigstorePubKeyVerify {
+                    image,
+                    pub_keys,
+                    annotations,
+                } => SigstoreVerificationInput::SigstorePubKeyVerify {
+                    image,
+                    pub_keys,
+                    annotations,
+                },
+                super::v1::SigstoreVerificationInput::SigstoreKeylessVerify {
+                    image,
+                    keyless,
+                    annotations,
+                } => SigstoreVerificationInput::SigstoreKeylessVerify {
+                    image,
+                    keyless,
+                    annotations,
+                },
+            }
+        }
+    }
 }
 
+pub use v1::{
+    KeylessInfo, KeylessPrefixInfo, SignatureInfo, SignaturesResponse, TrustStoreStatus,
+    VerificationResponse,
+};
+
 /// verify sigstore signatures of an image using public keys
 /// # Arguments
 /// * `image` -  image to be verified
@@ -42,9 +260,9 @@ pub struct KeylessPrefixInfo {
 pub fn verify_pub_keys_image(
     image: &str,
     pub_keys: Vec<String>,
-    annotations: Option<HashMap<String, String>>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> Result<VerificationResponse> {
-    let input = SigstoreVerificationInputV2::SigstorePubKeyVerify {
+    let input = v2::SigstoreVerificationInput::SigstorePubKeyVerify {
         image: image.to_string(),
         pub_keys,
         annotations,
@@ -61,9 +279,9 @@ pub fn verify_pub_keys_image(
 pub fn verify_keyless_exact_match(
     image: &str,
     keyless: Vec<KeylessInfo>,
-    annotations: Option<HashMap<String, String>>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> Result<VerificationResponse> {
-    let input = SigstoreVerificationInputV2::SigstoreKeylessVerify {
+    let input = v2::SigstoreVerificationInput::SigstoreKeylessVerify {
         image: image.to_string(),
         keyless,
         annotations,
@@ -83,9 +301,9 @@ pub fn verify_keyless_exact_match(
 pub fn verify_keyless_prefix_match(
     image: &str,
     keyless_prefix: Vec<KeylessPrefixInfo>,
-    annotations: Option<HashMap<String, String>>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> Result<VerificationResponse> {
-    let input = SigstoreVerificationInputV2::SigstoreKeylessPrefixVerify {
+    let input = v2::SigstoreVerificationInput::SigstoreKeylessPrefixVerify {
         image: image.to_string(),
         keyless_prefix,
         annotations,
@@ -105,9 +323,9 @@ pub fn verify_keyless_github_actions(
     image: &str,
     owner: String,
     repo: Option<String>,
-    annotations: Option<HashMap<String, String>>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> Result<VerificationResponse> {
-    let input = SigstoreVerificationInputV2::SigstoreGithubActionsVerify {
+    let input = v2::SigstoreVerificationInput::SigstoreGithubActionsVerify {
         image: image.to_string(),
         owner,
         repo,
@@ -122,25 +340,25 @@ pub fn verify_keyless_github_actions(
 /// * `image` -  image to be verified
 /// * `certificate` - PEM encoded certificate used to verify the signature
 /// * `certificate_chain` - Optional. PEM encoded certificates used to verify `certificate`.
-///    When not specified, the certificate is assumed to be trusted
+///   When not specified, the certificate is assumed to be trusted
 /// * `require_rekor_bundle` - require the  signature layer to have a Rekor bundle.
-///    Having a Rekor bundle allows further checks to be performed,
-///    like ensuring the signature has been produced during the validity
-///    time frame of the certificate.
-///    It is recommended to set this value to `true` to have a more secure
-///    verification process.
+///   Having a Rekor bundle allows further checks to be performed,
+///   like ensuring the signature has been produced during the validity
+///   time frame of the certificate.
+///   It is recommended to set this value to `true` to have a more secure
+///   verification process.
 /// * `annotations` - annotations that must have been provided by all signers when they signed the OCI artifact
 pub fn verify_certificate(
     image: &str,
     certificate: String,
     certificate_chain: Option<Vec<String>>,
     require_rekor_bundle: bool,
-    annotations: Option<HashMap<String, String>>,
+    annotations: Option<BTreeMap<String, String>>,
 ) -> Result<VerificationResponse> {
     let chain: Option<Vec<Vec<u8>>> =
         certificate_chain.map(|c| c.iter().map(|cert| cert.as_bytes().to_vec()).collect());
 
-    let input = SigstoreVerificationInputV2::SigstoreCertificateVerify {
+    let input = v2::SigstoreVerificationInput::SigstoreCertificateVerify {
         image: image.to_string(),
         certificate: certificate.as_bytes().to_vec(),
         certificate_chain: chain,
@@ -150,10 +368,406 @@ pub fn verify_certificate(
 
     verify(input)
 }
-fn verify(input: SigstoreVerificationInputV2) -> Result<VerificationResponse> {
+
+/// Get the status of the Sigstore trust root used by the host to verify
+/// signatures. Policies can use this information to warn users, or to fail
+/// closed, when the trust root is stale.
+pub fn trust_store_status() -> Result<TrustStoreStatus> {
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_OCI,
+        ops::OCI_V1_SIGSTORE_TRUST_STORE_STATUS,
+        &[],
+    )
+    .map_err(|e| {
+        anyhow!(
+            "error invoking wapc oci.sigstore_trust_store_status: {:?}",
+            e
+        )
+    })?;
+
+    let response: TrustStoreStatus = serde_json::from_slice(&response_raw)?;
+
+    Ok(response)
+}
+
+/// Get the metadata of the Sigstore signatures attached to an image, without
+/// verifying them against a fixed set of signers.
+///
+/// This allows policies to implement custom trust logic (e.g. "at least two
+/// distinct signers from this list") on top of the data. The signatures are
+/// **not** cryptographically verified by this call: policies must use one of
+/// the `verify_*` functions before trusting the image.
+/// # Arguments
+/// * `image` -  image whose signatures have to be fetched
+pub fn get_signatures(image: &str) -> Result<SignaturesResponse> {
+    let msg = serde_json::to_vec(&image)
+        .map_err(|e| anyhow!("error serializing the signatures request: {}", e))?;
+    let response_raw = host::host_call(
+        ops::BINDING,
+        ops::NAMESPACE_OCI,
+        ops::OCI_V1_SIGSTORE_SIGNATURES,
+        &msg,
+    )
+    .map_err(|e| anyhow!("error invoking wapc oci.sigstore_signatures: {:?}", e))?;
+
+    let response: SignaturesResponse = serde_json::from_slice(&response_raw)?;
+
+    Ok(response)
+}
+
+/// SignerSpec describes one of the signers accepted by [`verify_threshold`]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerSpec {
+    /// Signature produced with the given PEM encoded public key
+    PubKey { key: String },
+    /// Keyless signature matching exactly the given issuer and subject
+    Keyless(KeylessInfo),
+    /// Keyless signature whose subject starts with the given URL prefix
+    KeylessPrefix(KeylessPrefixInfo),
+    /// Keyless signature produced by a GitHub Actions workflow
+    GithubActions { owner: String, repo: Option<String> },
+}
+
+/// The outcome of the verification of one of the signers
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SignerOutcome {
+    /// The signer that has been verified
+    pub signer: SignerSpec,
+    /// The digest of the image, when the signer has been verified
+    pub digest: Option<String>,
+    /// Optional - the reason why the verification failed
+    pub error: Option<String>,
+}
+
+impl SignerOutcome {
+    /// true when a valid signature of the signer has been found
+    pub fn is_trusted(&self) -> bool {
+        self.digest.is_some()
+    }
+}
+
+/// ThresholdVerification holds the outcome of [`verify_threshold`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ThresholdVerification {
+    /// Number of signers that must have signed the image
+    pub required: usize,
+    /// The outcome of each signer, in the order they have been provided
+    pub outcomes: Vec<SignerOutcome>,
+}
+
+impl ThresholdVerification {
+    /// Number of signers that have signed the image
+    pub fn trusted_count(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.is_trusted()).count()
+    }
+
+    /// true when at least `required` signers have signed the image
+    pub fn is_satisfied(&self) -> bool {
+        self.trusted_count() >= self.required
+    }
+
+    /// The digest of the verified image, `None` when the threshold has not
+    /// been reached
+    pub fn digest(&self) -> Option<&str> {
+        if !self.is_satisfied() {
+            return None;
+        }
+        self.outcomes.iter().find_map(|o| o.digest.as_deref())
+    }
+}
+
+/// Verify the image has been signed by at least `required` of the given
+/// signers (m-of-n).
+///
+/// Each signer is verified by the host with a dedicated call, the outcome of
+/// all of them is reported. An error is returned when `required` is zero or
+/// greater than the number of signers, or when the signers verified
+/// different digests of the image.
+/// # Arguments
+/// * `image` -  image to be verified
+/// * `signers` - the accepted signers
+/// * `required` - minimum number of signers that must have signed the image
+/// * `annotations` - annotations that must have been provided by all signers when they signed the OCI artifact
+pub fn verify_threshold(
+    image: &str,
+    signers: &[SignerSpec],
+    required: usize,
+    annotations: Option<BTreeMap<String, String>>,
+) -> Result<ThresholdVerification> {
+    if required == 0 || required > signers.len() {
+        return Err(anyhow!(
+            "invalid threshold: {} of {} signers",
+            required,
+            signers.len()
+        ));
+    }
+
+    let outcomes: Vec<SignerOutcome> = signers
+        .iter()
+        .map(|signer| {
+            let annotations = annotations.clone();
+            let result = match signer.clone() {
+                SignerSpec::PubKey { key } => verify_pub_keys_image(image, vec![key], annotations),
+                SignerSpec::Keyless(keyless) => {
+                    verify_keyless_exact_match(image, vec![keyless], annotations)
+                }
+                SignerSpec::KeylessPrefix(prefix) => {
+                    verify_keyless_prefix_match(image, vec![prefix], annotations)
+                }
+                SignerSpec::GithubActions { owner, repo } => {
+                    verify_keyless_github_actions(image, owner, repo, annotations)
+                }
+            };
+            let (digest, error) = match result {
+                Ok(response) if response.is_trusted => (Some(response.digest), None),
+                Ok(_) => (None, Some("image is not trusted".to_string())),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            SignerOutcome {
+                signer: signer.clone(),
+                digest,
+                error,
+            }
+        })
+        .collect();
+
+    let mut digests = outcomes.iter().filter_map(|o| o.digest.as_deref());
+    if let Some(first) = digests.next() {
+        if let Some(other) = digests.find(|digest| *digest != first) {
+            return Err(anyhow!(
+                "signers verified different digests of '{}': {} and {}",
+                image,
+                first,
+                other
+            ));
+        }
+    }
+
+    Ok(ThresholdVerification { required, outcomes })
+}
+
+/// Returns `true` when `image_ref` is pinned to the digest of the verified
+/// image (e.g. `ghcr.io/kubewarden/policy-server:v1.0.0@sha256:...`)
+/// # Arguments
+/// * `image_ref` -  image reference, as found inside of the Pod specification
+/// * `verification_response` - the outcome of one of the `verify_*` functions
+pub fn digest_matches(image_ref: &str, verification_response: &VerificationResponse) -> bool {
+    image_ref
+        .rsplit_once('@')
+        .is_some_and(|(_, digest)| digest == verification_response.digest)
+}
+
+/// Returns `image_ref` pinned to the digest of the verified image. A digest
+/// already found inside of `image_ref` is replaced, while the tag is kept
+/// # Arguments
+/// * `image_ref` -  image reference, as found inside of the Pod specification
+/// * `verification_response` - the outcome of one of the `verify_*` functions
+pub fn pin_digest(image_ref: &str, verification_response: &VerificationResponse) -> String {
+    let name = image_ref
+        .rsplit_once('@')
+        .map_or(image_ref, |(name, _)| name);
+    format!("{}@{}", name, verification_response.digest)
+}
+
+/// Prefix of the audit annotations recorded by [`pin_image_field`]. The
+/// prefix is followed by the name of the container, e.g.
+/// `original-image.nginx`, or by the location of the image when it has no
+/// container name, e.g. `original-image.spec.containers.0.image`
+pub const ORIGINAL_IMAGE_ANNOTATION_PREFIX: &str = "original-image.";
+
+/// Maximum length of the name of an annotation key
+const ANNOTATION_NAME_MAX_LENGTH: usize = 63;
+
+/// The key of the audit annotation recording the original image found at
+/// `pointer`. The characters not allowed inside of an annotation key are
+/// replaced by `-`. Keys that would be too long are truncated, and end with
+/// a hash of the location to keep them unique
+fn original_image_annotation_key(value: &serde_json::Value, pointer: &str) -> String {
+    let container_name = pointer
+        .rsplit_once('/')
+        .and_then(|(parent, _)| value.pointer(parent))
+        .and_then(|container| container.get("name"))
+        .and_then(serde_json::Value::as_str);
+    let location = container_name.map_or_else(
+        || {
+            pointer
+                .trim_start_matches('/')
+                .replace('/', ".")
+                .replace("~1", "/")
+                .replace("~0", "~")
+        },
+        str::to_string,
+    );
+    let sanitized: String = location
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    let key = format!("{ORIGINAL_IMAGE_ANNOTATION_PREFIX}{sanitized}");
+    if key.len() <= ANNOTATION_NAME_MAX_LENGTH && !key.ends_with(['-', '_', '.']) {
+        return key;
+    }
+    // FNV-1a, stable across builds of the SDK
+    let hash = pointer.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    let suffix = format!("-{hash:08x}");
+    let kept = ANNOTATION_NAME_MAX_LENGTH - suffix.len();
+    format!("{}{}", &key[..kept.min(key.len())], suffix)
+}
+
+/// Rewrite the image reference found at the JSON `pointer` of `value` (e.g.
+/// `/spec/containers/0/image`) to include the digest of the verified image.
+///
+/// The original reference is recorded as an audit annotation, see
+/// [`record_audit_annotation`](crate::response::record_audit_annotation),
+/// which is attached to the response created by
+/// [`mutate_request`](crate::mutate_request).
+///
+/// Returns `true` when the image has been changed, `false` when it was
+/// already pinned to the verified digest. An error is returned when no
+/// string is found at `pointer`.
+/// # Arguments
+/// * `value` - the object to update
+/// * `pointer` - the location of the image reference that has been verified
+/// * `verification_response` - the outcome of one of the `verify_*` functions
+pub fn pin_image_field(
+    value: &mut serde_json::Value,
+    pointer: &str,
+    verification_response: &VerificationResponse,
+) -> Result<bool> {
+    let image = value
+        .pointer_mut(pointer)
+        .ok_or_else(|| anyhow!("no image found at '{}'", pointer))?;
+    let original = image
+        .as_str()
+        .ok_or_else(|| anyhow!("the value found at '{}' is not a string", pointer))?
+        .to_string();
+    if digest_matches(&original, verification_response) {
+        return Ok(false);
+    }
+
+    *image = serde_json::Value::String(pin_digest(&original, verification_response));
+    let key = original_image_annotation_key(value, pointer);
+    crate::response::record_audit_annotation(&key, &original);
+
+    Ok(true)
+}
+
+#[cfg(feature = "cluster-context")]
+/// Pin all the containers of the Pod using `image_ref` to the digest of the
+/// verified image. Init and ephemeral containers are updated too.
+///
+/// Returns `true` when at least one container has been changed, meaning the
+/// policy has to mutate the request.
+/// # Arguments
+/// * `pod_spec` - the Pod specification to update
+/// * `image_ref` -  the image reference that has been verified
+/// * `verification_response` - the outcome of one of the `verify_*` functions
+pub fn pin_verified_digest(
+    pod_spec: &mut k8s_openapi::api::core::v1::PodSpec,
+    image_ref: &str,
+    verification_response: &VerificationResponse,
+) -> bool {
+    let mut changed = false;
+    let mut pin = |image: &mut Option<String>| {
+        if let Some(image) = image {
+            if image == image_ref && !digest_matches(image, verification_response) {
+                *image = pin_digest(image, verification_response);
+                changed = true;
+            }
+        }
+    };
+
+    pod_spec
+        .containers
+        .iter_mut()
+        .for_each(|container| pin(&mut container.image));
+    pod_spec
+        .init_containers
+        .iter_mut()
+        .flatten()
+        .for_each(|container| pin(&mut container.image));
+    pod_spec
+        .ephemeral_containers
+        .iter_mut()
+        .flatten()
+        .for_each(|container| pin(&mut container.image));
+
+    changed
+}
+
+/// Annotation holding the digests of the images verified by the policy
+pub const VERIFIED_DIGEST_ANNOTATION: &str = "kubewarden.io/verified-digest";
+/// Annotation holding the time, in RFC 3339 format, the images were verified
+pub const VERIFIED_AT_ANNOTATION: &str = "kubewarden.io/verified-at";
+
+/// Stamp the provenance annotations onto the metadata of `object`, after its
+/// images have been successfully verified. The annotations are
+/// [`VERIFIED_DIGEST_ANNOTATION`], holding the digests of the verified images
+/// sorted and separated by commas, and [`VERIFIED_AT_ANNOTATION`], holding
+/// the time given by `clock`.
+///
+/// Use the [`HostClock`](crate::host_capabilities::time::HostClock) to read
+/// the time from the host. The annotated object can then be returned to the
+/// host via [`mutate_request`](crate::mutate_request).
+///
+/// An error is returned when one of the images is not trusted, or when
+/// `verifications` is empty.
+/// # Arguments
+/// * `object` - the object being admitted, e.g. a Deployment
+/// * `verifications` - the outcomes of the `verify_*` functions
+/// * `clock` - the source of the current time
+pub fn stamp_provenance<C: crate::host_capabilities::time::Clock>(
+    object: &mut serde_json::Value,
+    verifications: &[VerificationResponse],
+    clock: &C,
+) -> Result<()> {
+    if verifications.is_empty() {
+        return Err(anyhow!("no verified image to stamp"));
+    }
+    if let Some(untrusted) = verifications.iter().find(|v| !v.is_trusted) {
+        return Err(anyhow!(
+            "image with digest '{}' is not trusted",
+            untrusted.digest
+        ));
+    }
+
+    let mut digests: Vec<&str> = verifications.iter().map(|v| v.digest.as_str()).collect();
+    digests.sort_unstable();
+    digests.dedup();
+    let verified_at = clock.now()?;
+
+    let annotation_pointer = |key: &str| {
+        format!(
+            "/metadata/annotations/{}",
+            key.replace('~', "~0").replace('/', "~1")
+        )
+    };
+    crate::mutation::set(
+        object,
+        &annotation_pointer(VERIFIED_DIGEST_ANNOTATION),
+        serde_json::Value::String(digests.join(",")),
+    )?;
+    crate::mutation::set(
+        object,
+        &annotation_pointer(VERIFIED_AT_ANNOTATION),
+        serde_json::Value::String(verified_at),
+    )
+}
+
+fn verify(input: v2::SigstoreVerificationInput) -> Result<VerificationResponse> {
     let msg = serde_json::to_vec(&input)
         .map_err(|e| anyhow!("error serializing the validation request: {}", e))?;
-    let response_raw = wapc_guest::host_call("kubewarden", "oci", "v2/verify", &msg)
+    let response_raw = host::host_call(ops::BINDING, ops::NAMESPACE_OCI, ops::OCI_V2_VERIFY, &msg)
         .map_err(|e| anyhow!("{}", e))?;
 
     let response: VerificationResponse = serde_json::from_slice(&response_raw)?;
@@ -162,7 +776,7 @@ fn verify(input: SigstoreVerificationInputV2) -> Result<VerificationResponse> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use mockall::automock;
     use serial_test::serial;
@@ -178,6 +792,22 @@ mod tests {
         }
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn convert_v1_verification_input() {
+        let input: v2::SigstoreVerificationInput =
+            v1::SigstoreVerificationInput::SigstorePubKeyVerify {
+                image: "image".to_string(),
+                pub_keys: vec!["key".to_string()],
+                annotations: None,
+            }
+            .into();
+
+        let input_json = serde_json::to_value(input).unwrap();
+        assert_eq!(input_json["type"], "SigstorePubKeyVerify");
+        assert_eq!(input_json["pub_keys"], serde_json::json!(["key"]));
+    }
+
     // these tests need to run sequentially because mockall creates a global context to create the mocks
     #[serial]
     #[test]
@@ -356,4 +986,340 @@ mod tests {
 
         assert!(res.is_err())
     }
+
+    #[serial]
+    #[test]
+    fn trust_store_status_fresh() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|binding: &str, ns: &str, op: &str, _msg: &[u8]| {
+                binding == "kubewarden" && ns == "oci" && op == "v1/sigstore_trust_store_status"
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&TrustStoreStatus {
+                    fresh: true,
+                    last_refreshed: Some("2024-06-17T17:55:55Z".to_string()),
+                    expires: Some("2024-06-24T17:55:55Z".to_string()),
+                    root_version: Some(9),
+                })
+                .unwrap())
+            });
+        let res = trust_store_status().unwrap();
+
+        assert!(res.fresh);
+        assert_eq!(res.root_version, Some(9));
+    }
+
+    #[serial]
+    #[test]
+    fn trust_store_status_host_error() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .returning(|_, _, _, _| Err(Box::new(core::fmt::Error {})));
+        let res = trust_store_status();
+
+        assert!(res.is_err())
+    }
+
+    #[serial]
+    #[test]
+    fn annotations_are_serialized_in_order() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, _, _, msg| {
+                let msg = String::from_utf8(msg.to_vec()).unwrap();
+                msg.contains(r#""annotations":{"a":"1","b":"2","c":"3"}"#)
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&VerificationResponse {
+                    is_trusted: true,
+                    digest: "digest".to_string(),
+                })
+                .unwrap())
+            });
+
+        let annotations: BTreeMap<String, String> = [("c", "3"), ("a", "1"), ("b", "2")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let res = verify_pub_keys_image("image", vec!["key".to_string()], Some(annotations));
+
+        assert!(res.unwrap().is_trusted)
+    }
+
+    fn verified(digest: &str) -> VerificationResponse {
+        VerificationResponse {
+            is_trusted: true,
+            digest: digest.to_string(),
+        }
+    }
+
+    #[test]
+    fn digest_matching() {
+        let response = verified("sha256:123");
+
+        assert!(digest_matches("busybox@sha256:123", &response));
+        assert!(digest_matches(
+            "registry:5000/busybox:1.0@sha256:123",
+            &response
+        ));
+        assert!(!digest_matches("busybox@sha256:456", &response));
+        assert!(!digest_matches("busybox:1.0", &response));
+
+        assert_eq!(
+            pin_digest("registry:5000/busybox:1.0", &response),
+            "registry:5000/busybox:1.0@sha256:123"
+        );
+        assert_eq!(
+            pin_digest("busybox@sha256:456", &response),
+            "busybox@sha256:123"
+        );
+    }
+
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn pin_verified_digest_in_pod_spec() {
+        use k8s_openapi::api::core::v1::{Container, PodSpec};
+
+        let container = |image: &str| Container {
+            image: Some(image.to_string()),
+            ..Default::default()
+        };
+        let mut pod_spec = PodSpec {
+            containers: vec![container("busybox:1.0"), container("nginx")],
+            init_containers: Some(vec![container("busybox:1.0")]),
+            ..Default::default()
+        };
+        let response = verified("sha256:123");
+
+        assert!(pin_verified_digest(&mut pod_spec, "busybox:1.0", &response));
+        assert_eq!(
+            pod_spec.containers[0].image.as_deref(),
+            Some("busybox:1.0@sha256:123")
+        );
+        assert_eq!(pod_spec.containers[1].image.as_deref(), Some("nginx"));
+        assert_eq!(
+            pod_spec.init_containers.as_ref().unwrap()[0]
+                .image
+                .as_deref(),
+            Some("busybox:1.0@sha256:123")
+        );
+
+        // already pinned, nothing to mutate
+        assert!(!pin_verified_digest(
+            &mut pod_spec,
+            "busybox:1.0@sha256:123",
+            &response
+        ));
+    }
+
+    #[serial]
+    #[test]
+    fn get_signatures_metadata() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect()
+            .times(1)
+            .withf(|_, ns, op, msg| {
+                ns == "oci" && op == "v1/sigstore_signatures" && msg == br#""busybox:1.0""#
+            })
+            .returning(|_, _, _, _| {
+                Ok(serde_json::to_vec(&serde_json::json!({
+                    "digest": "sha256:123",
+                    "signatures": [
+                        {
+                            "issuer": "https://token.actions.githubusercontent.com",
+                            "subject": "https://github.com/kubewarden/policy",
+                            "annotations": {"env": "prod"},
+                            "has_rekor_bundle": true
+                        },
+                        {"certificate": null}
+                    ]
+                }))
+                .unwrap())
+            });
+
+        let res = get_signatures("busybox:1.0").unwrap();
+        assert_eq!(res.digest, "sha256:123");
+        assert_eq!(res.signatures.len(), 2);
+        let signer = res.signatures[0].keyless_signer().unwrap();
+        assert_eq!(signer.subject, "https://github.com/kubewarden/policy");
+        assert!(res.signatures[0].has_rekor_bundle);
+        assert_eq!(res.signatures[0].annotations["env"], "prod");
+        assert!(res.signatures[1].keyless_signer().is_none());
+        assert!(!res.signatures[1].has_rekor_bundle);
+    }
+
+    fn threshold_signers() -> Vec<SignerSpec> {
+        vec![
+            SignerSpec::PubKey {
+                key: "alice".to_string(),
+            },
+            SignerSpec::PubKey {
+                key: "bob".to_string(),
+            },
+            SignerSpec::GithubActions {
+                owner: "kubewarden".to_string(),
+                repo: None,
+            },
+        ]
+    }
+
+    /// Trust the signatures of alice and of the GitHub workflow
+    fn mock_threshold_host(digest_of_workflow: &'static str) -> mock_wapc::__host_call::Context {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(3).returning(move |_, _, _, msg| {
+            let input: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            if input["pub_keys"] == serde_json::json!(["bob"]) {
+                return Err(Box::new(core::fmt::Error {}));
+            }
+            let digest = if input["type"] == "SigstoreGithubActionsVerify" {
+                digest_of_workflow
+            } else {
+                "sha256:123"
+            };
+            Ok(serde_json::to_vec(&verified(digest)).unwrap())
+        });
+        ctx
+    }
+
+    #[serial]
+    #[test]
+    fn threshold_verification() {
+        let _ctx = mock_threshold_host("sha256:123");
+        let res = verify_threshold("busybox:1.0", &threshold_signers(), 2, None).unwrap();
+        assert!(res.is_satisfied());
+        assert_eq!(res.trusted_count(), 2);
+        assert_eq!(res.digest(), Some("sha256:123"));
+        assert!(!res.outcomes[1].is_trusted());
+        assert!(res.outcomes[1].error.is_some());
+
+        let res = ThresholdVerification { required: 3, ..res };
+        assert!(!res.is_satisfied());
+        assert_eq!(res.digest(), None);
+    }
+
+    #[serial]
+    #[test]
+    fn threshold_with_different_digests() {
+        let _ctx = mock_threshold_host("sha256:456");
+        let err = verify_threshold("busybox:1.0", &threshold_signers(), 2, None).unwrap_err();
+        assert!(err.to_string().contains("different digests"));
+    }
+
+    #[test]
+    fn invalid_threshold() {
+        assert!(verify_threshold("busybox:1.0", &threshold_signers(), 0, None).is_err());
+        assert!(verify_threshold("busybox:1.0", &threshold_signers(), 4, None).is_err());
+    }
+
+    #[test]
+    fn signer_spec_serialization() {
+        let spec: SignerSpec = serde_json::from_value(serde_json::json!({
+            "type": "keyless_prefix",
+            "issuer": "https://token.actions.githubusercontent.com",
+            "url_prefix": "https://github.com/kubewarden"
+        }))
+        .unwrap();
+        assert!(matches!(spec, SignerSpec::KeylessPrefix(_)));
+    }
+
+    #[test]
+    fn pin_image_at_pointer() {
+        let mut pod = serde_json::json!({"spec": {"containers": [{"image": "busybox:1.0"}]}});
+        let response = verified("sha256:123");
+        crate::response::take_recorded_audit_annotations();
+
+        assert!(pin_image_field(&mut pod, "/spec/containers/0/image", &response).unwrap());
+        assert_eq!(
+            pod["spec"]["containers"][0]["image"],
+            "busybox:1.0@sha256:123"
+        );
+        let annotations = crate::response::take_recorded_audit_annotations();
+        assert_eq!(
+            annotations["original-image.spec.containers.0.image"],
+            "busybox:1.0"
+        );
+
+        assert!(!pin_image_field(&mut pod, "/spec/containers/0/image", &response).unwrap());
+        assert!(crate::response::take_recorded_audit_annotations().is_empty());
+        assert!(pin_image_field(&mut pod, "/spec/containers/1/image", &response).is_err());
+        assert!(pin_image_field(&mut pod, "/spec", &response).is_err());
+    }
+
+    #[test]
+    fn pin_image_annotation_keys_are_bounded() {
+        let response = verified("sha256:123");
+        let pointer = "/spec/jobTemplate/spec/template/spec/containers/0/image";
+        let mut cron_job = serde_json::json!({"spec": {"jobTemplate": {"spec": {"template":
+            {"spec": {"containers": [{"image": "busybox:1.0"}]}}}}}});
+        crate::response::take_recorded_audit_annotations();
+
+        assert!(pin_image_field(&mut cron_job, pointer, &response).unwrap());
+        let annotations = crate::response::take_recorded_audit_annotations();
+        let key = annotations.keys().next().unwrap();
+        assert!(key.len() <= 63, "{key} is too long");
+        assert!(key.starts_with("original-image.spec.jobTemplate.spec."));
+        assert_eq!(annotations[key], "busybox:1.0");
+        // the key is stable
+        assert_eq!(key, &original_image_annotation_key(&cron_job, pointer));
+
+        // the name of the container is used when available
+        cron_job["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0] =
+            serde_json::json!({"name": "backup", "image": "busybox:1.0"});
+        assert!(pin_image_field(&mut cron_job, pointer, &response).unwrap());
+        let annotations = crate::response::take_recorded_audit_annotations();
+        assert_eq!(annotations["original-image.backup"], "busybox:1.0");
+
+        // escaped characters never end up inside of the key
+        let mut object =
+            serde_json::json!({"metadata": {"annotations": {"example.com/image": "busybox"}}});
+        assert!(pin_image_field(
+            &mut object,
+            "/metadata/annotations/example.com~1image",
+            &response
+        )
+        .unwrap());
+        let annotations = crate::response::take_recorded_audit_annotations();
+        assert!(annotations.contains_key("original-image.metadata.annotations.example.com-image"));
+    }
+
+    #[test]
+    fn stamp_provenance_annotations() {
+        use crate::host_capabilities::time::FixedClock;
+
+        let clock = FixedClock("2024-01-01T10:00:00Z".to_string());
+        let mut deployment = serde_json::json!({
+            "metadata": {"name": "web", "annotations": {"team": "blue"}},
+            "spec": {}
+        });
+
+        stamp_provenance(
+            &mut deployment,
+            &[
+                verified("sha256:2"),
+                verified("sha256:1"),
+                verified("sha256:2"),
+            ],
+            &clock,
+        )
+        .unwrap();
+        assert_eq!(
+            deployment["metadata"]["annotations"],
+            serde_json::json!({
+                "team": "blue",
+                "kubewarden.io/verified-digest": "sha256:1,sha256:2",
+                "kubewarden.io/verified-at": "2024-01-01T10:00:00Z"
+            })
+        );
+
+        let untrusted = VerificationResponse {
+            is_trusted: false,
+            digest: "sha256:3".to_string(),
+        };
+        assert!(stamp_provenance(&mut deployment, &[untrusted], &clock).is_err());
+        assert!(stamp_provenance(&mut deployment, &[], &clock).is_err());
+    }
 }
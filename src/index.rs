@@ -0,0 +1,278 @@
+//! Indexed views over lists of Kubernetes resources.
+//!
+//! Policies cross-referencing many resources (e.g. "every Service must have
+//! a matching NetworkPolicy") can build a [`ResourceIndex`] out of the
+//! results of the [`kubernetes`](crate::host_capabilities::kubernetes) host
+//! capability, and look resources up by name, namespace, label or owner in
+//! constant time.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::index::ResourceIndex;
+//! use k8s_openapi::api::core::v1::Service;
+//! use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+//!
+//! let services = vec![Service {
+//!     metadata: ObjectMeta {
+//!         name: Some("web".to_string()),
+//!         namespace: Some("team-a".to_string()),
+//!         labels: Some([("app".to_string(), "web".to_string())].into()),
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! }];
+//! let index = ResourceIndex::new(services);
+//!
+//! assert!(index.get(Some("team-a"), "web").is_some());
+//! assert_eq!(index.with_label("app", "web").count(), 1);
+//! assert_eq!(index.in_namespace("team-b").count(), 0);
+//! ```
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::Metadata;
+use std::collections::HashMap;
+
+/// A list of Kubernetes resources, indexed by name, namespace, label and
+/// owner UID
+#[derive(Debug, Clone)]
+pub struct ResourceIndex<T> {
+    items: Vec<T>,
+    // namespace ("" for cluster level resources) -> name -> position
+    by_name: HashMap<String, HashMap<String, usize>>,
+    // label key -> label value -> positions
+    by_label: HashMap<String, HashMap<String, Vec<usize>>>,
+    // owner UID -> positions
+    by_owner: HashMap<String, Vec<usize>>,
+}
+
+impl<T> Default for ResourceIndex<T> {
+    fn default() -> Self {
+        ResourceIndex {
+            items: Vec::new(),
+            by_name: HashMap::new(),
+            by_label: HashMap::new(),
+            by_owner: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ResourceIndex<T>
+where
+    T: Metadata<Ty = ObjectMeta>,
+{
+    /// Index the given resources
+    pub fn new(items: Vec<T>) -> Self {
+        let mut index = ResourceIndex::default();
+        for (position, item) in items.iter().enumerate() {
+            let metadata = item.metadata();
+            index
+                .by_name
+                .entry(metadata.namespace.clone().unwrap_or_default())
+                .or_default()
+                .insert(metadata.name.clone().unwrap_or_default(), position);
+            for (key, value) in metadata.labels.iter().flatten() {
+                index
+                    .by_label
+                    .entry(key.clone())
+                    .or_default()
+                    .entry(value.clone())
+                    .or_default()
+                    .push(position);
+            }
+            for owner in metadata.owner_references.iter().flatten() {
+                index
+                    .by_owner
+                    .entry(owner.uid.clone())
+                    .or_default()
+                    .push(position);
+            }
+        }
+        index.items = items;
+        index
+    }
+
+    /// Find the resource with the given name. Cluster level resources must
+    /// set `namespace` to `None`
+    pub fn get(&self, namespace: Option<&str>, name: &str) -> Option<&T> {
+        self.by_name
+            .get(namespace.unwrap_or_default())
+            .and_then(|names| names.get(name))
+            .map(|position| &self.items[*position])
+    }
+
+    /// Returns `true` when the resource with the given name exists, see [`Self::get`]
+    pub fn contains(&self, namespace: Option<&str>, name: &str) -> bool {
+        self.get(namespace, name).is_some()
+    }
+
+    /// All the resources defined inside of `namespace`
+    pub fn in_namespace<'a>(&'a self, namespace: &str) -> impl Iterator<Item = &'a T> + 'a {
+        self.by_name
+            .get(namespace)
+            .into_iter()
+            .flat_map(|names| names.values())
+            .map(|position| &self.items[*position])
+    }
+
+    /// All the resources having the `key` label set to `value`
+    pub fn with_label<'a>(&'a self, key: &str, value: &str) -> impl Iterator<Item = &'a T> + 'a {
+        self.by_label
+            .get(key)
+            .and_then(|values| values.get(value))
+            .into_iter()
+            .flatten()
+            .map(|position| &self.items[*position])
+    }
+
+    /// All the resources having the `key` label, regardless of its value
+    pub fn with_label_key<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a T> + 'a {
+        self.by_label
+            .get(key)
+            .into_iter()
+            .flat_map(|values| values.values().flatten())
+            .map(|position| &self.items[*position])
+    }
+
+    /// All the resources owned by the resource with the given UID
+    pub fn owned_by<'a>(&'a self, uid: &str) -> impl Iterator<Item = &'a T> + 'a {
+        self.by_owner
+            .get(uid)
+            .into_iter()
+            .flatten()
+            .map(|position| &self.items[*position])
+    }
+}
+
+impl<T> ResourceIndex<T> {
+    /// All the indexed resources, in their original order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    /// The number of indexed resources
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` when no resource is indexed
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Consume the index, returning the indexed resources
+    pub fn into_inner(self) -> Vec<T> {
+        self.items
+    }
+}
+
+impl<T> From<Vec<T>> for ResourceIndex<T>
+where
+    T: Metadata<Ty = ObjectMeta>,
+{
+    fn from(items: Vec<T>) -> Self {
+        ResourceIndex::new(items)
+    }
+}
+
+impl<T> From<k8s_openapi::List<T>> for ResourceIndex<T>
+where
+    T: k8s_openapi::ListableResource + Metadata<Ty = ObjectMeta>,
+{
+    fn from(list: k8s_openapi::List<T>) -> Self {
+        ResourceIndex::new(list.items)
+    }
+}
+
+impl<T> FromIterator<T> for ResourceIndex<T>
+where
+    T: Metadata<Ty = ObjectMeta>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        ResourceIndex::new(iter.into_iter().collect())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a ResourceIndex<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{Namespace, Pod};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+
+    fn pod(namespace: &str, name: &str, app: &str, owner: Option<&str>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                labels: Some([("app".to_string(), app.to_string())].into()),
+                owner_references: owner.map(|uid| {
+                    vec![OwnerReference {
+                        uid: uid.to_string(),
+                        ..Default::default()
+                    }]
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn names<'a>(pods: impl Iterator<Item = &'a Pod>) -> Vec<&'a str> {
+        let mut names: Vec<&str> = pods
+            .map(|pod| pod.metadata.name.as_deref().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn lookups() {
+        let index: ResourceIndex<Pod> = vec![
+            pod("team-a", "web-1", "web", Some("rs-1")),
+            pod("team-a", "web-2", "web", Some("rs-1")),
+            pod("team-a", "db", "db", None),
+            pod("team-b", "web-1", "web", Some("rs-2")),
+        ]
+        .into();
+
+        assert_eq!(index.len(), 4);
+        assert!(index.contains(Some("team-b"), "web-1"));
+        assert!(!index.contains(Some("team-b"), "db"));
+        assert!(!index.contains(None, "db"));
+        assert_eq!(
+            names(index.in_namespace("team-a")),
+            vec!["db", "web-1", "web-2"]
+        );
+        assert_eq!(index.with_label("app", "web").count(), 3);
+        assert_eq!(index.with_label_key("app").count(), 4);
+        assert_eq!(index.with_label("app", "cache").count(), 0);
+        assert_eq!(names(index.owned_by("rs-1")), vec!["web-1", "web-2"]);
+        assert_eq!(index.owned_by("unknown").count(), 0);
+    }
+
+    #[test]
+    fn cluster_level_resources() {
+        let index: ResourceIndex<Namespace> = ["default", "kube-system"]
+            .into_iter()
+            .map(|name| Namespace {
+                metadata: ObjectMeta {
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        assert!(index.get(None, "kube-system").is_some());
+        assert_eq!(index.iter().count(), 2);
+        assert_eq!(index.into_inner().len(), 2);
+    }
+}
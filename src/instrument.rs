@@ -0,0 +1,179 @@
+//! Lightweight self-instrumentation helpers.
+//!
+//! An [`Instrument`] guard measures the time elapsed between its creation and
+//! the moment it goes out of scope. The measurement is recorded, and optionally
+//! emitted through a [`slog::Logger`] (for example one backed by the
+//! [`KubewardenDrain`](crate::logging::KubewardenDrain)).
+//!
+//! The recorded timings can then be attached to the response as audit
+//! annotations, which helps policy authors find slow host calls in production.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::{instrument, reject_request};
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     {
+//!         let _t = instrument!("verify-images");
+//!         // perform the expensive host calls...
+//!     }
+//!
+//!     reject_request(
+//!         Some("not allowed".to_string()),
+//!         None,
+//!         Some(instrument::take_audit_annotations()),
+//!         None,
+//!     )
+//! }
+//! ```
+use slog::debug;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Prefix used by the audit annotation keys produced by [`take_audit_annotations`]
+pub const AUDIT_ANNOTATION_PREFIX: &str = "timing.";
+
+thread_local! {
+    static TIMINGS: RefCell<Vec<Timing>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The time spent evaluating a named block of code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    /// Name given to the instrumented block
+    pub name: String,
+    /// Time spent inside of the block
+    pub elapsed: Duration,
+}
+
+/// A guard that measures the time elapsed since its creation. The measurement
+/// is recorded when the guard is dropped.
+pub struct Instrument {
+    name: String,
+    start: Instant,
+    logger: Option<slog::Logger>,
+}
+
+impl Instrument {
+    /// Start measuring a block of code identified by `name`
+    pub fn new(name: &str) -> Self {
+        Instrument {
+            name: name.to_string(),
+            start: Instant::now(),
+            logger: None,
+        }
+    }
+
+    /// Start measuring a block of code identified by `name`. The measurement
+    /// is also emitted as a debug event through the given `logger`
+    pub fn with_logger(name: &str, logger: &slog::Logger) -> Self {
+        Instrument {
+            name: name.to_string(),
+            start: Instant::now(),
+            logger: Some(logger.clone()),
+        }
+    }
+
+    /// Time elapsed since the creation of the guard
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+impl Drop for Instrument {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        if let Some(logger) = &self.logger {
+            debug!(logger, "instrumented block completed";
+                "block" => self.name.as_str(),
+                "elapsed_ms" => elapsed.as_secs_f64() * 1000.0);
+        }
+        TIMINGS.with(|timings| {
+            timings.borrow_mut().push(Timing {
+                name: std::mem::take(&mut self.name),
+                elapsed,
+            })
+        });
+    }
+}
+
+/// Return all the timings recorded so far, clearing the internal registry
+pub fn take_timings() -> Vec<Timing> {
+    TIMINGS.with(|timings| std::mem::take(&mut *timings.borrow_mut()))
+}
+
+/// Return all the timings recorded so far as audit annotations, clearing the
+/// internal registry. Each block is stored under the `timing.<name>` key,
+/// the value is the elapsed time expressed in milliseconds (e.g. `12.345ms`).
+/// When the same block is measured multiple times, the timings are summed up.
+pub fn take_audit_annotations() -> HashMap<String, String> {
+    let mut totals: HashMap<String, Duration> = HashMap::new();
+    for timing in take_timings() {
+        *totals.entry(timing.name).or_default() += timing.elapsed;
+    }
+
+    totals
+        .into_iter()
+        .map(|(name, elapsed)| {
+            (
+                format!("{AUDIT_ANNOTATION_PREFIX}{name}"),
+                format!("{:.3}ms", elapsed.as_secs_f64() * 1000.0),
+            )
+        })
+        .collect()
+}
+
+/// Create an [`Instrument`] guard measuring the current block of code
+///
+/// ```rust
+/// use kubewarden_policy_sdk::instrument;
+///
+/// let _t = instrument!("verify-images");
+/// ```
+///
+/// A [`slog::Logger`] can be provided as second argument to emit the
+/// measurement as a log event too.
+#[macro_export]
+macro_rules! instrument {
+    ($name:expr) => {
+        $crate::instrument::Instrument::new($name)
+    };
+    ($name:expr, $logger:expr) => {
+        $crate::instrument::Instrument::with_logger($name, &$logger)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_timing_on_drop() {
+        take_timings();
+        {
+            let _t = instrument!("block");
+        }
+        let timings = take_timings();
+        assert_eq!(timings.len(), 1);
+        assert_eq!(timings[0].name, "block");
+        assert!(take_timings().is_empty());
+    }
+
+    #[test]
+    fn timings_as_audit_annotations() {
+        take_timings();
+        {
+            let _a = instrument!("a");
+            let _b = instrument!("b");
+        }
+        {
+            let _a = instrument!("a");
+        }
+        let annotations = take_audit_annotations();
+        assert_eq!(annotations.len(), 2);
+        assert!(annotations["timing.a"].ends_with("ms"));
+        assert!(annotations.contains_key("timing.b"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Compact string tables for the hot lookups of large allow-lists.
+//!
+//! Policies evaluated at high rates can be configured with thousands of
+//! registries or namespaces. A [`StringSet`] stores them sorted and without
+//! duplicates, and answers lookups with a binary search instead of scanning a
+//! `Vec<String>`. The strings are interned: the same value used by many sets
+//! is allocated only once. The pool of interned strings is bounded: once it
+//! holds [`POOL_PRUNE_THRESHOLD`] strings, the ones that are no longer used
+//! by any set are dropped.
+//!
+//! Binary searches pay off only for large sets: a `HashSet` remains the best
+//! choice for the sets holding a handful of values.
+//!
+//! This module is available when the `interning` feature is enabled.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::intern::StringSet;
+//!
+//! let namespaces: StringSet = ["kube-system", "default", "kube-system"].into_iter().collect();
+//! assert_eq!(namespaces.len(), 2);
+//! assert!(namespaces.contains("default"));
+//! assert!(!namespaces.contains("team-a"));
+//! ```
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Number of interned strings that triggers the removal of the unused ones.
+/// When most of the strings are still in use, the next removal happens once
+/// the pool has doubled in size
+pub const POOL_PRUNE_THRESHOLD: usize = 4096;
+
+thread_local! {
+    static POOL: RefCell<HashSet<Arc<str>>> = RefCell::new(HashSet::new());
+    static NEXT_PRUNE: Cell<usize> = const { Cell::new(POOL_PRUNE_THRESHOLD) };
+}
+
+/// Get the shared copy of `value`, allocating it on first use
+pub fn intern(value: &str) -> Arc<str> {
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if let Some(interned) = pool.get(value) {
+            return interned.clone();
+        }
+        if pool.len() >= NEXT_PRUNE.with(Cell::get) {
+            prune(&mut pool);
+        }
+        let interned: Arc<str> = Arc::from(value);
+        pool.insert(interned.clone());
+        interned
+    })
+}
+
+/// Drop the strings that are referenced only by the pool
+fn prune(pool: &mut HashSet<Arc<str>>) {
+    pool.retain(|interned| Arc::strong_count(interned) > 1);
+    NEXT_PRUNE.with(|next| next.set(POOL_PRUNE_THRESHOLD.max(pool.len() * 2)));
+}
+
+/// Number of distinct strings interned so far
+pub fn interned_count() -> usize {
+    POOL.with(|pool| pool.borrow().len())
+}
+
+/// A sorted set of interned strings, with binary search lookups. It is
+/// serialized as a list of strings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct StringSet {
+    items: Vec<Arc<str>>,
+}
+
+impl StringSet {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` when the set contains `value`
+    pub fn contains(&self, value: &str) -> bool {
+        self.items
+            .binary_search_by(|item| item.as_ref().cmp(value))
+            .is_ok()
+    }
+
+    /// Number of strings inside of the set
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` when the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Iterate over the strings, in lexicographic order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(AsRef::as_ref)
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for StringSet {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut set = StringSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<S: AsRef<str>> Extend<S> for StringSet {
+    fn extend<I: IntoIterator<Item = S>>(&mut self, iter: I) {
+        self.items
+            .extend(iter.into_iter().map(|value| intern(value.as_ref())));
+        self.items.sort_unstable();
+        self.items.dedup();
+    }
+}
+
+impl IntoIterator for StringSet {
+    type Item = Arc<str>;
+    type IntoIter = std::vec::IntoIter<Arc<str>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl Serialize for StringSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for StringSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for StringSet {
+    fn schema_name() -> String {
+        "StringSet".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <Vec<String>>::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sorted_and_deduplicated() {
+        let mut set: StringSet = ["b", "a", "b"].into_iter().collect();
+        set.extend(["c", "a"]);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert!(set.contains("c"));
+        assert!(!set.contains("d"));
+        assert!(StringSet::new().is_empty());
+    }
+
+    #[test]
+    fn strings_are_interned() {
+        let first: StringSet = ["registry.example.com"].into_iter().collect();
+        let count = interned_count();
+        let second: StringSet = ["registry.example.com".to_string()].into_iter().collect();
+        assert_eq!(interned_count(), count);
+        assert!(Arc::ptr_eq(&first.items[0], &second.items[0]));
+    }
+
+    #[test]
+    fn unused_strings_are_dropped() {
+        let kept: StringSet = ["kept"].into_iter().collect();
+        for i in 0..POOL_PRUNE_THRESHOLD {
+            drop(intern(&format!("value-{i}")));
+        }
+        assert!(interned_count() <= POOL_PRUNE_THRESHOLD);
+        intern("one-more");
+        assert!(interned_count() < POOL_PRUNE_THRESHOLD);
+        let again: StringSet = ["kept"].into_iter().collect();
+        assert!(Arc::ptr_eq(&kept.items[0], &again.items[0]));
+    }
+
+    #[test]
+    fn serialized_as_list() {
+        let set: StringSet = serde_json::from_value(json!(["b", "a", "a"])).unwrap();
+        assert_eq!(serde_json::to_value(&set).unwrap(), json!(["a", "b"]));
+    }
+}
@@ -0,0 +1,53 @@
+//! The Kubernetes version supported by the `k8s_openapi` types.
+//!
+//! The SDK does not enable any version feature of `k8s-openapi`: the choice
+//! is left to the policy, which is the final binary. Policies should use the
+//! [`k8s_openapi`](crate::k8s_openapi) crate re-exported by the SDK, and
+//! enable the feature of the minimum Kubernetes version they support on
+//! their own `k8s-openapi` dependency, which must have the same version used
+//! by the SDK:
+//!
+//! ```toml
+//! [dependencies]
+//! kubewarden-policy-sdk = "0.12"
+//! k8s-openapi = { version = "0.24", default-features = false, features = ["v1_31"] }
+//! ```
+//!
+//! Enabling the features from a library crate, including this SDK, leads to
+//! build failures as soon as two crates of the dependency graph pick different
+//! versions. Code depending on a specific version can be written with the
+//! `k8s_if_*` macros provided by `k8s_openapi`, e.g. `k8s_openapi::k8s_if_ge_1_31!`.
+
+/// The Kubernetes versions supported by the `k8s-openapi` release used by the SDK
+pub const SUPPORTED_VERSIONS: &[&str] = &["1.28", "1.29", "1.30", "1.31", "1.32"];
+
+k8s_openapi::k8s_if_1_28! {
+    /// The Kubernetes version enabled on the `k8s-openapi` crate
+    pub const ENABLED_VERSION: &str = "1.28";
+}
+k8s_openapi::k8s_if_1_29! {
+    /// The Kubernetes version enabled on the `k8s-openapi` crate
+    pub const ENABLED_VERSION: &str = "1.29";
+}
+k8s_openapi::k8s_if_1_30! {
+    /// The Kubernetes version enabled on the `k8s-openapi` crate
+    pub const ENABLED_VERSION: &str = "1.30";
+}
+k8s_openapi::k8s_if_1_31! {
+    /// The Kubernetes version enabled on the `k8s-openapi` crate
+    pub const ENABLED_VERSION: &str = "1.31";
+}
+k8s_openapi::k8s_if_1_32! {
+    /// The Kubernetes version enabled on the `k8s-openapi` crate
+    pub const ENABLED_VERSION: &str = "1.32";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabled_version_is_supported() {
+        assert!(SUPPORTED_VERSIONS.contains(&ENABLED_VERSION));
+    }
+}
@@ -1,20 +1,68 @@
 use std::collections::HashMap;
 
-use anyhow::anyhow;
-
+#[cfg(feature = "cluster-context")]
+pub use k8s_openapi;
 pub use wapc_guest;
 
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod codes;
+pub mod compression;
+#[cfg(feature = "cluster-context")]
+pub mod env;
+#[cfg(feature = "cluster-context")]
+pub mod exemptions;
 pub mod host_capabilities;
+#[cfg(feature = "cluster-context")]
+pub mod index;
+pub mod instrument;
+#[cfg(feature = "interning")]
+pub mod intern;
+#[cfg(feature = "cluster-context")]
+pub mod k8s_version;
 pub mod logging;
+pub mod matcher;
 pub mod metadata;
+pub mod middleware;
+pub mod mutation;
+pub mod native;
+pub mod net;
 #[cfg(not(target_arch = "wasm32"))]
 mod non_wasm;
+pub mod panic_handler;
+pub mod patterns;
+#[cfg(feature = "cluster-context")]
+pub mod priority;
+pub mod quantity;
+#[cfg(feature = "cluster-context")]
+pub mod quota;
+#[cfg(feature = "cluster-context")]
+pub mod rbac;
+#[cfg(feature = "cluster-context")]
+pub mod reliability;
 pub mod request;
+#[cfg(feature = "cluster-context")]
+pub mod resources;
 pub mod response;
+pub mod router;
+#[cfg(feature = "cluster-context")]
+pub mod scheduling;
+#[cfg(feature = "cluster-context")]
+pub mod security_context;
+#[cfg(feature = "cluster-context")]
+pub mod service_account;
 pub mod settings;
+#[cfg(feature = "slim-k8s")]
+pub mod slim;
+pub mod summary;
 pub mod test;
+pub mod violations;
+pub mod wire;
+#[cfg(feature = "cluster-context")]
+pub mod workload;
 
-use crate::metadata::ProtocolVersion;
+use crate::host_capabilities::policy::PolicyMode;
+use crate::metadata::{GuestMetadata, ProtocolVersion};
 #[cfg(feature = "cluster-context")]
 use crate::request::ValidationRequest;
 use crate::response::*;
@@ -24,37 +72,65 @@ pub mod crd;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "cluster-context")] {
-        use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet};
-        use k8s_openapi::api::batch::v1::{CronJob, Job};
-        use k8s_openapi::api::core::v1::{Pod, PodSpec, ReplicationController};
+        use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
         use k8s_openapi::Resource;
+        use crate::workload::WorkloadKind;
     }
 }
 
-/// Create an acceptance response
+/// Create an acceptance response.
+///
+/// The audit annotations recorded with [`response::record_audit_annotation`]
+/// are attached to the response, this is true for [`mutate_request`] and
+/// [`reject_request`] too.
+///
+/// This is the serialized form of [`accept_response`].
 pub fn accept_request() -> wapc_guest::CallResult {
-    Ok(serde_json::to_vec(&ValidationResponse {
+    accept_response().into_call_result()
+}
+
+/// Create an acceptance response, without serializing it. This allows
+/// further changes to be done before returning it to the host, see
+/// [`ValidationResponse::into_call_result`].
+pub fn accept_response() -> ValidationResponse {
+    ValidationResponse {
         accepted: true,
         message: None,
         code: None,
         mutated_object: None,
-        audit_annotations: None,
+        audit_annotations: with_recorded_audit_annotations(None),
         warnings: None,
-    })?)
+    }
 }
 
-/// Create an acceptance response that mutates the original object
+/// Create an acceptance response that mutates the original object.
+///
+/// An error is returned when the host reports the policy is not registered
+/// as mutating, see [`host_capabilities::policy::ensure_mutation_allowed`].
+///
+/// The size of the mutated object is checked against the
+/// [`response::response_size_limit`], see [`response::enforce_size_limit`].
 /// # Arguments
 /// * `mutated_object` - the mutated Object
 pub fn mutate_request(mutated_object: serde_json::Value) -> wapc_guest::CallResult {
-    Ok(serde_json::to_vec(&ValidationResponse {
+    mutate_response(mutated_object)?.into_call_result()
+}
+
+/// Create an acceptance response that mutates the original object, without
+/// serializing it. The same checks of [`mutate_request`] are performed.
+pub fn mutate_response(
+    mut mutated_object: serde_json::Value,
+) -> anyhow::Result<ValidationResponse> {
+    host_capabilities::policy::ensure_mutation_allowed()?;
+    let warning = enforce_size_limit(&mut mutated_object, &response_size_limit())?;
+    Ok(ValidationResponse {
         accepted: true,
         message: None,
         code: None,
         mutated_object: Some(mutated_object),
-        audit_annotations: None,
-        warnings: None,
-    })?)
+        audit_annotations: with_recorded_audit_annotations(None),
+        warnings: warning.map(|warning| vec![warning]),
+    })
 }
 
 #[cfg(feature = "cluster-context")]
@@ -67,82 +143,127 @@ pub fn mutate_pod_spec_from_request<T: std::default::Default>(
     validation_request: ValidationRequest<T>,
     pod_spec: PodSpec,
 ) -> wapc_guest::CallResult {
-    match validation_request.request.kind.kind.as_str() {
-        Deployment::KIND => {
-            let mut deployment =
-                serde_json::from_value::<Deployment>(validation_request.request.object.clone())?;
-            let mut deployment_spec = deployment.spec.unwrap_or_default();
-            deployment_spec.template.spec = Some(pod_spec);
-            deployment.spec = Some(deployment_spec);
-            mutate_request(serde_json::to_value(deployment)?)
-        }
-        ReplicaSet::KIND => {
-            let mut replicaset =
-                serde_json::from_value::<ReplicaSet>(validation_request.request.object.clone())?;
-            let mut replicaset_spec = replicaset.spec.unwrap_or_default();
-            let mut template = replicaset_spec.template.unwrap_or_default();
-            template.spec = Some(pod_spec);
-            replicaset_spec.template = Some(template);
-            replicaset.spec = Some(replicaset_spec);
-            mutate_request(serde_json::to_value(replicaset)?)
-        }
-        StatefulSet::KIND => {
-            let mut statefulset =
-                serde_json::from_value::<StatefulSet>(validation_request.request.object.clone())?;
-            let mut statefulset_spec = statefulset.spec.unwrap_or_default();
-            statefulset_spec.template.spec = Some(pod_spec);
-            statefulset.spec = Some(statefulset_spec);
-            mutate_request(serde_json::to_value(statefulset)?)
-        }
-        DaemonSet::KIND => {
-            let mut daemonset =
-                serde_json::from_value::<DaemonSet>(validation_request.request.object.clone())?;
-            let mut daemonset_spec = daemonset.spec.unwrap_or_default();
-            daemonset_spec.template.spec = Some(pod_spec);
-            daemonset.spec = Some(daemonset_spec);
-            mutate_request(serde_json::to_value(daemonset)?)
-        }
-        ReplicationController::KIND => {
-            let mut replication_controller = serde_json::from_value::<ReplicationController>(
-                validation_request.request.object.clone(),
-            )?;
-            let mut replication_controller_spec = replication_controller.spec.unwrap_or_default();
-            let mut template = replication_controller_spec.template.unwrap_or_default();
-            template.spec = Some(pod_spec);
-            replication_controller_spec.template = Some(template);
-            replication_controller.spec = Some(replication_controller_spec);
-            mutate_request(serde_json::to_value(replication_controller)?)
-        }
-        CronJob::KIND => {
-            let mut cronjob =
-                serde_json::from_value::<CronJob>(validation_request.request.object.clone())?;
-            let mut cronjob_spec = cronjob.spec.unwrap_or_default();
-            let mut job_template_spec = cronjob_spec.job_template;
-            let mut job_spec = job_template_spec.spec.unwrap_or_default();
-            let mut pod_template_spec = job_spec.template;
-            pod_template_spec.spec = Some(pod_spec);
-            job_spec.template = pod_template_spec;
-            job_template_spec.spec = Some(job_spec);
-            cronjob_spec.job_template = job_template_spec;
-            cronjob.spec = Some(cronjob_spec);
-            mutate_request(serde_json::to_value(cronjob)?)
-        }
-        Job::KIND => {
-            let mut job = serde_json::from_value::<Job>(validation_request.request.object.clone())?;
-            let mut job_spec = job.spec.unwrap_or_default();
-            job_spec.template.spec = Some(pod_spec);
-            job.spec = Some(job_spec);
-            mutate_request(serde_json::to_value(job)?)
-        }
-        Pod::KIND => {
-            let mut pod = serde_json::from_value::<Pod>(validation_request.request.object.clone())?;
-            pod.spec = Some(pod_spec);
-            mutate_request(serde_json::to_value(pod)?)
+    mutate_pod_template(validation_request, |template| {
+        template.spec = Some(pod_spec)
+    })
+}
+
+#[cfg(feature = "cluster-context")]
+/// Merge the given pod template, both metadata and spec, into the one of
+/// the resource defined in the original object and create an acceptance
+/// response.
+///
+/// This allows policies to change the labels and annotations of the pods
+/// together with their spec, for example to inject a sidecar container
+/// and the annotations configuring it.
+///
+/// The labels and the annotations of `pod_template` are added to the
+/// existing ones, replacing the values of the keys found in both. The
+/// other fields of the existing template metadata are left untouched. The
+/// spec is replaced only when `pod_template` has one.
+///
+/// When the original object is a Pod, only the labels and the annotations
+/// of the template metadata are merged into the metadata of the Pod.
+/// # Arguments
+/// * `validation_request` - the original admission request
+/// * `pod_template` - PodTemplateSpec to be merged into the existing one
+pub fn mutate_pod_template_from_request<T: std::default::Default>(
+    validation_request: ValidationRequest<T>,
+    pod_template: PodTemplateSpec,
+) -> wapc_guest::CallResult {
+    mutate_pod_template(validation_request, |template| {
+        if let Some(metadata) = pod_template.metadata {
+            let current = template.metadata.get_or_insert_with(Default::default);
+            if let Some(labels) = metadata.labels {
+                current
+                    .labels
+                    .get_or_insert_with(Default::default)
+                    .extend(labels);
+            }
+            if let Some(annotations) = metadata.annotations {
+                current
+                    .annotations
+                    .get_or_insert_with(Default::default)
+                    .extend(annotations);
+            }
         }
-        _ => {
-            reject_request(Some("Object should be one of these kinds: Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController, Job, CronJob, Pod".to_string()), None, None, None)
+        if pod_template.spec.is_some() {
+            template.spec = pod_template.spec;
         }
+    })
+}
+
+#[cfg(feature = "cluster-context")]
+/// Apply `mutate` to the pod template of the resource defined in the original
+/// object and create an acceptance response
+fn mutate_pod_template<T, F>(
+    validation_request: ValidationRequest<T>,
+    mutate: F,
+) -> wapc_guest::CallResult
+where
+    T: std::default::Default,
+    F: FnOnce(&mut PodTemplateSpec),
+{
+    let workload = match WorkloadKind::try_from_gvk(&validation_request.request.kind) {
+        Ok(workload) => workload,
+        Err(e) => return reject_request(Some(e.to_string()), None, None, None),
+    };
+    mutate_request(workload.visit(validation_request.request.object, mutate)?)
+}
+
+#[cfg(feature = "cluster-context")]
+/// Deserialize the object of the request into `K`, change it with the
+/// given closure and create an acceptance response carrying the mutated
+/// object.
+///
+/// The request is rejected when its kind does not match the one of `K`.
+/// # Arguments
+/// * `validation_request` - the original admission request
+/// * `mutate` - the closure changing the object
+///
+/// # Example
+///
+/// ```
+/// use kubewarden_policy_sdk::{mutate_typed_from_request, request::ValidationRequest};
+/// use k8s_openapi::api::core::v1::Service;
+///
+/// fn add_owner_label(validation_request: ValidationRequest<()>) -> wapc_guest::CallResult {
+///     mutate_typed_from_request(validation_request, |service: &mut Service| {
+///         service
+///             .metadata
+///             .labels
+///             .get_or_insert_with(Default::default)
+///             .insert("owner".to_string(), "platform-team".to_string());
+///     })
+/// }
+/// ```
+pub fn mutate_typed_from_request<T, K, F>(
+    validation_request: ValidationRequest<T>,
+    mutate: F,
+) -> wapc_guest::CallResult
+where
+    T: std::default::Default,
+    K: Resource + serde::de::DeserializeOwned + serde::Serialize,
+    F: FnOnce(&mut K),
+{
+    if !validation_request.request.kind.is::<K>() {
+        return reject_request(
+            Some(format!(
+                "Object should be of kind {} {}, got {} {}",
+                K::API_VERSION,
+                K::KIND,
+                validation_request.request.kind.api_version(),
+                validation_request.request.kind.kind
+            )),
+            None,
+            None,
+            None,
+        );
     }
+
+    let mut object = serde_json::from_value::<K>(validation_request.request.object)?;
+    mutate(&mut object);
+    mutate_request(serde_json::to_value(object)?)
 }
 
 /// Create a rejection response
@@ -157,14 +278,47 @@ pub fn reject_request(
     audit_annotations: Option<HashMap<String, String>>,
     warnings: Option<Vec<String>>,
 ) -> wapc_guest::CallResult {
-    Ok(serde_json::to_vec(&ValidationResponse {
+    reject_response(message, code, audit_annotations, warnings).into_call_result()
+}
+
+/// Create a rejection response, without serializing it. The arguments are
+/// the same of [`reject_request`].
+pub fn reject_response(
+    message: Option<String>,
+    code: Option<u16>,
+    audit_annotations: Option<HashMap<String, String>>,
+    warnings: Option<Vec<String>>,
+) -> ValidationResponse {
+    ValidationResponse {
         accepted: false,
         mutated_object: None,
         message,
         code,
-        audit_annotations,
+        audit_annotations: with_recorded_audit_annotations(audit_annotations),
         warnings,
-    })?)
+    }
+}
+
+/// Create the response of a policy that supports soft enforcement.
+///
+/// When `would_reject` is `false` the request is accepted. Otherwise the
+/// request is rejected when running in protect mode, while in monitor mode it
+/// is accepted with a warning explaining the request would have been rejected.
+/// # Arguments
+/// * `mode` - the execution mode of the policy, taken from the policy settings or from [`host_capabilities::policy_info`]
+/// * `would_reject` - whether the policy would reject the request
+/// * `message` - message explaining why the request would be rejected
+pub fn respond_respecting_mode(
+    mode: PolicyMode,
+    would_reject: bool,
+    message: Option<String>,
+) -> wapc_guest::CallResult {
+    if !would_reject {
+        return accept_request();
+    }
+    reject_response(message, None, None, None)
+        .respecting_mode(mode)
+        .into_call_result()
 }
 
 /// waPC guest function to register under the name `validate_settings`
@@ -202,13 +356,8 @@ pub fn validate_settings<T>(payload: &[u8]) -> wapc_guest::CallResult
 where
     T: serde::de::DeserializeOwned + settings::Validatable,
 {
-    let settings: T = serde_json::from_slice::<T>(payload).map_err(|e| {
-        anyhow!(
-            "Error decoding validation payload {}: {:?}",
-            String::from_utf8_lossy(payload),
-            e
-        )
-    })?;
+    let settings: T = serde_json::from_slice::<T>(payload)
+        .map_err(|e| request::payload_decoding_error("validation payload", payload, e))?;
 
     let res = match settings.validate() {
         Ok(_) => settings::SettingsValidationResponse {
@@ -224,6 +373,76 @@ where
     Ok(serde_json::to_vec(&res)?)
 }
 
+/// waPC guest function to register under the name `validate_settings`,
+/// for settings whose validation depends on how the policy is deployed.
+///
+/// Hosts can send the settings together with information about the policy
+/// (its name and the rules it is bound to), using the following payload:
+/// `{"settings": {...}, "context": {"policy_name": ..., "rules": [...]}}`.
+/// When the host sends only the settings, the context given to
+/// [`settings::ValidatableWithContext::validate_with_context`] is `None`.
+/// # Example
+///
+/// ```
+/// use kubewarden_policy_sdk::settings::{SettingsValidationContext, ValidatableWithContext};
+/// use kubewarden_policy_sdk::validate_settings_with_context;
+/// use serde::Deserialize;
+/// use wapc_guest::register_function;
+///
+/// #[derive(Deserialize)]
+/// struct Settings {}
+///
+/// impl ValidatableWithContext for Settings {
+///   fn validate_with_context(&self, context: Option<&SettingsValidationContext>) -> Result<(), String> {
+///     match context {
+///       Some(context) if !context.targets_only(&["pods"]) => {
+///         Err("this policy can be bound only to pods".to_string())
+///       }
+///       _ => Ok(()),
+///     }
+///   }
+/// }
+///
+/// register_function("validate_settings", validate_settings_with_context::<Settings>);
+/// ```
+pub fn validate_settings_with_context<T>(payload: &[u8]) -> wapc_guest::CallResult
+where
+    T: serde::de::DeserializeOwned + settings::ValidatableWithContext,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct SettingsWithContext {
+        settings: serde_json::Value,
+        context: settings::SettingsValidationContext,
+    }
+
+    let decoding_error =
+        |e: serde_json::Error| request::payload_decoding_error("validation payload", payload, e);
+    let (settings, context): (T, _) = match serde_json::from_slice::<SettingsWithContext>(payload) {
+        Ok(extended) => (
+            serde_json::from_value(extended.settings).map_err(decoding_error)?,
+            Some(extended.context),
+        ),
+        Err(_) => (
+            serde_json::from_slice(payload).map_err(decoding_error)?,
+            None,
+        ),
+    };
+
+    let res = match settings.validate_with_context(context.as_ref()) {
+        Ok(_) => settings::SettingsValidationResponse {
+            valid: true,
+            message: None,
+        },
+        Err(e) => settings::SettingsValidationResponse {
+            valid: false,
+            message: Some(e),
+        },
+    };
+
+    Ok(serde_json::to_vec(&res)?)
+}
+
 /// Helper function that provides the `protocol_version` implementation
 /// # Example
 ///
@@ -239,7 +458,88 @@ where
 /// }
 /// ```
 pub fn protocol_version_guest(_payload: &[u8]) -> wapc_guest::CallResult {
-    Ok(serde_json::to_vec(&ProtocolVersion::default())?)
+    Ok(serde_json::to_vec(&ProtocolVersion::V1)?)
+}
+
+/// Helper function that provides the `guest_metadata` implementation. It
+/// reports the SDK version, the protocol version, the enabled SDK features
+/// and the build target of the policy, see [`metadata::GuestMetadata`].
+///
+/// The function is registered by [`register_policy`].
+pub fn guest_metadata_guest(_payload: &[u8]) -> wapc_guest::CallResult {
+    Ok(serde_json::to_vec(&GuestMetadata::current())?)
+}
+
+/// Signature of the functions exposed by a policy through waPC
+pub type GuestFunction = fn(&[u8]) -> wapc_guest::CallResult;
+
+/// Name of the waPC function evaluating admission requests
+pub const VALIDATE_FUNCTION: &str = "validate";
+/// Name of the waPC function validating the policy settings
+pub const VALIDATE_SETTINGS_FUNCTION: &str = "validate_settings";
+/// Name of the waPC function reporting the protocol version of the policy
+pub const PROTOCOL_VERSION_FUNCTION: &str = "protocol_version";
+/// Name of the waPC function reporting the build metadata of the policy
+pub const GUEST_METADATA_FUNCTION: &str = "guest_metadata";
+
+/// The waPC functions registered by [`register_policy`], together with
+/// their names
+fn protocol_functions(
+    validate_fn: GuestFunction,
+    validate_settings_fn: GuestFunction,
+) -> Vec<(&'static str, GuestFunction)> {
+    vec![
+        (VALIDATE_FUNCTION, validate_fn),
+        (VALIDATE_SETTINGS_FUNCTION, validate_settings_fn),
+        (PROTOCOL_VERSION_FUNCTION, protocol_version_guest),
+        (GUEST_METADATA_FUNCTION, guest_metadata_guest),
+    ]
+}
+
+/// Register all the waPC functions required by the Kubewarden protocol.
+///
+/// Policies should call this function from their `wapc_init` instead of
+/// registering each function by hand: this way, entry points introduced
+/// by newer versions of the protocol are registered automatically.
+///
+/// The `validate_fn` runs surrounded by the hooks registered through the
+/// [`middleware`] module. Once [`panic_handler::install_panic_hook`] has
+/// been invoked, the panics of both functions are logged; they are turned
+/// into responses only in native builds, `wasm32-wasi` policies abort.
+/// # Example
+///
+/// ```
+/// use kubewarden_policy_sdk::{accept_request, register_policy, validate_settings};
+/// use kubewarden_policy_sdk::settings::Validatable;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Settings {}
+///
+/// impl Validatable for Settings {
+///   fn validate(&self) -> Result<(), String> {
+///     Ok(())
+///   }
+/// }
+///
+/// fn validate(_payload: &[u8]) -> wapc_guest::CallResult {
+///     accept_request()
+/// }
+///
+/// #[no_mangle]
+/// pub extern "C" fn wapc_init() {
+///     register_policy(validate, validate_settings::<Settings>);
+/// }
+/// ```
+pub fn register_policy(validate_fn: GuestFunction, validate_settings_fn: GuestFunction) {
+    middleware::set_validate_function(validate_fn);
+    middleware::set_validate_settings_function(validate_settings_fn);
+    for (name, function) in protocol_functions(
+        middleware::validate_guest,
+        middleware::validate_settings_guest,
+    ) {
+        wapc_guest::register_function(name, function);
+    }
 }
 
 #[cfg(test)]
@@ -247,6 +547,7 @@ mod tests {
     use super::*;
     use assert_json_diff::assert_json_eq;
     use serde_json::json;
+    use serial_test::serial;
 
     cfg_if::cfg_if! {
         if #[cfg(feature = "cluster-context")] {
@@ -256,9 +557,10 @@ mod tests {
             use serde::Serialize;
             use serde::ser::StdError;
 
-            use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec, JobSpec, JobTemplateSpec};
-            use k8s_openapi::api::core::v1::PodTemplateSpec;
+            use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec, Job, JobSpec, JobTemplateSpec};
+            use k8s_openapi::api::core::v1::{Pod, PodTemplateSpec};
             use k8s_openapi::api::core::v1::{ReplicationController, ReplicationControllerSpec};
+            use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
             use k8s_openapi::api::apps::v1::{
                 DaemonSet, DaemonSetSpec, Deployment, DeploymentSpec, ReplicaSet, ReplicaSetSpec,
                 StatefulSet, StatefulSetSpec,
@@ -266,8 +568,31 @@ mod tests {
         }
     }
 
+    #[serial]
+    #[test]
+    fn test_mutate_request_size_limit() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        set_response_size_limit(ResponseSizeLimit {
+            max_bytes: 10,
+            strategy: OversizeStrategy::Warn,
+        });
+        let raw_response = mutate_request(json!({"metadata": {"name": "web"}})).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert!(response.accepted);
+        assert_eq!(response.warnings.unwrap().len(), 1);
+
+        set_response_size_limit(ResponseSizeLimit {
+            max_bytes: 10,
+            strategy: OversizeStrategy::Fail,
+        });
+        assert!(mutate_request(json!({"metadata": {"name": "web"}})).is_err());
+        set_response_size_limit(ResponseSizeLimit::default());
+    }
+
+    #[serial]
     #[test]
     fn test_mutate_request() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let mutated_object = json!({
             "apiVersion": "v1",
             "kind": "Pod",
@@ -343,6 +668,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_respond_respecting_mode() {
+        let response = |mode, would_reject| -> ValidationResponse {
+            let raw = respond_respecting_mode(mode, would_reject, Some("privileged".to_string()))
+                .unwrap();
+            serde_json::from_slice(&raw).unwrap()
+        };
+
+        for mode in [PolicyMode::Protect, PolicyMode::Monitor] {
+            let accepted = response(mode, false);
+            assert!(accepted.accepted);
+            assert!(accepted.warnings.is_none());
+        }
+
+        let rejected = response(PolicyMode::Protect, true);
+        assert!(!rejected.accepted);
+        assert_eq!(rejected.message.as_deref(), Some("privileged"));
+
+        let monitored = response(PolicyMode::Monitor, true);
+        assert!(monitored.accepted);
+        assert!(monitored.message.is_none());
+        assert_eq!(
+            monitored.warnings,
+            Some(vec![
+                "running in monitor mode, would have rejected: privileged".to_string()
+            ])
+        );
+    }
+
     #[test]
     fn try_protocol_version_guest() -> Result<(), ()> {
         let reponse = protocol_version_guest(&[0; 0]).unwrap();
@@ -355,9 +709,9 @@ mod tests {
     #[cfg(feature = "cluster-context")]
     fn create_validation_request<T: Serialize>(object: T, kind: &str) -> ValidationRequest<()> {
         let value = serde_json::to_value(object).unwrap();
-        ValidationRequest {
-            settings: (),
-            request: KubernetesAdmissionRequest {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
                 kind: GroupVersionKind {
                     kind: kind.to_string(),
                     ..Default::default()
@@ -365,7 +719,7 @@ mod tests {
                 object: value,
                 ..Default::default()
             },
-        }
+        )
     }
 
     #[cfg(feature = "cluster-context")]
@@ -394,9 +748,11 @@ mod tests {
         Ok(())
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_deployment() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let deployment = Deployment {
             spec: Some(DeploymentSpec {
                 template: PodTemplateSpec {
@@ -421,9 +777,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_replicaset() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let replicaset = ReplicaSet {
             spec: Some(ReplicaSetSpec {
                 template: Some(PodTemplateSpec {
@@ -448,9 +806,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_statefulset() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let statefulset = StatefulSet {
             spec: Some(StatefulSetSpec {
                 template: PodTemplateSpec {
@@ -474,9 +834,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_daemonset() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let daemonset = DaemonSet {
             spec: Some(DaemonSetSpec {
                 template: PodTemplateSpec {
@@ -500,9 +862,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_replicationcontroller() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let replicationcontroller = ReplicationController {
             spec: Some(ReplicationControllerSpec {
                 template: Some(PodTemplateSpec {
@@ -527,9 +891,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_cronjob() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let cronjob = CronJob {
             spec: Some(CronJobSpec {
                 job_template: JobTemplateSpec {
@@ -579,9 +945,11 @@ mod tests {
         Ok(())
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_job() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let job = Job {
             spec: Some(JobSpec {
                 template: PodTemplateSpec {
@@ -605,9 +973,11 @@ mod tests {
         check_if_automount_service_account_token_is_true(raw_response)
     }
 
+    #[serial]
     #[cfg(feature = "cluster-context")]
     #[test]
     fn test_mutate_pod_spec_from_request_with_pod() -> Result<(), ()> {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
         let pod = Pod {
             spec: Some(PodSpec {
                 automount_service_account_token: Some(false),
@@ -671,4 +1041,309 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn protocol_functions_cover_all_entry_points() {
+        #[derive(serde::Deserialize)]
+        struct NoSettings {}
+        impl settings::Validatable for NoSettings {
+            fn validate(&self) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        let functions = protocol_functions(|_| accept_request(), validate_settings::<NoSettings>);
+        let names: Vec<&str> = functions.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "validate",
+                "validate_settings",
+                "protocol_version",
+                "guest_metadata"
+            ]
+        );
+
+        let (_, protocol_version) = functions[2];
+        let version: ProtocolVersion =
+            serde_json::from_slice(&protocol_version(b"").unwrap()).unwrap();
+        assert_eq!(version, ProtocolVersion::V1);
+
+        let (_, guest_metadata) = functions[3];
+        let metadata: GuestMetadata =
+            serde_json::from_slice(&guest_metadata(b"").unwrap()).unwrap();
+        assert_eq!(metadata, GuestMetadata::current());
+
+        register_policy(|_| accept_request(), validate_settings::<NoSettings>);
+    }
+
+    #[cfg(feature = "cluster-context")]
+    fn sidecar_pod_template() -> PodTemplateSpec {
+        PodTemplateSpec {
+            metadata: Some(ObjectMeta {
+                annotations: Some(
+                    [("sidecar.example.com/inject".to_string(), "true".to_string())].into(),
+                ),
+                ..Default::default()
+            }),
+            spec: Some(PodSpec {
+                automount_service_account_token: Some(true),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[serial]
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_pod_template_from_request_with_deployment() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let deployment = Deployment {
+            spec: Some(DeploymentSpec {
+                template: PodTemplateSpec {
+                    metadata: Some(ObjectMeta {
+                        labels: Some([("app".to_string(), "web".to_string())].into()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let validation_request = create_validation_request(deployment, "Deployment");
+
+        let raw_response =
+            mutate_pod_template_from_request(validation_request, sidecar_pod_template()).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert!(response.accepted);
+        assert_json_eq!(
+            response.mutated_object.unwrap()["spec"]["template"],
+            json!({
+                "metadata": {
+                    "labels": {"app": "web"},
+                    "annotations": {"sidecar.example.com/inject": "true"}
+                },
+                "spec": {
+                    "automountServiceAccountToken": true,
+                    "containers": []
+                }
+            })
+        );
+    }
+
+    #[serial]
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_pod_template_from_request_with_pod() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("web".to_string()),
+                labels: Some([("app".to_string(), "web".to_string())].into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let validation_request = create_validation_request(pod, "Pod");
+
+        let raw_response =
+            mutate_pod_template_from_request(validation_request, sidecar_pod_template()).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert!(response.accepted);
+        assert_json_eq!(
+            response.mutated_object.unwrap()["metadata"],
+            json!({
+                "name": "web",
+                "labels": {"app": "web"},
+                "annotations": {"sidecar.example.com/inject": "true"}
+            })
+        );
+    }
+
+    #[serial]
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_pod_template_from_request_merges_metadata() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let deployment = Deployment {
+            spec: Some(DeploymentSpec {
+                template: PodTemplateSpec {
+                    metadata: Some(ObjectMeta {
+                        name: Some("web".to_string()),
+                        labels: Some(
+                            [
+                                ("app".to_string(), "web".to_string()),
+                                ("tier".to_string(), "frontend".to_string()),
+                            ]
+                            .into(),
+                        ),
+                        ..Default::default()
+                    }),
+                    spec: Some(PodSpec {
+                        hostname: Some("web".to_string()),
+                        ..Default::default()
+                    }),
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let validation_request = create_validation_request(deployment, "Deployment");
+
+        let pod_template = PodTemplateSpec {
+            metadata: Some(ObjectMeta {
+                labels: Some([("tier".to_string(), "backend".to_string())].into()),
+                ..Default::default()
+            }),
+            spec: None,
+        };
+        let raw_response =
+            mutate_pod_template_from_request(validation_request, pod_template).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert_json_eq!(
+            response.mutated_object.unwrap()["spec"]["template"],
+            json!({
+                "metadata": {
+                    "name": "web",
+                    "labels": {"app": "web", "tier": "backend"}
+                },
+                "spec": {
+                    "hostname": "web",
+                    "containers": []
+                }
+            })
+        );
+    }
+
+    #[serial]
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_pod_spec_from_request_keeps_pod_metadata() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let pod = Pod {
+            metadata: ObjectMeta {
+                name: Some("web".to_string()),
+                labels: Some([("app".to_string(), "web".to_string())].into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let validation_request = create_validation_request(pod, "Pod");
+
+        let raw_response =
+            mutate_pod_spec_from_request(validation_request, PodSpec::default()).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert_json_eq!(
+            response.mutated_object.unwrap()["metadata"],
+            json!({"name": "web", "labels": {"app": "web"}})
+        );
+    }
+
+    #[serial]
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_typed_from_request() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        use k8s_openapi::api::core::v1::Service;
+
+        let service = Service {
+            metadata: ObjectMeta {
+                name: Some("web".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut validation_request = create_validation_request(service, Service::KIND);
+        validation_request.request.kind = GroupVersionKind::of::<Service>();
+
+        let raw_response = mutate_typed_from_request(validation_request, |svc: &mut Service| {
+            svc.metadata
+                .labels
+                .get_or_insert_with(Default::default)
+                .insert("owner".to_string(), "platform".to_string());
+        })
+        .unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert!(response.accepted);
+        assert_json_eq!(
+            response.mutated_object.unwrap()["metadata"],
+            json!({"name": "web", "labels": {"owner": "platform"}})
+        );
+    }
+
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_mutate_typed_from_request_with_wrong_kind() {
+        use k8s_openapi::api::core::v1::Service;
+
+        let mut validation_request = create_validation_request(Pod::default(), Pod::KIND);
+        validation_request.request.kind = GroupVersionKind::of::<Pod>();
+
+        let raw_response =
+            mutate_typed_from_request(validation_request, |_: &mut Service| {}).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_response).unwrap();
+        assert!(!response.accepted);
+        assert_eq!(
+            response.message.unwrap(),
+            "Object should be of kind v1 Service, got v1 Pod"
+        );
+    }
+
+    #[test]
+    fn test_validate_settings_with_context() {
+        #[derive(serde::Deserialize)]
+        struct PodSettings {
+            #[serde(default)]
+            allowed: bool,
+        }
+        impl settings::ValidatableWithContext for PodSettings {
+            fn validate_with_context(
+                &self,
+                context: Option<&settings::SettingsValidationContext>,
+            ) -> Result<(), String> {
+                if !self.allowed {
+                    return Err("not allowed".to_string());
+                }
+                match context {
+                    Some(context) if !context.targets_only(&["pods"]) => Err(format!(
+                        "policy {} can be bound only to pods",
+                        context.policy_name.as_deref().unwrap_or_default()
+                    )),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let validate = |payload: serde_json::Value| -> settings::SettingsValidationResponse {
+            let raw = validate_settings_with_context::<PodSettings>(
+                &serde_json::to_vec(&payload).unwrap(),
+            )
+            .unwrap();
+            serde_json::from_slice(&raw).unwrap()
+        };
+
+        assert!(validate(json!({"allowed": true})).valid);
+        assert!(!validate(json!({})).valid);
+        assert!(
+            validate(json!({
+                "settings": {"allowed": true},
+                "context": {"rules": [{"apiGroups": [""], "resources": ["pods"]}]}
+            }))
+            .valid
+        );
+        let response = validate(json!({
+            "settings": {"allowed": true},
+            "context": {
+                "policy_name": "pods-only",
+                "rules": [{"apiGroups": ["apps"], "resources": ["deployments"]}]
+            }
+        }));
+        assert!(!response.valid);
+        assert_eq!(
+            response.message.as_deref(),
+            Some("policy pods-only can be bound only to pods")
+        );
+        assert!(validate_settings_with_context::<PodSettings>(b"not json").is_err());
+    }
 }
@@ -54,8 +54,13 @@ impl slog::Drain for KubewardenDrain {
     fn log(&self, rinfo: &Record, logger_values: &OwnedKVList) -> Result<()> {
         let event = event::new(rinfo, logger_values).unwrap();
         let msg = serde_json::to_vec(&event).unwrap();
-        wapc_guest::host_call("kubewarden", "tracing", "log", &msg)
-            .map(|_| ())
-            .map_err(|e| anyhow::anyhow!("erorr invoking wapc logging facility: {:?}", e))
+        wapc_guest::host_call(
+            crate::host_capabilities::ops::BINDING,
+            crate::host_capabilities::ops::NAMESPACE_TRACING,
+            crate::host_capabilities::ops::TRACING_LOG,
+            &msg,
+        )
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("erorr invoking wapc logging facility: {:?}", e))
     }
 }
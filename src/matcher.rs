@@ -0,0 +1,257 @@
+//! Declarative matching of admission requests.
+//!
+//! Most policies are interested only in a subset of the requests they
+//! receive: some kinds, some operations, some namespaces. A [`RequestMatcher`]
+//! describes this subset, allowing the policy to exit early and accept all
+//! the requests that are not relevant.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::matcher::{Operation, RequestMatcher};
+//! use kubewarden_policy_sdk::request::ValidationRequest;
+//!
+//! #[derive(serde::Deserialize, Default)]
+//! struct Settings {}
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     let validation_request: ValidationRequest<Settings> = ValidationRequest::new(payload)?;
+//!
+//!     let matcher = RequestMatcher::new()
+//!         .kinds(["Pod"])
+//!         .operations([Operation::Create, Operation::Update])
+//!         .namespaces_not(["kube-system"]);
+//!     if let Some(response) = matcher.evaluate(&validation_request) {
+//!         return response;
+//!     }
+//!
+//!     // the actual validation logic...
+//!     kubewarden_policy_sdk::accept_request()
+//! }
+//! ```
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::request::{KubernetesAdmissionRequest, ValidationRequest};
+
+/// The operation performed by an admission request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+    Connect,
+}
+
+impl Operation {
+    /// The name of the operation, as found inside of the admission request
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Create => "CREATE",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+            Operation::Connect => "CONNECT",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Describes the admission requests a policy is interested in.
+///
+/// Each criterion that is not set matches all the requests. A request
+/// matches only when it satisfies all the criteria that have been set.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMatcher {
+    kinds: Option<HashSet<String>>,
+    groups: Option<HashSet<String>>,
+    operations: Option<HashSet<Operation>>,
+    namespaces: Option<HashSet<String>>,
+    excluded_namespaces: HashSet<String>,
+    sub_resources: Option<HashSet<String>>,
+}
+
+fn to_set<I, S>(values: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    values.into_iter().map(Into::into).collect()
+}
+
+impl RequestMatcher {
+    /// Create a matcher that matches all the requests
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match only requests about objects of the given kinds (e.g. `Pod`)
+    pub fn kinds<I, S>(mut self, kinds: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.kinds = Some(to_set(kinds));
+        self
+    }
+
+    /// Match only requests about objects belonging to the given API groups.
+    /// The core group is identified by the empty string
+    pub fn groups<I, S>(mut self, groups: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.groups = Some(to_set(groups));
+        self
+    }
+
+    /// Match only requests performing one of the given operations
+    pub fn operations<I>(mut self, operations: I) -> Self
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        self.operations = Some(operations.into_iter().collect());
+        self
+    }
+
+    /// Match only requests about objects defined inside of the given namespaces
+    pub fn namespaces<I, S>(mut self, namespaces: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.namespaces = Some(to_set(namespaces));
+        self
+    }
+
+    /// Do not match requests about objects defined inside of the given namespaces
+    pub fn namespaces_not<I, S>(mut self, namespaces: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.excluded_namespaces.extend(to_set(namespaces));
+        self
+    }
+
+    /// Match only requests targeting the given sub-resources (e.g. `status`).
+    /// Requests targeting the main resource are identified by the empty string
+    pub fn sub_resources<I, S>(mut self, sub_resources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sub_resources = Some(to_set(sub_resources));
+        self
+    }
+
+    /// Returns `true` when the admission request satisfies all the criteria
+    pub fn matches(&self, request: &KubernetesAdmissionRequest) -> bool {
+        let contains = |set: &Option<HashSet<String>>, value: &str| {
+            set.as_ref().is_none_or(|set| set.contains(value))
+        };
+
+        contains(&self.kinds, &request.kind.kind)
+            && contains(&self.groups, &request.kind.group)
+            && contains(&self.namespaces, &request.namespace)
+            && contains(&self.sub_resources, &request.sub_resource)
+            && !self
+                .excluded_namespaces
+                .contains(request.namespace.as_str())
+            && self.operations.as_ref().is_none_or(|operations| {
+                operations
+                    .iter()
+                    .any(|operation| operation.as_str() == request.operation)
+            })
+    }
+
+    /// Decide whether the policy has to evaluate the given request.
+    ///
+    /// Returns `None` when the request matches, meaning the policy must go
+    /// on with its validation logic. Otherwise an acceptance response is
+    /// returned, which the policy can hand back to the host right away.
+    pub fn evaluate<T: Default>(
+        &self,
+        validation_request: &ValidationRequest<T>,
+    ) -> Option<wapc_guest::CallResult> {
+        if self.matches(&validation_request.request) {
+            None
+        } else {
+            Some(crate::accept_request())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::GroupVersionKind;
+    use crate::response::ValidationResponse;
+
+    fn request(kind: &str, operation: &str, namespace: &str) -> KubernetesAdmissionRequest {
+        KubernetesAdmissionRequest {
+            kind: GroupVersionKind::new("", "v1", kind),
+            operation: operation.to_string(),
+            namespace: namespace.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_matcher_matches_everything() {
+        let matcher = RequestMatcher::new();
+        assert!(matcher.matches(&request("Pod", "CREATE", "default")));
+        assert!(matcher.matches(&request("Service", "DELETE", "")));
+    }
+
+    #[test]
+    fn all_criteria_must_be_satisfied() {
+        let matcher = RequestMatcher::new()
+            .kinds(["Pod"])
+            .operations([Operation::Create, Operation::Update])
+            .namespaces_not(["kube-system"]);
+
+        assert!(matcher.matches(&request("Pod", "CREATE", "default")));
+        assert!(matcher.matches(&request("Pod", "UPDATE", "default")));
+        assert!(!matcher.matches(&request("Pod", "DELETE", "default")));
+        assert!(!matcher.matches(&request("Service", "CREATE", "default")));
+        assert!(!matcher.matches(&request("Pod", "CREATE", "kube-system")));
+    }
+
+    #[test]
+    fn match_namespaces_and_sub_resources() {
+        let matcher = RequestMatcher::new()
+            .namespaces(["team-a", "team-b"])
+            .sub_resources([""]);
+
+        assert!(matcher.matches(&request("Pod", "CREATE", "team-a")));
+        assert!(!matcher.matches(&request("Pod", "CREATE", "team-c")));
+
+        let mut status_update = request("Pod", "UPDATE", "team-a");
+        status_update.sub_resource = "status".to_string();
+        assert!(!matcher.matches(&status_update));
+    }
+
+    #[test]
+    fn evaluate_accepts_requests_not_matching() {
+        let matcher = RequestMatcher::new().kinds(["Pod"]);
+
+        let validation_request =
+            ValidationRequest::from_parts((), request("Pod", "CREATE", "default"));
+        assert!(matcher.evaluate(&validation_request).is_none());
+
+        let validation_request =
+            ValidationRequest::from_parts((), request("Service", "CREATE", "default"));
+        let response = matcher
+            .evaluate(&validation_request)
+            .expect("an early response")
+            .expect("a valid response");
+        let response: ValidationResponse = serde_json::from_slice(&response).unwrap();
+        assert!(response.accepted);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::compression::{ContentEncoding, SUPPORTED_ENCODINGS};
+use crate::wire::{WireFormat, SUPPORTED_WIRE_FORMATS};
 use num_derive::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 use std::{convert::TryFrom, fmt};
@@ -7,21 +9,18 @@ use std::{convert::TryFrom, fmt};
 ///
 /// Policies built with this SDK provide the right value via the `protocol_version_guest`
 /// function.
-#[derive(Deserialize, Serialize, Debug, Clone, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Default, FromPrimitive, ToPrimitive, PartialEq, Eq,
+)]
 pub enum ProtocolVersion {
     /// This is an invalid version
     #[serde(rename = "Unknown")]
     Unknown = 0,
+    #[default]
     #[serde(rename = "v1")]
     V1,
 }
 
-impl Default for ProtocolVersion {
-    fn default() -> Self {
-        Self::V1
-    }
-}
-
 impl TryFrom<Vec<u8>> for ProtocolVersion {
     type Error = anyhow::Error;
 
@@ -39,10 +38,110 @@ impl fmt::Display for ProtocolVersion {
     }
 }
 
+/// Version of the SDK the policy has been built with
+pub const SDK_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The optional features of the SDK enabled at build time
+pub const ENABLED_CAPABILITIES: &[&str] = &[
+    #[cfg(feature = "cluster-context")]
+    "cluster-context",
+    #[cfg(feature = "crd")]
+    "crd",
+    #[cfg(feature = "kube")]
+    "kube",
+    #[cfg(feature = "slim-k8s")]
+    "slim-k8s",
+    #[cfg(feature = "terse-decode-errors")]
+    "terse-decode-errors",
+    #[cfg(feature = "compression")]
+    "compression",
+    #[cfg(feature = "msgpack")]
+    "msgpack",
+];
+
+/// GuestMetadata describes how a policy has been built. It is returned by
+/// the `guest_metadata_guest` function, allowing policy-server and kwctl to
+/// display and gate on the SDK version when debugging incompatibilities.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuestMetadata {
+    /// Version of the SDK, see [`SDK_VERSION`]
+    pub sdk_version: String,
+    /// Version of the communication protocol implemented by the policy
+    pub protocol_version: ProtocolVersion,
+    /// The optional features of the SDK, see [`ENABLED_CAPABILITIES`]
+    pub capabilities: Vec<String>,
+    /// The target the policy has been built for, e.g. `wasm32-wasi`
+    pub target: String,
+    /// The encodings of the payloads accepted by the policy, in order of
+    /// preference. The host must not compress the payloads it sends when
+    /// this is empty, which is the case of policies built with older
+    /// versions of the SDK. See [`crate::compression`]
+    #[serde(default)]
+    pub accepted_encodings: Vec<ContentEncoding>,
+    /// The formats of the payloads understood by the policy, in order of
+    /// preference. Only JSON is used when this is empty, see [`crate::wire`].
+    /// Binary formats are an opt-in of the host, they do not change the
+    /// protocol version reported by the policy
+    #[serde(default)]
+    pub wire_formats: Vec<WireFormat>,
+}
+
+impl GuestMetadata {
+    /// The metadata of the running policy
+    pub fn current() -> Self {
+        GuestMetadata {
+            sdk_version: SDK_VERSION.to_string(),
+            protocol_version: ProtocolVersion::V1,
+            capabilities: ENABLED_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            accepted_encodings: SUPPORTED_ENCODINGS.to_vec(),
+            wire_formats: SUPPORTED_WIRE_FORMATS.to_vec(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn current_guest_metadata() {
+        let metadata = GuestMetadata::current();
+        assert_eq!(metadata.sdk_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(metadata.protocol_version, ProtocolVersion::V1);
+        assert_eq!(metadata.wire_formats.last(), Some(&WireFormat::Json));
+        assert_eq!(
+            metadata
+                .capabilities
+                .contains(&"cluster-context".to_string()),
+            cfg!(feature = "cluster-context")
+        );
+        assert!(metadata.target.starts_with(std::env::consts::ARCH));
+        assert!(metadata
+            .accepted_encodings
+            .contains(&ContentEncoding::Identity));
+
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["protocol_version"], "v1");
+        assert_eq!(
+            serde_json::from_value::<GuestMetadata>(json).unwrap(),
+            metadata
+        );
+    }
+
+    #[test]
+    fn guest_metadata_without_encodings() {
+        let metadata: GuestMetadata = serde_json::from_value(serde_json::json!({
+            "sdk_version": "0.11.0",
+            "protocol_version": "v1",
+            "capabilities": [],
+            "target": "wasm32-wasi"
+        }))
+        .unwrap();
+        assert!(metadata.accepted_encodings.is_empty());
+        assert!(metadata.wire_formats.is_empty());
+    }
+
     #[test]
     fn protocol_version_try_display() {
         let version = ProtocolVersion::V1;
@@ -0,0 +1,239 @@
+//! Hooks wrapping the evaluation of every request.
+//!
+//! Cross-cutting concerns, like logging, metrics, deadline checks or the
+//! translation of rejections in monitor mode, can be installed once per
+//! policy instead of being repeated inside of the `validate` function:
+//!
+//! * the hooks registered with [`on_request`] receive the raw payload before
+//!   the `validate` function runs. They can stop the evaluation by returning
+//!   a response, or an error
+//! * the hooks registered with [`on_response`] receive the response produced
+//!   by the `validate` function, and can change it
+//!
+//! The hooks are executed in registration order by the `validate` entry point
+//! registered with [`register_policy`](crate::register_policy).
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::host_capabilities::policy::PolicyMode;
+//! use kubewarden_policy_sdk::{middleware, register_policy, reject_request, validate_settings};
+//! use kubewarden_policy_sdk::settings::Validatable;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {}
+//!
+//! impl Validatable for Settings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn validate(_payload: &[u8]) -> wapc_guest::CallResult {
+//!     reject_request(Some("not allowed".to_string()), None, None, None)
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn wapc_init() {
+//!     middleware::on_request(|payload| {
+//!         if payload.len() > 10 * 1024 * 1024 {
+//!             return Ok(Some(kubewarden_policy_sdk::reject_response(
+//!                 Some("object too large".to_string()),
+//!                 Some(413),
+//!                 None,
+//!                 None,
+//!             )));
+//!         }
+//!         Ok(None)
+//!     });
+//!     middleware::on_response(middleware::monitor_mode(PolicyMode::Monitor));
+//!     register_policy(validate, validate_settings::<Settings>);
+//! }
+//! ```
+use crate::host_capabilities::policy::PolicyMode;
+use crate::response::ValidationResponse;
+use crate::wire::WireFormat;
+use crate::GuestFunction;
+use anyhow::{anyhow, Result};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// A hook executed before the `validate` function. Returning a response
+/// skips the `validate` function
+pub type RequestHook = Rc<dyn Fn(&[u8]) -> Result<Option<ValidationResponse>>>;
+/// A hook executed on the response of the `validate` function
+pub type ResponseHook = Rc<dyn Fn(ValidationResponse) -> ValidationResponse>;
+
+thread_local! {
+    static VALIDATE_FUNCTION: Cell<Option<GuestFunction>> = const { Cell::new(None) };
+    static VALIDATE_SETTINGS_FUNCTION: Cell<Option<GuestFunction>> = const { Cell::new(None) };
+    static REQUEST_HOOKS: RefCell<Vec<RequestHook>> = const { RefCell::new(Vec::new()) };
+    static RESPONSE_HOOKS: RefCell<Vec<ResponseHook>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Register a hook executed before the `validate` function
+pub fn on_request<F>(hook: F)
+where
+    F: Fn(&[u8]) -> Result<Option<ValidationResponse>> + 'static,
+{
+    REQUEST_HOOKS.with(|hooks| hooks.borrow_mut().push(Rc::new(hook)));
+}
+
+/// Register a hook executed on the response of the `validate` function, or
+/// on the one returned by a request hook
+pub fn on_response<F>(hook: F)
+where
+    F: Fn(ValidationResponse) -> ValidationResponse + 'static,
+{
+    RESPONSE_HOOKS.with(|hooks| hooks.borrow_mut().push(Rc::new(hook)));
+}
+
+/// Remove all the registered hooks
+pub fn clear_hooks() {
+    REQUEST_HOOKS.with(|hooks| hooks.borrow_mut().clear());
+    RESPONSE_HOOKS.with(|hooks| hooks.borrow_mut().clear());
+}
+
+/// A response hook applying the execution mode of the policy, see
+/// [`ValidationResponse::respecting_mode`]
+pub fn monitor_mode(mode: PolicyMode) -> impl Fn(ValidationResponse) -> ValidationResponse {
+    move |response| response.respecting_mode(mode)
+}
+
+/// Set the `validate` function wrapped by [`validate_guest`]
+pub(crate) fn set_validate_function(validate_fn: GuestFunction) {
+    VALIDATE_FUNCTION.with(|function| function.set(Some(validate_fn)));
+}
+
+/// Set the `validate_settings` function wrapped by [`validate_settings_guest`]
+pub(crate) fn set_validate_settings_function(validate_settings_fn: GuestFunction) {
+    VALIDATE_SETTINGS_FUNCTION.with(|function| function.set(Some(validate_settings_fn)));
+}
+
+/// The `validate_settings` entry point registered by
+/// [`register_policy`](crate::register_policy). Its panics are handled as
+/// described by [`crate::panic_handler`]
+pub(crate) fn validate_settings_guest(payload: &[u8]) -> wapc_guest::CallResult {
+    let validate_settings_fn = VALIDATE_SETTINGS_FUNCTION
+        .with(Cell::get)
+        .ok_or_else(|| anyhow!("no validate_settings function has been registered"))?;
+    crate::panic_handler::guard_validate_settings(validate_settings_fn, payload)
+}
+
+/// The `validate` entry point registered by
+/// [`register_policy`](crate::register_policy): it runs the `validate`
+/// function of the policy, surrounded by the registered hooks. Its panics
+/// are handled as described by [`crate::panic_handler`]
+pub(crate) fn validate_guest(payload: &[u8]) -> wapc_guest::CallResult {
+    let validate_fn = VALIDATE_FUNCTION
+        .with(Cell::get)
+        .ok_or_else(|| anyhow!("no validate function has been registered"))?;
+    crate::response::clear_recorded_audit_annotations();
+    crate::host_capabilities::client::reset_circuit_breakers();
+
+    let request_hooks = REQUEST_HOOKS.with(|hooks| hooks.borrow().clone());
+    for hook in request_hooks {
+        if let Some(response) = hook(payload)? {
+            return apply_response_hooks(response).into_call_result();
+        }
+    }
+
+    let response = crate::panic_handler::guard_validate(validate_fn, payload)?;
+    if RESPONSE_HOOKS.with(|hooks| hooks.borrow().is_empty()) {
+        return Ok(response);
+    }
+    let response: ValidationResponse = match crate::wire::response_format() {
+        WireFormat::Json => serde_json::from_slice(&response)?,
+        format => crate::wire::from_binary_slice(format, &response)?,
+    };
+    apply_response_hooks(response).into_call_result()
+}
+
+fn apply_response_hooks(response: ValidationResponse) -> ValidationResponse {
+    let response_hooks = RESPONSE_HOOKS.with(|hooks| hooks.borrow().clone());
+    response_hooks
+        .iter()
+        .fold(response, |response, hook| hook(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reject(_payload: &[u8]) -> wapc_guest::CallResult {
+        crate::reject_request(Some("denied".to_string()), Some(403), None, None)
+    }
+
+    fn evaluate(payload: &[u8]) -> ValidationResponse {
+        serde_json::from_slice(&validate_guest(payload).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn without_hooks() {
+        set_validate_function(reject);
+        let response = evaluate(b"{}");
+        assert!(!response.accepted);
+        assert_eq!(response.code, Some(403));
+    }
+
+    #[test]
+    fn response_hooks_in_order() {
+        set_validate_function(reject);
+        on_response(|response| response.with_warning("first"));
+        on_response(monitor_mode(PolicyMode::Monitor));
+
+        let response = evaluate(b"{}");
+        assert!(response.accepted);
+        assert_eq!(
+            response.warnings,
+            Some(vec![
+                "first".to_string(),
+                "running in monitor mode, would have rejected: denied".to_string()
+            ])
+        );
+
+        clear_hooks();
+        assert!(!evaluate(b"{}").accepted);
+    }
+
+    #[test]
+    fn request_hooks_short_circuit() {
+        set_validate_function(reject);
+        on_request(|payload| Ok((payload == b"skip").then(crate::accept_response)));
+        on_request(|payload| match payload {
+            b"fail" => Err(anyhow!("deadline exceeded")),
+            _ => Ok(None),
+        });
+        on_response(|response| response.with_audit_annotation("seen", "true"));
+
+        let response = evaluate(b"skip");
+        assert!(response.accepted);
+        assert_eq!(response.audit_annotations.unwrap()["seen"], "true");
+
+        assert!(!evaluate(b"{}").accepted);
+        assert!(validate_guest(b"fail")
+            .unwrap_err()
+            .to_string()
+            .contains("deadline exceeded"));
+    }
+
+    #[test]
+    fn audit_annotations_of_failed_evaluations_are_dropped() {
+        fn fail(_payload: &[u8]) -> wapc_guest::CallResult {
+            crate::response::record_audit_annotation("rewritten", "busybox");
+            Err(anyhow!("host unreachable").into())
+        }
+
+        set_validate_function(fail);
+        assert!(validate_guest(b"{}").is_err());
+
+        set_validate_function(reject);
+        assert!(evaluate(b"{}").audit_annotations.is_none());
+    }
+
+    #[test]
+    fn missing_validate_function() {
+        assert!(validate_guest(b"{}").is_err());
+    }
+}
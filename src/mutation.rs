@@ -0,0 +1,540 @@
+//! Helpers to perform targeted edits of Kubernetes objects represented as
+//! [`serde_json::Value`].
+//!
+//! Locations inside of the document are expressed using
+//! [JSON Pointers](https://datatracker.ietf.org/doc/html/rfc6901), the same
+//! notation used by JSON Patch. The edited object can then be returned to the
+//! host via [`mutate_request`](crate::mutate_request).
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::mutation;
+//! use serde_json::json;
+//!
+//! let mut pod = json!({"metadata": {"name": "nginx"}});
+//! mutation::set(&mut pod, "/metadata/labels/team", json!("blue")).unwrap();
+//! mutation::ensure_array_contains(&mut pod, "/metadata/finalizers", json!("example.com/cleanup")).unwrap();
+//!
+//! assert_eq!(pod["metadata"]["labels"]["team"], "blue");
+//! assert_eq!(pod["metadata"]["finalizers"], json!(["example.com/cleanup"]));
+//! ```
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Lists that are merged item by item by [`deep_merge`], together with the
+/// field identifying each item. These mirror the `patchMergeKey` markers of
+/// the Kubernetes API types.
+const MERGE_KEYS: &[(&str, &[&str])] = &[
+    ("containers", &["name"]),
+    ("initContainers", &["name"]),
+    ("ephemeralContainers", &["name"]),
+    ("env", &["name"]),
+    ("volumes", &["name"]),
+    ("volumeMounts", &["mountPath"]),
+    ("volumeDevices", &["devicePath"]),
+    ("imagePullSecrets", &["name"]),
+    ("hostAliases", &["ip"]),
+    ("ports", &["containerPort", "port"]),
+];
+
+/// Ensure the JSON pointer is well formed, returning the part following the
+/// leading `/`. `None` is returned for the empty pointer
+fn pointer_body(pointer: &str) -> Result<Option<&str>> {
+    if pointer.is_empty() {
+        return Ok(None);
+    }
+    pointer
+        .strip_prefix('/')
+        .map(Some)
+        .ok_or_else(|| anyhow!("invalid JSON pointer '{}': it must start with '/'", pointer))
+}
+
+/// Split a JSON pointer into its unescaped reference tokens, and hand them
+/// to `f`. The tokens are allocated inside of the [`arena`](crate::arena)
+#[cfg(feature = "arena")]
+fn with_pointer_tokens<R>(pointer: &str, f: impl FnOnce(&[&str]) -> Result<R>) -> Result<R> {
+    let body = pointer_body(pointer)?;
+    crate::arena::with_arena(|bump| {
+        let mut tokens = bumpalo::collections::Vec::new_in(bump);
+        for token in body.into_iter().flat_map(|body| body.split('/')) {
+            if token.contains('~') {
+                tokens.push(&*bump.alloc_str(&token.replace("~1", "/").replace("~0", "~")));
+            } else {
+                tokens.push(token);
+            }
+        }
+        f(&tokens)
+    })
+}
+
+/// Split a JSON pointer into its unescaped reference tokens, and hand them
+/// to `f`
+#[cfg(not(feature = "arena"))]
+fn with_pointer_tokens<R>(pointer: &str, f: impl FnOnce(&[&str]) -> Result<R>) -> Result<R> {
+    let tokens: Vec<String> = pointer_body(pointer)?
+        .into_iter()
+        .flat_map(|body| body.split('/'))
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    f(&tokens.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+fn parse_index(token: &str, len: usize, pointer: &str) -> Result<usize> {
+    let index = token
+        .parse::<usize>()
+        .map_err(|_| anyhow!("invalid array index '{}' in '{}'", token, pointer))?;
+    if index >= len {
+        return Err(anyhow!(
+            "array index '{}' out of bounds in '{}'",
+            token,
+            pointer
+        ));
+    }
+    Ok(index)
+}
+
+/// Set `new_value` at the location referenced by `pointer`.
+///
+/// Missing intermediate objects are created. Inside of arrays, the `-` token
+/// can be used to append a new element at the end of the array.
+pub fn set(value: &mut Value, pointer: &str, new_value: Value) -> Result<()> {
+    with_pointer_tokens(pointer, |tokens| {
+        set_tokens(value, tokens, pointer, new_value)
+    })
+}
+
+fn set_tokens(value: &mut Value, tokens: &[&str], pointer: &str, new_value: Value) -> Result<()> {
+    let (last, parents) = match tokens.split_last() {
+        Some(split) => split,
+        None => {
+            *value = new_value;
+            return Ok(());
+        }
+    };
+
+    let mut current = value;
+    for token in parents {
+        if current.is_null() {
+            *current = Value::Object(serde_json::Map::new());
+        }
+        current = match current {
+            Value::Object(map) => map
+                .entry(*token)
+                .or_insert_with(|| Value::Object(serde_json::Map::new())),
+            Value::Array(array) => {
+                let index = parse_index(token, array.len(), pointer)?;
+                &mut array[index]
+            }
+            _ => {
+                return Err(anyhow!(
+                    "cannot traverse '{}': '{}' is not an object or an array",
+                    pointer,
+                    token
+                ))
+            }
+        };
+    }
+
+    if current.is_null() {
+        *current = Value::Object(serde_json::Map::new());
+    }
+    match current {
+        Value::Object(map) => {
+            map.insert(last.to_string(), new_value);
+        }
+        Value::Array(array) if *last == "-" => array.push(new_value),
+        Value::Array(array) => {
+            let index = parse_index(last, array.len(), pointer)?;
+            array[index] = new_value;
+        }
+        _ => {
+            return Err(anyhow!(
+                "cannot set '{}': parent is not an object or an array",
+                pointer
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the value referenced by `pointer`.
+///
+/// Returns the removed value, or `None` when nothing exists at the given
+/// location.
+pub fn remove(value: &mut Value, pointer: &str) -> Result<Option<Value>> {
+    with_pointer_tokens(pointer, |tokens| remove_tokens(value, tokens))
+}
+
+fn remove_tokens(value: &mut Value, tokens: &[&str]) -> Result<Option<Value>> {
+    let (last, parents) = match tokens.split_last() {
+        Some(split) => split,
+        None => return Err(anyhow!("cannot remove the root of the document")),
+    };
+
+    let mut current = value;
+    for token in parents {
+        current = match current {
+            Value::Object(map) => match map.get_mut(*token) {
+                Some(v) => v,
+                None => return Ok(None),
+            },
+            Value::Array(array) => match token.parse::<usize>() {
+                Ok(index) if index < array.len() => &mut array[index],
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+    }
+
+    match current {
+        Value::Object(map) => Ok(map.remove(*last)),
+        Value::Array(array) => match last.parse::<usize>() {
+            Ok(index) if index < array.len() => Ok(Some(array.remove(index))),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Remove `metadata.managedFields` from the given Kubernetes object. These
+/// fields are owned by the API server and can be large, policies should not
+/// include them inside of the mutated objects.
+///
+/// Returns `true` when the document has been changed.
+pub fn strip_managed_fields(value: &mut Value) -> bool {
+    value
+        .get_mut("metadata")
+        .and_then(Value::as_object_mut)
+        .and_then(|metadata| metadata.remove("managedFields"))
+        .is_some()
+}
+
+/// Metadata fields set by the API server. Policies cannot change them
+const SERVER_OWNED_METADATA: &[&str] = &[
+    "creationTimestamp",
+    "deletionTimestamp",
+    "deletionGracePeriodSeconds",
+    "generation",
+    "resourceVersion",
+    "uid",
+];
+
+/// Remove the fields owned by the API server from a mutated object, before
+/// returning it through [`mutate_request`](crate::mutate_request):
+///
+/// * `metadata.managedFields`, see [`strip_managed_fields`]
+/// * `metadata.selfLink`, which is no longer set by Kubernetes
+/// * the server owned metadata (e.g. `metadata.creationTimestamp`) when
+///   they are `null`, an artifact of serializing typed objects with some
+///   libraries
+/// * `status`, which cannot be changed through admission
+///
+/// Returns `true` when the document has been changed.
+pub fn sanitize_for_response(value: &mut Value) -> bool {
+    let mut changed = strip_managed_fields(value);
+
+    if let Some(metadata) = value.get_mut("metadata").and_then(Value::as_object_mut) {
+        changed |= metadata.remove("selfLink").is_some();
+        for field in SERVER_OWNED_METADATA {
+            if metadata.get(*field).is_some_and(Value::is_null) {
+                metadata.remove(*field);
+                changed = true;
+            }
+        }
+    }
+    if let Some(object) = value.as_object_mut() {
+        changed |= object.remove("status").is_some();
+    }
+
+    changed
+}
+
+/// Ensure the array referenced by `pointer` contains `item`, appending it
+/// when missing. The array is created when it does not exist yet.
+///
+/// Returns `true` when the document has been changed.
+pub fn ensure_array_contains(value: &mut Value, pointer: &str, item: Value) -> Result<bool> {
+    match value.pointer_mut(pointer) {
+        Some(Value::Array(array)) => {
+            if array.contains(&item) {
+                Ok(false)
+            } else {
+                array.push(item);
+                Ok(true)
+            }
+        }
+        Some(Value::Null) | None => {
+            set(value, pointer, Value::Array(vec![item]))?;
+            Ok(true)
+        }
+        Some(_) => Err(anyhow!("'{}' does not reference an array", pointer)),
+    }
+}
+
+/// Fill `original` with the defaults found inside of `overlay`, merging lists
+/// like Kubernetes' strategic merge patch does:
+///
+/// * objects are merged recursively, only the keys missing from `original`
+///   are added. The values already set inside of `original` always win,
+///   `null` values of `overlay` are ignored
+/// * well-known lists (containers and env by `name`, container ports by
+///   `containerPort`, volume mounts by `mountPath`,...) are merged item by
+///   item: items sharing the same merge key are merged recursively, new items
+///   are appended
+/// * all the other lists of `original` are left untouched
+///
+/// This is useful to build the `mutated_object` of "ensure these defaults
+/// exist" policies without clobbering the entries provided by the user.
+pub fn deep_merge(original: &mut Value, overlay: Value) {
+    merge_value(original, overlay, None);
+}
+
+fn merge_value(original: &mut Value, overlay: Value, field: Option<&str>) {
+    match (original, overlay) {
+        (Value::Object(original), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    continue;
+                }
+                match original.get_mut(&key) {
+                    Some(current) => merge_value(current, value, Some(key.as_str())),
+                    None => {
+                        original.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(original), Value::Array(overlay)) => {
+            let Some(merge_key) = field.and_then(|field| merge_key(field, original, &overlay))
+            else {
+                return;
+            };
+            for item in overlay {
+                let existing = original
+                    .iter_mut()
+                    .find(|current| current.get(merge_key) == item.get(merge_key));
+                match existing {
+                    Some(current) => merge_value(current, item, None),
+                    None => original.push(item),
+                }
+            }
+        }
+        // the value set inside of `original` wins
+        _ => {}
+    }
+}
+
+/// Find the merge key to be used for the list stored under `field`. All the
+/// items of both lists must have the key, otherwise the list of `original` is
+/// left untouched.
+fn merge_key(field: &str, original: &[Value], overlay: &[Value]) -> Option<&'static str> {
+    let candidates = MERGE_KEYS
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, keys)| *keys)?;
+
+    candidates.iter().copied().find(|key| {
+        original
+            .iter()
+            .chain(overlay.iter())
+            .all(|item| item.get(key).is_some_and(|v| !v.is_null()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn set_creates_missing_objects() {
+        let mut obj = json!({"metadata": {"name": "nginx"}});
+        set(&mut obj, "/metadata/annotations/a~1b", json!("c")).unwrap();
+
+        assert_eq!(
+            obj,
+            json!({"metadata": {"name": "nginx", "annotations": {"a/b": "c"}}})
+        );
+    }
+
+    #[test]
+    fn set_inside_of_arrays() {
+        let mut obj = json!({"spec": {"containers": [{"name": "a"}]}});
+        set(&mut obj, "/spec/containers/0/image", json!("busybox")).unwrap();
+        set(&mut obj, "/spec/containers/-", json!({"name": "b"})).unwrap();
+
+        assert_eq!(
+            obj,
+            json!({"spec": {"containers": [{"name": "a", "image": "busybox"}, {"name": "b"}]}})
+        );
+        assert!(set(&mut obj, "/spec/containers/5/image", json!("x")).is_err());
+        assert!(set(&mut obj, "/spec/containers/0/name/foo", json!("x")).is_err());
+        assert!(set(&mut obj, "spec", json!("x")).is_err());
+    }
+
+    #[test]
+    fn remove_values() {
+        let mut obj = json!({"metadata": {"labels": {"a": "1"}, "finalizers": ["x", "y"]}});
+
+        assert_eq!(
+            remove(&mut obj, "/metadata/labels/a").unwrap(),
+            Some(json!("1"))
+        );
+        assert_eq!(
+            remove(&mut obj, "/metadata/finalizers/0").unwrap(),
+            Some(json!("x"))
+        );
+        assert_eq!(remove(&mut obj, "/metadata/annotations/foo").unwrap(), None);
+        assert_eq!(
+            obj,
+            json!({"metadata": {"labels": {}, "finalizers": ["y"]}})
+        );
+        assert!(remove(&mut obj, "").is_err());
+    }
+
+    #[test]
+    fn ensure_array_contains_item() {
+        let mut obj = json!({"metadata": {}});
+
+        assert!(ensure_array_contains(&mut obj, "/metadata/finalizers", json!("a")).unwrap());
+        assert!(!ensure_array_contains(&mut obj, "/metadata/finalizers", json!("a")).unwrap());
+        assert!(ensure_array_contains(&mut obj, "/metadata/finalizers", json!("b")).unwrap());
+        assert_eq!(obj, json!({"metadata": {"finalizers": ["a", "b"]}}));
+        assert!(ensure_array_contains(&mut obj, "/metadata", json!("c")).is_err());
+    }
+
+    #[test]
+    fn deep_merge_objects() {
+        let mut obj = json!({"metadata": {"labels": {"a": "1", "b": "2"}}});
+        deep_merge(
+            &mut obj,
+            json!({"metadata": {"labels": {"b": null, "c": "3"}}, "spec": {"replicas": 3}}),
+        );
+
+        assert_eq!(
+            obj,
+            json!({"metadata": {"labels": {"a": "1", "b": "2", "c": "3"}}, "spec": {"replicas": 3}})
+        );
+    }
+
+    #[test]
+    fn deep_merge_keeps_conflicting_values() {
+        let mut obj = json!({
+            "metadata": {"labels": {"tier": "frontend"}, "annotations": "invalid"},
+            "spec": {"replicas": 1, "paused": false},
+        });
+        deep_merge(
+            &mut obj,
+            json!({
+                "metadata": {"labels": {"tier": "backend"}, "annotations": {"a": "1"}},
+                "spec": {"replicas": 3, "paused": true, "strategy": {"type": "Recreate"}},
+            }),
+        );
+
+        assert_eq!(
+            obj,
+            json!({
+                "metadata": {"labels": {"tier": "frontend"}, "annotations": "invalid"},
+                "spec": {"replicas": 1, "paused": false, "strategy": {"type": "Recreate"}},
+            })
+        );
+    }
+
+    #[test]
+    fn deep_merge_lists_by_key() {
+        let mut obj = json!({"spec": {
+            "containers": [{
+                "name": "app",
+                "image": "nginx",
+                "env": [{"name": "MODE", "value": "user"}],
+                "ports": [{"containerPort": 80, "protocol": "TCP"}],
+                "args": ["--a"],
+            }],
+        }});
+        deep_merge(
+            &mut obj,
+            json!({"spec": {
+                "containers": [
+                    {
+                        "name": "app",
+                        "env": [{"name": "MODE", "value": "default"}, {"name": "LOG", "value": "info"}],
+                        "ports": [{"containerPort": 80, "name": "http"}, {"containerPort": 443}],
+                        "args": ["--b"],
+                    },
+                    {"name": "sidecar", "image": "envoy"},
+                ],
+            }}),
+        );
+
+        assert_eq!(
+            obj,
+            json!({"spec": {
+                "containers": [
+                    {
+                        "name": "app",
+                        "image": "nginx",
+                        "env": [{"name": "MODE", "value": "user"}, {"name": "LOG", "value": "info"}],
+                        "ports": [{"containerPort": 80, "protocol": "TCP", "name": "http"}, {"containerPort": 443}],
+                        "args": ["--a"],
+                    },
+                    {"name": "sidecar", "image": "envoy"},
+                ],
+            }})
+        );
+    }
+
+    #[test]
+    fn deep_merge_service_ports_use_port_key() {
+        let mut obj = json!({"spec": {"ports": [{"port": 80, "targetPort": 8080}]}});
+        deep_merge(
+            &mut obj,
+            json!({"spec": {"ports": [{"port": 80, "name": "http"}]}}),
+        );
+
+        assert_eq!(
+            obj,
+            json!({"spec": {"ports": [{"port": 80, "targetPort": 8080, "name": "http"}]}})
+        );
+    }
+
+    #[test]
+    fn strip_managed_fields_from_object() {
+        let mut obj = json!({"metadata": {"name": "web", "managedFields": []}});
+        assert!(strip_managed_fields(&mut obj));
+        assert_eq!(obj, json!({"metadata": {"name": "web"}}));
+        assert!(!strip_managed_fields(&mut obj));
+        assert!(!strip_managed_fields(&mut json!("not an object")));
+    }
+
+    #[test]
+    fn sanitize_mutated_object() {
+        let mut obj = json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "web",
+                "uid": "1234",
+                "resourceVersion": "42",
+                "creationTimestamp": null,
+                "selfLink": "/api/v1/namespaces/default/pods/web",
+                "managedFields": [{"manager": "kubectl"}]
+            },
+            "spec": {"containers": []},
+            "status": {"phase": "Pending"}
+        });
+
+        assert!(sanitize_for_response(&mut obj));
+        assert_eq!(
+            obj,
+            json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {"name": "web", "uid": "1234", "resourceVersion": "42"},
+                "spec": {"containers": []}
+            })
+        );
+        assert!(!sanitize_for_response(&mut obj));
+    }
+}
@@ -0,0 +1,180 @@
+//! Invoke the functions of a policy natively, without going through the
+//! waPC byte protocol.
+//!
+//! Unit tests, fuzzers and tools embedding a policy (e.g. a CLI linting
+//! manifests offline) can link the policy crate as a regular Rust library
+//! and call its `validate` and `validate_settings` functions through these
+//! helpers, dealing with typed requests and responses instead of bytes.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::native;
+//! use kubewarden_policy_sdk::request::{KubernetesAdmissionRequest, ValidationRequest};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize, Default)]
+//! struct Settings {}
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     let validation_request: ValidationRequest<Settings> = ValidationRequest::new(payload)?;
+//!     if validation_request.request.namespace == "kube-system" {
+//!         return kubewarden_policy_sdk::reject_request(None, None, None, None);
+//!     }
+//!     kubewarden_policy_sdk::accept_request()
+//! }
+//!
+//! let request = KubernetesAdmissionRequest {
+//!     namespace: "kube-system".to_string(),
+//!     ..Default::default()
+//! };
+//! let response = native::evaluate_request(validate, &request, &Settings {}).unwrap();
+//! assert!(!response.accepted);
+//! ```
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::request::KubernetesAdmissionRequest;
+use crate::response::ValidationResponse;
+use crate::settings::SettingsValidationResponse;
+
+/// Invoke `validate` with the given raw `payload`, decoding its response
+pub fn evaluate<F>(validate: F, payload: &[u8]) -> anyhow::Result<ValidationResponse>
+where
+    F: FnOnce(&[u8]) -> wapc_guest::CallResult,
+{
+    let raw_response = validate(payload).map_err(|e| anyhow!("policy evaluation failed: {}", e))?;
+    serde_json::from_slice(&raw_response)
+        .map_err(|e| anyhow!("cannot decode the validation response: {}", e))
+}
+
+/// Invoke `validate` with a payload built from the admission `request` and
+/// the policy `settings`
+pub fn evaluate_request<F, T>(
+    validate: F,
+    request: &KubernetesAdmissionRequest,
+    settings: &T,
+) -> anyhow::Result<ValidationResponse>
+where
+    F: FnOnce(&[u8]) -> wapc_guest::CallResult,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(&json!({
+        "settings": settings,
+        "request": request,
+    }))?;
+    evaluate(validate, &payload)
+}
+
+/// Invoke the `validate` function of a raw policy with a payload built from
+/// the JSON document `request` and the policy `settings`
+pub fn evaluate_raw<F, T>(
+    validate: F,
+    request: &serde_json::Value,
+    settings: &T,
+) -> anyhow::Result<ValidationResponse>
+where
+    F: FnOnce(&[u8]) -> wapc_guest::CallResult,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(&json!({
+        "settings": settings,
+        "request": request,
+    }))?;
+    evaluate(validate, &payload)
+}
+
+/// Invoke `validate_settings` with the given `settings`, decoding its response
+pub fn validate_settings<F, T>(
+    validate_settings: F,
+    settings: &T,
+) -> anyhow::Result<SettingsValidationResponse>
+where
+    F: FnOnce(&[u8]) -> wapc_guest::CallResult,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(settings)?;
+    let raw_response =
+        validate_settings(&payload).map_err(|e| anyhow!("settings validation failed: {}", e))?;
+    serde_json::from_slice(&raw_response)
+        .map_err(|e| anyhow!("cannot decode the settings validation response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RawValidationRequest;
+    use crate::settings::Validatable;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Settings {
+        denied_user: String,
+    }
+
+    impl Validatable for Settings {
+        fn validate(&self) -> Result<(), String> {
+            if self.denied_user.is_empty() {
+                Err("denied_user cannot be empty".to_string())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = RawValidationRequest::<Settings>::new(payload)?;
+        let user = req.request["user_info"]["username"]
+            .as_str()
+            .or(req.request["user"].as_str())
+            .unwrap_or_default();
+        if user == req.settings.denied_user {
+            crate::reject_request(Some(format!("{} is denied", user)), None, None, None)
+        } else {
+            crate::accept_request()
+        }
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            denied_user: "mallory".to_string(),
+        }
+    }
+
+    #[test]
+    fn evaluate_admission_request() {
+        let mut request = KubernetesAdmissionRequest::default();
+        request.user_info.username = "mallory".to_string();
+
+        let response = evaluate_request(validate, &request, &settings()).unwrap();
+        assert!(!response.accepted);
+        assert_eq!(response.message.as_deref(), Some("mallory is denied"));
+    }
+
+    #[test]
+    fn evaluate_raw_document() {
+        let response = evaluate_raw(validate, &json!({"user": "alice"}), &settings()).unwrap();
+        assert!(response.accepted);
+    }
+
+    #[test]
+    fn evaluate_errors() {
+        assert!(evaluate(validate, b"not json").is_err());
+        assert!(evaluate(|_: &[u8]| Ok(b"garbage".to_vec()), b"{}").is_err());
+    }
+
+    #[test]
+    fn native_settings_validation() {
+        let response =
+            validate_settings(crate::validate_settings::<Settings>, &settings()).unwrap();
+        assert!(response.valid);
+
+        let response =
+            validate_settings(crate::validate_settings::<Settings>, &Settings::default()).unwrap();
+        assert!(!response.valid);
+        assert_eq!(
+            response.message.as_deref(),
+            Some("denied_user cannot be empty")
+        );
+    }
+}
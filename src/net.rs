@@ -0,0 +1,2 @@
+//! Network related utilities
+pub mod ip;
@@ -0,0 +1,244 @@
+//! IP address and network utilities.
+//!
+//! Policies implementing egress or ingress allow-lists can use [`Cidr`] inside
+//! of their settings, and check the addresses resolved through the
+//! [`lookup_host`](crate::host_capabilities::net::lookup_host) host
+//! capability without depending on an IP math crate.
+//!
+//! IPv4-mapped IPv6 addresses (e.g. `::ffff:10.0.0.1`) are treated as IPv4
+//! addresses.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::host_capabilities::net::LookupResponse;
+use crate::settings::Validatable;
+
+/// A network expressed in CIDR notation (e.g. `10.0.0.0/8` or `fd00::/8`)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// The address of the network
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// The length of the network prefix
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    fn mask(&self) -> u128 {
+        let bits = self.bits();
+        if self.prefix_len == 0 {
+            0
+        } else {
+            (u128::MAX << (bits - self.prefix_len as u32)) & (u128::MAX >> (128 - bits))
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        match self.address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// Returns `true` when the network contains the given address
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        let address = &address.to_canonical();
+        match (self.address, address) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                ip_bits(&self.address) & self.mask() == ip_bits(address) & self.mask()
+            }
+            _ => false,
+        }
+    }
+}
+
+fn ip_bits(address: &IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(*address) as u128,
+        IpAddr::V6(address) => u128::from(*address),
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = value
+            .split_once('/')
+            .ok_or_else(|| format!("invalid CIDR '{}': missing prefix length", value))?;
+        let address: IpAddr = address
+            .parse()
+            .map_err(|e| format!("invalid CIDR '{}': {}", value, e))?;
+        let prefix_len: u8 = prefix_len
+            .parse()
+            .map_err(|e| format!("invalid CIDR '{}': {}", value, e))?;
+
+        let cidr = Cidr {
+            address,
+            prefix_len,
+        };
+        if prefix_len as u32 > cidr.bits() {
+            return Err(format!(
+                "invalid CIDR '{}': prefix length must be at most {}",
+                value,
+                cidr.bits()
+            ));
+        }
+        // IPv4-mapped networks are handled as IPv4 ones
+        let cidr = match address {
+            IpAddr::V6(v6) if prefix_len >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => Cidr {
+                    address: IpAddr::V4(v4),
+                    prefix_len: prefix_len - 96,
+                },
+                None => cidr,
+            },
+            _ => cidr,
+        };
+        Ok(cidr)
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl Validatable for Cidr {
+    fn validate(&self) -> Result<(), String> {
+        if ip_bits(&self.address) & !self.mask() != 0 {
+            return Err(format!(
+                "invalid CIDR '{}': the address has host bits set",
+                self
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for Cidr {
+    fn schema_name() -> String {
+        "Cidr".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// Parse an IP address, IPv4-mapped IPv6 addresses are converted to IPv4
+pub fn parse_ip(address: &str) -> Result<IpAddr, String> {
+    address
+        .parse::<IpAddr>()
+        .map(|address| address.to_canonical())
+        .map_err(|e| format!("invalid IP address '{}': {}", address, e))
+}
+
+/// Returns `true` when the address is contained by at least one of the networks
+pub fn within_any(address: &IpAddr, cidrs: &[Cidr]) -> bool {
+    cidrs.iter().any(|cidr| cidr.contains(address))
+}
+
+impl LookupResponse {
+    /// Returns `true` when all the resolved addresses are contained by at
+    /// least one of the given networks. Addresses that cannot be parsed are
+    /// considered outside of the networks
+    pub fn all_ips_within(&self, cidrs: &[Cidr]) -> bool {
+        self.ips_outside(cidrs).is_empty()
+    }
+
+    /// The resolved addresses that are not contained by any of the given
+    /// networks, including the ones that cannot be parsed
+    pub fn ips_outside(&self, cidrs: &[Cidr]) -> Vec<String> {
+        self.ips
+            .iter()
+            .filter(|ip| !parse_ip(ip).is_ok_and(|ip| within_any(&ip, cidrs)))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cidr() {
+        let cidrs: Vec<Cidr> = serde_json::from_value(json!(["10.0.0.0/8", "fd00::/8"])).unwrap();
+        assert!(cidrs.iter().all(|c| c.validate().is_ok()));
+        assert!(cidrs[0].contains(&"10.1.2.3".parse().unwrap()));
+        assert!(!cidrs[0].contains(&"192.168.0.1".parse().unwrap()));
+        assert!(cidrs[1].contains(&"fd12::1".parse().unwrap()));
+        assert!(!cidrs[1].contains(&"10.1.2.3".parse().unwrap()));
+        assert_eq!(serde_json::to_value(cidrs[0]).unwrap(), json!("10.0.0.0/8"));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(&"1.2.3.4".parse().unwrap()));
+
+        assert!("10.0.0.1/8".parse::<Cidr>().unwrap().validate().is_err());
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0.0".parse::<Cidr>().is_err());
+        assert!(serde_json::from_value::<Cidr>(json!("nope/8")).is_err());
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+        assert_eq!(
+            parse_ip("::ffff:10.1.2.3").unwrap(),
+            "10.1.2.3".parse::<IpAddr>().unwrap()
+        );
+        assert!(parse_ip("10.1.2").is_err());
+
+        let cidr: Cidr = "::ffff:10.0.0.0/104".parse().unwrap();
+        assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+    }
+
+    #[test]
+    fn lookup_response_within_cidrs() {
+        let cidrs: Vec<Cidr> = vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()];
+        let response = LookupResponse {
+            ips: vec!["10.1.2.3".to_string(), "fd00::1".to_string()],
+        };
+        assert!(response.all_ips_within(&cidrs));
+
+        let response = LookupResponse {
+            ips: vec![
+                "10.1.2.3".to_string(),
+                "8.8.8.8".to_string(),
+                "garbage".to_string(),
+            ],
+        };
+        assert!(!response.all_ips_within(&cidrs));
+        assert_eq!(response.ips_outside(&cidrs), vec!["8.8.8.8", "garbage"]);
+        assert!(within_any(&"10.0.0.1".parse().unwrap(), &cidrs));
+    }
+}
@@ -0,0 +1,226 @@
+//! Log the panics of a policy, and turn them into well-formed responses when
+//! they can be caught.
+//!
+//! A panic raised while evaluating a request traps the Wasm instance: the
+//! host gets an opaque error, without any hint about what went wrong.
+//! Policies can call [`install_panic_hook`] from their `wapc_init`. After
+//! that, the message and the location of every panic are logged through the
+//! [`KubewardenDrain`](crate::logging::KubewardenDrain) before the instance
+//! traps.
+//!
+//! The `wasm32-wasi` target supports only `panic = "abort"`: deployed
+//! policies cannot catch their panics, the log line is all they get. Only
+//! native builds, like the unit tests of a policy, unwind. There:
+//!
+//! * a panic raised by the `validate` function registered with
+//!   [`register_policy`](crate::register_policy) becomes a rejection, with
+//!   the [`PANIC_ANNOTATION`] audit annotation pointing to its location
+//! * a panic raised by the `validate_settings` function makes the settings
+//!   invalid
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::{panic_handler, register_policy, validate_settings};
+//! use kubewarden_policy_sdk::settings::Validatable;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {}
+//!
+//! impl Validatable for Settings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn validate(_payload: &[u8]) -> wapc_guest::CallResult {
+//!     unimplemented!()
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn wapc_init() {
+//!     panic_handler::install_panic_hook();
+//!     register_policy(validate, validate_settings::<Settings>);
+//! }
+//! ```
+use slog::{error, o, Logger};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::logging::KubewardenDrain;
+use crate::settings::SettingsValidationResponse;
+use crate::GuestFunction;
+
+/// Message of the responses built out of a panic
+pub const PANIC_MESSAGE: &str = "the policy failed unexpectedly";
+/// Key of the audit annotation holding the location of the panic
+pub const PANIC_ANNOTATION: &str = "panic-location";
+/// Code of the rejections built out of a panic
+pub const PANIC_CODE: u16 = 500;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// A panic observed by the hook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+    /// The message given to `panic!`
+    pub message: String,
+    /// Where the panic has been raised, e.g. `src/lib.rs:42:5`
+    pub location: Option<String>,
+}
+
+impl fmt::Display for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{} ({})", self.message, location),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+fn report_panic(info: &PanicHookInfo<'_>) {
+    let report = PanicReport {
+        message: payload_message(info.payload()),
+        location: info.location().map(ToString::to_string),
+    };
+    let logger = Logger::root(KubewardenDrain::new(), o!());
+    error!(logger, "policy panicked";
+        "message" => report.message.as_str(),
+        "location" => report.location.as_deref().unwrap_or("unknown"));
+    LAST_PANIC.with(|last| *last.borrow_mut() = Some(report));
+}
+
+/// Log the panics through the Kubewarden drain. In native builds, the ones
+/// raised by the functions registered with
+/// [`register_policy`](crate::register_policy) are turned into responses,
+/// see the [module documentation](self). The panic hook in place is still
+/// invoked
+pub fn install_panic_hook() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        report_panic(info);
+        previous(info);
+    }));
+}
+
+/// Returns `true` when [`install_panic_hook`] has been invoked
+pub fn panic_hook_installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst)
+}
+
+/// The latest panic observed by the hook, clearing it
+pub fn take_last_panic() -> Option<PanicReport> {
+    LAST_PANIC.with(|last| last.borrow_mut().take())
+}
+
+/// Run `function`, converting its panics into the response built by
+/// `on_panic` once the hook is installed. With `panic = "abort"`, the only
+/// strategy of `wasm32-wasi`, nothing is caught
+fn guard(
+    function: GuestFunction,
+    payload: &[u8],
+    on_panic: fn(PanicReport) -> wapc_guest::CallResult,
+) -> wapc_guest::CallResult {
+    if !panic_hook_installed() {
+        return function(payload);
+    }
+    take_last_panic();
+    panic::catch_unwind(AssertUnwindSafe(|| function(payload))).unwrap_or_else(|payload| {
+        on_panic(take_last_panic().unwrap_or_else(|| PanicReport {
+            message: payload_message(payload.as_ref()),
+            location: None,
+        }))
+    })
+}
+
+/// Run a `validate` function: a panic becomes a rejection
+pub(crate) fn guard_validate(function: GuestFunction, payload: &[u8]) -> wapc_guest::CallResult {
+    guard(function, payload, |report| {
+        crate::reject_request(
+            Some(format!("{}: {}", PANIC_MESSAGE, report.message)),
+            Some(PANIC_CODE),
+            report
+                .location
+                .map(|location| HashMap::from([(PANIC_ANNOTATION.to_string(), location)])),
+            None,
+        )
+    })
+}
+
+/// Run a `validate_settings` function: a panic makes the settings invalid
+pub(crate) fn guard_validate_settings(
+    function: GuestFunction,
+    payload: &[u8],
+) -> wapc_guest::CallResult {
+    guard(function, payload, |report| {
+        Ok(serde_json::to_vec(&SettingsValidationResponse {
+            valid: false,
+            message: Some(format!("{}: {}", PANIC_MESSAGE, report)),
+        })?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::ValidationResponse;
+
+    fn explode(_payload: &[u8]) -> wapc_guest::CallResult {
+        panic!("boom")
+    }
+
+    fn explode_formatted(payload: &[u8]) -> wapc_guest::CallResult {
+        panic!("unexpected payload of {} bytes", payload.len())
+    }
+
+    #[test]
+    fn panic_becomes_rejection() {
+        install_panic_hook();
+        install_panic_hook();
+        assert!(panic_hook_installed());
+
+        let response: ValidationResponse =
+            serde_json::from_slice(&guard_validate(explode, b"{}").unwrap()).unwrap();
+        assert!(!response.accepted);
+        assert_eq!(response.code, Some(PANIC_CODE));
+        assert_eq!(
+            response.message.as_deref(),
+            Some("the policy failed unexpectedly: boom")
+        );
+        assert!(response.audit_annotations.unwrap()[PANIC_ANNOTATION].contains("panic_handler.rs"));
+        assert!(take_last_panic().is_none());
+    }
+
+    #[test]
+    fn panic_invalidates_settings() {
+        install_panic_hook();
+        let response: SettingsValidationResponse =
+            serde_json::from_slice(&guard_validate_settings(explode_formatted, b"{}").unwrap())
+                .unwrap();
+        assert!(!response.valid);
+        assert!(response
+            .message
+            .unwrap()
+            .starts_with("the policy failed unexpectedly: unexpected payload of 2 bytes ("));
+    }
+}
@@ -0,0 +1,173 @@
+//! String patterns that are safe to be configured by the users of a policy.
+//!
+//! [`GlobPattern`] is meant to be used inside of the policy settings (e.g.
+//! registry or namespace allow-lists). It is serialized as a plain string
+//! and is validated when the settings are deserialized, rejecting patterns
+//! that are too complex to be evaluated inside of a WebAssembly guest.
+//!
+//! The `regex-patterns` feature adds `SafeRegex`, a regular expression
+//! validated the same way. It is disabled by default, since the regular
+//! expression engine makes the policies noticeably bigger.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::patterns::GlobPattern;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     allowed_registries: Vec<GlobPattern>,
+//! }
+//!
+//! let settings: Settings = serde_json::from_str(
+//!     r#"{"allowed_registries": ["*.example.com"]}"#,
+//! ).unwrap();
+//! assert!(settings.allowed_registries[0].matches("ghcr.example.com"));
+//! ```
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "regex-patterns")]
+mod safe_regex;
+#[cfg(feature = "regex-patterns")]
+pub use safe_regex::*;
+
+/// Maximum length of a pattern
+pub const MAX_PATTERN_LEN: usize = 1024;
+/// Maximum number of `*` wildcards allowed inside of a [`GlobPattern`]
+pub const MAX_GLOB_WILDCARDS: usize = 16;
+
+/// Match a string against a simple glob expression. `*` matches any sequence
+/// of characters, `?` matches a single character.
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A glob expression: `*` matches any sequence of characters, `?` matches a
+/// single character. The whole string must be matched.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct GlobPattern(String);
+
+impl GlobPattern {
+    /// Returns `true` when `value` matches the pattern
+    pub fn matches(&self, value: &str) -> bool {
+        wildcard_match(&self.0, value)
+    }
+
+    /// The pattern, as provided by the user
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for GlobPattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if pattern.is_empty() {
+            return Err("glob pattern cannot be empty".to_string());
+        }
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!(
+                "glob pattern is too long: {} characters, the limit is {}",
+                pattern.len(),
+                MAX_PATTERN_LEN
+            ));
+        }
+        let wildcards = pattern.matches('*').count();
+        if wildcards > MAX_GLOB_WILDCARDS {
+            return Err(format!(
+                "glob pattern '{}' has too many wildcards: {}, the limit is {}",
+                pattern, wildcards, MAX_GLOB_WILDCARDS
+            ));
+        }
+        Ok(GlobPattern(pattern.to_string()))
+    }
+}
+
+impl TryFrom<String> for GlobPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+impl From<GlobPattern> for String {
+    fn from(pattern: GlobPattern) -> Self {
+        pattern.0
+    }
+}
+
+impl fmt::Display for GlobPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for GlobPattern {
+    fn schema_name() -> String {
+        "GlobPattern".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("*.json", "pod.json"));
+        assert!(wildcard_match("pod-?.json", "pod-1.json"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(!wildcard_match("*.json", "pod.yaml"));
+        assert!(!wildcard_match("pod-?.json", "pod-10.json"));
+    }
+
+    #[test]
+    fn glob_pattern() {
+        let glob: GlobPattern = serde_json::from_value(json!("*.example.com")).unwrap();
+        assert!(glob.matches("registry.example.com"));
+        assert!(!glob.matches("example.com"));
+        assert_eq!(serde_json::to_value(&glob).unwrap(), json!("*.example.com"));
+
+        assert!("".parse::<GlobPattern>().is_err());
+        assert!("*"
+            .repeat(MAX_GLOB_WILDCARDS + 1)
+            .parse::<GlobPattern>()
+            .is_err());
+        assert!("a"
+            .repeat(MAX_PATTERN_LEN + 1)
+            .parse::<GlobPattern>()
+            .is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::MAX_PATTERN_LEN;
+
+/// Maximum size, in bytes, of a compiled [`SafeRegex`]
+pub const MAX_REGEX_SIZE: usize = 256 * 1024;
+
+/// A regular expression with bounded complexity.
+///
+/// The regular expression engine runs in linear time with respect to the
+/// input, which prevents ReDoS attacks. On top of that, the length of the
+/// pattern and the size of the compiled expression are limited.
+///
+/// # Example
+///
+/// ```rust
+/// use kubewarden_policy_sdk::patterns::SafeRegex;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Settings {
+///     allowed_hostnames: Vec<SafeRegex>,
+/// }
+///
+/// let settings: Settings = serde_json::from_str(
+///     r#"{"allowed_hostnames": ["^[a-z]+\\.lan$"]}"#,
+/// ).unwrap();
+/// assert!(settings.allowed_hostnames[0].matches("printer.lan"));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct SafeRegex(Regex);
+
+impl SafeRegex {
+    /// Returns `true` when the regular expression matches inside of `value`.
+    /// Use `^` and `$` to match the whole string
+    pub fn matches(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+
+    /// The regular expression, as provided by the user
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// The compiled regular expression
+    pub fn as_regex(&self) -> &Regex {
+        &self.0
+    }
+}
+
+impl FromStr for SafeRegex {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!(
+                "regular expression is too long: {} characters, the limit is {}",
+                pattern.len(),
+                MAX_PATTERN_LEN
+            ));
+        }
+        RegexBuilder::new(pattern)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_SIZE)
+            .build()
+            .map(SafeRegex)
+            .map_err(|e| format!("invalid regular expression '{}': {}", pattern, e))
+    }
+}
+
+impl TryFrom<String> for SafeRegex {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+impl From<SafeRegex> for String {
+    fn from(regex: SafeRegex) -> Self {
+        regex.0.as_str().to_string()
+    }
+}
+
+impl PartialEq for SafeRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SafeRegex {}
+
+impl fmt::Display for SafeRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for SafeRegex {
+    fn schema_name() -> String {
+        "SafeRegex".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn safe_regex() {
+        let regex: SafeRegex = serde_json::from_value(json!("^[a-z]+\\.lan$")).unwrap();
+        assert!(regex.matches("printer.lan"));
+        assert!(!regex.matches("printer.lan.evil.com"));
+        assert_eq!(
+            serde_json::to_value(&regex).unwrap(),
+            json!("^[a-z]+\\.lan$")
+        );
+        assert_eq!(regex, "^[a-z]+\\.lan$".parse().unwrap());
+
+        assert!(serde_json::from_value::<SafeRegex>(json!("(unclosed")).is_err());
+        assert!("a"
+            .repeat(MAX_PATTERN_LEN + 1)
+            .parse::<SafeRegex>()
+            .is_err());
+        // the compiled program would be too big
+        assert!(r"\w{1000}\w{1000}".parse::<SafeRegex>().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Restrict the priority, and the preemption capabilities, of Pods.
+//!
+//! Pods requesting a high priority can evict other workloads from the
+//! cluster. [`PriorityClassPolicy`] allows only some PriorityClasses to be
+//! used, resolving them through the Kubernetes host capability to check their
+//! value and preemption policy.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use kubewarden_policy_sdk::priority::{PriorityClassPolicy, PriorityVerdict};
+//! use k8s_openapi::api::core::v1::PodSpec;
+//!
+//! let policy: PriorityClassPolicy = serde_json::from_str(
+//!     r#"{"allowedPriorityClasses": ["low", "medium"], "maxValue": 1000, "allowPreemption": false}"#,
+//! ).unwrap();
+//!
+//! let pod_spec = PodSpec {
+//!     priority_class_name: Some("medium".to_string()),
+//!     ..Default::default()
+//! };
+//! match policy.evaluate(&pod_spec).unwrap() {
+//!     PriorityVerdict::Allowed => println!("accepted"),
+//!     verdict => println!("rejected: {}", verdict),
+//! }
+//! ```
+use anyhow::Result;
+use k8s_openapi::api::core::v1::PodSpec;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::host_capabilities::kubernetes::get_priority_class;
+
+/// Preemption policy of the PriorityClasses that never preempt other Pods
+pub const PREEMPTION_POLICY_NEVER: &str = "Never";
+
+/// Restrictions on the PriorityClasses used by Pods
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase", default)]
+pub struct PriorityClassPolicy {
+    /// The PriorityClasses Pods can use. Any class can be used when empty
+    pub allowed_priority_classes: Vec<String>,
+    /// Optional - the highest priority value Pods can request
+    pub max_value: Option<i32>,
+    /// Whether Pods can use PriorityClasses that preempt other Pods.
+    /// Defaults to `true`
+    pub allow_preemption: bool,
+}
+
+impl Default for PriorityClassPolicy {
+    fn default() -> Self {
+        PriorityClassPolicy {
+            allowed_priority_classes: Vec::new(),
+            max_value: None,
+            allow_preemption: true,
+        }
+    }
+}
+
+/// The outcome of [`PriorityClassPolicy::evaluate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PriorityVerdict {
+    /// The Pod can be admitted
+    Allowed,
+    /// The PriorityClass is not part of the allow-list
+    NotAllowed {
+        /// Name of the PriorityClass
+        name: String,
+    },
+    /// The value of the PriorityClass is higher than the allowed one
+    ValueTooHigh {
+        /// Name of the PriorityClass
+        name: String,
+        /// The value of the PriorityClass
+        value: i32,
+        /// The highest value allowed
+        max_value: i32,
+    },
+    /// The PriorityClass allows the Pod to preempt other Pods
+    PreemptionNotAllowed {
+        /// Name of the PriorityClass
+        name: String,
+    },
+}
+
+impl PriorityVerdict {
+    /// Returns `true` when the Pod can be admitted
+    pub fn is_allowed(&self) -> bool {
+        *self == PriorityVerdict::Allowed
+    }
+}
+
+impl fmt::Display for PriorityVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriorityVerdict::Allowed => write!(f, "priority class allowed"),
+            PriorityVerdict::NotAllowed { name } => {
+                write!(f, "priority class '{}' is not allowed", name)
+            }
+            PriorityVerdict::ValueTooHigh {
+                name,
+                value,
+                max_value,
+            } => write!(
+                f,
+                "priority class '{}' has value {}, the maximum allowed is {}",
+                name, value, max_value
+            ),
+            PriorityVerdict::PreemptionNotAllowed { name } => write!(
+                f,
+                "priority class '{}' can preempt other pods, which is not allowed",
+                name
+            ),
+        }
+    }
+}
+
+impl PriorityClassPolicy {
+    /// Evaluate the `priorityClassName` of the given Pod.
+    ///
+    /// Pods that do not set a `priorityClassName` are allowed: they get the
+    /// priority of the global default PriorityClass, which is chosen by the
+    /// cluster administrators. An error is returned when the PriorityClass
+    /// cannot be looked up.
+    pub fn evaluate(&self, pod_spec: &PodSpec) -> Result<PriorityVerdict> {
+        let Some(name) = pod_spec.priority_class_name.as_deref() else {
+            return Ok(PriorityVerdict::Allowed);
+        };
+
+        if !self.allowed_priority_classes.is_empty()
+            && !self
+                .allowed_priority_classes
+                .iter()
+                .any(|allowed| allowed == name)
+        {
+            return Ok(PriorityVerdict::NotAllowed {
+                name: name.to_string(),
+            });
+        }
+
+        if self.max_value.is_none() && self.allow_preemption {
+            return Ok(PriorityVerdict::Allowed);
+        }
+
+        let priority_class = get_priority_class(name)?;
+        if let Some(max_value) = self.max_value {
+            if priority_class.value > max_value {
+                return Ok(PriorityVerdict::ValueTooHigh {
+                    name: name.to_string(),
+                    value: priority_class.value,
+                    max_value,
+                });
+            }
+        }
+        if !self.allow_preemption
+            && priority_class.preemption_policy.as_deref() != Some(PREEMPTION_POLICY_NEVER)
+        {
+            return Ok(PriorityVerdict::PreemptionNotAllowed {
+                name: name.to_string(),
+            });
+        }
+
+        Ok(PriorityVerdict::Allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_capabilities::kubernetes::clear_priority_class_cache;
+    use crate::host_capabilities::kubernetes::tests::mock_wapc;
+    use serial_test::serial;
+
+    fn pod_spec(priority_class_name: Option<&str>) -> PodSpec {
+        PodSpec {
+            priority_class_name: priority_class_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn expect_priority_classes() -> mock_wapc::__host_call::Context {
+        clear_priority_class_cache();
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().returning(|_, _, _, msg| {
+            let req: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            let (value, preemption_policy) = match req["name"].as_str().unwrap() {
+                "low" => (100, "Never"),
+                "medium" => (500, "PreemptLowerPriority"),
+                "high" => (1_000_000, "PreemptLowerPriority"),
+                _ => return Err("not found".into()),
+            };
+            Ok(serde_json::to_vec(&serde_json::json!({
+                "apiVersion": "scheduling.k8s.io/v1",
+                "kind": "PriorityClass",
+                "metadata": {"name": req["name"]},
+                "value": value,
+                "preemptionPolicy": preemption_policy
+            }))
+            .unwrap())
+        });
+        ctx
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy: PriorityClassPolicy = serde_json::from_str("{}").unwrap();
+        assert_eq!(policy, PriorityClassPolicy::default());
+        assert!(policy.allow_preemption);
+        assert_eq!(
+            policy.evaluate(&pod_spec(Some("anything"))).unwrap(),
+            PriorityVerdict::Allowed
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn evaluate_priority_classes() {
+        let _ctx = expect_priority_classes();
+        let policy = PriorityClassPolicy {
+            allowed_priority_classes: vec![
+                "low".to_string(),
+                "medium".to_string(),
+                "high".to_string(),
+            ],
+            max_value: Some(1000),
+            allow_preemption: false,
+        };
+
+        assert!(policy.evaluate(&pod_spec(None)).unwrap().is_allowed());
+        assert!(policy
+            .evaluate(&pod_spec(Some("low")))
+            .unwrap()
+            .is_allowed());
+        assert_eq!(
+            policy.evaluate(&pod_spec(Some("system"))).unwrap(),
+            PriorityVerdict::NotAllowed {
+                name: "system".to_string()
+            }
+        );
+        assert_eq!(
+            policy.evaluate(&pod_spec(Some("medium"))).unwrap(),
+            PriorityVerdict::PreemptionNotAllowed {
+                name: "medium".to_string()
+            }
+        );
+        assert_eq!(
+            policy
+                .evaluate(&pod_spec(Some("high")))
+                .unwrap()
+                .to_string(),
+            "priority class 'high' has value 1000000, the maximum allowed is 1000"
+        );
+        clear_priority_class_cache();
+    }
+
+    #[serial]
+    #[test]
+    fn missing_priority_class() {
+        let _ctx = expect_priority_classes();
+        let policy = PriorityClassPolicy {
+            max_value: Some(1000),
+            ..Default::default()
+        };
+        assert!(policy.evaluate(&pod_spec(Some("missing"))).is_err());
+    }
+
+    #[serial]
+    #[test]
+    fn evaluate_mocked_priority_class() {
+        use k8s_openapi::api::scheduling::v1::PriorityClass;
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+        clear_priority_class_cache();
+        crate::test::mock_priority_class(PriorityClass {
+            metadata: ObjectMeta {
+                name: Some("critical".to_string()),
+                ..Default::default()
+            },
+            value: 2_000_000,
+            ..Default::default()
+        });
+        let policy = PriorityClassPolicy {
+            max_value: Some(1000),
+            ..Default::default()
+        };
+
+        // no expectation is set on the host: the mocked PriorityClass is used
+        assert_eq!(
+            policy.evaluate(&pod_spec(Some("critical"))).unwrap(),
+            PriorityVerdict::ValueTooHigh {
+                name: "critical".to_string(),
+                value: 2_000_000,
+                max_value: 1000,
+            }
+        );
+        clear_priority_class_cache();
+    }
+}
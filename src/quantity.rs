@@ -0,0 +1,237 @@
+//! Parsing and arithmetic of Kubernetes resource quantities
+//! (e.g. `500m`, `1.5Gi`, `2e3`).
+//!
+//! Quantities are parsed into a [`ParsedQuantity`], which stores their exact
+//! value as a number of thousandths. This is the same precision used by
+//! Kubernetes when comparing CPU and memory amounts: fractions smaller than
+//! `1m` are rounded up.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::quantity::ParsedQuantity;
+//!
+//! let requested: ParsedQuantity = "1500m".parse().unwrap();
+//! let limit: ParsedQuantity = "2".parse().unwrap();
+//! assert!(requested < limit);
+//! assert_eq!(requested + "500m".parse().unwrap(), limit);
+//! ```
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
+
+/// An exact Kubernetes quantity, stored as a number of thousandths
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ParsedQuantity {
+    millis: i128,
+}
+
+impl ParsedQuantity {
+    /// A quantity of zero
+    pub const ZERO: ParsedQuantity = ParsedQuantity { millis: 0 };
+
+    /// Create a quantity from a number of thousandths (e.g. millicores)
+    pub fn from_millis(millis: i128) -> Self {
+        ParsedQuantity { millis }
+    }
+
+    /// Create a quantity from a whole number of units (e.g. bytes or pods)
+    pub fn from_units(units: i64) -> Self {
+        ParsedQuantity {
+            millis: i128::from(units) * 1000,
+        }
+    }
+
+    /// The value of the quantity, expressed in thousandths
+    pub fn millis(&self) -> i128 {
+        self.millis
+    }
+
+    /// The value of the quantity, rounded up to a whole number of units
+    pub fn units(&self) -> i128 {
+        self.millis.div_euclid(1000) + i128::from(self.millis.rem_euclid(1000) != 0)
+    }
+
+    /// The approximate value of the quantity
+    pub fn as_f64(&self) -> f64 {
+        self.millis as f64 / 1000.0
+    }
+}
+
+impl FromStr for ParsedQuantity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid quantity '{}'", s);
+
+        let number_len = s
+            .char_indices()
+            .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && "+-".contains(*c))))
+            .map_or(s.len(), |(i, _)| i);
+        let (number, suffix) = s.split_at(number_len);
+
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if (integer.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+            return Err(invalid());
+        }
+        let digits = format!("{}{}", integer, fraction);
+        let mantissa: i128 = digits.parse().map_err(|_| invalid())?;
+        let scale = i32::try_from(fraction.len()).map_err(|_| invalid())?;
+
+        // The value is `mantissa * multiplier * 10^(exponent - scale)`
+        let (multiplier, exponent): (i128, i32) = match suffix {
+            "" => (1, 0),
+            "Ki" => (1 << 10, 0),
+            "Mi" => (1 << 20, 0),
+            "Gi" => (1 << 30, 0),
+            "Ti" => (1 << 40, 0),
+            "Pi" => (1 << 50, 0),
+            "Ei" => (1 << 60, 0),
+            "n" => (1, -9),
+            "u" => (1, -6),
+            "m" => (1, -3),
+            "k" => (1, 3),
+            "M" => (1, 6),
+            "G" => (1, 9),
+            "T" => (1, 12),
+            "P" => (1, 15),
+            "E" => (1, 18),
+            exp => {
+                let exp = exp
+                    .strip_prefix('e')
+                    .or_else(|| exp.strip_prefix('E'))
+                    .ok_or_else(invalid)?;
+                (1, exp.parse().map_err(|_| invalid())?)
+            }
+        };
+
+        let value = mantissa.checked_mul(multiplier).ok_or_else(invalid)?;
+        // thousandths
+        let power = exponent - scale + 3;
+        let millis = if power >= 0 {
+            10i128
+                .checked_pow(power.unsigned_abs())
+                .and_then(|p| value.checked_mul(p))
+                .ok_or_else(invalid)?
+        } else {
+            match 10i128.checked_pow(power.unsigned_abs()) {
+                // round up, as Kubernetes does
+                Some(divisor) => value / divisor + i128::from(value % divisor != 0),
+                None => i128::from(value != 0),
+            }
+        };
+
+        Ok(ParsedQuantity {
+            millis: if negative { -millis } else { millis },
+        })
+    }
+}
+
+#[cfg(feature = "cluster-context")]
+impl TryFrom<&k8s_openapi::apimachinery::pkg::api::resource::Quantity> for ParsedQuantity {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        quantity: &k8s_openapi::apimachinery::pkg::api::resource::Quantity,
+    ) -> Result<Self> {
+        quantity.0.parse()
+    }
+}
+
+impl fmt::Display for ParsedQuantity {
+    /// Whole quantities are printed as plain numbers (e.g. `2`), the other
+    /// ones using the `m` suffix (e.g. `1500m`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.millis % 1000 == 0 {
+            write!(f, "{}", self.millis / 1000)
+        } else {
+            write!(f, "{}m", self.millis)
+        }
+    }
+}
+
+impl Add for ParsedQuantity {
+    type Output = ParsedQuantity;
+
+    fn add(self, other: ParsedQuantity) -> ParsedQuantity {
+        ParsedQuantity {
+            millis: self.millis.saturating_add(other.millis),
+        }
+    }
+}
+
+impl AddAssign for ParsedQuantity {
+    fn add_assign(&mut self, other: ParsedQuantity) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for ParsedQuantity {
+    type Output = ParsedQuantity;
+
+    fn sub(self, other: ParsedQuantity) -> ParsedQuantity {
+        ParsedQuantity {
+            millis: self.millis.saturating_sub(other.millis),
+        }
+    }
+}
+
+impl Sum for ParsedQuantity {
+    fn sum<I: Iterator<Item = ParsedQuantity>>(iter: I) -> ParsedQuantity {
+        iter.fold(ParsedQuantity::ZERO, Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_quantities() {
+        for (input, millis) in [
+            ("0", 0),
+            ("2", 2000),
+            ("500m", 500),
+            ("1.5", 1500),
+            (".5", 500),
+            ("0.1m", 1),
+            ("250u", 1),
+            ("1k", 1_000_000),
+            ("1Ki", 1_024_000),
+            ("1.5Gi", 1_610_612_736_000),
+            ("129M", 129_000_000_000),
+            ("1e3", 1_000_000),
+            ("12E-1", 1200),
+            ("-1", -1000),
+            ("+1", 1000),
+        ] {
+            let quantity: ParsedQuantity = input.parse().unwrap();
+            assert_eq!(quantity.millis(), millis, "{}", input);
+        }
+    }
+
+    #[test]
+    fn invalid_quantities() {
+        for input in ["", "m", "1.2.3", "1Xi", "1 Gi", "1e", "1e400"] {
+            assert!(input.parse::<ParsedQuantity>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn quantity_arithmetic() {
+        let quantities: Vec<ParsedQuantity> = ["100m", "200m", "700m"]
+            .iter()
+            .map(|q| q.parse().unwrap())
+            .collect();
+        let total: ParsedQuantity = quantities.into_iter().sum();
+        assert_eq!(total, ParsedQuantity::from_units(1));
+        assert_eq!(total.to_string(), "1");
+        assert_eq!((total - "1m".parse().unwrap()).to_string(), "999m");
+        assert_eq!(ParsedQuantity::from_millis(1001).units(), 2);
+    }
+}
@@ -0,0 +1,273 @@
+//! Evaluate Pods against the `ResourceQuota` objects of their namespace.
+//!
+//! The API server rejects Pods exceeding a quota with a terse message. Policies
+//! can use [`would_exceed`] to reject them earlier, explaining which quota is
+//! violated and by how much.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use kubewarden_policy_sdk::host_capabilities::kubernetes::get_resource_quotas;
+//! use kubewarden_policy_sdk::quota::would_exceed;
+//! use k8s_openapi::api::core::v1::PodSpec;
+//!
+//! fn check(namespace: &str, pod_spec: &PodSpec) -> anyhow::Result<Option<String>> {
+//!     let quotas = get_resource_quotas(namespace)?;
+//!     let violations = would_exceed(pod_spec, &quotas)?;
+//!     Ok((!violations.is_empty()).then(|| {
+//!         violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; ")
+//!     }))
+//! }
+//! ```
+use anyhow::Result;
+use k8s_openapi::api::core::v1::{Container, PodSpec, ResourceQuota};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::quantity::ParsedQuantity;
+
+/// A quota that would be exceeded by admitting a Pod
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaViolation {
+    /// Name of the `ResourceQuota`
+    pub quota: String,
+    /// The resource being exceeded (e.g. `requests.cpu`)
+    pub resource: String,
+    /// The amount requested by the Pod
+    pub requested: ParsedQuantity,
+    /// The amount already in use inside of the namespace
+    pub used: ParsedQuantity,
+    /// The limit enforced by the quota
+    pub hard: ParsedQuantity,
+}
+
+impl fmt::Display for QuotaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "quota '{}' would be exceeded for {}: requested {}, already used {}, limited to {}",
+            self.quota, self.resource, self.requested, self.used, self.hard
+        )
+    }
+}
+
+/// Returns the quotas that would be exceeded by admitting a Pod with the
+/// given spec.
+///
+/// The `pods`, `cpu`, `memory`, `ephemeral-storage`, `requests.*` and
+/// `limits.*` resources are evaluated, taking into account init containers
+/// and the Pod overhead like the API server does. Quotas restricted through
+/// scopes are skipped, since they might not apply to the Pod. The usage of
+/// each quota is read from its status, as reported by Kubernetes.
+///
+/// An error is returned when a quantity cannot be parsed.
+pub fn would_exceed(pod_spec: &PodSpec, quotas: &[ResourceQuota]) -> Result<Vec<QuotaViolation>> {
+    let usage = pod_usage(pod_spec)?;
+    let mut violations = Vec::new();
+
+    for quota in quotas {
+        let spec = quota.spec.as_ref();
+        if spec.is_some_and(|spec| {
+            spec.scopes.as_ref().is_some_and(|s| !s.is_empty()) || spec.scope_selector.is_some()
+        }) {
+            continue;
+        }
+
+        let status = quota.status.as_ref();
+        let hard = status
+            .and_then(|status| status.hard.as_ref())
+            .or_else(|| spec.and_then(|spec| spec.hard.as_ref()));
+        let used = status.and_then(|status| status.used.as_ref());
+
+        for (resource, hard) in hard.into_iter().flatten() {
+            let Some(requested) = usage.get(resource.as_str()) else {
+                continue;
+            };
+            let hard = ParsedQuantity::try_from(hard)?;
+            let used = used
+                .and_then(|used| used.get(resource))
+                .map(ParsedQuantity::try_from)
+                .transpose()?
+                .unwrap_or_default();
+
+            if used + *requested > hard {
+                violations.push(QuotaViolation {
+                    quota: quota.metadata.name.clone().unwrap_or_default(),
+                    resource: resource.clone(),
+                    requested: *requested,
+                    used,
+                    hard,
+                });
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+/// The amount of each quota resource consumed by a Pod
+fn pod_usage(pod_spec: &PodSpec) -> Result<BTreeMap<&'static str, ParsedQuantity>> {
+    let mut usage = BTreeMap::from([("pods", ParsedQuantity::from_units(1))]);
+
+    // (kind, resource, quota resources)
+    for (kind, resource, keys) in [
+        ("requests", "cpu", &["requests.cpu", "cpu"][..]),
+        ("requests", "memory", &["requests.memory", "memory"]),
+        (
+            "requests",
+            "ephemeral-storage",
+            &["requests.ephemeral-storage", "ephemeral-storage"],
+        ),
+        ("limits", "cpu", &["limits.cpu"]),
+        ("limits", "memory", &["limits.memory"]),
+        ("limits", "ephemeral-storage", &["limits.ephemeral-storage"]),
+    ] {
+        let amount = |container: &Container| -> Result<ParsedQuantity> {
+            let resources = container.resources.as_ref();
+            let quantities = match kind {
+                "requests" => resources.and_then(|r| r.requests.as_ref()),
+                _ => resources.and_then(|r| r.limits.as_ref()),
+            };
+            quantity(quantities, resource)
+        };
+
+        let containers = pod_spec
+            .containers
+            .iter()
+            .map(amount)
+            .sum::<Result<ParsedQuantity>>()?;
+        // init containers run one at a time, before the other containers
+        let init_containers = pod_spec
+            .init_containers
+            .iter()
+            .flatten()
+            .map(amount)
+            .try_fold(ParsedQuantity::ZERO, |max, q| q.map(|q| max.max(q)))?;
+        let overhead = quantity(pod_spec.overhead.as_ref(), resource)?;
+        let total = containers.max(init_containers) + overhead;
+
+        for key in keys {
+            usage.insert(*key, total);
+        }
+    }
+
+    Ok(usage)
+}
+
+fn quantity(
+    quantities: Option<&BTreeMap<String, Quantity>>,
+    resource: &str,
+) -> Result<ParsedQuantity> {
+    quantities
+        .and_then(|q| q.get(resource))
+        .map(ParsedQuantity::try_from)
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{
+        ResourceQuotaSpec, ResourceQuotaStatus, ResourceRequirements,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn quantities(values: &[(&str, &str)]) -> BTreeMap<String, Quantity> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), Quantity(v.to_string())))
+            .collect()
+    }
+
+    fn container(requests: &[(&str, &str)], limits: &[(&str, &str)]) -> Container {
+        Container {
+            resources: Some(ResourceRequirements {
+                requests: Some(quantities(requests)),
+                limits: Some(quantities(limits)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn quota(name: &str, hard: &[(&str, &str)], used: &[(&str, &str)]) -> ResourceQuota {
+        ResourceQuota {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            spec: Some(ResourceQuotaSpec {
+                hard: Some(quantities(hard)),
+                ..Default::default()
+            }),
+            status: Some(ResourceQuotaStatus {
+                hard: Some(quantities(hard)),
+                used: Some(quantities(used)),
+            }),
+        }
+    }
+
+    #[test]
+    fn pod_within_quota() {
+        let pod_spec = PodSpec {
+            containers: vec![container(&[("cpu", "500m")], &[("memory", "1Gi")])],
+            ..Default::default()
+        };
+        let quotas = [quota(
+            "compute",
+            &[
+                ("requests.cpu", "2"),
+                ("limits.memory", "4Gi"),
+                ("pods", "10"),
+            ],
+            &[
+                ("requests.cpu", "1500m"),
+                ("limits.memory", "3Gi"),
+                ("pods", "9"),
+            ],
+        )];
+        assert!(would_exceed(&pod_spec, &quotas).unwrap().is_empty());
+    }
+
+    #[test]
+    fn pod_exceeding_quota() {
+        let pod_spec = PodSpec {
+            containers: vec![
+                container(&[("cpu", "300m")], &[]),
+                container(&[("cpu", "300m")], &[]),
+            ],
+            init_containers: Some(vec![container(&[("memory", "2Gi")], &[])]),
+            ..Default::default()
+        };
+        let quotas = [quota(
+            "compute",
+            &[("cpu", "2"), ("requests.memory", "3Gi"), ("pods", "5")],
+            &[("cpu", "1500m"), ("requests.memory", "1Gi"), ("pods", "5")],
+        )];
+
+        let violations = would_exceed(&pod_spec, &quotas).unwrap();
+        let resources: Vec<&str> = violations.iter().map(|v| v.resource.as_str()).collect();
+        assert_eq!(resources, vec!["cpu", "pods"]);
+        assert_eq!(
+            violations[0].to_string(),
+            "quota 'compute' would be exceeded for cpu: requested 600m, already used 1500m, limited to 2"
+        );
+    }
+
+    #[test]
+    fn scoped_quotas_are_skipped() {
+        let mut scoped = quota("best-effort", &[("pods", "0")], &[]);
+        scoped.spec.as_mut().unwrap().scopes = Some(vec!["BestEffort".to_string()]);
+
+        let violations = would_exceed(&PodSpec::default(), &[scoped]).unwrap();
+        assert!(violations.is_empty());
+    }
+
+    #[test]
+    fn invalid_quantity() {
+        let quotas = [quota("broken", &[("pods", "lots")], &[])];
+        assert!(would_exceed(&PodSpec::default(), &quotas).is_err());
+    }
+}
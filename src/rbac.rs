@@ -0,0 +1,221 @@
+//! Analysis of the permissions granted by Roles and ClusterRoles.
+//!
+//! Policies guarding RBAC resources usually look for the same dangerous
+//! permissions: wildcards, the verbs allowing privilege escalation and the
+//! read access to Secrets. [`analyze_rules`] finds all of them, the policy only
+//! has to decide which [`Finding`]s are acceptable.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::rbac::{analyze_object, FindingKind};
+//! use serde_json::json;
+//!
+//! let role = json!({
+//!     "apiVersion": "rbac.authorization.k8s.io/v1",
+//!     "kind": "Role",
+//!     "metadata": {"name": "reader", "namespace": "team-a"},
+//!     "rules": [{"apiGroups": [""], "resources": ["secrets"], "verbs": ["get"]}]
+//! });
+//!
+//! let findings = analyze_object(&role).unwrap();
+//! assert_eq!(findings[0].kind, FindingKind::SecretsReadAccess);
+//! ```
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::rbac::v1::{ClusterRole, PolicyRule, Role};
+use k8s_openapi::Resource;
+
+use crate::request::ValidationRequest;
+
+/// The verbs granting privilege escalation, see
+/// <https://kubernetes.io/docs/concepts/security/rbac-good-practices/#privilege-escalation-risks>
+pub const ESCALATION_VERBS: [&str; 3] = ["bind", "escalate", "impersonate"];
+
+/// The verbs granting read access to a resource
+pub const READ_VERBS: [&str; 3] = ["get", "list", "watch"];
+
+/// A dangerous permission granted by a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// The rule grants all the verbs
+    WildcardVerb,
+    /// The rule applies to all the resources
+    WildcardResource,
+    /// The rule grants one of the [`ESCALATION_VERBS`]
+    Escalation(String),
+    /// The rule allows reading Secrets
+    SecretsReadAccess,
+}
+
+/// A dangerous permission granted by a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Index of the rule inside of the `rules` list
+    pub rule: usize,
+    /// The permission granted
+    pub kind: FindingKind,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            FindingKind::WildcardVerb => write!(f, "rule {} grants all the verbs", self.rule),
+            FindingKind::WildcardResource => {
+                write!(f, "rule {} applies to all the resources", self.rule)
+            }
+            FindingKind::Escalation(verb) => {
+                write!(f, "rule {} grants the '{}' verb", self.rule, verb)
+            }
+            FindingKind::SecretsReadAccess => {
+                write!(f, "rule {} allows reading Secrets", self.rule)
+            }
+        }
+    }
+}
+
+/// Find the dangerous permissions granted by `rules`. The findings are
+/// sorted by rule
+pub fn analyze_rules(rules: &[PolicyRule]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        let mut push = |kind| findings.push(Finding { rule: index, kind });
+        let verbs = &rule.verbs;
+        let resources = rule.resources.as_deref().unwrap_or_default();
+        let api_groups = rule.api_groups.as_deref().unwrap_or_default();
+
+        let wildcard_verb = verbs.iter().any(|verb| verb == "*");
+        if wildcard_verb {
+            push(FindingKind::WildcardVerb);
+        }
+        if resources.iter().any(|resource| resource == "*") {
+            push(FindingKind::WildcardResource);
+        }
+        for verb in ESCALATION_VERBS {
+            if verbs.iter().any(|v| v == verb) {
+                push(FindingKind::Escalation(verb.to_string()));
+            }
+        }
+
+        let core_group = api_groups
+            .iter()
+            .any(|group| group.is_empty() || group == "*");
+        let secrets = resources
+            .iter()
+            .any(|resource| resource == "secrets" || resource == "*");
+        let read = wildcard_verb || verbs.iter().any(|v| READ_VERBS.contains(&v.as_str()));
+        if core_group && secrets && read {
+            push(FindingKind::SecretsReadAccess);
+        }
+    }
+    findings
+}
+
+/// Find the dangerous permissions granted by a Role or a ClusterRole.
+///
+/// The rules a ClusterRole obtains through its `aggregationRule` are set by
+/// the controller manager, after the admission. They are not analyzed.
+pub fn analyze_object(object: &serde_json::Value) -> Result<Vec<Finding>> {
+    let kind = object.get("kind").and_then(|kind| kind.as_str());
+    let rules = match kind {
+        Some(Role::KIND) => {
+            serde_json::from_value::<Role>(object.clone())
+                .map_err(|e| anyhow!("cannot deserialize Role: {}", e))?
+                .rules
+        }
+        Some(ClusterRole::KIND) => {
+            serde_json::from_value::<ClusterRole>(object.clone())
+                .map_err(|e| anyhow!("cannot deserialize ClusterRole: {}", e))?
+                .rules
+        }
+        _ => {
+            return Err(anyhow!(
+                "Object should be one of these kinds: Role, ClusterRole"
+            ))
+        }
+    };
+    Ok(analyze_rules(rules.as_deref().unwrap_or_default()))
+}
+
+/// Find the dangerous permissions granted by the Role or ClusterRole being
+/// admitted, see [`analyze_object`]
+pub fn analyze_request<T>(request: &ValidationRequest<T>) -> Result<Vec<Finding>>
+where
+    T: Default,
+{
+    analyze_object(&request.request.object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(api_groups: &[&str], resources: &[&str], verbs: &[&str]) -> PolicyRule {
+        PolicyRule {
+            api_groups: Some(api_groups.iter().map(|s| s.to_string()).collect()),
+            resources: Some(resources.iter().map(|s| s.to_string()).collect()),
+            verbs: verbs.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn analyze_dangerous_rules() {
+        let rules = vec![
+            rule(&["apps"], &["deployments"], &["get", "list"]),
+            rule(&["*"], &["*"], &["*"]),
+            rule(
+                &["rbac.authorization.k8s.io"],
+                &["clusterroles"],
+                &["bind", "escalate"],
+            ),
+            rule(&[""], &["users", "groups"], &["impersonate"]),
+            rule(&[""], &["secrets"], &["watch"]),
+            rule(&[""], &["secrets"], &["create"]),
+        ];
+
+        let findings: Vec<(usize, FindingKind)> = analyze_rules(&rules)
+            .into_iter()
+            .map(|finding| (finding.rule, finding.kind))
+            .collect();
+        assert_eq!(
+            findings,
+            vec![
+                (1, FindingKind::WildcardVerb),
+                (1, FindingKind::WildcardResource),
+                (1, FindingKind::SecretsReadAccess),
+                (2, FindingKind::Escalation("bind".to_string())),
+                (2, FindingKind::Escalation("escalate".to_string())),
+                (3, FindingKind::Escalation("impersonate".to_string())),
+                (4, FindingKind::SecretsReadAccess),
+            ]
+        );
+    }
+
+    #[test]
+    fn analyze_cluster_role_object() {
+        let cluster_role = json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {"name": "admin"},
+            "rules": [{"apiGroups": ["apps"], "resources": ["*"], "verbs": ["get"]}]
+        });
+        let findings = analyze_object(&cluster_role).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].to_string(),
+            "rule 0 applies to all the resources"
+        );
+
+        let aggregated = json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {"name": "aggregated"},
+            "aggregationRule": {"clusterRoleSelectors": []}
+        });
+        assert!(analyze_object(&aggregated).unwrap().is_empty());
+
+        let pod = json!({"apiVersion": "v1", "kind": "Pod", "metadata": {}});
+        assert!(analyze_object(&pod).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Validation of the probes of the containers.
+//!
+//! [`check_probes`] ensures the containers define the probes required by the
+//! [`ProbeRequirements`] configured by the policy user, and that the probes
+//! are sane:
+//!
+//! ```yaml
+//! requireLiveness: true
+//! requireReadiness: true
+//! maxInitialDelaySeconds: 60
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::{Container, PodSpec};
+//! use kubewarden_policy_sdk::reliability::{check_probes, ProbeRequirements};
+//!
+//! let pod_spec = PodSpec {
+//!     containers: vec![Container { name: "app".to_string(), ..Default::default() }],
+//!     ..Default::default()
+//! };
+//! let requirements = ProbeRequirements { require_readiness: true, ..Default::default() };
+//!
+//! let violations = check_probes(&pod_spec, &requirements);
+//! assert_eq!(violations[0].path, "containers[0].readinessProbe");
+//! ```
+use k8s_openapi::api::core::v1::{PodSpec, Probe};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Validatable;
+use crate::violations::Violation;
+
+/// Default value of the `timeoutSeconds` of a probe
+pub const DEFAULT_TIMEOUT_SECONDS: i32 = 1;
+/// Default value of the `periodSeconds` of a probe
+pub const DEFAULT_PERIOD_SECONDS: i32 = 10;
+
+/// The probes every container must define
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeRequirements {
+    /// The containers must define a liveness probe
+    #[serde(default)]
+    pub require_liveness: bool,
+    /// The containers must define a readiness probe
+    #[serde(default)]
+    pub require_readiness: bool,
+    /// The containers must define a startup probe
+    #[serde(default)]
+    pub require_startup: bool,
+    /// Optional - the highest `initialDelaySeconds` allowed on the probes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_initial_delay_seconds: Option<i32>,
+}
+
+impl Validatable for ProbeRequirements {
+    fn validate(&self) -> Result<(), String> {
+        match self.max_initial_delay_seconds {
+            Some(delay) if delay < 0 => Err(format!(
+                "maxInitialDelaySeconds cannot be negative, got {}",
+                delay
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Ensure the containers of the Pod define the probes required by
+/// `requirements`, and that all the probes defined are sane: the timeout
+/// must be shorter than the period, and the initial delay must be within the
+/// configured bound.
+///
+/// Init containers are not checked. The paths of the violations are relative
+/// to the PodSpec (e.g. `containers[0].livenessProbe.timeoutSeconds`).
+pub fn check_probes(pod_spec: &PodSpec, requirements: &ProbeRequirements) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (index, container) in pod_spec.containers.iter().enumerate() {
+        let probes = [
+            (
+                "livenessProbe",
+                requirements.require_liveness,
+                &container.liveness_probe,
+            ),
+            (
+                "readinessProbe",
+                requirements.require_readiness,
+                &container.readiness_probe,
+            ),
+            (
+                "startupProbe",
+                requirements.require_startup,
+                &container.startup_probe,
+            ),
+        ];
+        for (field, required, probe) in probes {
+            let path = format!("containers[{}].{}", index, field);
+            match probe {
+                Some(probe) => check_probe(probe, &path, requirements, &mut violations),
+                None if required => violations.push(Violation {
+                    path,
+                    message: format!("container '{}' must define a {}", container.name, field),
+                }),
+                None => {}
+            }
+        }
+    }
+    violations
+}
+
+fn check_probe(
+    probe: &Probe,
+    path: &str,
+    requirements: &ProbeRequirements,
+    violations: &mut Vec<Violation>,
+) {
+    let timeout = probe.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS);
+    let period = probe.period_seconds.unwrap_or(DEFAULT_PERIOD_SECONDS);
+    if timeout >= period {
+        violations.push(Violation {
+            path: format!("{}.timeoutSeconds", path),
+            message: format!(
+                "timeoutSeconds ({}) must be lower than periodSeconds ({})",
+                timeout, period
+            ),
+        });
+    }
+
+    let initial_delay = probe.initial_delay_seconds.unwrap_or_default();
+    if let Some(max_initial_delay) = requirements.max_initial_delay_seconds {
+        if initial_delay > max_initial_delay {
+            violations.push(Violation {
+                path: format!("{}.initialDelaySeconds", path),
+                message: format!(
+                    "initialDelaySeconds ({}) cannot be greater than {}",
+                    initial_delay, max_initial_delay
+                ),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod_spec(spec: serde_json::Value) -> PodSpec {
+        serde_json::from_value(spec).unwrap()
+    }
+
+    #[test]
+    fn required_probes() {
+        let spec = pod_spec(json!({"containers": [
+            {"name": "app", "livenessProbe": {"httpGet": {"port": 8080}}},
+            {"name": "sidecar"}
+        ]}));
+        let requirements = ProbeRequirements {
+            require_liveness: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            check_probes(&spec, &requirements),
+            vec![Violation {
+                path: "containers[1].livenessProbe".to_string(),
+                message: "container 'sidecar' must define a livenessProbe".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn probe_sanity() {
+        let spec = pod_spec(json!({"containers": [{
+            "name": "app",
+            "readinessProbe": {"exec": {"command": ["true"]}, "timeoutSeconds": 10},
+            "startupProbe": {"exec": {"command": ["true"]}, "initialDelaySeconds": 120, "periodSeconds": 5}
+        }]}));
+        let requirements = ProbeRequirements {
+            max_initial_delay_seconds: Some(60),
+            ..Default::default()
+        };
+
+        let paths: Vec<String> = check_probes(&spec, &requirements)
+            .into_iter()
+            .map(|violation| violation.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "containers[0].readinessProbe.timeoutSeconds",
+                "containers[0].startupProbe.initialDelaySeconds",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_requirements() {
+        let requirements: ProbeRequirements =
+            serde_json::from_value(json!({"requireLiveness": true, "maxInitialDelaySeconds": -1}))
+                .unwrap();
+        assert!(requirements.require_liveness);
+        assert!(requirements.validate().is_err());
+    }
+}
@@ -1,13 +1,13 @@
+use crate::wire::WireFormat;
 use anyhow::anyhow;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "cluster-context")] {
-        use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet};
-        use k8s_openapi::api::batch::v1::{CronJob, Job};
-        use k8s_openapi::api::core::v1::{Pod, PodSpec, ReplicationController};
-        use k8s_openapi::Resource;
+        use k8s_openapi::api::core::v1::PodSpec;
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+        use crate::workload::WorkloadKind;
     }
 }
 
@@ -19,6 +19,93 @@ pub struct ValidationRequest<T: Default> {
 
     /// Kubernetes' [AdmissionReview](https://kubernetes.io/docs/reference/access-authn-authz/extensible-admission-controllers/) request
     pub request: KubernetesAdmissionRequest,
+
+    /// Per-request parameters, see [`ValidationRequest::params`]
+    #[serde(default, alias = "configuration", skip_serializing)]
+    params: Option<serde_json::Value>,
+}
+
+impl<T: Default> ValidationRequest<T> {
+    /// Create a `ValidationRequest` without per-request parameters. Useful
+    /// to build requests inside of tests
+    pub fn from_parts(settings: T, request: KubernetesAdmissionRequest) -> Self {
+        ValidationRequest {
+            settings,
+            request,
+            params: None,
+        }
+    }
+
+    /// Set the per-request parameters returned by
+    /// [`ValidationRequest::params`]
+    pub fn with_params(mut self, params: serde_json::Value) -> Self {
+        self.params = Some(params);
+        self
+    }
+}
+
+/// RawValidationRequest holds the data provided to a raw policy at
+/// evaluation time. Raw policies evaluate arbitrary JSON documents, not
+/// wrapped inside of a Kubernetes AdmissionReview.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RawValidationRequest<T: Default> {
+    /// The policy settings
+    pub settings: T,
+
+    /// The JSON document to be evaluated
+    pub request: serde_json::Value,
+}
+
+impl<T> RawValidationRequest<T>
+where
+    T: Default + DeserializeOwned,
+{
+    /// Crates a new `RawValidationRequest` starting from the payload provided
+    /// to the policy at invocation time.
+    ///
+    /// Compressed payloads are decoded, see [`crate::compression`]. The
+    /// payload can be encoded with any of the formats enabled at build
+    /// time, the response uses the same format, see [`crate::wire`].
+    pub fn new(payload: &[u8]) -> anyhow::Result<Self> {
+        decode_payload("raw validation payload", payload)
+    }
+}
+
+/// Decode the payload provided by the host, handling compression and the
+/// wire format negotiated with the host, see [`crate::wire`]
+pub(crate) fn decode_payload<R: DeserializeOwned>(what: &str, payload: &[u8]) -> anyhow::Result<R> {
+    let payload = crate::compression::decode(payload)?;
+    match crate::wire::negotiate(&payload)? {
+        WireFormat::Json => {
+            serde_json::from_slice(&payload).map_err(|e| payload_decoding_error(what, &payload, e))
+        }
+        format => crate::wire::from_binary_slice(format, &payload)
+            .map_err(|e| anyhow!("Error decoding {} ({}): {}", what, format, e)),
+    }
+}
+
+/// Build the error returned when the payload provided by the host cannot be
+/// decoded. The whole payload is included in the message, unless the
+/// `terse-decode-errors` feature is enabled: in that case only the position of
+/// the error is reported, which avoids copying and formatting large objects.
+pub(crate) fn payload_decoding_error(
+    what: &str,
+    payload: &[u8],
+    error: serde_json::Error,
+) -> anyhow::Error {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "terse-decode-errors")] {
+            let _ = payload;
+            anyhow!("Error decoding {}: {}", what, error)
+        } else {
+            anyhow!(
+                "Error decoding {} {}: {:?}",
+                what,
+                String::from_utf8_lossy(payload),
+                error
+            )
+        }
+    }
 }
 
 /// Kubernetes' [AdmissionReview](https://kubernetes.io/docs/reference/access-authn-authz/extensible-admission-controllers/)
@@ -110,7 +197,7 @@ pub struct KubernetesAdmissionRequest {
 }
 
 /// GroupVersionKind unambiguously identifies a kind
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
 #[serde(default)]
 pub struct GroupVersionKind {
     pub group: String,
@@ -119,7 +206,7 @@ pub struct GroupVersionKind {
 }
 
 /// GroupVersionResource unambiguously identifies a resource
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
 #[serde(default)]
 pub struct GroupVersionResource {
     pub group: String,
@@ -127,6 +214,167 @@ pub struct GroupVersionResource {
     pub kind: String,
 }
 
+impl KubernetesAdmissionRequest {
+    /// Returns `true` when the object has been converted by the API server
+    /// before being sent to the policy.
+    ///
+    /// This happens when the policy is registered with `matchPolicy:
+    /// Equivalent` and the request targets an equivalent version of the
+    /// resources the policy is registered for (e.g. `apps/v1beta1` instead of
+    /// `apps/v1` Deployments). In that case `kind` and `resource` describe
+    /// the converted object, while `request_kind` and `request_resource`
+    /// describe the original request. Requests sent by API servers that do
+    /// not set `request_kind` and `request_resource` are never reported as
+    /// converted.
+    pub fn was_converted(&self) -> bool {
+        let kind_converted =
+            self.request_kind != GroupVersionKind::default() && self.request_kind != self.kind;
+        let resource_converted = self.request_resource != GroupVersionKind::default()
+            && (self.request_resource.group != self.resource.group
+                || self.request_resource.version != self.resource.version
+                || self.request_resource.kind != self.resource.kind);
+        kind_converted || resource_converted
+    }
+
+    /// The kind of the original API request, before any conversion
+    /// performed by the API server. See [`Self::was_converted`]
+    pub fn original_gvk(&self) -> &GroupVersionKind {
+        if self.request_kind == GroupVersionKind::default() {
+            &self.kind
+        } else {
+            &self.request_kind
+        }
+    }
+
+    /// The resource of the original API request, before any conversion
+    /// performed by the API server. See [`Self::was_converted`]
+    pub fn original_gvr(&self) -> GroupVersionResource {
+        if self.request_resource == GroupVersionKind::default() {
+            self.resource.clone()
+        } else {
+            GroupVersionResource {
+                group: self.request_resource.group.clone(),
+                version: self.request_resource.version.clone(),
+                kind: self.request_resource.kind.clone(),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "kube")]
+impl KubernetesAdmissionRequest {
+    /// The object being evaluated as a `kube::core::DynamicObject`. This
+    /// gives access to the metadata of objects of any kind, including
+    /// unknown CRDs, through the [`ResourceExt`](kube_core::ResourceExt)
+    /// trait (e.g. `name_any()`, `labels()`, `annotations()`), without
+    /// deserializing them into a concrete type.
+    ///
+    /// The fields that are not part of the metadata are kept inside of the
+    /// `data` field, serializing the `DynamicObject` produces the original
+    /// document again.
+    pub fn dynamic_object(&self) -> anyhow::Result<kube_core::DynamicObject> {
+        serde_json::from_value(self.object.clone())
+            .map_err(|e| anyhow!("cannot convert object into a DynamicObject: {}", e))
+    }
+
+    /// The old object as a `kube::core::DynamicObject`. `None` when the
+    /// request has no old object (e.g. `CREATE` operations)
+    pub fn old_dynamic_object(&self) -> anyhow::Result<Option<kube_core::DynamicObject>> {
+        if self.old_object.is_null() {
+            return Ok(None);
+        }
+        serde_json::from_value(self.old_object.clone())
+            .map(Some)
+            .map_err(|e| anyhow!("cannot convert old object into a DynamicObject: {}", e))
+    }
+}
+
+#[cfg(feature = "kube")]
+impl TryFrom<&KubernetesAdmissionRequest> for kube_core::DynamicObject {
+    type Error = anyhow::Error;
+
+    fn try_from(request: &KubernetesAdmissionRequest) -> anyhow::Result<Self> {
+        request.dynamic_object()
+    }
+}
+
+#[cfg(feature = "kube")]
+impl From<&kube_core::GroupVersionKind> for GroupVersionKind {
+    fn from(gvk: &kube_core::GroupVersionKind) -> Self {
+        GroupVersionKind::new(&gvk.group, &gvk.version, &gvk.kind)
+    }
+}
+
+#[cfg(feature = "kube")]
+impl From<&GroupVersionKind> for kube_core::GroupVersionKind {
+    fn from(gvk: &GroupVersionKind) -> Self {
+        kube_core::GroupVersionKind::gvk(&gvk.group, &gvk.version, &gvk.kind)
+    }
+}
+
+#[cfg(feature = "cluster-context")]
+fn object_metadata(object: &serde_json::Value) -> anyhow::Result<Option<ObjectMeta>> {
+    match object.get("metadata") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(metadata) => ObjectMeta::deserialize(metadata)
+            .map(Some)
+            .map_err(|e| anyhow!("cannot decode object metadata: {}", e)),
+    }
+}
+
+/// Join a group and a version into an `apiVersion` string. Resources of the
+/// core group have an `apiVersion` made only by the version.
+fn join_api_version(group: &str, version: &str) -> String {
+    if group.is_empty() {
+        version.to_string()
+    } else {
+        format!("{}/{}", group, version)
+    }
+}
+
+impl GroupVersionKind {
+    /// Create a new `GroupVersionKind`
+    pub fn new(group: &str, version: &str, kind: &str) -> Self {
+        GroupVersionKind {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+
+    /// Create a new `GroupVersionKind` from the `apiVersion` and `kind`
+    /// fields of a Kubernetes object (e.g. `apps/v1` and `Deployment`)
+    pub fn from_api_version(api_version: &str, kind: &str) -> Self {
+        let (group, version) = api_version.split_once('/').unwrap_or(("", api_version));
+        GroupVersionKind::new(group, version, kind)
+    }
+
+    /// The `apiVersion` of the kind, e.g. `apps/v1` or `v1`
+    pub fn api_version(&self) -> String {
+        join_api_version(&self.group, &self.version)
+    }
+
+    #[cfg(feature = "cluster-context")]
+    /// The `GroupVersionKind` of a type provided by `k8s_openapi`
+    pub fn of<K: k8s_openapi::Resource>() -> Self {
+        GroupVersionKind::new(K::GROUP, K::VERSION, K::KIND)
+    }
+
+    #[cfg(feature = "cluster-context")]
+    /// Returns `true` when this is the `GroupVersionKind` of the given
+    /// `k8s_openapi` type
+    pub fn is<K: k8s_openapi::Resource>(&self) -> bool {
+        self.group == K::GROUP && self.version == K::VERSION && self.kind == K::KIND
+    }
+}
+
+impl GroupVersionResource {
+    /// The `apiVersion` of the resource, e.g. `apps/v1` or `v1`
+    pub fn api_version(&self) -> String {
+        join_api_version(&self.group, &self.version)
+    }
+}
+
 /// UserInfo holds information about the user who made the request
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(default)]
@@ -152,14 +400,94 @@ where
 {
     /// Crates a new `ValidationRequest` starting from the payload provided
     /// to the policy at invocation time.
+    ///
+    /// Compressed payloads are decoded, see [`crate::compression`]. The
+    /// payload can be encoded with any of the formats enabled at build
+    /// time, the response uses the same format, see [`crate::wire`].
+    ///
+    /// Decoding a new admission request, identified by its `uid`, closes the
+    /// circuits of the host capabilities, see
+    /// [`crate::host_capabilities::client`].
     pub fn new(payload: &[u8]) -> anyhow::Result<Self> {
-        serde_json::from_slice::<ValidationRequest<T>>(payload).map_err(|e| {
-            anyhow!(
-                "Error decoding validation payload {}: {:?}",
-                String::from_utf8_lossy(payload),
-                e
-            )
-        })
+        let validation_request: Self = decode_payload("validation payload", payload)?;
+        crate::host_capabilities::client::begin_evaluation(&validation_request.request.uid);
+        Ok(validation_request)
+    }
+
+    /// The per-request parameters, deserialized into `P`. Returns `None` when
+    /// the payload does not provide any parameter.
+    ///
+    /// The parameters are provided alongside the settings under the `params`
+    /// (or `configuration`) key. Policy groups use them to pass the values
+    /// computed by their expressions to the member policies
+    pub fn params<P: DeserializeOwned>(&self) -> anyhow::Result<Option<P>> {
+        self.params
+            .as_ref()
+            .map(|params| {
+                P::deserialize(params).map_err(|e| anyhow!("Error decoding request params: {}", e))
+            })
+            .transpose()
+    }
+
+    /// Returns `true` when the request is about the deletion of an object.
+    /// In this case `object` is empty and the resource being deleted is found
+    /// inside of `old_object`
+    pub fn is_deletion(&self) -> bool {
+        self.request.operation == "DELETE"
+    }
+
+    /// The document the policy should look at: `old_object` for `DELETE`
+    /// operations, `object` otherwise
+    pub fn relevant_object(&self) -> &serde_json::Value {
+        if self.is_deletion() {
+            &self.request.old_object
+        } else {
+            &self.request.object
+        }
+    }
+
+    /// Returns `true` when the finalizer `name` is set on the
+    /// [relevant object](Self::relevant_object)
+    pub fn has_finalizer(&self, name: &str) -> bool {
+        self.relevant_object()["metadata"]["finalizers"]
+            .as_array()
+            .is_some_and(|finalizers| finalizers.iter().any(|f| f.as_str() == Some(name)))
+    }
+
+    /// The grace period of a deletion, expressed in seconds.
+    ///
+    /// For `DELETE` operations this is the `gracePeriodSeconds` of the delete
+    /// options. For other operations, this is the
+    /// `metadata.deletionGracePeriodSeconds` of the object, which is set when
+    /// an object with finalizers is marked for deletion.
+    pub fn deletion_grace_period(&self) -> Option<i64> {
+        if self.is_deletion() {
+            if let Some(seconds) = self
+                .request
+                .options
+                .get("gracePeriodSeconds")
+                .and_then(|s| s.as_i64())
+            {
+                return Some(seconds);
+            }
+        }
+        self.relevant_object()["metadata"]["deletionGracePeriodSeconds"].as_i64()
+    }
+
+    #[cfg(feature = "cluster-context")]
+    /// The metadata of the object being evaluated. Only the `metadata` field
+    /// of the object is deserialized, this works with objects of any kind,
+    /// including unknown CRDs. Returns `None` when the object has no metadata
+    /// (e.g. `DELETE` operations).
+    pub fn metadata(&self) -> anyhow::Result<Option<ObjectMeta>> {
+        object_metadata(&self.request.object)
+    }
+
+    #[cfg(feature = "cluster-context")]
+    /// The metadata of the old object. Returns `None` when the request has
+    /// no old object (e.g. `CREATE` operations).
+    pub fn old_metadata(&self) -> anyhow::Result<Option<ObjectMeta>> {
+        object_metadata(&self.request.old_object)
     }
 
     #[cfg(feature = "cluster-context")]
@@ -168,58 +496,310 @@ where
     /// Objects supported are: Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController, Job, CronJob, Pod
     /// It returns an error if the object is not one of those. If it is a supported object it returns the PodSpec if present, otherwise returns None.
     pub fn extract_pod_spec_from_object(&self) -> anyhow::Result<Option<PodSpec>> {
-        match self.request.kind.kind.as_str() {
-            Deployment::KIND => {
-                let deployment = serde_json::from_value::<Deployment>(self.request.object.clone())?;
-                Ok(deployment.spec.and_then(|spec| spec.template.spec))
-            },
-            ReplicaSet::KIND => {
-                let replicaset = serde_json::from_value::<ReplicaSet>(self.request.object.clone())?;
-                Ok(replicaset.spec.and_then(|spec| spec.template.and_then(|template| template.spec)))
-            },
-            StatefulSet::KIND => {
-                let statefulset = serde_json::from_value::<StatefulSet>(self.request.object.clone())?;
-                Ok(statefulset.spec.and_then(|spec| spec.template.spec))
-            },
-            DaemonSet::KIND => {
-                let daemonset = serde_json::from_value::<DaemonSet>(self.request.object.clone())?;
-                Ok(daemonset.spec.and_then(|spec| spec.template.spec))
-            },
-            ReplicationController::KIND => {
-                let replication_controller = serde_json::from_value::<ReplicationController>(self.request.object.clone())?;
-                Ok(replication_controller.spec.and_then(|spec| spec.template.and_then(|template| template.spec)))
-            },
-            CronJob::KIND => {
-                let cronjob = serde_json::from_value::<CronJob>(self.request.object.clone())?;
-                Ok(cronjob.spec.and_then(|spec| spec.job_template.spec.and_then(|spec| spec.template.spec)))
-            },
-            Job::KIND => {
-                let job = serde_json::from_value::<Job>(self.request.object.clone())?;
-                Ok(job.spec.and_then(|spec| spec.template.spec))
-            },
-            Pod::KIND => {
-                let pod = serde_json::from_value::<Pod>(self.request.object.clone())?;
-                Ok(pod.spec)
-            },
-            _ => {
-                Err(anyhow!("Object should be one of these kinds: Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController, Job, CronJob, Pod"))
-            }
-        }
+        let workload = WorkloadKind::try_from_gvk(&self.request.kind)?;
+        Ok(workload
+            .pod_template(&self.request.object)?
+            .and_then(|template| template.spec))
     }
 }
 
 #[cfg(test)]
-#[cfg(feature = "cluster-context")]
 mod tests {
     use super::*;
-    use k8s_openapi::api::apps::v1::{
-        DaemonSetSpec, DeploymentSpec, ReplicaSetSpec, StatefulSetSpec,
-    };
-    use k8s_openapi::api::batch::v1::{CronJobSpec, JobSpec, JobTemplateSpec};
-    use k8s_openapi::api::core::v1::{ConfigMap, PodTemplateSpec};
+    use serde_json::json;
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "cluster-context")] {
+            use k8s_openapi::api::apps::v1::{
+                DaemonSet, DaemonSetSpec, Deployment, DeploymentSpec, ReplicaSet, ReplicaSetSpec,
+                StatefulSet, StatefulSetSpec,
+            };
+            use k8s_openapi::api::batch::v1::{CronJob, CronJobSpec, Job, JobSpec, JobTemplateSpec};
+            use k8s_openapi::api::core::v1::{ConfigMap, Pod, PodTemplateSpec};
+            use serde::Serialize;
+        }
+    }
+    #[cfg(feature = "kube")]
+    use kube_core::ResourceExt;
+
+    fn validation_request(
+        operation: &str,
+        object: serde_json::Value,
+        old_object: serde_json::Value,
+    ) -> ValidationRequest<()> {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
+                operation: operation.to_string(),
+                object,
+                old_object,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn deletion_looks_at_old_object() {
+        let mut req = validation_request(
+            "DELETE",
+            serde_json::Value::Null,
+            json!({"metadata": {"name": "foo", "finalizers": ["example.com/cleanup"]}}),
+        );
+        req.request
+            .options
+            .insert("gracePeriodSeconds".to_string(), json!(30));
+
+        assert!(req.is_deletion());
+        assert_eq!(req.relevant_object()["metadata"]["name"], "foo");
+        assert!(req.has_finalizer("example.com/cleanup"));
+        assert!(!req.has_finalizer("example.com/other"));
+        assert_eq!(req.deletion_grace_period(), Some(30));
+    }
+
+    #[test]
+    fn update_marking_object_for_deletion() {
+        let req = validation_request(
+            "UPDATE",
+            json!({"metadata": {
+                "name": "foo",
+                "finalizers": ["example.com/cleanup"],
+                "deletionGracePeriodSeconds": 0
+            }}),
+            json!({"metadata": {"name": "foo"}}),
+        );
 
-    use serde::Serialize;
+        assert!(!req.is_deletion());
+        assert!(req.has_finalizer("example.com/cleanup"));
+        assert_eq!(req.deletion_grace_period(), Some(0));
+
+        let req = validation_request("CREATE", json!({"metadata": {}}), serde_json::Value::Null);
+        assert!(!req.has_finalizer("example.com/cleanup"));
+        assert_eq!(req.deletion_grace_period(), None);
+    }
+
+    fn request(request_kind: GroupVersionKind) -> KubernetesAdmissionRequest {
+        KubernetesAdmissionRequest {
+            kind: GroupVersionKind::new("apps", "v1", "Deployment"),
+            resource: GroupVersionResource {
+                group: "apps".to_string(),
+                version: "v1".to_string(),
+                kind: "deployments".to_string(),
+            },
+            request_resource: GroupVersionKind::new(
+                &request_kind.group,
+                &request_kind.version,
+                "deployments",
+            ),
+            request_kind,
+            ..Default::default()
+        }
+    }
 
+    #[test]
+    fn converted_request() {
+        let req = request(GroupVersionKind::new("apps", "v1beta1", "Deployment"));
+        assert!(req.was_converted());
+        assert_eq!(req.original_gvk().api_version(), "apps/v1beta1");
+        assert_eq!(req.original_gvr().api_version(), "apps/v1beta1");
+        assert_eq!(req.original_gvr().kind, "deployments");
+    }
+
+    #[test]
+    fn request_not_converted() {
+        let req = request(GroupVersionKind::new("apps", "v1", "Deployment"));
+        assert!(!req.was_converted());
+        assert_eq!(req.original_gvk(), &req.kind);
+    }
+
+    #[test]
+    fn request_without_conversion_info() {
+        let req = KubernetesAdmissionRequest {
+            kind: GroupVersionKind::new("", "v1", "Pod"),
+            ..Default::default()
+        };
+        assert!(!req.was_converted());
+        assert_eq!(req.original_gvk(), &req.kind);
+        assert_eq!(req.original_gvr(), req.resource);
+    }
+
+    #[test]
+    fn decoding_error_message() {
+        let payload = br#"{"settings": null, "request": 42}"#;
+        let err = ValidationRequest::<()>::new(payload)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Error decoding validation payload"));
+        assert_eq!(
+            err.contains(r#""request": 42"#),
+            cfg!(not(feature = "terse-decode-errors"))
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decode_compressed_payload() {
+        let payload = serde_json::json!({
+            "settings": null,
+            "request": {"uid": "abc", "operation": "CREATE"}
+        })
+        .to_string();
+        for compressed in [
+            crate::compression::tests::gzip(payload.as_bytes()),
+            crate::compression::tests::zstd(payload.as_bytes()),
+        ] {
+            let req = ValidationRequest::<()>::new(&compressed).unwrap();
+            assert_eq!(req.request.uid, "abc");
+            let raw = RawValidationRequest::<()>::new(&compressed).unwrap();
+            assert_eq!(raw.request["uid"], "abc");
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn decode_msgpack_payload() {
+        let payload = rmp_serde::to_vec_named(&serde_json::json!({
+            "settings": null,
+            "request": {"uid": "abc", "operation": "CREATE"}
+        }))
+        .unwrap();
+        let req = ValidationRequest::<()>::new(&payload).unwrap();
+        assert_eq!(req.request.uid, "abc");
+        assert_eq!(crate::wire::response_format(), WireFormat::MessagePack);
+
+        let response = crate::accept_request().unwrap();
+        let response: crate::response::ValidationResponse =
+            rmp_serde::from_slice(&response).unwrap();
+        assert!(response.accepted);
+
+        ValidationRequest::<()>::new(br#"{"settings": null, "request": {}}"#).unwrap();
+        assert_eq!(crate::wire::response_format(), WireFormat::Json);
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Params {
+        replicas: u32,
+    }
+
+    #[test]
+    fn typed_params() {
+        for key in ["params", "configuration"] {
+            let payload = json!({"settings": null, "request": {}, key: {"replicas": 3}});
+            let req = ValidationRequest::<()>::new(payload.to_string().as_bytes()).unwrap();
+            assert_eq!(
+                req.params::<Params>().unwrap(),
+                Some(Params { replicas: 3 })
+            );
+        }
+    }
+
+    #[test]
+    fn missing_or_invalid_params() {
+        let payload = json!({"settings": null, "request": {}});
+        let req = ValidationRequest::<()>::new(payload.to_string().as_bytes()).unwrap();
+        assert_eq!(req.params::<Params>().unwrap(), None);
+        assert!(!serde_json::to_value(&req)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .contains_key("params"));
+
+        let payload = json!({"settings": null, "request": {}, "params": {"replicas": "many"}});
+        let req = ValidationRequest::<()>::new(payload.to_string().as_bytes()).unwrap();
+        assert!(req.params::<Params>().is_err());
+    }
+
+    #[test]
+    fn params_belong_to_their_request() {
+        let payload = json!({"settings": null, "request": {}, "params": {"replicas": 3}});
+        let first = ValidationRequest::<()>::new(payload.to_string().as_bytes()).unwrap();
+
+        let payload = json!({"settings": null, "request": {}});
+        let second = ValidationRequest::<()>::new(payload.to_string().as_bytes()).unwrap();
+        assert_eq!(second.params::<Params>().unwrap(), None);
+        assert_eq!(
+            first.params::<Params>().unwrap(),
+            Some(Params { replicas: 3 })
+        );
+
+        let built = ValidationRequest::from_parts((), KubernetesAdmissionRequest::default());
+        assert_eq!(built.params::<Params>().unwrap(), None);
+        let built = built.with_params(json!({"replicas": 1}));
+        assert_eq!(
+            built.params::<Params>().unwrap(),
+            Some(Params { replicas: 1 })
+        );
+    }
+
+    #[test]
+    fn test_api_version() {
+        assert_eq!(
+            GroupVersionKind::new("apps", "v1", "Deployment").api_version(),
+            "apps/v1"
+        );
+        assert_eq!(GroupVersionKind::new("", "v1", "Pod").api_version(), "v1");
+        assert_eq!(
+            GroupVersionKind::from_api_version("networking.k8s.io/v1", "Ingress"),
+            GroupVersionKind::new("networking.k8s.io", "v1", "Ingress")
+        );
+        assert_eq!(
+            GroupVersionKind::from_api_version("v1", "Pod"),
+            GroupVersionKind::new("", "v1", "Pod")
+        );
+    }
+
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_gvk_of_k8s_openapi_type() {
+        use k8s_openapi::api::apps::v1::Deployment;
+        use k8s_openapi::api::core::v1::Pod;
+
+        let gvk = GroupVersionKind::of::<Deployment>();
+        assert_eq!(gvk, GroupVersionKind::new("apps", "v1", "Deployment"));
+        assert!(gvk.is::<Deployment>());
+        assert!(!gvk.is::<Pod>());
+    }
+
+    #[cfg(feature = "kube")]
+    #[test]
+    fn dynamic_object_preserves_unknown_fields() {
+        let object = json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Widget",
+            "metadata": {
+                "name": "foo",
+                "namespace": "default",
+                "labels": { "app": "widget" },
+                "annotations": { "owner": "team-a" }
+            },
+            "spec": { "size": 3, "color": "blue" },
+            "status": { "ready": true }
+        });
+        let request = KubernetesAdmissionRequest {
+            kind: GroupVersionKind::new("example.com", "v1", "Widget"),
+            object: object.clone(),
+            ..Default::default()
+        };
+
+        let dynamic_object = kube_core::DynamicObject::try_from(&request).unwrap();
+        assert_eq!(dynamic_object.name_any(), "foo");
+        assert_eq!(dynamic_object.labels()["app"], "widget");
+        assert_eq!(dynamic_object.annotations()["owner"], "team-a");
+        assert_eq!(dynamic_object.data["spec"]["size"], 3);
+
+        assert_eq!(serde_json::to_value(&dynamic_object).unwrap(), object);
+        assert!(request.old_dynamic_object().unwrap().is_none());
+    }
+
+    #[cfg(feature = "kube")]
+    #[test]
+    fn gvk_conversion() {
+        let gvk = GroupVersionKind::new("apps", "v1", "Deployment");
+        let kube_gvk = kube_core::GroupVersionKind::from(&gvk);
+        assert_eq!(kube_gvk.api_version(), "apps/v1");
+        assert_eq!(GroupVersionKind::from(&kube_gvk), gvk);
+    }
+
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_deployment() {
         let pod_spec = PodSpec {
@@ -243,6 +823,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_deployment_without_pod_spec() {
         let deployment = Deployment {
@@ -263,6 +844,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_deployment_without_deployment_spec() {
         let deployment = Deployment {
@@ -277,6 +859,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_replicaset() {
         let pod_spec = PodSpec {
@@ -300,6 +883,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_cronjob() {
         let pod_spec = PodSpec {
@@ -329,6 +913,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_job() {
         let pod_spec = PodSpec {
@@ -352,6 +937,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_pod() {
         let pod_spec = PodSpec {
@@ -369,6 +955,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_object_statefulset() {
         let pod_spec = PodSpec {
@@ -392,6 +979,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_object_daemonset() {
         let pod_spec = PodSpec {
@@ -415,6 +1003,7 @@ mod tests {
         )
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_object_not_supported() {
         let configmap = ConfigMap {
@@ -425,6 +1014,7 @@ mod tests {
         assert!(validation_request.extract_pod_spec_from_object().is_err())
     }
 
+    #[cfg(feature = "cluster-context")]
     #[test]
     fn test_extract_pod_spec_from_object_invalid() {
         let validation_request = create_validation_request("invalid", "Pod");
@@ -432,11 +1022,44 @@ mod tests {
         assert!(validation_request.extract_pod_spec_from_object().is_err())
     }
 
+    #[cfg(feature = "cluster-context")]
+    #[test]
+    fn test_metadata_of_unknown_kind() {
+        let mut validation_request = create_validation_request(
+            serde_json::json!({
+                "apiVersion": "example.com/v1",
+                "kind": "Widget",
+                "metadata": {
+                    "name": "foo",
+                    "labels": { "app": "widget" },
+                    "ownerReferences": [{
+                        "apiVersion": "apps/v1",
+                        "kind": "ReplicaSet",
+                        "name": "foo-123",
+                        "uid": "abc"
+                    }]
+                },
+                "spec": { "size": 3 }
+            }),
+            "Widget",
+        );
+
+        let metadata = validation_request.metadata().unwrap().unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("foo"));
+        assert_eq!(metadata.labels.unwrap()["app"], "widget");
+        assert_eq!(metadata.owner_references.unwrap()[0].kind, "ReplicaSet");
+        assert!(validation_request.old_metadata().unwrap().is_none());
+
+        validation_request.request.object = serde_json::json!({"metadata": "invalid"});
+        assert!(validation_request.metadata().is_err());
+    }
+
+    #[cfg(feature = "cluster-context")]
     fn create_validation_request<T: Serialize>(object: T, kind: &str) -> ValidationRequest<()> {
         let value = serde_json::to_value(object).unwrap();
-        ValidationRequest {
-            settings: (),
-            request: KubernetesAdmissionRequest {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
                 kind: GroupVersionKind {
                     kind: kind.to_string(),
                     ..Default::default()
@@ -444,6 +1067,6 @@ mod tests {
                 object: value,
                 ..Default::default()
             },
-        }
+        )
     }
 }
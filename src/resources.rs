@@ -0,0 +1,321 @@
+//! Enforcement of the resource requests and limits of the containers.
+//!
+//! The [`ResourcePolicy`] configured by the policy user describes the
+//! resources every container must request and limit, the highest ratio
+//! between limits and requests, and the defaults injected into the containers
+//! that do not set them, like a `LimitRange` does:
+//!
+//! ```yaml
+//! requiredRequests: [cpu, memory]
+//! requiredLimits: [memory]
+//! maxLimitRequestRatio:
+//!   memory: "2"
+//! defaultRequests:
+//!   cpu: 100m
+//! defaultLimits:
+//!   memory: 512Mi
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::{Container, PodSpec};
+//! use kubewarden_policy_sdk::resources::{evaluate, ResourcePolicy};
+//! use std::collections::BTreeMap;
+//!
+//! let policy = ResourcePolicy {
+//!     required_requests: vec!["cpu".to_string()],
+//!     default_requests: BTreeMap::from([("cpu".to_string(), "100m".to_string())]),
+//!     ..Default::default()
+//! };
+//! let pod_spec = PodSpec {
+//!     containers: vec![Container { name: "app".to_string(), ..Default::default() }],
+//!     ..Default::default()
+//! };
+//!
+//! let evaluation = evaluate(&pod_spec, &policy).unwrap();
+//! assert!(evaluation.violations.is_empty());
+//! let mutated = evaluation.mutated_pod_spec.unwrap();
+//! let requests = mutated.containers[0].resources.as_ref().unwrap().requests.as_ref().unwrap();
+//! assert_eq!(requests["cpu"].0, "100m");
+//! ```
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1::{Container, PodSpec, ResourceRequirements};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::quantity::ParsedQuantity;
+use crate::settings::Validatable;
+use crate::violations::Violation;
+
+/// The requests and limits every container must set
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcePolicy {
+    /// The resources every container must request (e.g. `cpu`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_requests: Vec<String>,
+    /// The resources every container must limit (e.g. `memory`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_limits: Vec<String>,
+    /// The highest ratio between the limit and the request of each resource,
+    /// like the `maxLimitRequestRatio` of a `LimitRange`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max_limit_request_ratio: BTreeMap<String, String>,
+    /// The requests set on the containers that do not define them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_requests: BTreeMap<String, String>,
+    /// The limits set on the containers that do not define them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_limits: BTreeMap<String, String>,
+}
+
+impl Validatable for ResourcePolicy {
+    fn validate(&self) -> Result<(), String> {
+        for (field, quantities) in [
+            ("maxLimitRequestRatio", &self.max_limit_request_ratio),
+            ("defaultRequests", &self.default_requests),
+            ("defaultLimits", &self.default_limits),
+        ] {
+            for (resource, quantity) in quantities {
+                let parsed: ParsedQuantity = quantity
+                    .parse()
+                    .map_err(|e| format!("{}.{}: {}", field, resource, e))?;
+                if parsed <= ParsedQuantity::ZERO {
+                    return Err(format!("{}.{} must be greater than zero", field, resource));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of [`evaluate`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceEvaluation {
+    /// The violations found after injecting the defaults
+    pub violations: Vec<Violation>,
+    /// The PodSpec with the defaults injected, `None` when no default had to
+    /// be injected
+    pub mutated_pod_spec: Option<PodSpec>,
+}
+
+/// Evaluate the requests and limits of the containers, and of the init
+/// containers, of the Pod against `policy`.
+///
+/// The defaults of the policy are injected first, and the requirements are
+/// then checked against the resulting containers. Policies that must not
+/// mutate the requests should not configure any default. The paths of the
+/// violations are relative to the PodSpec (e.g.
+/// `containers[0].resources.limits.memory`).
+///
+/// An error is returned when a quantity cannot be parsed.
+pub fn evaluate(pod_spec: &PodSpec, policy: &ResourcePolicy) -> Result<ResourceEvaluation> {
+    let mut mutated = pod_spec.clone();
+    let mut injected = false;
+    let mut violations = Vec::new();
+
+    let init_containers = mutated.init_containers.iter_mut().flatten();
+    let containers = std::iter::repeat("initContainers")
+        .zip(init_containers.enumerate())
+        .chain(std::iter::repeat("containers").zip(mutated.containers.iter_mut().enumerate()));
+    for (field, (index, container)) in containers {
+        let path = format!("{}[{}].resources", field, index);
+        injected |= inject_defaults(container, policy);
+        check_container(container, &path, policy, &mut violations)?;
+    }
+
+    Ok(ResourceEvaluation {
+        violations,
+        mutated_pod_spec: injected.then_some(mutated),
+    })
+}
+
+/// Set the default requests and limits missing from the container. Returns
+/// `true` when the container has been changed
+fn inject_defaults(container: &mut Container, policy: &ResourcePolicy) -> bool {
+    let mut injected = false;
+    let resources = container.resources.get_or_insert_with(Default::default);
+    for (quantities, defaults) in [
+        (&mut resources.requests, &policy.default_requests),
+        (&mut resources.limits, &policy.default_limits),
+    ] {
+        for (resource, default) in defaults {
+            let quantities = quantities.get_or_insert_with(Default::default);
+            if !quantities.contains_key(resource) {
+                quantities.insert(resource.clone(), Quantity(default.clone()));
+                injected = true;
+            }
+        }
+    }
+    if *resources == ResourceRequirements::default() {
+        container.resources = None;
+    }
+    injected
+}
+
+fn check_container(
+    container: &Container,
+    path: &str,
+    policy: &ResourcePolicy,
+    violations: &mut Vec<Violation>,
+) -> Result<()> {
+    let resources = container.resources.as_ref();
+    let requests = resources.and_then(|r| r.requests.as_ref());
+    let limits = resources.and_then(|r| r.limits.as_ref());
+
+    for (kind, required, quantities) in [
+        ("requests", &policy.required_requests, requests),
+        ("limits", &policy.required_limits, limits),
+    ] {
+        for resource in required {
+            if !quantities.is_some_and(|q| q.contains_key(resource)) {
+                violations.push(Violation {
+                    path: format!("{}.{}.{}", path, kind, resource),
+                    message: format!(
+                        "container '{}' must set the {} of {}",
+                        container.name, kind, resource
+                    ),
+                });
+            }
+        }
+    }
+
+    for (resource, max_ratio) in &policy.max_limit_request_ratio {
+        let quantity = |quantities: Option<&BTreeMap<String, Quantity>>| {
+            quantities
+                .and_then(|q| q.get(resource))
+                .map(ParsedQuantity::try_from)
+                .transpose()
+        };
+        let (Some(request), Some(limit)) = (quantity(requests)?, quantity(limits)?) else {
+            continue;
+        };
+        let max_ratio: ParsedQuantity = max_ratio
+            .parse()
+            .map_err(|e| anyhow!("maxLimitRequestRatio.{}: {}", resource, e))?;
+        if request > ParsedQuantity::ZERO
+            && limit.millis() * 1000 > max_ratio.millis() * request.millis()
+        {
+            violations.push(Violation {
+                path: format!("{}.limits.{}", path, resource),
+                message: format!(
+                    "container '{}': the {} limit ({}) cannot exceed {} times the request ({})",
+                    container.name, resource, limit, max_ratio, request
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod_spec(spec: serde_json::Value) -> PodSpec {
+        serde_json::from_value(spec).unwrap()
+    }
+
+    fn policy(policy: serde_json::Value) -> ResourcePolicy {
+        serde_json::from_value(policy).unwrap()
+    }
+
+    #[test]
+    fn required_requests_and_limits() {
+        let spec = pod_spec(json!({
+            "initContainers": [{"name": "init"}],
+            "containers": [{"name": "app", "resources": {
+                "requests": {"cpu": "100m", "memory": "128Mi"},
+                "limits": {"memory": "128Mi"}
+            }}]
+        }));
+        let policy = policy(json!({
+            "requiredRequests": ["cpu"],
+            "requiredLimits": ["memory"]
+        }));
+
+        let evaluation = evaluate(&spec, &policy).unwrap();
+        assert!(evaluation.mutated_pod_spec.is_none());
+        let paths: Vec<String> = evaluation
+            .violations
+            .into_iter()
+            .map(|violation| violation.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "initContainers[0].resources.requests.cpu",
+                "initContainers[0].resources.limits.memory",
+            ]
+        );
+    }
+
+    #[test]
+    fn limit_request_ratio() {
+        let spec = pod_spec(json!({"containers": [
+            {"name": "fine", "resources": {"requests": {"memory": "1Gi"}, "limits": {"memory": "1536Mi"}}},
+            {"name": "greedy", "resources": {"requests": {"memory": "1Gi"}, "limits": {"memory": "3Gi"}}}
+        ]}));
+        let policy = policy(json!({"maxLimitRequestRatio": {"memory": "1.5"}}));
+
+        let evaluation = evaluate(&spec, &policy).unwrap();
+        assert_eq!(evaluation.violations.len(), 1);
+        assert_eq!(
+            evaluation.violations[0].path,
+            "containers[1].resources.limits.memory"
+        );
+    }
+
+    #[test]
+    fn inject_defaults() {
+        let spec = pod_spec(json!({"containers": [
+            {"name": "app", "resources": {"limits": {"memory": "1Gi"}}},
+            {"name": "sidecar"}
+        ]}));
+        let policy = policy(json!({
+            "requiredRequests": ["cpu"],
+            "requiredLimits": ["memory"],
+            "maxLimitRequestRatio": {"memory": "2"},
+            "defaultRequests": {"cpu": "100m", "memory": "256Mi"},
+            "defaultLimits": {"memory": "512Mi"}
+        }));
+
+        let evaluation = evaluate(&spec, &policy).unwrap();
+        assert_eq!(
+            evaluation.violations,
+            vec![Violation {
+                path: "containers[0].resources.limits.memory".to_string(),
+                message: "container 'app': the memory limit (1073741824) cannot exceed 2 times the request (268435456)".to_string(),
+            }]
+        );
+        let mutated = serde_json::to_value(evaluation.mutated_pod_spec.unwrap()).unwrap();
+        assert_eq!(
+            mutated["containers"][1]["resources"],
+            json!({
+                "requests": {"cpu": "100m", "memory": "256Mi"},
+                "limits": {"memory": "512Mi"}
+            })
+        );
+        assert_eq!(
+            mutated["containers"][0]["resources"]["limits"]["memory"],
+            "1Gi"
+        );
+    }
+
+    #[test]
+    fn validate_policy() {
+        assert!(policy(json!({"defaultLimits": {"memory": "512Mi"}}))
+            .validate()
+            .is_ok());
+        assert!(policy(json!({"defaultLimits": {"memory": "lots"}}))
+            .validate()
+            .is_err());
+        assert!(policy(json!({"maxLimitRequestRatio": {"cpu": "0"}}))
+            .validate()
+            .is_err());
+    }
+}
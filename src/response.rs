@@ -1,6 +1,28 @@
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
+use crate::host_capabilities::policy::PolicyMode;
+use crate::mutation::strip_managed_fields;
+use crate::wire::WireFormat;
+
+/// Default maximum size, in bytes, of a serialized mutated object. This is
+/// the default request size limit of etcd: larger objects cannot be stored
+/// by Kubernetes
+pub const DEFAULT_MAX_MUTATED_OBJECT_SIZE: usize = 1_572_864;
+
+thread_local! {
+    static RESPONSE_SIZE_LIMIT: Cell<ResponseSizeLimit> = const {
+        Cell::new(ResponseSizeLimit {
+            max_bytes: DEFAULT_MAX_MUTATED_OBJECT_SIZE,
+            strategy: OversizeStrategy::Warn,
+        })
+    };
+    static DETERMINISTIC_SERIALIZATION: Cell<bool> = const { Cell::new(false) };
+    static RECORDED_AUDIT_ANNOTATIONS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
 /// A ValidationResponse object holds the outcome of policy
 /// evaluation.
 #[derive(Deserialize, Serialize, Debug)]
@@ -24,3 +46,389 @@ pub struct ValidationResponse {
     /// Warnings over 256 characters and large numbers of warnings may be truncated.
     pub warnings: Option<Vec<String>>,
 }
+
+impl ValidationResponse {
+    /// Add a warning returned to the API client
+    pub fn with_warning(mut self, warning: &str) -> Self {
+        self.warnings
+            .get_or_insert_with(Vec::new)
+            .push(warning.to_string());
+        self
+    }
+
+    /// Add an audit annotation. An existing value of the same key is
+    /// replaced
+    pub fn with_audit_annotation(mut self, key: &str, value: &str) -> Self {
+        self.audit_annotations
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Apply the execution mode of the policy to the response. In monitor
+    /// mode a rejection is turned into an acceptance, with a warning
+    /// explaining the request would have been rejected. The response is left
+    /// untouched otherwise
+    pub fn respecting_mode(self, mode: PolicyMode) -> Self {
+        if self.accepted || mode != PolicyMode::Monitor {
+            return self;
+        }
+        let warning = match &self.message {
+            Some(message) => format!("running in monitor mode, would have rejected: {message}"),
+            None => "running in monitor mode, would have rejected".to_string(),
+        };
+        ValidationResponse {
+            accepted: true,
+            message: None,
+            code: None,
+            mutated_object: None,
+            ..self
+        }
+        .with_warning(&warning)
+    }
+
+    /// Serialize the response, see [`to_vec`](Self::to_vec), so that it can
+    /// be returned by the `validate` waPC function
+    pub fn into_call_result(self) -> wapc_guest::CallResult {
+        Ok(self.to_vec()?)
+    }
+
+    /// Serialize the response. The keys of all the maps, including the ones
+    /// of the mutated object and of the audit annotations, are sorted when
+    /// [`set_deterministic_serialization`] is enabled.
+    ///
+    /// The response is encoded using the format of the request, see
+    /// [`crate::wire::response_format`].
+    ///
+    /// This marks the end of the evaluation: when the `arena` feature is
+    /// enabled, the [`arena`](crate::arena) is reset
+    pub fn to_vec(&self) -> serde_json::Result<Vec<u8>> {
+        let response = match crate::wire::response_format() {
+            WireFormat::Json if deterministic_serialization() => to_canonical_vec(self),
+            WireFormat::Json => serde_json::to_vec(self),
+            format => {
+                let encoded = if deterministic_serialization() {
+                    let mut value = serde_json::to_value(self)?;
+                    sort_keys(&mut value);
+                    crate::wire::to_vec(format, &value)
+                } else {
+                    crate::wire::to_vec(format, self)
+                };
+                encoded.map_err(serde::ser::Error::custom)
+            }
+        };
+        #[cfg(feature = "arena")]
+        crate::arena::reset();
+        response
+    }
+}
+
+/// Serialize responses, and the objects they contain, with sorted map keys
+/// for the lifetime of the policy instance. This makes golden-file tests and
+/// the hashes of the responses stable, regardless of the iteration order of
+/// the maps
+pub fn set_deterministic_serialization(enabled: bool) {
+    DETERMINISTIC_SERIALIZATION.with(|current| current.set(enabled));
+}
+
+/// Whether responses are serialized with sorted map keys
+pub fn deterministic_serialization() -> bool {
+    DETERMINISTIC_SERIALIZATION.with(Cell::get)
+}
+
+/// Serialize `value` to JSON, sorting the keys of all its maps
+pub fn to_canonical_vec<T: Serialize>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let mut value = serde_json::to_value(value)?;
+    sort_keys(&mut value);
+    serde_json::to_vec(&value)
+}
+
+/// Recursively sort the keys of all the maps inside of `value`. This is
+/// required when serde_json is built with the `preserve_order` feature, which
+/// keeps the insertion order of the keys
+pub fn sort_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<(String, serde_json::Value)> =
+                std::mem::take(map).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut child) in entries {
+                sort_keys(&mut child);
+                map.insert(key, child);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Record an audit annotation to be attached to the next response created by
+/// [`accept_request`](crate::accept_request),
+/// [`mutate_request`](crate::mutate_request) or
+/// [`reject_request`](crate::reject_request). This allows helpers deep inside
+/// of the evaluation to explain what they did, e.g. which image reference
+/// has been rewritten.
+///
+/// The annotations are dropped when the next evaluation starts, those of an
+/// evaluation that failed never reach the following response.
+pub fn record_audit_annotation(key: &str, value: &str) {
+    RECORDED_AUDIT_ANNOTATIONS.with(|annotations| {
+        annotations
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string())
+    });
+}
+
+/// Return the audit annotations recorded so far, clearing the internal
+/// registry
+pub fn take_recorded_audit_annotations() -> HashMap<String, String> {
+    RECORDED_AUDIT_ANNOTATIONS.with(|annotations| std::mem::take(&mut *annotations.borrow_mut()))
+}
+
+/// Drop the audit annotations recorded by a previous evaluation
+pub(crate) fn clear_recorded_audit_annotations() {
+    RECORDED_AUDIT_ANNOTATIONS.with(|annotations| annotations.borrow_mut().clear());
+}
+
+/// Merge the recorded audit annotations with the given ones, which take
+/// precedence. Returns `None` when there is no annotation at all
+pub(crate) fn with_recorded_audit_annotations(
+    audit_annotations: Option<HashMap<String, String>>,
+) -> Option<HashMap<String, String>> {
+    let mut recorded = take_recorded_audit_annotations();
+    match audit_annotations {
+        Some(annotations) => {
+            recorded.extend(annotations);
+            Some(recorded)
+        }
+        None if recorded.is_empty() => None,
+        None => Some(recorded),
+    }
+}
+
+/// What to do when a mutated object is larger than the [`ResponseSizeLimit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizeStrategy {
+    /// Return the object as-is, adding a warning to the response
+    #[default]
+    Warn,
+    /// Drop `metadata.managedFields` from the object. An error is returned
+    /// when the object is still too large
+    StripManagedFields,
+    /// Return an error
+    Fail,
+}
+
+/// The size limit enforced on the objects returned by
+/// [`mutate_request`](crate::mutate_request)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseSizeLimit {
+    /// Maximum size, in bytes, of the serialized mutated object
+    pub max_bytes: usize,
+    /// What to do when the limit is exceeded
+    pub strategy: OversizeStrategy,
+}
+
+impl Default for ResponseSizeLimit {
+    fn default() -> Self {
+        ResponseSizeLimit {
+            max_bytes: DEFAULT_MAX_MUTATED_OBJECT_SIZE,
+            strategy: OversizeStrategy::default(),
+        }
+    }
+}
+
+/// Change the size limit enforced by [`mutate_request`](crate::mutate_request)
+/// for the lifetime of the policy instance
+pub fn set_response_size_limit(limit: ResponseSizeLimit) {
+    RESPONSE_SIZE_LIMIT.with(|current| current.set(limit));
+}
+
+/// The size limit currently enforced by [`mutate_request`](crate::mutate_request)
+pub fn response_size_limit() -> ResponseSizeLimit {
+    RESPONSE_SIZE_LIMIT.with(Cell::get)
+}
+
+/// Ensure the serialized `mutated_object` fits inside of the given `limit`,
+/// applying its [`OversizeStrategy`] otherwise.
+///
+/// Returns the warning to be added to the response, if any. An error
+/// describing the size of the object is returned when it cannot be fixed.
+pub fn enforce_size_limit(
+    mutated_object: &mut serde_json::Value,
+    limit: &ResponseSizeLimit,
+) -> Result<Option<String>> {
+    let size = serde_json::to_vec(mutated_object)?.len();
+    if size <= limit.max_bytes {
+        return Ok(None);
+    }
+
+    let too_large = |size: usize| {
+        format!(
+            "the mutated object is {} bytes, exceeding the limit of {} bytes",
+            size, limit.max_bytes
+        )
+    };
+    match limit.strategy {
+        OversizeStrategy::Warn => Ok(Some(too_large(size))),
+        OversizeStrategy::Fail => Err(anyhow!(too_large(size))),
+        OversizeStrategy::StripManagedFields => {
+            strip_managed_fields(mutated_object);
+            let size = serde_json::to_vec(mutated_object)?.len();
+            if size <= limit.max_bytes {
+                Ok(None)
+            } else {
+                Err(anyhow!(
+                    "{}, even without its managed fields",
+                    too_large(size)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object() -> serde_json::Value {
+        json!({
+            "metadata": {
+                "name": "web",
+                "managedFields": [{"manager": "kubectl", "fieldsV1": {"f:spec": {}}}]
+            },
+            "spec": {}
+        })
+    }
+
+    fn limit(strategy: OversizeStrategy) -> ResponseSizeLimit {
+        ResponseSizeLimit {
+            max_bytes: 60,
+            strategy,
+        }
+    }
+
+    #[test]
+    fn objects_within_limit() {
+        let mut obj = object();
+        let res = enforce_size_limit(&mut obj, &ResponseSizeLimit::default()).unwrap();
+        assert!(res.is_none());
+        assert_eq!(obj, object());
+    }
+
+    #[test]
+    fn oversize_strategies() {
+        let mut obj = object();
+        let warning = enforce_size_limit(&mut obj, &limit(OversizeStrategy::Warn)).unwrap();
+        assert!(warning.unwrap().contains("exceeding the limit of 60 bytes"));
+        assert_eq!(obj, object());
+
+        assert!(enforce_size_limit(&mut object(), &limit(OversizeStrategy::Fail)).is_err());
+
+        let mut obj = object();
+        let res = enforce_size_limit(&mut obj, &limit(OversizeStrategy::StripManagedFields));
+        assert!(res.unwrap().is_none());
+        assert_eq!(obj, json!({"metadata": {"name": "web"}, "spec": {}}));
+
+        let tiny = ResponseSizeLimit {
+            max_bytes: 10,
+            strategy: OversizeStrategy::StripManagedFields,
+        };
+        let err = enforce_size_limit(&mut object(), &tiny).unwrap_err();
+        assert!(err.to_string().ends_with("even without its managed fields"));
+    }
+
+    #[test]
+    fn configurable_limit() {
+        assert_eq!(response_size_limit(), ResponseSizeLimit::default());
+        set_response_size_limit(limit(OversizeStrategy::Fail));
+        assert_eq!(response_size_limit().max_bytes, 60);
+        set_response_size_limit(ResponseSizeLimit::default());
+    }
+
+    #[test]
+    fn deterministic_responses() {
+        let response = ValidationResponse {
+            accepted: true,
+            message: None,
+            code: None,
+            mutated_object: Some(json!({"spec": {"b": 1, "a": [{"z": 0, "y": 1}]}, "kind": "Pod"})),
+            audit_annotations: Some(HashMap::from_iter(
+                ["e", "b", "d", "a", "c"].map(|k| (k.to_string(), k.to_string())),
+            )),
+            warnings: None,
+        };
+
+        assert!(!deterministic_serialization());
+        set_deterministic_serialization(true);
+        let serialized = String::from_utf8(response.to_vec().unwrap()).unwrap();
+        set_deterministic_serialization(false);
+
+        assert_eq!(
+            serialized,
+            concat!(
+                r#"{"accepted":true,"audit_annotations":{"a":"a","b":"b","c":"c","d":"d","e":"e"},"#,
+                r#""code":null,"message":null,"#,
+                r#""mutated_object":{"kind":"Pod","spec":{"a":[{"y":1,"z":0}],"b":1}},"#,
+                r#""warnings":null}"#
+            )
+        );
+        assert_eq!(
+            to_canonical_vec(&response).unwrap(),
+            serialized.into_bytes()
+        );
+    }
+
+    #[test]
+    fn recorded_audit_annotations() {
+        assert_eq!(with_recorded_audit_annotations(None), None);
+
+        record_audit_annotation("original-image", "nginx");
+        record_audit_annotation("reason", "recorded");
+        let annotations = with_recorded_audit_annotations(Some(HashMap::from([(
+            "reason".to_string(),
+            "explicit".to_string(),
+        )])))
+        .unwrap();
+        assert_eq!(annotations["original-image"], "nginx");
+        assert_eq!(annotations["reason"], "explicit");
+        assert!(take_recorded_audit_annotations().is_empty());
+    }
+
+    #[test]
+    fn compose_typed_response() {
+        let rejection = || {
+            ValidationResponse {
+                accepted: false,
+                message: Some("privileged".to_string()),
+                code: Some(400),
+                mutated_object: None,
+                audit_annotations: None,
+                warnings: None,
+            }
+            .with_audit_annotation("reason", "privileged")
+            .with_warning("check the docs")
+        };
+
+        let protected = rejection().respecting_mode(PolicyMode::Protect);
+        assert!(!protected.accepted);
+        assert_eq!(protected.code, Some(400));
+
+        let monitored = rejection().respecting_mode(PolicyMode::Monitor);
+        assert!(monitored.accepted);
+        assert!(monitored.message.is_none() && monitored.code.is_none());
+        assert_eq!(
+            monitored.warnings,
+            Some(vec![
+                "check the docs".to_string(),
+                "running in monitor mode, would have rejected: privileged".to_string()
+            ])
+        );
+        assert_eq!(monitored.audit_annotations.unwrap()["reason"], "privileged");
+
+        let raw = protected.into_call_result().unwrap();
+        let decoded: ValidationResponse = serde_json::from_slice(&raw).unwrap();
+        assert_eq!(decoded.message.as_deref(), Some("privileged"));
+    }
+}
@@ -0,0 +1,467 @@
+//! Ship many policies inside of a single WebAssembly module.
+//!
+//! A [`PolicyRouter`] holds a set of named sub-policies, each one with its own
+//! settings and `validate` function. The sub-policy evaluating a request is
+//! chosen by the `policy` key of the settings. The payload is handed over to
+//! the sub-policy untouched, hence its settings type must accept the `policy`
+//! key too (e.g. by not denying unknown fields):
+//!
+//! ```yaml
+//! settings:
+//!   policy: allowed-registries
+//!   registries: ["ghcr.io"]
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::router::PolicyRouter;
+//! use kubewarden_policy_sdk::settings::Validatable;
+//! use kubewarden_policy_sdk::accept_request;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct RegistriesSettings {
+//!     registries: Vec<String>,
+//! }
+//!
+//! impl Validatable for RegistriesSettings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         if self.registries.is_empty() {
+//!             return Err("at least one registry must be allowed".to_string());
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct NoSettings {}
+//!
+//! impl Validatable for NoSettings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn validate_registries(_payload: &[u8]) -> wapc_guest::CallResult {
+//!     accept_request()
+//! }
+//!
+//! fn validate_labels(_payload: &[u8]) -> wapc_guest::CallResult {
+//!     accept_request()
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn wapc_init() {
+//!     PolicyRouter::new()
+//!         .route::<RegistriesSettings>("allowed-registries", validate_registries)
+//!         .route::<NoSettings>("required-labels", validate_labels)
+//!         .register();
+//! }
+//! ```
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+
+use crate::request::decode_payload;
+use crate::settings::{SettingsValidationResponse, Validatable};
+use crate::{register_policy, validate_settings, GuestFunction};
+
+/// Settings key selecting the sub-policy, unless a different one is given
+/// to [`PolicyRouter::with_discriminator`]
+pub const DEFAULT_DISCRIMINATOR: &str = "policy";
+
+thread_local! {
+    static ROUTER: RefCell<Option<PolicyRouter>> = const { RefCell::new(None) };
+}
+
+/// A sub-policy registered into a [`PolicyRouter`]
+#[derive(Clone)]
+struct Route {
+    name: String,
+    validate: GuestFunction,
+    validate_settings: GuestFunction,
+}
+
+/// Dispatch the requests to a set of named sub-policies, see the
+/// [module documentation](self)
+#[derive(Clone)]
+pub struct PolicyRouter {
+    discriminator: String,
+    routes: Vec<Route>,
+}
+
+impl Default for PolicyRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PolicyRouter {
+    /// Create a router selecting the sub-policy through the `policy` key of
+    /// the settings
+    pub fn new() -> Self {
+        Self::with_discriminator(DEFAULT_DISCRIMINATOR)
+    }
+
+    /// Create a router selecting the sub-policy through the `discriminator`
+    /// key of the settings
+    pub fn with_discriminator(discriminator: &str) -> Self {
+        PolicyRouter {
+            discriminator: discriminator.to_string(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Add the sub-policy `name`, whose settings are of type `S` and whose
+    /// requests are evaluated by `validate`. A sub-policy registered with the
+    /// same name is replaced
+    pub fn route<S>(self, name: &str, validate: GuestFunction) -> Self
+    where
+        S: serde::de::DeserializeOwned + Validatable,
+    {
+        self.route_with_settings_validation(name, validate, validate_settings::<S>)
+    }
+
+    /// Add the sub-policy `name`, using a custom function to validate its
+    /// settings (e.g. [`validate_settings_with_context`](crate::validate_settings_with_context)).
+    /// A sub-policy registered with the same name is replaced
+    pub fn route_with_settings_validation(
+        mut self,
+        name: &str,
+        validate: GuestFunction,
+        validate_settings: GuestFunction,
+    ) -> Self {
+        self.routes.retain(|route| route.name != name);
+        self.routes.push(Route {
+            name: name.to_string(),
+            validate,
+            validate_settings,
+        });
+        self
+    }
+
+    /// The names of the sub-policies, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.routes
+            .iter()
+            .map(|route| route.name.as_str())
+            .collect()
+    }
+
+    /// Find the sub-policy selected by `settings`
+    fn select(&self, settings: &Value) -> Result<&Route, String> {
+        let settings = settings
+            .as_object()
+            .ok_or_else(|| "the settings must be an object".to_string())?;
+        let name = match settings.get(&self.discriminator) {
+            Some(Value::String(name)) => name,
+            Some(_) => return Err(format!("'{}' must be a string", self.discriminator)),
+            None => {
+                return Err(format!(
+                    "'{}' must be set to one of: {}",
+                    self.discriminator,
+                    self.names().join(", ")
+                ))
+            }
+        };
+        self.routes
+            .iter()
+            .find(|route| &route.name == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown policy '{}', expected one of: {}",
+                    name,
+                    self.names().join(", ")
+                )
+            })
+    }
+
+    /// Evaluate the request with the sub-policy selected by the settings.
+    /// The payload is the one given to the `validate` waPC function, it is
+    /// forwarded as-is to the sub-policy
+    pub fn validate(&self, payload: &[u8]) -> wapc_guest::CallResult {
+        #[derive(Deserialize)]
+        struct Envelope {
+            #[serde(default)]
+            settings: Value,
+        }
+
+        let envelope: Envelope = decode_payload("validation payload", payload)?;
+        let route = self
+            .select(&envelope.settings)
+            .map_err(|e| anyhow!("{}", e))?;
+        (route.validate)(payload)
+    }
+
+    /// Validate the settings of the sub-policy they select. The payload is the
+    /// one given to the `validate_settings` waPC function: either the
+    /// settings, or the `{"settings": {...}, "context": {...}}` envelope
+    /// described by [`validate_settings_with_context`](crate::validate_settings_with_context).
+    /// It is forwarded as-is to the sub-policy
+    pub fn validate_settings(&self, payload: &[u8]) -> wapc_guest::CallResult {
+        let mut settings: Value = decode_payload("settings", payload)?;
+        if let Some(envelope) = settings.as_object_mut() {
+            if envelope.len() == 2 && envelope.contains_key("context") {
+                if let Some(inner) = envelope.remove("settings") {
+                    settings = inner;
+                }
+            }
+        }
+        let route = match self.select(&settings) {
+            Ok(route) => route,
+            Err(message) => {
+                return Ok(serde_json::to_vec(&SettingsValidationResponse {
+                    valid: false,
+                    message: Some(message),
+                })?)
+            }
+        };
+
+        let response = (route.validate_settings)(payload)?;
+        let mut response: SettingsValidationResponse = serde_json::from_slice(&response)?;
+        response.message = response
+            .message
+            .map(|message| format!("{}: {}", route.name, message));
+        Ok(serde_json::to_vec(&response)?)
+    }
+
+    /// Install the router and register the waPC functions of the Kubewarden
+    /// protocol, see [`register_policy`]. Must be called from `wapc_init`
+    pub fn register(self) {
+        ROUTER.with(|router| *router.borrow_mut() = Some(self));
+        register_policy(validate_guest, validate_settings_guest);
+    }
+}
+
+fn with_router(
+    payload: &[u8],
+    f: fn(&PolicyRouter, &[u8]) -> wapc_guest::CallResult,
+) -> wapc_guest::CallResult {
+    ROUTER.with(|router| match router.borrow().as_ref() {
+        Some(router) => f(router, payload),
+        None => Err(anyhow!("no policy router has been registered").into()),
+    })
+}
+
+/// waPC guest function evaluating the requests with the router installed by
+/// [`PolicyRouter::register`]
+pub fn validate_guest(payload: &[u8]) -> wapc_guest::CallResult {
+    with_router(payload, PolicyRouter::validate)
+}
+
+/// waPC guest function validating the settings with the router installed by
+/// [`PolicyRouter::register`]
+pub fn validate_settings_guest(payload: &[u8]) -> wapc_guest::CallResult {
+    with_router(payload, PolicyRouter::validate_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ValidationRequest;
+    use crate::response::ValidationResponse;
+    use crate::{accept_request, reject_request};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize, Default)]
+    struct ReplicasSettings {
+        max: u32,
+    }
+
+    impl Validatable for ReplicasSettings {
+        fn validate(&self) -> Result<(), String> {
+            if self.max == 0 {
+                return Err("max must be greater than zero".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Deserialize, Default)]
+    struct NoSettings {}
+
+    impl Validatable for NoSettings {
+        fn validate(&self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn validate_replicas(payload: &[u8]) -> wapc_guest::CallResult {
+        let request = ValidationRequest::<ReplicasSettings>::new(payload)?;
+        if request.request.object["spec"]["replicas"].as_u64() > Some(request.settings.max.into()) {
+            return reject_request(Some("too many replicas".to_string()), None, None, None);
+        }
+        accept_request()
+    }
+
+    fn validate_nothing(payload: &[u8]) -> wapc_guest::CallResult {
+        ValidationRequest::<NoSettings>::new(payload)?;
+        accept_request()
+    }
+
+    fn router() -> PolicyRouter {
+        PolicyRouter::new()
+            .route::<ReplicasSettings>("replicas", validate_replicas)
+            .route::<NoSettings>("noop", validate_nothing)
+    }
+
+    fn payload(settings: Value) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "settings": settings,
+            "request": {
+                "uid": "1",
+                "kind": {"group": "apps", "version": "v1", "kind": "Deployment"},
+                "resource": {"group": "apps", "version": "v1", "resource": "deployments"},
+                "requestKind": {"group": "apps", "version": "v1", "kind": "Deployment"},
+                "requestResource": {"group": "apps", "version": "v1", "resource": "deployments"},
+                "name": "web",
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": {},
+                "object": {"spec": {"replicas": 5}}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn settings_response(router: &PolicyRouter, settings: Value) -> SettingsValidationResponse {
+        let response = router
+            .validate_settings(&serde_json::to_vec(&settings).unwrap())
+            .unwrap();
+        serde_json::from_slice(&response).unwrap()
+    }
+
+    #[test]
+    fn dispatch_requests() {
+        let router = router();
+        assert_eq!(router.names(), vec!["replicas", "noop"]);
+
+        let response: ValidationResponse = serde_json::from_slice(
+            &router
+                .validate(&payload(json!({"policy": "replicas", "max": 3})))
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(!response.accepted);
+
+        let response: ValidationResponse = serde_json::from_slice(
+            &router
+                .validate(&payload(json!({"policy": "noop"})))
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(response.accepted);
+
+        assert!(router.validate(&payload(json!({"max": 3}))).is_err());
+    }
+
+    #[test]
+    fn combined_settings_validation() {
+        let router = router();
+
+        assert!(settings_response(&router, json!({"policy": "replicas", "max": 1})).valid);
+        assert!(settings_response(&router, json!({"policy": "noop"})).valid);
+
+        let response = settings_response(&router, json!({"policy": "replicas", "max": 0}));
+        assert!(!response.valid);
+        assert_eq!(
+            response.message.unwrap(),
+            "replicas: max must be greater than zero"
+        );
+
+        let response = settings_response(&router, json!({"policy": "unknown"}));
+        assert_eq!(
+            response.message.unwrap(),
+            "unknown policy 'unknown', expected one of: replicas, noop"
+        );
+        let response = settings_response(&router, json!({}));
+        assert_eq!(
+            response.message.unwrap(),
+            "'policy' must be set to one of: replicas, noop"
+        );
+    }
+
+    #[test]
+    fn settings_with_context() {
+        #[derive(Deserialize)]
+        struct PodsOnlySettings {}
+
+        impl crate::settings::ValidatableWithContext for PodsOnlySettings {
+            fn validate_with_context(
+                &self,
+                context: Option<&crate::settings::SettingsValidationContext>,
+            ) -> Result<(), String> {
+                match context {
+                    Some(context) if !context.targets_only(&["pods"]) => {
+                        Err("only pods are supported".to_string())
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let router = router().route_with_settings_validation(
+            "pods-only",
+            validate_nothing,
+            crate::validate_settings_with_context::<PodsOnlySettings>,
+        );
+        let envelope = |resource: &str| {
+            json!({
+                "settings": {"policy": "pods-only"},
+                "context": {
+                    "policy_name": "pods-only",
+                    "rules": [{
+                        "apiGroups": [""],
+                        "apiVersions": ["v1"],
+                        "resources": [resource],
+                        "operations": ["CREATE"]
+                    }]
+                }
+            })
+        };
+
+        assert!(settings_response(&router, envelope("pods")).valid);
+        let response = settings_response(&router, envelope("services"));
+        assert_eq!(
+            response.message.unwrap(),
+            "pods-only: only pods are supported"
+        );
+        let response = settings_response(
+            &router,
+            json!({"settings": {"policy": "unknown"}, "context": {}}),
+        );
+        assert!(response.message.unwrap().starts_with("unknown policy"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn dispatch_compressed_requests() {
+        let payload = crate::compression::tests::gzip(&payload(json!({"policy": "noop"})));
+        let response: ValidationResponse =
+            serde_json::from_slice(&router().validate(&payload).unwrap()).unwrap();
+        assert!(response.accepted);
+    }
+
+    #[test]
+    fn custom_discriminator() {
+        let router = PolicyRouter::with_discriminator("kind")
+            .route::<NoSettings>("noop", validate_nothing)
+            .route::<ReplicasSettings>("noop", validate_replicas);
+
+        assert_eq!(router.names(), vec!["noop"]);
+        assert!(settings_response(&router, json!({"kind": "noop", "max": 2})).valid);
+    }
+
+    #[test]
+    fn guest_functions_without_router() {
+        ROUTER.with(|router| router.borrow_mut().take());
+        assert!(validate_guest(&payload(json!({"policy": "noop"}))).is_err());
+
+        ROUTER.with(|r| *r.borrow_mut() = Some(router()));
+        assert!(validate_guest(&payload(json!({"policy": "noop"}))).is_ok());
+        ROUTER.with(|router| router.borrow_mut().take());
+    }
+}
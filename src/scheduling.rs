@@ -0,0 +1,223 @@
+//! Validation of the high availability of workloads.
+//!
+//! Replicas of a workload survive the loss of a node, or of a whole zone, only
+//! when the scheduler is told to spread them. [`validate_spread`] ensures the
+//! PodSpec does so, either with `topologySpreadConstraints` or with pod
+//! anti-affinity terms, according to the [`SpreadRequirements`] configured by
+//! the policy user:
+//!
+//! ```yaml
+//! topologyKeys:
+//!   - topology.kubernetes.io/zone
+//! maxSkew: 1
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::PodSpec;
+//! use kubewarden_policy_sdk::scheduling::{validate_spread, SpreadRequirements, ZONE_TOPOLOGY_KEY};
+//!
+//! let requirements = SpreadRequirements {
+//!     topology_keys: vec![ZONE_TOPOLOGY_KEY.to_string()],
+//!     ..Default::default()
+//! };
+//!
+//! let violations = validate_spread(&PodSpec::default(), &requirements);
+//! assert_eq!(violations[0].path, "topologySpreadConstraints");
+//! ```
+use k8s_openapi::api::core::v1::PodSpec;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Validatable;
+use crate::violations::Violation;
+
+/// Topology key of the zones of the cluster
+pub const ZONE_TOPOLOGY_KEY: &str = "topology.kubernetes.io/zone";
+/// Topology key of the nodes of the cluster
+pub const HOSTNAME_TOPOLOGY_KEY: &str = "kubernetes.io/hostname";
+
+/// How the Pods of a workload must be spread
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SpreadRequirements {
+    /// The Pods must be spread across all these topology domains (e.g.
+    /// `topology.kubernetes.io/zone`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topology_keys: Vec<String>,
+    /// Optional - the highest `maxSkew` allowed on the topology spread
+    /// constraints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_skew: Option<i32>,
+    /// Accept the constraints the scheduler is allowed to ignore: topology
+    /// spread constraints with `whenUnsatisfiable: ScheduleAnyway` and
+    /// preferred pod anti-affinity terms. Defaults to `false`
+    #[serde(default)]
+    pub allow_soft_constraints: bool,
+}
+
+impl Validatable for SpreadRequirements {
+    fn validate(&self) -> Result<(), String> {
+        if self.topology_keys.iter().any(|key| key.trim().is_empty()) {
+            return Err("topology keys cannot be empty".to_string());
+        }
+        match self.max_skew {
+            Some(max_skew) if max_skew < 1 => Err(format!(
+                "maxSkew must be greater than zero, got {}",
+                max_skew
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Ensure the PodSpec spreads its Pods according to the `requirements`.
+///
+/// Each of the topology keys must be covered either by a topology spread
+/// constraint or by a pod anti-affinity term. The paths of the violations are
+/// relative to the PodSpec (e.g. `topologySpreadConstraints[0].maxSkew`).
+pub fn validate_spread(pod_spec: &PodSpec, requirements: &SpreadRequirements) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let constraints = pod_spec
+        .topology_spread_constraints
+        .as_deref()
+        .unwrap_or_default();
+
+    for (index, constraint) in constraints.iter().enumerate() {
+        if let Some(max_skew) = requirements.max_skew {
+            if constraint.max_skew > max_skew {
+                violations.push(Violation {
+                    path: format!("topologySpreadConstraints[{}].maxSkew", index),
+                    message: format!(
+                        "maxSkew of '{}' cannot be greater than {}",
+                        constraint.topology_key, max_skew
+                    ),
+                });
+            }
+        }
+    }
+
+    let anti_affinity = pod_spec
+        .affinity
+        .as_ref()
+        .and_then(|affinity| affinity.pod_anti_affinity.as_ref());
+    let required_terms = anti_affinity
+        .and_then(|anti_affinity| {
+            anti_affinity
+                .required_during_scheduling_ignored_during_execution
+                .as_ref()
+        })
+        .into_iter()
+        .flatten();
+    let preferred_terms = anti_affinity
+        .and_then(|anti_affinity| {
+            anti_affinity
+                .preferred_during_scheduling_ignored_during_execution
+                .as_ref()
+        })
+        .into_iter()
+        .flatten()
+        .filter(|_| requirements.allow_soft_constraints)
+        .map(|weighted| &weighted.pod_affinity_term);
+    let anti_affinity_keys: Vec<&str> = required_terms
+        .chain(preferred_terms)
+        .map(|term| term.topology_key.as_str())
+        .collect();
+
+    for key in &requirements.topology_keys {
+        let spread = constraints.iter().any(|constraint| {
+            constraint.topology_key == *key
+                && (requirements.allow_soft_constraints
+                    || constraint.when_unsatisfiable == "DoNotSchedule")
+        });
+        if !spread && !anti_affinity_keys.contains(&key.as_str()) {
+            violations.push(Violation {
+                path: "topologySpreadConstraints".to_string(),
+                message: format!("the Pods must be spread across '{}'", key),
+            });
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod_spec(spec: serde_json::Value) -> PodSpec {
+        serde_json::from_value(spec).unwrap()
+    }
+
+    fn requirements(allow_soft_constraints: bool) -> SpreadRequirements {
+        SpreadRequirements {
+            topology_keys: vec![
+                ZONE_TOPOLOGY_KEY.to_string(),
+                HOSTNAME_TOPOLOGY_KEY.to_string(),
+            ],
+            max_skew: Some(1),
+            allow_soft_constraints,
+        }
+    }
+
+    #[test]
+    fn spread_with_constraints_and_anti_affinity() {
+        let spec = pod_spec(json!({
+            "containers": [],
+            "topologySpreadConstraints": [{
+                "topologyKey": "topology.kubernetes.io/zone",
+                "maxSkew": 1,
+                "whenUnsatisfiable": "DoNotSchedule"
+            }],
+            "affinity": {"podAntiAffinity": {"requiredDuringSchedulingIgnoredDuringExecution": [
+                {"topologyKey": "kubernetes.io/hostname", "labelSelector": {"matchLabels": {"app": "web"}}}
+            ]}}
+        }));
+        assert!(validate_spread(&spec, &requirements(false)).is_empty());
+    }
+
+    #[test]
+    fn soft_constraints() {
+        let spec = pod_spec(json!({
+            "containers": [],
+            "topologySpreadConstraints": [{
+                "topologyKey": "topology.kubernetes.io/zone",
+                "maxSkew": 2,
+                "whenUnsatisfiable": "ScheduleAnyway"
+            }],
+            "affinity": {"podAntiAffinity": {"preferredDuringSchedulingIgnoredDuringExecution": [
+                {"weight": 100, "podAffinityTerm": {"topologyKey": "kubernetes.io/hostname"}}
+            ]}}
+        }));
+
+        assert_eq!(
+            validate_spread(&spec, &requirements(false)),
+            vec![
+                Violation {
+                    path: "topologySpreadConstraints[0].maxSkew".to_string(),
+                    message: "maxSkew of 'topology.kubernetes.io/zone' cannot be greater than 1"
+                        .to_string(),
+                },
+                Violation {
+                    path: "topologySpreadConstraints".to_string(),
+                    message: "the Pods must be spread across 'topology.kubernetes.io/zone'"
+                        .to_string(),
+                },
+                Violation {
+                    path: "topologySpreadConstraints".to_string(),
+                    message: "the Pods must be spread across 'kubernetes.io/hostname'".to_string(),
+                },
+            ]
+        );
+        assert_eq!(validate_spread(&spec, &requirements(true)).len(), 1);
+    }
+
+    #[test]
+    fn validate_requirements() {
+        assert!(requirements(false).validate().is_ok());
+        let requirements: SpreadRequirements =
+            serde_json::from_value(json!({"topologyKeys": [""], "maxSkew": 0})).unwrap();
+        assert!(requirements.validate().is_err());
+    }
+}
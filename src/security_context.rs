@@ -0,0 +1,488 @@
+//! Normalization of the seccomp and AppArmor profiles applied to containers.
+//!
+//! Profiles can be set on the container, on the Pod, and for AppArmor also
+//! through the legacy `container.apparmor.security.beta.kubernetes.io/<name>`
+//! annotation. [`effective_seccomp`] and [`effective_apparmor`] implement the
+//! precedence rules of Kubernetes and return a normalized [`Profile`].
+//!
+//! The module also provides the checks about the host namespaces and the
+//! sysctls defined by the baseline Pod Security Standard.
+//!
+//! seccomp, AppArmor and sysctls do not exist on Windows: the helpers skip the
+//! Pods declaring `spec.os.name: windows`. Like Pod Security Admission, the
+//! `kubernetes.io/os` node selector is not trusted for that, any Pod could
+//! set it to escape the checks.
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::PodSpec;
+//! use kubewarden_policy_sdk::security_context::{effective_seccomp, Profile};
+//! use serde_json::json;
+//!
+//! let pod_spec: PodSpec = serde_json::from_value(json!({
+//!     "securityContext": {"seccompProfile": {"type": "RuntimeDefault"}},
+//!     "containers": [{"name": "app", "image": "app:1.0"}]
+//! }))
+//! .unwrap();
+//!
+//! let profile = effective_seccomp(&pod_spec.containers[0], &pod_spec).unwrap();
+//! assert_eq!(profile, Some(Profile::RuntimeDefault));
+//! ```
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1::{Container, PodSpec, SeccompProfile};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+use crate::violations::Violation;
+
+/// Prefix of the legacy annotation setting the AppArmor profile of a
+/// container, the name of the container follows
+pub const APPARMOR_ANNOTATION_PREFIX: &str = "container.apparmor.security.beta.kubernetes.io/";
+
+/// The sysctls allowed by the baseline Pod Security Standard, see
+/// <https://kubernetes.io/docs/concepts/security/pod-security-standards/#baseline>
+pub const SAFE_SYSCTLS: &[&str] = &[
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.ip_unprivileged_port_start",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.ip_local_reserved_ports",
+    "net.ipv4.tcp_keepalive_time",
+    "net.ipv4.tcp_fin_timeout",
+    "net.ipv4.tcp_keepalive_intvl",
+    "net.ipv4.tcp_keepalive_probes",
+];
+
+/// Label of the nodes holding their operating system
+pub const NODE_OS_LABEL: &str = "kubernetes.io/os";
+
+/// The operating system a Pod runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PodOs {
+    Linux,
+    Windows,
+}
+
+/// Detect the operating system of the Pod, looking at `spec.os.name` first
+/// and then at the `kubernetes.io/os` node selector.
+///
+/// `None` is returned when the operating system is not declared, which
+/// usually means the Pod runs on Linux.
+///
+/// The node selector is only a scheduling hint, the result must not be used
+/// to skip security checks: only `spec.os.name` is validated by Kubernetes.
+pub fn pod_os(pod_spec: &PodSpec) -> Option<PodOs> {
+    let os = pod_spec.os.as_ref().map(|os| os.name.as_str()).or_else(|| {
+        pod_spec
+            .node_selector
+            .as_ref()
+            .and_then(|selector| selector.get(NODE_OS_LABEL))
+            .map(String::as_str)
+    });
+    match os {
+        Some("linux") => Some(PodOs::Linux),
+        Some("windows") => Some(PodOs::Windows),
+        _ => None,
+    }
+}
+
+/// Whether the Pod declares to be a Windows one through `spec.os.name`, the
+/// only field Pod Security Admission trusts to relax the Linux checks
+fn is_windows(pod_spec: &PodSpec) -> bool {
+    pod_spec.os.as_ref().is_some_and(|os| os.name == "windows")
+}
+
+/// A seccomp or AppArmor profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Profile {
+    /// No profile is enforced
+    Unconfined,
+    /// The default profile of the container runtime
+    RuntimeDefault,
+    /// A profile loaded on the node. Holds the path of the seccomp profile,
+    /// relative to the kubelet seccomp directory, or the name of the
+    /// AppArmor profile
+    Localhost(String),
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Profile::Unconfined => write!(f, "Unconfined"),
+            Profile::RuntimeDefault => write!(f, "RuntimeDefault"),
+            Profile::Localhost(profile) => write!(f, "Localhost/{}", profile),
+        }
+    }
+}
+
+/// Build a [`Profile`] out of the `type` and `localhostProfile` fields
+/// shared by the seccomp and AppArmor profiles
+fn profile_from_fields(type_: &str, localhost_profile: Option<&str>) -> Result<Profile> {
+    match type_ {
+        "Unconfined" => Ok(Profile::Unconfined),
+        "RuntimeDefault" => Ok(Profile::RuntimeDefault),
+        "Localhost" => localhost_profile
+            .filter(|profile| !profile.is_empty())
+            .map(|profile| Profile::Localhost(profile.to_string()))
+            .ok_or_else(|| anyhow!("localhostProfile must be set for Localhost profiles")),
+        _ => Err(anyhow!("unknown profile type '{}'", type_)),
+    }
+}
+
+fn seccomp_profile(profile: &SeccompProfile) -> Result<Profile> {
+    profile_from_fields(&profile.type_, profile.localhost_profile.as_deref())
+}
+
+/// The seccomp profile applied to `container`, which belongs to the Pod
+/// defined by `pod_spec`. The profile of the container takes precedence over
+/// the one of the Pod.
+///
+/// `None` is returned when no profile is set: the container runs
+/// unconfined, unless the kubelet enables the `RuntimeDefault` profile by
+/// default. `None` is also returned for Windows Pods.
+pub fn effective_seccomp(container: &Container, pod_spec: &PodSpec) -> Result<Option<Profile>> {
+    if is_windows(pod_spec) {
+        return Ok(None);
+    }
+    let container_profile = container
+        .security_context
+        .as_ref()
+        .and_then(|sc| sc.seccomp_profile.as_ref());
+    let pod_profile = pod_spec
+        .security_context
+        .as_ref()
+        .and_then(|sc| sc.seccomp_profile.as_ref());
+    container_profile
+        .or(pod_profile)
+        .map(seccomp_profile)
+        .transpose()
+}
+
+/// Parse the value of the legacy AppArmor annotation: `runtime/default`,
+/// `localhost/<profile>` or `unconfined`
+pub fn parse_apparmor_annotation(value: &str) -> Result<Profile> {
+    match value {
+        "runtime/default" => Ok(Profile::RuntimeDefault),
+        "unconfined" => Ok(Profile::Unconfined),
+        _ => value
+            .strip_prefix("localhost/")
+            .filter(|profile| !profile.is_empty())
+            .map(|profile| Profile::Localhost(profile.to_string()))
+            .ok_or_else(|| anyhow!("invalid AppArmor profile annotation '{}'", value)),
+    }
+}
+
+/// The AppArmor profile applied to `container`, which belongs to the Pod
+/// defined by `pod_metadata` and `pod_spec`.
+///
+/// The `appArmorProfile` field of the container has the highest precedence,
+/// followed by the legacy annotation of the container and by the
+/// `appArmorProfile` field of the Pod. The fields are taken into account only
+/// when the enabled Kubernetes version is 1.30 or newer.
+///
+/// `None` is returned when no profile is set: the container runtime applies
+/// its default profile, when AppArmor is enabled on the node. `None` is also
+/// returned for Windows Pods.
+pub fn effective_apparmor(
+    container: &Container,
+    pod_metadata: &ObjectMeta,
+    pod_spec: &PodSpec,
+) -> Result<Option<Profile>> {
+    if is_windows(pod_spec) {
+        return Ok(None);
+    }
+    if let Some(profile) = container_apparmor_field(container)? {
+        return Ok(Some(profile));
+    }
+    let annotation = pod_metadata.annotations.as_ref().and_then(|annotations| {
+        annotations.get(&format!("{}{}", APPARMOR_ANNOTATION_PREFIX, container.name))
+    });
+    if let Some(annotation) = annotation {
+        return parse_apparmor_annotation(annotation).map(Some);
+    }
+    pod_apparmor_field(pod_spec)
+}
+
+/// Returns `true` when the Pod uses the network namespace of the host
+pub fn uses_host_network(pod_spec: &PodSpec) -> bool {
+    pod_spec.host_network == Some(true)
+}
+
+/// Returns `true` when the Pod uses the PID namespace of the host
+pub fn uses_host_pid(pod_spec: &PodSpec) -> bool {
+    pod_spec.host_pid == Some(true)
+}
+
+/// Returns `true` when the Pod uses the IPC namespace of the host
+pub fn uses_host_ipc(pod_spec: &PodSpec) -> bool {
+    pod_spec.host_ipc == Some(true)
+}
+
+/// Find the sysctls of the Pod that are neither part of [`SAFE_SYSCTLS`] nor
+/// of `allowed`. Like the `--allowed-unsafe-sysctls` flag of the kubelet,
+/// the entries of `allowed` ending with `*` match all the sysctls starting
+/// with the given prefix (e.g. `net.core.*`).
+///
+/// The paths of the violations are relative to the PodSpec (e.g.
+/// `securityContext.sysctls[0].name`). Windows Pods cannot set sysctls, no
+/// violation is reported for them.
+pub fn unsafe_sysctls(pod_spec: &PodSpec, allowed: &[&str]) -> Vec<Violation> {
+    if is_windows(pod_spec) {
+        return Vec::new();
+    }
+    let is_allowed = |name: &str| {
+        SAFE_SYSCTLS.contains(&name)
+            || allowed
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => *pattern == name,
+                })
+    };
+
+    pod_spec
+        .security_context
+        .as_ref()
+        .and_then(|sc| sc.sysctls.as_ref())
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .filter(|(_, sysctl)| !is_allowed(&sysctl.name))
+        .map(|(index, sysctl)| Violation {
+            path: format!("securityContext.sysctls[{}].name", index),
+            message: format!("sysctl '{}' is not allowed", sysctl.name),
+        })
+        .collect()
+}
+
+k8s_openapi::k8s_if_ge_1_30! {
+    fn container_apparmor_field(container: &Container) -> Result<Option<Profile>> {
+        container
+            .security_context
+            .as_ref()
+            .and_then(|sc| sc.app_armor_profile.as_ref())
+            .map(|p| profile_from_fields(&p.type_, p.localhost_profile.as_deref()))
+            .transpose()
+    }
+
+    fn pod_apparmor_field(pod_spec: &PodSpec) -> Result<Option<Profile>> {
+        pod_spec
+            .security_context
+            .as_ref()
+            .and_then(|sc| sc.app_armor_profile.as_ref())
+            .map(|p| profile_from_fields(&p.type_, p.localhost_profile.as_deref()))
+            .transpose()
+    }
+}
+
+k8s_openapi::k8s_if_le_1_29! {
+    fn container_apparmor_field(_container: &Container) -> Result<Option<Profile>> {
+        Ok(None)
+    }
+
+    fn pod_apparmor_field(_pod_spec: &PodSpec) -> Result<Option<Profile>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod(object: serde_json::Value) -> (ObjectMeta, PodSpec) {
+        let pod: k8s_openapi::api::core::v1::Pod = serde_json::from_value(object).unwrap();
+        (pod.metadata, pod.spec.unwrap())
+    }
+
+    #[test]
+    fn seccomp_precedence() {
+        let (_, pod_spec) = pod(json!({
+            "spec": {
+                "securityContext": {"seccompProfile": {"type": "RuntimeDefault"}},
+                "containers": [
+                    {"name": "app"},
+                    {"name": "tracer", "securityContext": {"seccompProfile": {
+                        "type": "Localhost", "localhostProfile": "profiles/tracer.json"
+                    }}},
+                    {"name": "broken", "securityContext": {"seccompProfile": {"type": "Localhost"}}}
+                ]
+            }
+        }));
+
+        assert_eq!(
+            effective_seccomp(&pod_spec.containers[0], &pod_spec).unwrap(),
+            Some(Profile::RuntimeDefault)
+        );
+        assert_eq!(
+            effective_seccomp(&pod_spec.containers[1], &pod_spec).unwrap(),
+            Some(Profile::Localhost("profiles/tracer.json".to_string()))
+        );
+        assert!(effective_seccomp(&pod_spec.containers[2], &pod_spec).is_err());
+        assert_eq!(
+            effective_seccomp(&pod_spec.containers[0], &PodSpec::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn apparmor_annotation() {
+        assert_eq!(
+            parse_apparmor_annotation("runtime/default").unwrap(),
+            Profile::RuntimeDefault
+        );
+        assert_eq!(
+            parse_apparmor_annotation("localhost/k8s-nginx").unwrap(),
+            Profile::Localhost("k8s-nginx".to_string())
+        );
+        assert_eq!(
+            parse_apparmor_annotation("unconfined").unwrap(),
+            Profile::Unconfined
+        );
+        assert!(parse_apparmor_annotation("localhost/").is_err());
+        assert!(parse_apparmor_annotation("docker/default").is_err());
+    }
+
+    #[test]
+    fn apparmor_precedence() {
+        let (metadata, pod_spec) = pod(json!({
+            "metadata": {"annotations": {
+                "container.apparmor.security.beta.kubernetes.io/legacy": "localhost/legacy",
+                "container.apparmor.security.beta.kubernetes.io/app": "unconfined"
+            }},
+            "spec": {
+                "securityContext": {"appArmorProfile": {"type": "RuntimeDefault"}},
+                "containers": [
+                    {"name": "app", "securityContext": {"appArmorProfile": {
+                        "type": "Localhost", "localhostProfile": "app"
+                    }}},
+                    {"name": "legacy"},
+                    {"name": "sidecar"}
+                ]
+            }
+        }));
+
+        let profiles: Vec<Option<Profile>> = pod_spec
+            .containers
+            .iter()
+            .map(|container| effective_apparmor(container, &metadata, &pod_spec).unwrap())
+            .collect();
+        assert_eq!(
+            profiles,
+            vec![
+                Some(Profile::Localhost("app".to_string())),
+                Some(Profile::Localhost("legacy".to_string())),
+                Some(Profile::RuntimeDefault),
+            ]
+        );
+    }
+
+    #[test]
+    fn host_namespaces() {
+        let (_, pod_spec) = pod(json!({
+            "spec": {"hostNetwork": true, "hostIPC": false, "containers": []}
+        }));
+        assert!(uses_host_network(&pod_spec));
+        assert!(!uses_host_pid(&pod_spec));
+        assert!(!uses_host_ipc(&pod_spec));
+    }
+
+    #[test]
+    fn sysctls() {
+        let (_, pod_spec) = pod(json!({
+            "spec": {
+                "securityContext": {"sysctls": [
+                    {"name": "net.ipv4.tcp_syncookies", "value": "1"},
+                    {"name": "kernel.msgmax", "value": "65536"},
+                    {"name": "net.core.somaxconn", "value": "1024"},
+                    {"name": "kernel.shmmax", "value": "1"}
+                ]},
+                "containers": []
+            }
+        }));
+
+        assert_eq!(
+            unsafe_sysctls(&pod_spec, &["net.core.*", "kernel.shmmax"]),
+            vec![Violation {
+                path: "securityContext.sysctls[1].name".to_string(),
+                message: "sysctl 'kernel.msgmax' is not allowed".to_string(),
+            }]
+        );
+        assert_eq!(unsafe_sysctls(&pod_spec, &[]).len(), 3);
+        assert!(unsafe_sysctls(&PodSpec::default(), &[]).is_empty());
+    }
+
+    #[test]
+    fn detect_pod_os() {
+        let (_, linux) = pod(json!({"spec": {"os": {"name": "linux"}, "containers": []}}));
+        let (_, selected) = pod(json!({
+            "spec": {"nodeSelector": {"kubernetes.io/os": "windows"}, "containers": []}
+        }));
+        let (_, both) = pod(json!({
+            "spec": {
+                "os": {"name": "linux"},
+                "nodeSelector": {"kubernetes.io/os": "windows"},
+                "containers": []
+            }
+        }));
+
+        assert_eq!(pod_os(&linux), Some(PodOs::Linux));
+        assert_eq!(pod_os(&selected), Some(PodOs::Windows));
+        assert_eq!(pod_os(&both), Some(PodOs::Linux));
+        assert_eq!(pod_os(&PodSpec::default()), None);
+    }
+
+    #[test]
+    fn skip_linux_checks_on_windows() {
+        let (metadata, pod_spec) = pod(json!({
+            "metadata": {"annotations": {
+                "container.apparmor.security.beta.kubernetes.io/app": "invalid"
+            }},
+            "spec": {
+                "os": {"name": "windows"},
+                "securityContext": {
+                    "seccompProfile": {"type": "Localhost"},
+                    "sysctls": [{"name": "kernel.msgmax", "value": "1"}]
+                },
+                "containers": [{"name": "app"}]
+            }
+        }));
+
+        let container = &pod_spec.containers[0];
+        assert_eq!(effective_seccomp(container, &pod_spec).unwrap(), None);
+        assert_eq!(
+            effective_apparmor(container, &metadata, &pod_spec).unwrap(),
+            None
+        );
+        assert!(unsafe_sysctls(&pod_spec, &[]).is_empty());
+    }
+
+    #[test]
+    fn windows_node_selector_does_not_skip_checks() {
+        let (metadata, pod_spec) = pod(json!({
+            "metadata": {"annotations": {
+                "container.apparmor.security.beta.kubernetes.io/app": "unconfined"
+            }},
+            "spec": {
+                "nodeSelector": {"kubernetes.io/os": "windows"},
+                "securityContext": {
+                    "seccompProfile": {"type": "Unconfined"},
+                    "sysctls": [{"name": "kernel.msgmax", "value": "1"}]
+                },
+                "containers": [{"name": "app"}]
+            }
+        }));
+
+        let container = &pod_spec.containers[0];
+        assert_eq!(
+            effective_seccomp(container, &pod_spec).unwrap(),
+            Some(Profile::Unconfined)
+        );
+        assert_eq!(
+            effective_apparmor(container, &metadata, &pod_spec).unwrap(),
+            Some(Profile::Unconfined)
+        );
+        assert_eq!(unsafe_sysctls(&pod_spec, &[]).len(), 1);
+    }
+}
@@ -0,0 +1,214 @@
+//! Detection of the service account tokens made available to Pods.
+//!
+//! A token is given to the containers either because the automount of the
+//! service account token is not disabled, or because a projected volume
+//! explicitly requests one. [`analyze_pod_spec`] reports both cases, while
+//! [`disable_automount_from_request`] produces the mutation commonly used to
+//! opt out of the automount.
+//!
+//! # Example
+//!
+//! ```rust
+//! use k8s_openapi::api::core::v1::PodSpec;
+//! use kubewarden_policy_sdk::service_account::{analyze_pod_spec, disable_automount, TokenFinding};
+//!
+//! let mut pod_spec = PodSpec::default();
+//! assert_eq!(analyze_pod_spec(&pod_spec), vec![TokenFinding::Automount]);
+//!
+//! assert!(disable_automount(&mut pod_spec));
+//! assert!(analyze_pod_spec(&pod_spec).is_empty());
+//! ```
+use k8s_openapi::api::core::v1::PodSpec;
+
+use crate::request::ValidationRequest;
+use crate::workload::WorkloadKind;
+use crate::{accept_request, mutate_request, reject_request};
+
+/// A way a service account token is given to the containers of a Pod
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenFinding {
+    /// `automountServiceAccountToken` is not set to `false`. When it is not
+    /// set at all, the setting of the ServiceAccount applies, which enables
+    /// the automount by default
+    Automount,
+    /// A projected volume has a `serviceAccountToken` source
+    ProjectedToken {
+        /// Name of the volume
+        volume: String,
+        /// Intended audience of the token, the API server when `None`
+        audience: Option<String>,
+    },
+}
+
+impl std::fmt::Display for TokenFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenFinding::Automount => {
+                write!(f, "the service account token is automatically mounted")
+            }
+            TokenFinding::ProjectedToken { volume, .. } => {
+                write!(f, "volume '{}' projects a service account token", volume)
+            }
+        }
+    }
+}
+
+/// Find all the service account tokens given to the containers of the Pod
+pub fn analyze_pod_spec(pod_spec: &PodSpec) -> Vec<TokenFinding> {
+    let mut findings = Vec::new();
+    if pod_spec.automount_service_account_token != Some(false) {
+        findings.push(TokenFinding::Automount);
+    }
+    for volume in pod_spec.volumes.iter().flatten() {
+        let sources = volume
+            .projected
+            .as_ref()
+            .and_then(|projected| projected.sources.as_ref());
+        for source in sources.into_iter().flatten() {
+            if let Some(token) = &source.service_account_token {
+                findings.push(TokenFinding::ProjectedToken {
+                    volume: volume.name.clone(),
+                    audience: token.audience.clone(),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Set `automountServiceAccountToken` to `false`. Returns `true` when the
+/// PodSpec has been changed.
+///
+/// The projected volumes explicitly requesting a token are left untouched,
+/// removing them would break the containers mounting them.
+pub fn disable_automount(pod_spec: &mut PodSpec) -> bool {
+    let changed = pod_spec.automount_service_account_token != Some(false);
+    pod_spec.automount_service_account_token = Some(false);
+    changed
+}
+
+/// Disable the automount of the service account token inside of the pod
+/// template of the workload being admitted.
+///
+/// The request is accepted without mutation when the automount is already
+/// disabled, and rejected when the object is not a workload, see
+/// [`WorkloadKind`].
+pub fn disable_automount_from_request<T>(
+    validation_request: ValidationRequest<T>,
+) -> wapc_guest::CallResult
+where
+    T: Default,
+{
+    let workload = match WorkloadKind::try_from_gvk(&validation_request.request.kind) {
+        Ok(workload) => workload,
+        Err(e) => return reject_request(Some(e.to_string()), None, None, None),
+    };
+    let mut changed = false;
+    let mutated_object = workload.visit(validation_request.request.object, |template| {
+        changed = disable_automount(template.spec.get_or_insert_with(Default::default));
+    })?;
+    if changed {
+        mutate_request(mutated_object)
+    } else {
+        accept_request()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{GroupVersionKind, KubernetesAdmissionRequest};
+    use crate::response::ValidationResponse;
+    use serde_json::json;
+    use serial_test::serial;
+
+    fn validation_request(kind: &str, object: serde_json::Value) -> ValidationRequest<()> {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
+                kind: GroupVersionKind {
+                    kind: kind.to_string(),
+                    ..Default::default()
+                },
+                object,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn analyze_projected_tokens() {
+        let pod_spec: PodSpec = serde_json::from_value(json!({
+            "automountServiceAccountToken": false,
+            "containers": [],
+            "volumes": [
+                {"name": "config", "configMap": {"name": "config"}},
+                {"name": "vault-token", "projected": {"sources": [
+                    {"configMap": {"name": "ca"}},
+                    {"serviceAccountToken": {"audience": "vault", "path": "token"}}
+                ]}}
+            ]
+        }))
+        .unwrap();
+
+        let findings = analyze_pod_spec(&pod_spec);
+        assert_eq!(
+            findings,
+            vec![TokenFinding::ProjectedToken {
+                volume: "vault-token".to_string(),
+                audience: Some("vault".to_string()),
+            }]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "volume 'vault-token' projects a service account token"
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn disable_automount_of_deployment() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let deployment = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "spec": {
+                "selector": {},
+                "template": {"spec": {"containers": []}}
+            }
+        });
+
+        let response: ValidationResponse = serde_json::from_slice(
+            &disable_automount_from_request(validation_request("Deployment", deployment)).unwrap(),
+        )
+        .unwrap();
+        assert!(response.accepted);
+        assert_eq!(
+            response.mutated_object.unwrap()["spec"]["template"]["spec"]
+                ["automountServiceAccountToken"],
+            false
+        );
+    }
+
+    #[test]
+    fn disable_automount_already_disabled() {
+        let pod = json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "spec": {"automountServiceAccountToken": false, "containers": []}
+        });
+
+        let response: ValidationResponse = serde_json::from_slice(
+            &disable_automount_from_request(validation_request("Pod", pod)).unwrap(),
+        )
+        .unwrap();
+        assert!(response.accepted);
+        assert!(response.mutated_object.is_none());
+
+        let response: ValidationResponse = serde_json::from_slice(
+            &disable_automount_from_request(validation_request("Service", json!({}))).unwrap(),
+        )
+        .unwrap();
+        assert!(!response.accepted);
+    }
+}
@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 
+pub mod cache;
+pub mod common;
+pub mod sigstore;
+pub mod timewindow;
+
 /// Trait that must be implemented by setting
 /// object
 pub trait Validatable {
@@ -7,6 +12,132 @@ pub trait Validatable {
     fn validate(&self) -> Result<(), String>;
 }
 
+/// Trait implemented by settings whose validation depends on how the policy
+/// is deployed, see [`crate::validate_settings_with_context`]
+pub trait ValidatableWithContext {
+    /// Ensures the values given by the user are valid. `context` is `None`
+    /// when the host does not provide information about the policy
+    fn validate_with_context(
+        &self,
+        context: Option<&SettingsValidationContext>,
+    ) -> Result<(), String>;
+}
+
+/// Information about the deployment of the policy, provided by hosts
+/// alongside the settings to be validated
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SettingsValidationContext {
+    /// Optional - the name of the policy
+    pub policy_name: Option<String>,
+    /// Optional - the namespace of the policy. Set only for namespaced
+    /// policies (e.g. `AdmissionPolicy`)
+    pub namespace: Option<String>,
+    /// The rules the policy is bound to
+    pub rules: Vec<PolicyRule>,
+}
+
+impl SettingsValidationContext {
+    /// Returns `true` when all the rules of the policy target only the given
+    /// resources (e.g. `["pods"]`). Rules using the `*` wildcard target all
+    /// the resources
+    pub fn targets_only(&self, resources: &[&str]) -> bool {
+        self.rules
+            .iter()
+            .flat_map(|rule| rule.resources.iter())
+            .all(|resource| resources.contains(&resource.as_str()))
+    }
+}
+
+/// A rule the policy is bound to, as defined inside of its Kubernetes
+/// resource (e.g. `ClusterAdmissionPolicy`)
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PolicyRule {
+    /// The API groups the resources belong to
+    pub api_groups: Vec<String>,
+    /// The API versions the resources belong to
+    pub api_versions: Vec<String>,
+    /// The resources the rule applies to (e.g. `pods` or `deployments/scale`)
+    pub resources: Vec<String>,
+    /// The operations the rule applies to (e.g. `CREATE`)
+    pub operations: Vec<String>,
+    /// Optional - the scope of the rule (`Cluster`, `Namespaced` or `*`)
+    pub scope: Option<String>,
+}
+
+/// Settings keys redacted by default by [`log_settings`]. A key is redacted
+/// when it contains one of these words, regardless of the case
+pub const DEFAULT_REDACTED_KEYS: &[&str] = &["secret", "token", "password"];
+
+/// Value replacing the redacted settings
+pub const REDACTED_VALUE: &str = "[REDACTED]";
+
+/// Serialize `settings`, replacing the values of all the keys, at any depth,
+/// that contain one of the `redaction_rules` words (e.g. `apiToken` is
+/// redacted by the `token` rule). Words are matched regardless of the case
+pub fn redact_settings<T: Serialize>(
+    settings: &T,
+    redaction_rules: &[&str],
+) -> anyhow::Result<serde_json::Value> {
+    fn redact(value: &mut serde_json::Value, rules: &[String]) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    let key = key.to_lowercase();
+                    if rules.iter().any(|rule| key.contains(rule.as_str())) {
+                        *value = serde_json::Value::String(REDACTED_VALUE.to_string());
+                    } else {
+                        redact(value, rules);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                items.iter_mut().for_each(|item| redact(item, rules));
+            }
+            _ => {}
+        }
+    }
+
+    let rules: Vec<String> = redaction_rules.iter().map(|r| r.to_lowercase()).collect();
+    let mut value = serde_json::to_value(settings)?;
+    redact(&mut value, &rules);
+    Ok(value)
+}
+
+/// Emit a single structured log event with the given settings, redacted
+/// using the `redaction_rules` (see [`redact_settings`]). Policies usually
+/// call this function once the settings have been validated, to help
+/// operators debugging their deployments.
+///
+/// ```rust
+/// use kubewarden_policy_sdk::logging;
+/// use kubewarden_policy_sdk::settings::{log_settings, DEFAULT_REDACTED_KEYS};
+/// use slog::{o, Logger};
+///
+/// #[derive(serde::Serialize)]
+/// struct Settings {
+///     registry: String,
+///     registry_password: String,
+/// }
+///
+/// let logger = Logger::root(logging::KubewardenDrain::new(), o!("policy" => "sample"));
+/// let settings = Settings {
+///     registry: "ghcr.io".to_string(),
+///     registry_password: "hunter2".to_string(),
+/// };
+/// log_settings(&settings, &logger, DEFAULT_REDACTED_KEYS).unwrap();
+/// ```
+pub fn log_settings<T: Serialize>(
+    settings: &T,
+    logger: &slog::Logger,
+    redaction_rules: &[&str],
+) -> anyhow::Result<()> {
+    let settings = redact_settings(settings, redaction_rules)?;
+    slog::info!(logger, "policy settings"; "settings" => settings.to_string());
+    Ok(())
+}
+
 /// A SettingsValidationResponse object holds the outcome of settings
 /// validation.
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -16,3 +147,61 @@ pub struct SettingsValidationResponse {
     /// Message shown to the user when the settings are not valid
     pub message: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Settings {
+        registry: String,
+        api_token: String,
+        credentials: Vec<Credential>,
+    }
+
+    #[derive(Serialize)]
+    struct Credential {
+        user: String,
+        #[serde(rename = "Password")]
+        password: String,
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            registry: "ghcr.io".to_string(),
+            api_token: "abc".to_string(),
+            credentials: vec![Credential {
+                user: "admin".to_string(),
+                password: "hunter2".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn redact_default_keys() {
+        let redacted = redact_settings(&settings(), DEFAULT_REDACTED_KEYS).unwrap();
+        assert_eq!(
+            redacted,
+            json!({
+                "registry": "ghcr.io",
+                "apiToken": "[REDACTED]",
+                "credentials": [{"user": "admin", "Password": "[REDACTED]"}]
+            })
+        );
+    }
+
+    #[test]
+    fn redact_custom_keys() {
+        let redacted = redact_settings(&settings(), &["CREDENTIALS"]).unwrap();
+        assert_eq!(redacted["credentials"], json!("[REDACTED]"));
+        assert_eq!(redacted["apiToken"], json!("abc"));
+    }
+
+    #[test]
+    fn log_redacted_settings() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        assert!(log_settings(&settings(), &logger, DEFAULT_REDACTED_KEYS).is_ok());
+    }
+}
@@ -0,0 +1,231 @@
+//! Parse and validate the settings once per policy instance.
+//!
+//! The settings are sent to the policy together with every request. Policies
+//! with large settings (e.g. long allow-lists) can keep the parsed settings
+//! inside of a [`SettingsCache`], which decodes and validates them only when
+//! they change.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::request::ValidationRequest;
+//! use kubewarden_policy_sdk::settings::cache::SettingsCache;
+//! use kubewarden_policy_sdk::settings::Validatable;
+//! use kubewarden_policy_sdk::{accept_request, reject_request};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     allowed_registries: Vec<String>,
+//! }
+//!
+//! impl Validatable for Settings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! static SETTINGS: SettingsCache<Settings> = SettingsCache::new();
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     let settings = SETTINGS.get_from_payload(payload)?;
+//!     // the settings have already been decoded, skip them
+//!     let request = ValidationRequest::<serde::de::IgnoredAny>::new(payload)?;
+//!     // use the settings and the request...
+//!     accept_request()
+//! }
+//! ```
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::settings::Validatable;
+
+/// The outcome of decoding and validating one settings payload
+type Outcome<T> = Result<Arc<T>, String>;
+
+struct Entry<T> {
+    hash: u64,
+    outcome: Arc<OnceCell<Outcome<T>>>,
+}
+
+/// The settings of the policy, decoded and validated once per unique
+/// payload. Only the most recent settings are kept. It can be safely shared
+/// between threads, and used as a `static`
+pub struct SettingsCache<T> {
+    entry: Mutex<Option<Entry<T>>>,
+}
+
+impl<T> Default for SettingsCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSettings<'a> {
+    #[serde(borrow)]
+    settings: &'a RawValue,
+}
+
+impl<T> SettingsCache<T> {
+    /// Create an empty cache
+    pub const fn new() -> Self {
+        SettingsCache {
+            entry: Mutex::new(None),
+        }
+    }
+
+    /// Forget the cached settings
+    pub fn clear(&self) {
+        *self.entry.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl<T> SettingsCache<T>
+where
+    T: DeserializeOwned + Validatable,
+{
+    /// Get the settings encoded by the JSON document `settings`. They are
+    /// decoded and validated only when they differ from the cached ones.
+    ///
+    /// An error is returned when the settings cannot be decoded, or are not
+    /// valid. Errors are cached too.
+    pub fn get(&self, settings: &[u8]) -> Result<Arc<T>> {
+        let mut hasher = DefaultHasher::new();
+        settings.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let outcome = {
+            let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+            match entry.as_ref() {
+                Some(entry) if entry.hash == hash => entry.outcome.clone(),
+                _ => {
+                    let outcome = Arc::new(OnceCell::new());
+                    *entry = Some(Entry {
+                        hash,
+                        outcome: outcome.clone(),
+                    });
+                    outcome
+                }
+            }
+        };
+
+        // decoded outside of the lock, concurrent callers wait for the first one
+        outcome
+            .get_or_init(|| {
+                let parsed: T = serde_json::from_slice(settings)
+                    .map_err(|e| format!("cannot decode settings: {}", e))?;
+                parsed
+                    .validate()
+                    .map_err(|e| format!("invalid settings: {}", e))?;
+                Ok(Arc::new(parsed))
+            })
+            .clone()
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Get the settings included in the payload given to the `validate`
+    /// function, see [`SettingsCache::get`]
+    pub fn get_from_payload(&self, payload: &[u8]) -> Result<Arc<T>> {
+        let raw: RawSettings = serde_json::from_slice(payload)
+            .map_err(|e| anyhow!("cannot find settings inside of the payload: {}", e))?;
+        self.get(raw.settings.get().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ValidationRequest;
+    use serde_json::json;
+    use serial_test::serial;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static VALIDATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Deserialize, Debug)]
+    struct Settings {
+        allowed: Vec<String>,
+    }
+
+    impl Validatable for Settings {
+        fn validate(&self) -> Result<(), String> {
+            VALIDATIONS.fetch_add(1, Ordering::SeqCst);
+            if self.allowed.is_empty() {
+                return Err("allowed cannot be empty".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn payload(settings: serde_json::Value) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "settings": settings,
+            "request": {
+                "uid": "1",
+                "kind": {"group": "", "version": "v1", "kind": "Pod"},
+                "resource": {"group": "", "version": "v1", "resource": "pods"},
+                "requestKind": {"group": "", "version": "v1", "kind": "Pod"},
+                "requestResource": {"group": "", "version": "v1", "resource": "pods"},
+                "name": "nginx",
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": {},
+                "object": {}
+            }
+        }))
+        .unwrap()
+    }
+
+    // the tests counting the validations must not run concurrently
+    #[serial]
+    #[test]
+    fn parse_once_per_payload() {
+        let cache: SettingsCache<Settings> = SettingsCache::new();
+        let before = VALIDATIONS.load(Ordering::SeqCst);
+
+        let first = cache
+            .get_from_payload(&payload(json!({"allowed": ["a"]})))
+            .unwrap();
+        let second = cache
+            .get_from_payload(&payload(json!({"allowed": ["a"]})))
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(VALIDATIONS.load(Ordering::SeqCst) - before, 1);
+
+        let changed = cache.get(br#"{"allowed": ["b"]}"#).unwrap();
+        assert_eq!(changed.allowed, vec!["b".to_string()]);
+        assert_eq!(VALIDATIONS.load(Ordering::SeqCst) - before, 2);
+
+        cache.clear();
+        cache.get(br#"{"allowed": ["b"]}"#).unwrap();
+        assert_eq!(VALIDATIONS.load(Ordering::SeqCst) - before, 3);
+    }
+
+    #[serial]
+    #[test]
+    fn cache_errors() {
+        let cache: SettingsCache<Settings> = SettingsCache::new();
+
+        let error = cache.get(br#"{"allowed": []}"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid settings: allowed cannot be empty"
+        );
+        assert!(cache.get(b"{").is_err());
+        assert!(cache.get_from_payload(b"{}").is_err());
+    }
+
+    #[test]
+    fn skip_cached_settings_when_decoding_request() {
+        let payload = payload(json!({"allowed": ["a"]}));
+        let request = ValidationRequest::<serde::de::IgnoredAny>::new(&payload).unwrap();
+        assert_eq!(request.request.name, "nginx");
+    }
+}
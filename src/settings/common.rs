@@ -0,0 +1,386 @@
+//! Reusable building blocks for policy settings.
+//!
+//! Many policies share the same configuration patterns: matching image
+//! references, selecting namespaces, requiring labels, listing networks.
+//! The types of this module can be embedded inside of the settings of a
+//! policy. Each one of them implements [`Validatable`], and `JsonSchema` when
+//! the `schemars` feature is enabled.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::settings::common::ImageRefMatcher;
+//! use kubewarden_policy_sdk::settings::Validatable;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Settings {
+//!     allowed_images: Vec<ImageRefMatcher>,
+//! }
+//!
+//! impl Validatable for Settings {
+//!     fn validate(&self) -> Result<(), String> {
+//!         self.allowed_images.iter().try_for_each(|m| m.validate())
+//!     }
+//! }
+//!
+//! let settings: Settings = serde_json::from_str(
+//!     r#"{"allowed_images": [{"prefix": "ghcr.io/kubewarden/"}, {"glob": "*/busybox:1.*"}]}"#,
+//! ).unwrap();
+//! assert!(settings.validate().is_ok());
+//! assert!(settings.allowed_images[1].matches("docker.io/busybox:1.36"));
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::patterns::GlobPattern;
+use crate::settings::Validatable;
+
+pub use crate::net::ip::Cidr;
+
+/// Matches image references (e.g. `ghcr.io/kubewarden/policy-server:v1.0.0`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ImageRefMatcher {
+    /// The image reference must be exactly the given one
+    Exact(String),
+    /// The image reference must start with the given prefix
+    Prefix(String),
+    /// The image reference must match the given glob expression. `*` matches
+    /// any sequence of characters, `?` matches a single character
+    Glob(GlobPattern),
+}
+
+impl ImageRefMatcher {
+    /// Returns `true` when `image` is matched
+    pub fn matches(&self, image: &str) -> bool {
+        match self {
+            ImageRefMatcher::Exact(expected) => image == expected,
+            ImageRefMatcher::Prefix(prefix) => image.starts_with(prefix.as_str()),
+            ImageRefMatcher::Glob(pattern) => pattern.matches(image),
+        }
+    }
+}
+
+impl Validatable for ImageRefMatcher {
+    fn validate(&self) -> Result<(), String> {
+        let value = match self {
+            ImageRefMatcher::Exact(value) | ImageRefMatcher::Prefix(value) => value,
+            ImageRefMatcher::Glob(pattern) => pattern.as_str(),
+        };
+        if value.trim().is_empty() {
+            return Err("image reference matcher cannot be empty".to_string());
+        }
+        if value.chars().any(char::is_whitespace) {
+            return Err(format!(
+                "image reference matcher '{}' cannot contain whitespaces",
+                value
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The operator of a [`LabelRequirement`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum LabelOperator {
+    /// The label value must be one of the given values
+    In,
+    /// The label value must not be one of the given values. Satisfied when
+    /// the label is not set
+    NotIn,
+    /// The label must be set
+    Exists,
+    /// The label must not be set
+    DoesNotExist,
+}
+
+impl LabelOperator {
+    /// Returns `true` when `value`, the value of the label or `None` when it
+    /// is not set, satisfies the operator applied to `values`
+    pub fn matches(self, value: Option<&str>, values: &[String]) -> bool {
+        match self {
+            LabelOperator::In => value.is_some_and(|v| values.iter().any(|x| x == v)),
+            LabelOperator::NotIn => value.is_none_or(|v| !values.iter().any(|x| x == v)),
+            LabelOperator::Exists => value.is_some(),
+            LabelOperator::DoesNotExist => value.is_none(),
+        }
+    }
+}
+
+impl std::str::FromStr for LabelOperator {
+    type Err = String;
+
+    /// Parse the operator of a Kubernetes `LabelSelectorRequirement`
+    fn from_str(operator: &str) -> Result<Self, Self::Err> {
+        match operator {
+            "In" => Ok(LabelOperator::In),
+            "NotIn" => Ok(LabelOperator::NotIn),
+            "Exists" => Ok(LabelOperator::Exists),
+            "DoesNotExist" => Ok(LabelOperator::DoesNotExist),
+            _ => Err(format!("unknown label selector operator '{}'", operator)),
+        }
+    }
+}
+
+/// A requirement about a label, with the same semantics of the Kubernetes
+/// `LabelSelectorRequirement`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct LabelRequirement {
+    /// The label key
+    pub key: String,
+    /// The operator applied to the label
+    pub operator: LabelOperator,
+    /// The values of the label. Must be set when the operator is `In` or
+    /// `NotIn`, must be empty otherwise
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+}
+
+impl LabelRequirement {
+    /// Returns `true` when the given labels satisfy the requirement
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.operator
+            .matches(labels.get(&self.key).map(String::as_str), &self.values)
+    }
+}
+
+impl Validatable for LabelRequirement {
+    fn validate(&self) -> Result<(), String> {
+        if self.key.trim().is_empty() {
+            return Err("label requirement key cannot be empty".to_string());
+        }
+        match self.operator {
+            LabelOperator::In | LabelOperator::NotIn if self.values.is_empty() => Err(format!(
+                "label requirement on '{}': values must be provided with the {:?} operator",
+                self.key, self.operator
+            )),
+            LabelOperator::Exists | LabelOperator::DoesNotExist if !self.values.is_empty() => {
+                Err(format!(
+                    "label requirement on '{}': values must be empty with the {:?} operator",
+                    self.key, self.operator
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Select namespaces by name and by labels. A namespace is selected when all
+/// the criteria are satisfied, criteria that are not set match everything
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceSelectorSettings {
+    /// Select only these namespaces. All the namespaces when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub namespaces: Vec<String>,
+    /// Never select these namespaces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_namespaces: Vec<String>,
+    /// The namespace must have all these labels
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub match_labels: BTreeMap<String, String>,
+    /// The labels of the namespace must satisfy all these requirements
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub match_expressions: Vec<LabelRequirement>,
+}
+
+impl NamespaceSelectorSettings {
+    /// Returns `true` when the namespace name is selected. The label
+    /// criteria are not taken into account
+    pub fn matches_name(&self, namespace: &str) -> bool {
+        !self.excluded_namespaces.iter().any(|n| n == namespace)
+            && (self.namespaces.is_empty() || self.namespaces.iter().any(|n| n == namespace))
+    }
+
+    /// Build an index of the namespace names, with sorted lookups. Use it
+    /// instead of [`matches_name`](Self::matches_name) when the lists hold
+    /// thousands of entries and the settings are reused across evaluations
+    #[cfg(feature = "interning")]
+    pub fn name_index(&self) -> NamespaceNameIndex {
+        NamespaceNameIndex {
+            namespaces: self.namespaces.iter().collect(),
+            excluded_namespaces: self.excluded_namespaces.iter().collect(),
+        }
+    }
+
+    /// Returns `true` when the namespace is selected
+    pub fn matches(&self, namespace: &str, labels: &BTreeMap<String, String>) -> bool {
+        self.matches_name(namespace)
+            && self
+                .match_labels
+                .iter()
+                .all(|(key, value)| labels.get(key) == Some(value))
+            && self.match_expressions.iter().all(|r| r.matches(labels))
+    }
+}
+
+/// The namespace names of a [`NamespaceSelectorSettings`], stored as sorted
+/// string sets. Created by [`NamespaceSelectorSettings::name_index`]
+#[cfg(feature = "interning")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NamespaceNameIndex {
+    namespaces: crate::intern::StringSet,
+    excluded_namespaces: crate::intern::StringSet,
+}
+
+#[cfg(feature = "interning")]
+impl NamespaceNameIndex {
+    /// Returns `true` when the namespace name is selected
+    pub fn matches_name(&self, namespace: &str) -> bool {
+        !self.excluded_namespaces.contains(namespace)
+            && (self.namespaces.is_empty() || self.namespaces.contains(namespace))
+    }
+}
+
+impl Validatable for NamespaceSelectorSettings {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(namespace) = self
+            .namespaces
+            .iter()
+            .chain(self.excluded_namespaces.iter())
+            .find(|n| n.trim().is_empty())
+        {
+            return Err(format!("invalid namespace name '{}'", namespace));
+        }
+        if let Some(namespace) = self
+            .namespaces
+            .iter()
+            .find(|n| self.excluded_namespaces.contains(n))
+        {
+            return Err(format!(
+                "namespace '{}' is both selected and excluded",
+                namespace
+            ));
+        }
+        self.match_expressions
+            .iter()
+            .try_for_each(|requirement| requirement.validate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn image_ref_matcher() {
+        let matchers: Vec<ImageRefMatcher> = serde_json::from_value(json!([
+            {"exact": "busybox:1.36"},
+            {"prefix": "ghcr.io/kubewarden/"},
+            {"glob": "*/nginx:1.*"}
+        ]))
+        .unwrap();
+
+        assert!(matchers[0].matches("busybox:1.36"));
+        assert!(!matchers[0].matches("busybox:1.37"));
+        assert!(matchers[1].matches("ghcr.io/kubewarden/policy-server:v1"));
+        assert!(!matchers[1].matches("ghcr.io/other/policy-server:v1"));
+        assert!(matchers[2].matches("docker.io/nginx:1.25"));
+        assert!(!matchers[2].matches("nginx:1.25"));
+
+        assert!(matchers.iter().all(|m| m.validate().is_ok()));
+        assert!(ImageRefMatcher::Prefix("".to_string()).validate().is_err());
+        assert!(ImageRefMatcher::Exact("a b".to_string())
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn label_requirement() {
+        let requirement: LabelRequirement = serde_json::from_value(json!({
+            "key": "env", "operator": "In", "values": ["prod", "staging"]
+        }))
+        .unwrap();
+        assert!(requirement.validate().is_ok());
+        assert!(requirement.matches(&labels(&[("env", "prod")])));
+        assert!(!requirement.matches(&labels(&[("env", "dev")])));
+        assert!(!requirement.matches(&labels(&[])));
+
+        let not_in = LabelRequirement {
+            operator: LabelOperator::NotIn,
+            ..requirement.clone()
+        };
+        assert!(not_in.matches(&labels(&[])));
+        assert!(!not_in.matches(&labels(&[("env", "prod")])));
+
+        let exists = LabelRequirement {
+            key: "env".to_string(),
+            operator: LabelOperator::Exists,
+            values: vec![],
+        };
+        assert!(exists.matches(&labels(&[("env", "dev")])));
+
+        assert_eq!("NotIn".parse(), Ok(LabelOperator::NotIn));
+        assert_eq!("DoesNotExist".parse(), Ok(LabelOperator::DoesNotExist));
+        assert!("Gt".parse::<LabelOperator>().is_err());
+
+        assert!(LabelRequirement {
+            values: vec![],
+            ..requirement
+        }
+        .validate()
+        .is_err());
+        assert!(LabelRequirement {
+            values: vec!["x".to_string()],
+            ..exists
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn namespace_selector() {
+        let selector: NamespaceSelectorSettings = serde_json::from_value(json!({
+            "excludedNamespaces": ["kube-system"],
+            "matchLabels": {"team": "a"},
+            "matchExpressions": [{"key": "env", "operator": "DoesNotExist"}]
+        }))
+        .unwrap();
+        assert!(selector.validate().is_ok());
+
+        assert!(selector.matches("default", &labels(&[("team", "a")])));
+        assert!(!selector.matches("kube-system", &labels(&[("team", "a")])));
+        assert!(!selector.matches("default", &labels(&[("team", "b")])));
+        assert!(!selector.matches("default", &labels(&[("team", "a"), ("env", "prod")])));
+
+        let conflicting = NamespaceSelectorSettings {
+            namespaces: vec!["kube-system".to_string()],
+            ..selector
+        };
+        assert!(conflicting.validate().is_err());
+        assert!(!conflicting.matches_name("default"));
+    }
+
+    #[cfg(feature = "interning")]
+    #[test]
+    fn namespace_name_index() {
+        let selector: NamespaceSelectorSettings = serde_json::from_value(json!({
+            "namespaces": ["team-b", "team-a", "kube-system"],
+            "excludedNamespaces": ["kube-system"]
+        }))
+        .unwrap();
+        let index = selector.name_index();
+        for namespace in ["team-a", "team-b", "kube-system", "default"] {
+            assert_eq!(
+                index.matches_name(namespace),
+                selector.matches_name(namespace)
+            );
+        }
+        assert!(NamespaceSelectorSettings::default()
+            .name_index()
+            .matches_name("default"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Reusable settings describing the Sigstore signatures required by a policy.
+//!
+//! Policies verifying container images can embed [`SignatureRequirements`]
+//! inside of their settings, this way all of them share the same schema:
+//!
+//! ```yaml
+//! signatures:
+//!   - image:
+//!       glob: "ghcr.io/kubewarden/*"
+//!     githubActions:
+//!       owner: kubewarden
+//!   - image:
+//!       prefix: "registry.example.com/"
+//!     pubKeys:
+//!       - |
+//!         -----BEGIN PUBLIC KEY-----
+//!         ...
+//!         -----END PUBLIC KEY-----
+//!     annotations:
+//!       env: prod
+//! ```
+//!
+//! Each requirement applies to the images matched by its `image` matcher, and
+//! uses exactly one verification mode: `pubKeys`, `keyless`, `keylessPrefix`,
+//! `githubActions` or `certificate`.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::host_capabilities::verification::VerificationResponse;
+use crate::host_capabilities::verification::{self, KeylessInfo, KeylessPrefixInfo};
+use crate::settings::common::ImageRefMatcher;
+use crate::settings::Validatable;
+
+/// Keyless signature produced by a GitHub Actions workflow
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct GithubActionsSigner {
+    /// Owner of the repository, e.g. `octocat`
+    pub owner: String,
+    /// Optional - repository of the workflow that signed the image
+    #[serde(default)]
+    pub repo: Option<String>,
+}
+
+/// Signature produced with a user provided certificate
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSigner {
+    /// PEM encoded certificate used to verify the signature
+    pub certificate: String,
+    /// Optional - PEM encoded certificates used to verify `certificate`
+    #[serde(default)]
+    pub certificate_chain: Option<Vec<String>>,
+    /// Require the signature layer to have a Rekor bundle
+    #[serde(default = "default_require_rekor_bundle")]
+    pub require_rekor_bundle: bool,
+}
+
+fn default_require_rekor_bundle() -> bool {
+    true
+}
+
+/// How the signatures of an image are verified
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum VerificationMode {
+    /// PEM encoded public keys that must have been used to sign the image
+    PubKeys(Vec<String>),
+    /// Keyless signatures that must be found, matching issuer and subject
+    Keyless(Vec<KeylessInfo>),
+    /// Keyless signatures that must be found, matching the subject prefix
+    KeylessPrefix(Vec<KeylessPrefixInfo>),
+    /// Keyless signature produced by a GitHub Actions workflow
+    GithubActions(GithubActionsSigner),
+    /// Signature produced with a user provided certificate
+    Certificate(CertificateSigner),
+}
+
+/// The signatures required for the images matched by `image`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SignatureRequirement {
+    /// The images the requirement applies to
+    pub image: ImageRefMatcher,
+    /// How the signatures are verified
+    #[serde(flatten)]
+    pub mode: VerificationMode,
+    /// Optional - annotations that must have been provided by all signers
+    #[serde(default)]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+impl SignatureRequirement {
+    /// Verify the signatures of `image` using the host capability matching
+    /// the verification mode
+    pub fn verify(&self, image: &str) -> anyhow::Result<VerificationResponse> {
+        let annotations = self.annotations.clone();
+        match &self.mode {
+            VerificationMode::PubKeys(keys) => {
+                verification::verify_pub_keys_image(image, keys.clone(), annotations)
+            }
+            VerificationMode::Keyless(keyless) => {
+                verification::verify_keyless_exact_match(image, keyless.clone(), annotations)
+            }
+            VerificationMode::KeylessPrefix(prefixes) => {
+                verification::verify_keyless_prefix_match(image, prefixes.clone(), annotations)
+            }
+            VerificationMode::GithubActions(signer) => verification::verify_keyless_github_actions(
+                image,
+                signer.owner.clone(),
+                signer.repo.clone(),
+                annotations,
+            ),
+            VerificationMode::Certificate(signer) => verification::verify_certificate(
+                image,
+                signer.certificate.clone(),
+                signer.certificate_chain.clone(),
+                signer.require_rekor_bundle,
+                annotations,
+            ),
+        }
+    }
+}
+
+impl Validatable for SignatureRequirement {
+    fn validate(&self) -> Result<(), String> {
+        self.image.validate()?;
+
+        let not_empty = |what: &str, value: &str| {
+            if value.trim().is_empty() {
+                Err(format!("{} cannot be empty", what))
+            } else {
+                Ok(())
+            }
+        };
+        match &self.mode {
+            VerificationMode::PubKeys(keys) => {
+                if keys.is_empty() {
+                    return Err("pubKeys cannot be empty".to_string());
+                }
+                keys.iter().try_for_each(|key| {
+                    if key.contains("-----BEGIN") {
+                        Ok(())
+                    } else {
+                        Err("pubKeys must contain PEM encoded keys".to_string())
+                    }
+                })
+            }
+            VerificationMode::Keyless(keyless) => {
+                if keyless.is_empty() {
+                    return Err("keyless cannot be empty".to_string());
+                }
+                keyless.iter().try_for_each(|k| {
+                    not_empty("keyless issuer", &k.issuer)?;
+                    not_empty("keyless subject", &k.subject)
+                })
+            }
+            VerificationMode::KeylessPrefix(prefixes) => {
+                if prefixes.is_empty() {
+                    return Err("keylessPrefix cannot be empty".to_string());
+                }
+                prefixes.iter().try_for_each(|k| {
+                    not_empty("keylessPrefix issuer", &k.issuer)?;
+                    not_empty("keylessPrefix url_prefix", &k.url_prefix)
+                })
+            }
+            VerificationMode::GithubActions(signer) => {
+                not_empty("githubActions owner", &signer.owner)
+            }
+            VerificationMode::Certificate(signer) => not_empty("certificate", &signer.certificate),
+        }
+    }
+}
+
+/// The signatures required by a policy, see the [module](self) documentation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SignatureRequirements {
+    pub signatures: Vec<SignatureRequirement>,
+}
+
+impl SignatureRequirements {
+    /// The requirements applying to `image`
+    pub fn requirements_for<'a>(
+        &'a self,
+        image: &'a str,
+    ) -> impl Iterator<Item = &'a SignatureRequirement> + 'a {
+        self.signatures
+            .iter()
+            .filter(move |r| r.image.matches(image))
+    }
+
+    /// Verify `image` against all the requirements applying to it.
+    ///
+    /// Returns `None` when no requirement applies to the image, the outcome
+    /// of the verification otherwise. An error is returned when one of the
+    /// requirements is not satisfied, or when the requirements verified
+    /// different digests of the image.
+    pub fn verify(&self, image: &str) -> anyhow::Result<Option<VerificationResponse>> {
+        let mut verified: Option<VerificationResponse> = None;
+        for requirement in self.requirements_for(image) {
+            let response = requirement.verify(image)?;
+            if !response.is_trusted {
+                return Err(anyhow::anyhow!("image '{}' is not trusted", image));
+            }
+            if let Some(previous) = &verified {
+                if previous.digest != response.digest {
+                    return Err(anyhow::anyhow!(
+                        "signatures of '{}' verified different digests: {} and {}",
+                        image,
+                        previous.digest,
+                        response.digest
+                    ));
+                }
+            }
+            verified = Some(response);
+        }
+
+        Ok(verified)
+    }
+}
+
+impl Validatable for SignatureRequirements {
+    fn validate(&self) -> Result<(), String> {
+        if self.signatures.is_empty() {
+            return Err("at least one signature requirement must be provided".to_string());
+        }
+        self.signatures
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, requirement)| {
+                requirement
+                    .validate()
+                    .map_err(|e| format!("signatures[{}]: {}", index, e))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_capabilities::verification::tests::mock_wapc;
+    use serde_json::json;
+    use serial_test::serial;
+
+    fn requirements() -> SignatureRequirements {
+        serde_json::from_value(json!({
+            "signatures": [
+                {
+                    "image": {"glob": "ghcr.io/kubewarden/*"},
+                    "githubActions": {"owner": "kubewarden"}
+                },
+                {
+                    "image": {"prefix": "ghcr.io/"},
+                    "pubKeys": ["-----BEGIN PUBLIC KEY-----\nabc\n-----END PUBLIC KEY-----"],
+                    "annotations": {"env": "prod"}
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_and_validate() {
+        let settings = requirements();
+        assert!(settings.validate().is_ok());
+        assert_eq!(
+            settings.signatures[0].mode,
+            VerificationMode::GithubActions(GithubActionsSigner {
+                owner: "kubewarden".to_string(),
+                repo: None
+            })
+        );
+        assert_eq!(
+            settings
+                .requirements_for("ghcr.io/kubewarden/policy")
+                .count(),
+            2
+        );
+        assert_eq!(settings.requirements_for("docker.io/busybox").count(), 0);
+
+        let invalid: SignatureRequirements = serde_json::from_value(json!({
+            "signatures": [{"image": {"exact": "busybox"}, "pubKeys": ["not a key"]}]
+        }))
+        .unwrap();
+        assert_eq!(
+            invalid.validate().unwrap_err(),
+            "signatures[0]: pubKeys must contain PEM encoded keys"
+        );
+        assert!(SignatureRequirements::default().validate().is_err());
+
+        let certificate: SignatureRequirement = serde_json::from_value(json!({
+            "image": {"exact": "busybox"},
+            "certificate": {"certificate": "-----BEGIN CERTIFICATE-----"}
+        }))
+        .unwrap();
+        assert!(matches!(
+            certificate.mode,
+            VerificationMode::Certificate(CertificateSigner {
+                require_rekor_bundle: true,
+                ..
+            })
+        ));
+    }
+
+    #[serial]
+    #[test]
+    fn verify_dispatches_to_all_matching_requirements() {
+        let ctx = mock_wapc::host_call_context();
+        ctx.expect().times(2).returning(|_, _, _, msg| {
+            let input: serde_json::Value = serde_json::from_slice(msg).unwrap();
+            assert!(matches!(
+                input["type"].as_str(),
+                Some("SigstoreGithubActionsVerify" | "SigstorePubKeyVerify")
+            ));
+            Ok(serde_json::to_vec(&VerificationResponse {
+                is_trusted: true,
+                digest: "sha256:123".to_string(),
+            })
+            .unwrap())
+        });
+
+        let settings = requirements();
+        let response = settings.verify("ghcr.io/kubewarden/policy").unwrap();
+        assert_eq!(response.unwrap().digest, "sha256:123");
+        assert!(settings.verify("docker.io/busybox").unwrap().is_none());
+    }
+}
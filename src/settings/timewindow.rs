@@ -0,0 +1,389 @@
+//! Time related settings, for policies enforcing freeze or maintenance windows.
+//!
+//! [`Duration`] parses Kubernetes style durations (e.g. `30s`, `5m`,
+//! `1h30m`), while [`MaintenanceWindow`] describes a recurring window of time
+//! (e.g. every Saturday between 22:00 and 02:00, UTC).
+//!
+//! Windows are evaluated against a RFC 3339 timestamp, usually obtained
+//! through a [`Clock`] backed by the time host capability. Only fixed UTC
+//! offsets (e.g. `+02:00`) are supported as timezone: WebAssembly guests have
+//! no access to the timezone database.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::host_capabilities::time::FixedClock;
+//! use kubewarden_policy_sdk::settings::timewindow::MaintenanceWindow;
+//! use kubewarden_policy_sdk::settings::Validatable;
+//!
+//! let window: MaintenanceWindow = serde_json::from_str(
+//!     r#"{"days": ["saturday", "sunday"], "start": "22:00", "end": "02:00", "timezone": "+01:00"}"#,
+//! ).unwrap();
+//! assert!(window.validate().is_ok());
+//!
+//! // Sunday 00:30 in the +01:00 timezone
+//! let clock = FixedClock("2024-06-01T23:30:00Z".to_string());
+//! assert!(window.is_active(&clock).unwrap());
+//! ```
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, FixedOffset, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::host_capabilities::time::Clock;
+use crate::settings::Validatable;
+
+/// A span of time, expressed with the same syntax used by Kubernetes (e.g.
+/// `300ms`, `30s`, `5m`, `1h30m`, `1.5h`). The supported units are `ns`,
+/// `us`, `ms`, `s`, `m` and `h`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct Duration(std::time::Duration);
+
+impl Duration {
+    /// The duration as a standard library type
+    pub fn as_std(&self) -> std::time::Duration {
+        self.0
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Duration(duration)
+    }
+}
+
+impl FromStr for Duration {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid duration '{}'", value);
+        if value == "0" {
+            return Ok(Duration(std::time::Duration::ZERO));
+        }
+        if value.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut total_nanos: f64 = 0.0;
+        let mut rest = value;
+        while !rest.is_empty() {
+            let number_len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .ok_or_else(|| format!("invalid duration '{}': missing unit", value))?;
+            if number_len == 0 {
+                return Err(invalid());
+            }
+            let number: f64 = rest[..number_len].parse().map_err(|_| invalid())?;
+            rest = &rest[number_len..];
+
+            let unit_len = rest
+                .find(|c: char| c.is_ascii_digit() || c == '.')
+                .unwrap_or(rest.len());
+            let multiplier = match &rest[..unit_len] {
+                "ns" => 1.0,
+                "us" | "µs" => 1e3,
+                "ms" => 1e6,
+                "s" => 1e9,
+                "m" => 60e9,
+                "h" => 3600e9,
+                unit => {
+                    return Err(format!(
+                        "invalid duration '{}': unknown unit '{}'",
+                        value, unit
+                    ))
+                }
+            };
+            rest = &rest[unit_len..];
+            total_nanos += number * multiplier;
+        }
+
+        if total_nanos > u64::MAX as f64 {
+            return Err(format!("invalid duration '{}': overflow", value));
+        }
+        Ok(Duration(std::time::Duration::from_nanos(
+            total_nanos.round() as u64,
+        )))
+    }
+}
+
+impl TryFrom<String> for Duration {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Duration> for String {
+    fn from(duration: Duration) -> Self {
+        duration.to_string()
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.0.as_nanos();
+        if nanos == 0 {
+            return f.write_str("0s");
+        }
+        if !nanos.is_multiple_of(1_000_000_000) {
+            return match nanos {
+                n if n.is_multiple_of(1_000_000) => write!(f, "{}ms", n / 1_000_000),
+                n if n.is_multiple_of(1_000) => write!(f, "{}us", n / 1_000),
+                n => write!(f, "{}ns", n),
+            };
+        }
+
+        let secs = self.0.as_secs();
+        let (h, m, s) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+        if h > 0 {
+            write!(f, "{}h", h)?;
+        }
+        if m > 0 {
+            write!(f, "{}m", m)?;
+        }
+        if s > 0 {
+            write!(f, "{}s", s)?;
+        }
+        Ok(())
+    }
+}
+
+/// A day of the week
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    #[serde(alias = "mon")]
+    Monday,
+    #[serde(alias = "tue")]
+    Tuesday,
+    #[serde(alias = "wed")]
+    Wednesday,
+    #[serde(alias = "thu")]
+    Thursday,
+    #[serde(alias = "fri")]
+    Friday,
+    #[serde(alias = "sat")]
+    Saturday,
+    #[serde(alias = "sun")]
+    Sunday,
+}
+
+impl From<chrono::Weekday> for Weekday {
+    fn from(day: chrono::Weekday) -> Self {
+        match day {
+            chrono::Weekday::Mon => Weekday::Monday,
+            chrono::Weekday::Tue => Weekday::Tuesday,
+            chrono::Weekday::Wed => Weekday::Wednesday,
+            chrono::Weekday::Thu => Weekday::Thursday,
+            chrono::Weekday::Fri => Weekday::Friday,
+            chrono::Weekday::Sat => Weekday::Saturday,
+            chrono::Weekday::Sun => Weekday::Sunday,
+        }
+    }
+}
+
+/// A recurring window of time.
+///
+/// The window starts at `start` on each one of the `days`, and lasts until
+/// `end`. When `end` comes before `start` the window crosses midnight, and
+/// ends the following day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct MaintenanceWindow {
+    /// The days in which the window starts. Every day when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    /// Start of the window, in `HH:MM` format
+    pub start: String,
+    /// End of the window, in `HH:MM` format
+    pub end: String,
+    /// Timezone used by `start` and `end`: `UTC` (the default), or a fixed
+    /// offset like `+02:00`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn parse_time_of_day(value: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid time '{}': expected HH:MM format", value);
+    let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return Err(invalid());
+    }
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+fn parse_timezone(value: &str) -> Result<FixedOffset, String> {
+    if value == "UTC" || value == "Z" {
+        return FixedOffset::east_opt(0).ok_or_else(|| "invalid timezone".to_string());
+    }
+    let invalid = || {
+        format!(
+            "invalid timezone '{}': expected UTC or an offset like +02:00",
+            value
+        )
+    };
+    let sign = match value.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+    let minutes = parse_time_of_day(&value[1..]).map_err(|_| invalid())?;
+    FixedOffset::east_opt(sign * minutes as i32 * 60).ok_or_else(invalid)
+}
+
+impl MaintenanceWindow {
+    /// Returns `true` when the RFC 3339 timestamp `now` falls inside of the
+    /// window
+    pub fn contains(&self, now: &str) -> anyhow::Result<bool> {
+        let start = parse_time_of_day(&self.start).map_err(|e| anyhow!(e))?;
+        let end = parse_time_of_day(&self.end).map_err(|e| anyhow!(e))?;
+        let timezone = parse_timezone(&self.timezone).map_err(|e| anyhow!(e))?;
+
+        let now = DateTime::parse_from_rfc3339(now)
+            .map_err(|e| anyhow!("invalid timestamp '{}': {}", now, e))?
+            .with_timezone(&timezone);
+        let minute = now.hour() * 60 + now.minute();
+        let today: Weekday = now.weekday().into();
+        let yesterday: Weekday = now.weekday().pred().into();
+        let starts_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        Ok(if start <= end {
+            starts_on(today) && minute >= start && minute < end
+        } else {
+            (starts_on(today) && minute >= start) || (starts_on(yesterday) && minute < end)
+        })
+    }
+
+    /// Returns `true` when the current time, as reported by the `clock`,
+    /// falls inside of the window
+    pub fn is_active(&self, clock: &impl Clock) -> anyhow::Result<bool> {
+        self.contains(&clock.now()?)
+    }
+}
+
+impl Validatable for MaintenanceWindow {
+    fn validate(&self) -> Result<(), String> {
+        let start = parse_time_of_day(&self.start)?;
+        let end = parse_time_of_day(&self.end)?;
+        if start == end {
+            return Err(format!(
+                "maintenance window cannot start and end at the same time: {}",
+                self.start
+            ));
+        }
+        parse_timezone(&self.timezone).map(|_| ())
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for Duration {
+    fn schema_name() -> String {
+        "Duration".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_capabilities::time::FixedClock;
+    use serde_json::json;
+
+    #[test]
+    fn parse_duration() {
+        let secs = |s: &str| s.parse::<Duration>().unwrap().as_std().as_secs_f64();
+        assert_eq!(secs("30s"), 30.0);
+        assert_eq!(secs("5m"), 300.0);
+        assert_eq!(secs("1h30m"), 5400.0);
+        assert_eq!(secs("1.5h"), 5400.0);
+        assert_eq!(secs("300ms"), 0.3);
+        assert_eq!(secs("0"), 0.0);
+
+        for invalid in ["", "5", "m", "5d", "1h-5m", "1..5s"] {
+            assert!(invalid.parse::<Duration>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn duration_serialization() {
+        let duration: Duration = serde_json::from_value(json!("90m")).unwrap();
+        assert_eq!(serde_json::to_value(duration).unwrap(), json!("1h30m"));
+        assert_eq!("1500ms".parse::<Duration>().unwrap().to_string(), "1500ms");
+        assert_eq!("0s".parse::<Duration>().unwrap().to_string(), "0s");
+        assert!(serde_json::from_value::<Duration>(json!("forever")).is_err());
+    }
+
+    #[test]
+    fn window_within_the_same_day() {
+        let window: MaintenanceWindow = serde_json::from_value(json!({
+            "days": ["mon", "wednesday"],
+            "start": "09:00",
+            "end": "17:30"
+        }))
+        .unwrap();
+        assert!(window.validate().is_ok());
+
+        // 2024-06-03 is a Monday
+        assert!(window.contains("2024-06-03T09:00:00Z").unwrap());
+        assert!(window.contains("2024-06-03T17:29:59Z").unwrap());
+        assert!(!window.contains("2024-06-03T17:30:00Z").unwrap());
+        assert!(!window.contains("2024-06-04T10:00:00Z").unwrap());
+        assert!(window.contains("2024-06-05T10:00:00+00:00").unwrap());
+        assert!(window.contains("not a timestamp").is_err());
+    }
+
+    #[test]
+    fn window_crossing_midnight_with_timezone() {
+        let window = MaintenanceWindow {
+            days: vec![Weekday::Saturday],
+            start: "22:00".to_string(),
+            end: "02:00".to_string(),
+            timezone: "+02:00".to_string(),
+        };
+        assert!(window.validate().is_ok());
+
+        // Saturday 23:00 in +02:00
+        assert!(window.contains("2024-06-01T21:00:00Z").unwrap());
+        // Sunday 01:00 in +02:00
+        assert!(window.contains("2024-06-01T23:00:00Z").unwrap());
+        // Sunday 03:00 in +02:00
+        assert!(!window.contains("2024-06-02T01:00:00Z").unwrap());
+        // Friday 23:00 in +02:00
+        assert!(!window.contains("2024-05-31T21:00:00Z").unwrap());
+
+        let clock = FixedClock("2024-06-01T21:00:00Z".to_string());
+        assert!(window.is_active(&clock).unwrap());
+    }
+
+    #[test]
+    fn window_validation() {
+        let window = |start: &str, end: &str, timezone: &str| MaintenanceWindow {
+            days: vec![],
+            start: start.to_string(),
+            end: end.to_string(),
+            timezone: timezone.to_string(),
+        };
+        assert!(window("00:00", "23:59", "UTC").validate().is_ok());
+        assert!(window("00:00", "23:59", "-05:30").validate().is_ok());
+        assert!(window("24:00", "01:00", "UTC").validate().is_err());
+        assert!(window("9:00", "10:00", "UTC").validate().is_err());
+        assert!(window("10:00", "10:00", "UTC").validate().is_err());
+        assert!(window("10:00", "11:00", "Europe/Rome").validate().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Minimal copies of the Kubernetes types used by the pod spec helpers.
+//!
+//! The `k8s_openapi` crate describes the whole Kubernetes API, which has a
+//! noticeable impact on the compilation time and on the size of the Wasm
+//! module. Policies that only look at the pods created by the workload
+//! resources can disable the `cluster-context` feature and enable the
+//! `slim-k8s` one instead:
+//!
+//! ```toml
+//! [dependencies]
+//! kubewarden-policy-sdk = { version = "0.12", default-features = false, features = ["slim-k8s"] }
+//! ```
+//!
+//! Only the most commonly used fields are typed. All the other fields are
+//! kept inside of the `other` maps, hence mutating an object through these
+//! types never drops any of its fields.
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::request::ValidationRequest;
+use crate::{mutate_request, reject_request};
+
+/// The JSON pointers of the pod spec inside of the supported workload
+/// resources, indexed by kind
+pub const POD_SPEC_POINTERS: &[(&str, &str)] = &[
+    ("Deployment", "/spec/template/spec"),
+    ("ReplicaSet", "/spec/template/spec"),
+    ("StatefulSet", "/spec/template/spec"),
+    ("DaemonSet", "/spec/template/spec"),
+    ("ReplicationController", "/spec/template/spec"),
+    ("Job", "/spec/template/spec"),
+    ("CronJob", "/spec/jobTemplate/spec/template/spec"),
+    ("Pod", "/spec"),
+];
+
+/// Standard object's metadata
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// All the other fields of the metadata
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Template used to create the pods of a workload resource
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PodTemplateSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ObjectMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec: Option<PodSpec>,
+}
+
+/// Specification of a pod
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PodSpec {
+    #[serde(default)]
+    pub containers: Vec<Container>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub init_containers: Option<Vec<Container>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ephemeral_containers: Option<Vec<Container>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account_name: Option<String>,
+    /// All the other fields of the pod spec
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A container running inside of a pod
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Container {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// All the other fields of the container
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl PodSpec {
+    /// All the containers of the pod: init, regular and ephemeral ones
+    pub fn all_containers(&self) -> impl Iterator<Item = &Container> {
+        self.init_containers
+            .iter()
+            .flatten()
+            .chain(self.containers.iter())
+            .chain(self.ephemeral_containers.iter().flatten())
+    }
+}
+
+/// The JSON pointer of the pod spec inside of the objects of the given kind,
+/// `None` when the kind is not supported
+pub fn pod_spec_pointer(kind: &str) -> Option<&'static str> {
+    POD_SPEC_POINTERS
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, pointer)| *pointer)
+}
+
+fn unsupported_kind_message() -> String {
+    let kinds: Vec<&str> = POD_SPEC_POINTERS.iter().map(|(kind, _)| *kind).collect();
+    format!("Object should be one of these kinds: {}", kinds.join(", "))
+}
+
+/// Extract the PodSpec from the object of the request. This is the slim
+/// counterpart of
+/// `ValidationRequest::extract_pod_spec_from_object`.
+///
+/// It returns an error if the object is not one of the supported kinds. If
+/// it is a supported object it returns the PodSpec if present, otherwise
+/// returns None.
+pub fn extract_pod_spec<T: Default>(
+    validation_request: &ValidationRequest<T>,
+) -> anyhow::Result<Option<PodSpec>> {
+    let pointer = pod_spec_pointer(&validation_request.request.kind.kind)
+        .ok_or_else(|| anyhow!(unsupported_kind_message()))?;
+
+    match validation_request.request.object.pointer(pointer) {
+        Some(spec) if !spec.is_null() => Ok(Some(PodSpec::deserialize(spec)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Update the pod spec of the object of the request and create an acceptance
+/// response. This is the slim counterpart of `mutate_pod_spec_from_request`.
+///
+/// The request is rejected when the object is not one of the supported kinds.
+pub fn mutate_pod_spec_from_request<T: Default>(
+    validation_request: ValidationRequest<T>,
+    pod_spec: PodSpec,
+) -> wapc_guest::CallResult {
+    let pointer = match pod_spec_pointer(&validation_request.request.kind.kind) {
+        Some(pointer) => pointer,
+        None => return reject_request(Some(unsupported_kind_message()), None, None, None),
+    };
+
+    let mut object = validation_request.request.object;
+    crate::mutation::set(&mut object, pointer, serde_json::to_value(pod_spec)?)?;
+    mutate_request(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{GroupVersionKind, KubernetesAdmissionRequest};
+    use crate::response::ValidationResponse;
+    use serde_json::json;
+    use serial_test::serial;
+
+    fn request(kind: &str, object: serde_json::Value) -> ValidationRequest<()> {
+        ValidationRequest::from_parts(
+            (),
+            KubernetesAdmissionRequest {
+                kind: GroupVersionKind {
+                    kind: kind.to_string(),
+                    ..Default::default()
+                },
+                object,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn cronjob() -> serde_json::Value {
+        json!({
+            "apiVersion": "batch/v1",
+            "kind": "CronJob",
+            "metadata": {"name": "backup", "uid": "1234"},
+            "spec": {"schedule": "@daily", "jobTemplate": {"spec": {"template": {"spec": {
+                "restartPolicy": "Never",
+                "containers": [{"name": "backup", "image": "busybox", "args": ["sh"]}]
+            }}}}}
+        })
+    }
+
+    #[test]
+    fn extract_from_workloads() {
+        let spec = extract_pod_spec(&request("CronJob", cronjob()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(spec.containers[0].image.as_deref(), Some("busybox"));
+        assert_eq!(spec.other["restartPolicy"], "Never");
+        assert_eq!(spec.all_containers().count(), 1);
+
+        let deployment = json!({"spec": {"selector": {}}});
+        assert!(extract_pod_spec(&request("Deployment", deployment))
+            .unwrap()
+            .is_none());
+
+        let err = extract_pod_spec(&request("Service", json!({}))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Object should be one of these kinds: Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController, Job, CronJob, Pod"
+        );
+    }
+
+    #[serial]
+    #[test]
+    fn mutation_keeps_unknown_fields() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let req = request("CronJob", cronjob());
+        let mut spec = extract_pod_spec(&req).unwrap().unwrap();
+        spec.containers[0].image = Some("alpine".to_string());
+
+        let raw = mutate_pod_spec_from_request(req, spec).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw).unwrap();
+        let mut expected = cronjob();
+        expected["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0]["image"] =
+            json!("alpine");
+        assert_eq!(response.mutated_object, Some(expected));
+
+        let raw = mutate_pod_spec_from_request(request("Service", json!({})), PodSpec::default())
+            .unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw).unwrap();
+        assert!(!response.accepted);
+    }
+}
@@ -0,0 +1,168 @@
+//! Machine-readable summary of a policy evaluation.
+//!
+//! An [`EvaluationSummary`] records which checks have been run by the policy,
+//! their outcome and how long they took. The summary can then be attached to
+//! the response as a compact JSON audit annotation, giving auditors a
+//! standard way to find out why a request has been rejected.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::summary::EvaluationSummary;
+//!
+//! let mut summary = EvaluationSummary::new();
+//! summary.check("no-latest-tag", || Ok(()));
+//! summary.check("trusted-registry", || {
+//!     Err("registry docker.io is not trusted".to_string())
+//! });
+//!
+//! assert!(!summary.passed());
+//! let annotations = summary.audit_annotations();
+//! assert!(annotations.contains_key("evaluation-summary"));
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Key of the audit annotation produced by [`EvaluationSummary::audit_annotations`]
+pub const AUDIT_ANNOTATION_KEY: &str = "evaluation-summary";
+
+/// The outcome of a single check
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckOutcome {
+    /// Name of the check
+    pub name: String,
+    /// True if the check passed
+    pub passed: bool,
+    /// Optional - why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Optional - time spent running the check, expressed in milliseconds
+    #[serde(rename = "ms", skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<f64>,
+}
+
+/// Collects the outcome of the checks performed by a policy
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EvaluationSummary {
+    checks: Vec<CheckOutcome>,
+}
+
+impl EvaluationSummary {
+    /// Create an empty summary
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of a check that has been performed elsewhere
+    pub fn record(&mut self, name: &str, outcome: Result<(), String>) {
+        self.checks.push(CheckOutcome {
+            name: name.to_string(),
+            passed: outcome.is_ok(),
+            reason: outcome.err(),
+            elapsed_ms: None,
+        });
+    }
+
+    /// Run a check, recording its outcome and the time it took.
+    /// Returns `true` when the check passed
+    pub fn check<F>(&mut self, name: &str, check: F) -> bool
+    where
+        F: FnOnce() -> Result<(), String>,
+    {
+        let start = Instant::now();
+        let outcome = check();
+        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let passed = outcome.is_ok();
+        self.checks.push(CheckOutcome {
+            name: name.to_string(),
+            passed,
+            reason: outcome.err(),
+            elapsed_ms: Some((elapsed_ms * 1000.0).round() / 1000.0),
+        });
+        passed
+    }
+
+    /// All the checks recorded so far
+    pub fn checks(&self) -> &[CheckOutcome] {
+        &self.checks
+    }
+
+    /// The checks that did not pass
+    pub fn failures(&self) -> impl Iterator<Item = &CheckOutcome> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+
+    /// Returns `true` when all the recorded checks passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// The reasons of all the failed checks, joined in a message that can be
+    /// shown to the user. `None` when all the checks passed
+    pub fn rejection_message(&self) -> Option<String> {
+        let reasons: Vec<String> = self
+            .failures()
+            .map(|check| match &check.reason {
+                Some(reason) => format!("{}: {}", check.name, reason),
+                None => check.name.clone(),
+            })
+            .collect();
+        (!reasons.is_empty()).then(|| reasons.join("; "))
+    }
+
+    /// Serialize the summary as compact JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// The summary as audit annotations, stored under the
+    /// [`AUDIT_ANNOTATION_KEY`] key
+    pub fn audit_annotations(&self) -> HashMap<String, String> {
+        HashMap::from([(AUDIT_ANNOTATION_KEY.to_string(), self.to_json())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_checks() {
+        let mut summary = EvaluationSummary::new();
+        assert!(summary.passed());
+        assert!(summary.rejection_message().is_none());
+
+        assert!(summary.check("first", || Ok(())));
+        assert!(!summary.check("second", || Err("boom".to_string())));
+        summary.record("third", Err("bang".to_string()));
+
+        assert!(!summary.passed());
+        assert_eq!(summary.checks().len(), 3);
+        assert_eq!(summary.failures().count(), 2);
+        assert!(summary.checks()[0].elapsed_ms.is_some());
+        assert!(summary.checks()[2].elapsed_ms.is_none());
+        assert_eq!(
+            summary.rejection_message().unwrap(),
+            "second: boom; third: bang"
+        );
+    }
+
+    #[test]
+    fn compact_audit_annotation() {
+        let mut summary = EvaluationSummary::new();
+        summary.record("first", Ok(()));
+        summary.record("second", Err("boom".to_string()));
+
+        let annotations = summary.audit_annotations();
+        assert_eq!(
+            annotations[AUDIT_ANNOTATION_KEY],
+            r#"{"checks":[{"name":"first","passed":true},{"name":"second","passed":false,"reason":"boom"}]}"#
+        );
+
+        let parsed: EvaluationSummary =
+            serde_json::from_str(&annotations[AUDIT_ANNOTATION_KEY]).unwrap();
+        assert_eq!(parsed, summary);
+    }
+}
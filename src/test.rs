@@ -1,9 +1,23 @@
+use crate::patterns::wildcard_match;
 use crate::response::ValidationResponse;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::json;
 use std::fs::File;
 use std::io::BufReader;
 
+pub mod conformance;
+pub mod diff;
+
+#[cfg(feature = "fuzzing")]
+mod fuzz;
+#[cfg(feature = "fuzzing")]
+pub use fuzz::*;
+
+#[cfg(feature = "fixture-matrix")]
+mod matrix;
+#[cfg(feature = "fixture-matrix")]
+pub use matrix::*;
+
 fn read_request_file(path: &str) -> anyhow::Result<serde_json::Value> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -18,6 +32,13 @@ where
     T: DeserializeOwned + Serialize,
 {
     let req = read_request_file(request_file).unwrap();
+    make_validate_payload_from_request(&req, settings)
+}
+
+fn make_validate_payload_from_request<T>(req: &serde_json::Value, settings: &T) -> String
+where
+    T: Serialize,
+{
     let payload = json!({
         "settings": settings,
         "request": req
@@ -26,8 +47,12 @@ where
     payload.to_string()
 }
 
-#[allow(dead_code)]
-type ValidateFn = fn(&[u8]) -> wapc_guest::CallResult;
+/// Environment variable that, when set to `1`, causes
+/// [`Testcase::eval_snapshot`] to regenerate the golden files
+pub const UPDATE_SNAPSHOTS_ENV: &str = "KUBEWARDEN_UPDATE_SNAPSHOTS";
+
+/// Signature of the `validate` function exposed by a policy
+pub type ValidateFn = fn(&[u8]) -> wapc_guest::CallResult;
 
 pub struct Testcase<T>
 where
@@ -56,4 +81,503 @@ where
 
         Ok(response)
     }
+
+    /// Write the request and the settings of the test case inside of `dir`,
+    /// returning the `kwctl run` command that evaluates `policy_uri` (e.g.
+    /// `annotated-policy.wasm`) against them. The files can be attached to
+    /// bug reports, to reproduce a failing test outside of `cargo test`.
+    pub fn export_kwctl_command(&self, dir: &str, policy_uri: &str) -> anyhow::Result<String> {
+        let request = read_request_file(&self.fixture_file)?;
+        export_kwctl_command(&self.name, dir, policy_uri, &request, &self.settings, false)
+    }
+
+    /// Evaluate the test case, like [`Testcase::eval`] does, and compare the
+    /// whole response against the golden JSON document stored at `snapshot_file`.
+    ///
+    /// An error is returned when the golden file does not exist. Setting the
+    /// `KUBEWARDEN_UPDATE_SNAPSHOTS` environment variable to `1` creates it,
+    /// or regenerates it when it exists already. This allows complex responses, with patches, warnings and
+    /// annotations, to be reviewed as diffs.
+    pub fn eval_snapshot(
+        &self,
+        validate: ValidateFn,
+        snapshot_file: &str,
+    ) -> anyhow::Result<ValidationResponse> {
+        let response = self.eval(validate)?;
+        let actual = serde_json::to_value(&response)?;
+
+        let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|v| v == "1");
+        if update {
+            std::fs::write(snapshot_file, serde_json::to_string_pretty(&actual)? + "\n")?;
+            return Ok(response);
+        }
+        if !std::path::Path::new(snapshot_file).exists() {
+            return Err(anyhow::anyhow!(
+                "snapshot '{}' of test case '{}' does not exist, run with {}=1 to create it",
+                snapshot_file,
+                self.name,
+                UPDATE_SNAPSHOTS_ENV,
+            ));
+        }
+
+        let expected = read_request_file(snapshot_file)?;
+        assert!(
+            actual == expected,
+            "Failure for test case: '{}': response differs from snapshot '{}':\n{}Run with {}=1 to update the snapshot.",
+            self.name,
+            snapshot_file,
+            diff::render(&expected, &actual),
+            UPDATE_SNAPSHOTS_ENV,
+        );
+
+        Ok(response)
+    }
+}
+
+/// A test case for raw policies: the `request` is an arbitrary JSON document,
+/// not wrapped inside of a Kubernetes AdmissionReview.
+pub struct RawTestcase<T>
+where
+    T: DeserializeOwned,
+{
+    pub name: String,
+    pub request: serde_json::Value,
+    pub expected_validation_result: bool,
+    pub settings: T,
+}
+
+#[allow(dead_code)]
+impl<T> RawTestcase<T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    /// Create a test case using the JSON document stored inside of `fixture_file`
+    pub fn from_fixture(
+        name: &str,
+        fixture_file: &str,
+        expected_validation_result: bool,
+        settings: T,
+    ) -> anyhow::Result<Self> {
+        Ok(RawTestcase {
+            name: name.to_string(),
+            request: read_request_file(fixture_file)?,
+            expected_validation_result,
+            settings,
+        })
+    }
+
+    /// Write the request and the settings of the test case inside of `dir`,
+    /// returning the `kwctl run --raw` command that evaluates `policy_uri`
+    /// against them
+    pub fn export_kwctl_command(&self, dir: &str, policy_uri: &str) -> anyhow::Result<String> {
+        export_kwctl_command(
+            &self.name,
+            dir,
+            policy_uri,
+            &self.request,
+            &self.settings,
+            true,
+        )
+    }
+
+    pub fn eval(&self, validate: ValidateFn) -> anyhow::Result<ValidationResponse> {
+        let payload = make_validate_payload_from_request(&self.request, &self.settings);
+        let raw_result = validate(payload.as_bytes()).unwrap();
+        let response: ValidationResponse = serde_json::from_slice(&raw_result)?;
+        assert_eq!(
+            response.accepted, self.expected_validation_result,
+            "Failure for test case: '{}': got {:?} instead of {:?}",
+            self.name, response.accepted, self.expected_validation_result,
+        );
+
+        Ok(response)
+    }
+}
+
+/// Write the request and the settings of a test case inside of `dir`, and
+/// return the `kwctl run` invocation reproducing it
+fn export_kwctl_command<T: Serialize>(
+    name: &str,
+    dir: &str,
+    policy_uri: &str,
+    request: &serde_json::Value,
+    settings: &T,
+    raw: bool,
+) -> anyhow::Result<String> {
+    let slug: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let dir = std::path::Path::new(dir);
+    std::fs::create_dir_all(dir)?;
+
+    let request_path = dir.join(format!("{}-request.json", slug));
+    let document = if raw {
+        json!({ "request": request })
+    } else {
+        json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": request,
+        })
+    };
+    std::fs::write(
+        &request_path,
+        serde_json::to_string_pretty(&document)? + "\n",
+    )?;
+
+    let settings_path = dir.join(format!("{}-settings.json", slug));
+    std::fs::write(
+        &settings_path,
+        serde_json::to_string_pretty(settings)? + "\n",
+    )?;
+
+    Ok(format!(
+        "kwctl run {}--request-path {} --settings-path {} {}",
+        if raw { "--raw " } else { "" },
+        request_path.display(),
+        settings_path.display(),
+        policy_uri,
+    ))
+}
+
+/// Ensure a mutating policy is idempotent.
+///
+/// The policy is evaluated against the request stored inside of `fixture_file`.
+/// When the policy mutates the object, the policy is evaluated a second time
+/// using the mutated object as input. The second evaluation must not produce
+/// any further change, otherwise the function panics.
+///
+/// Non-idempotent mutations cause admission loops, since the object is
+/// changed every time it goes through the policy.
+///
+/// Returns the response of the first evaluation.
+pub fn assert_mutation_idempotent<T>(
+    validate: ValidateFn,
+    fixture_file: &str,
+    settings: &T,
+) -> anyhow::Result<ValidationResponse>
+where
+    T: Serialize,
+{
+    let mut req = read_request_file(fixture_file)?;
+    let payload = make_validate_payload_from_request(&req, settings);
+    let raw_result = validate(payload.as_bytes()).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let first: ValidationResponse = serde_json::from_slice(&raw_result)?;
+
+    let mutated_object = match &first.mutated_object {
+        Some(mutated_object) => mutated_object.clone(),
+        None => return Ok(first),
+    };
+
+    req["object"] = mutated_object.clone();
+    let payload = make_validate_payload_from_request(&req, settings);
+    let raw_result = validate(payload.as_bytes()).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let second: ValidationResponse = serde_json::from_slice(&raw_result)?;
+
+    assert!(
+        second.accepted,
+        "Mutation of '{}' is not idempotent: the mutated object is rejected: {:?}",
+        fixture_file, second.message,
+    );
+    if let Some(second_mutation) = second.mutated_object {
+        assert!(
+            second_mutation == mutated_object,
+            "Mutation of '{}' is not idempotent: the mutated object is changed again:\n{}",
+            fixture_file,
+            diff::render(&mutated_object, &second_mutation),
+        );
+    }
+
+    Ok(first)
+}
+
+/// Ensure a validating policy never mutates the incoming objects.
+///
+/// The policy is evaluated against all the request files matching
+/// `fixtures_glob` (e.g. `test_data/*.json`). The `*` and `?` wildcards are
+/// supported inside of the file name. The function panics when one of the
+/// responses contains a `mutated_object`: policies registered with
+/// `mutating: false` are not allowed to do that, the policy-server would
+/// reject the response.
+///
+/// Returns the number of fixtures that have been evaluated.
+pub fn assert_never_mutates<T>(
+    validate: ValidateFn,
+    fixtures_glob: &str,
+    settings: &T,
+) -> anyhow::Result<usize>
+where
+    T: Serialize,
+{
+    let fixtures = find_fixtures(fixtures_glob)?;
+    if fixtures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no fixture file matches '{}'",
+            fixtures_glob
+        ));
+    }
+
+    for fixture in &fixtures {
+        let req = read_request_file(fixture)?;
+        let payload = make_validate_payload_from_request(&req, settings);
+        let raw_result = validate(payload.as_bytes()).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let response: ValidationResponse = serde_json::from_slice(&raw_result)?;
+        assert!(
+            response.mutated_object.is_none(),
+            "Policy mutated the object of fixture '{}'",
+            fixture
+        );
+    }
+
+    Ok(fixtures.len())
+}
+
+/// Make [`get_priority_class`](crate::host_capabilities::kubernetes::get_priority_class)
+/// return `priority_class` without reaching the host, so that policies
+/// relying on it (e.g. through
+/// [`PriorityClassPolicy`](crate::priority::PriorityClassPolicy)) can be
+/// unit tested.
+///
+/// The PriorityClass is looked up by its `metadata.name`, which must be set.
+/// It stays available until
+/// [`clear_priority_class_cache`](crate::host_capabilities::kubernetes::clear_priority_class_cache)
+/// is invoked.
+#[cfg(feature = "cluster-context")]
+pub fn mock_priority_class(priority_class: k8s_openapi::api::scheduling::v1::PriorityClass) {
+    let name = priority_class
+        .metadata
+        .name
+        .clone()
+        .expect("the mocked PriorityClass must have a name");
+    crate::host_capabilities::kubernetes::cache_priority_class(&name, priority_class);
+}
+
+/// Find all the files matching a simple glob expression. Wildcards are
+/// allowed only inside of the file name.
+fn find_fixtures(glob: &str) -> anyhow::Result<Vec<String>> {
+    let path = std::path::Path::new(glob);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    let pattern = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("invalid fixtures glob '{}'", glob))?;
+
+    let mut fixtures: Vec<String> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| wildcard_match(pattern, name))
+        })
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .collect();
+    fixtures.sort();
+
+    Ok(fixtures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ValidationRequest;
+    use serial_test::serial;
+    use std::io::Write;
+
+    #[derive(serde::Serialize, serde::Deserialize, Default)]
+    struct Settings {}
+
+    fn write_fixture(name: &str, request: &serde_json::Value) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "kubewarden-sdk-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(request.to_string().as_bytes()).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn add_label(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = ValidationRequest::<Settings>::new(payload)?;
+        let mut object = req.request.object;
+        crate::mutation::set(&mut object, "/metadata/labels/owner", json!("team-a"))?;
+        crate::mutate_request(object)
+    }
+
+    fn append_suffix(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = ValidationRequest::<Settings>::new(payload)?;
+        let mut object = req.request.object;
+        let name = format!("{}-x", object["metadata"]["name"].as_str().unwrap());
+        object["metadata"]["name"] = json!(name);
+        crate::mutate_request(object)
+    }
+
+    #[serial]
+    #[test]
+    fn idempotent_mutation() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let fixture = write_fixture(
+            "idempotent",
+            &json!({"object": {"metadata": {"name": "nginx"}}}),
+        );
+        let response = assert_mutation_idempotent(add_label, &fixture, &Settings {}).unwrap();
+        assert!(response.mutated_object.is_some());
+    }
+
+    #[serial]
+    #[test]
+    #[should_panic(expected = "is not idempotent")]
+    fn non_idempotent_mutation() {
+        let _host = crate::host_capabilities::policy::tests::mock_mutating_policy();
+        let fixture = write_fixture(
+            "non-idempotent",
+            &json!({"object": {"metadata": {"name": "nginx"}}}),
+        );
+        let _ = assert_mutation_idempotent(append_suffix, &fixture, &Settings {});
+    }
+
+    fn reject_with_details(_payload: &[u8]) -> wapc_guest::CallResult {
+        crate::reject_request(
+            Some("denied".to_string()),
+            Some(403),
+            None,
+            Some(vec!["careful".to_string()]),
+        )
+    }
+
+    #[serial]
+    #[test]
+    fn snapshot_roundtrip() {
+        let fixture = write_fixture("snapshot-request", &json!({"object": {}}));
+        let snapshot = std::env::temp_dir().join(format!(
+            "kubewarden-sdk-{}-snapshot.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&snapshot);
+        let snapshot = snapshot.to_string_lossy().to_string();
+
+        let tc = Testcase {
+            name: "snapshot".to_string(),
+            fixture_file: fixture,
+            expected_validation_result: false,
+            settings: Settings {},
+        };
+        assert!(tc.eval_snapshot(reject_with_details, &snapshot).is_err());
+        assert!(!std::path::Path::new(&snapshot).exists());
+
+        std::env::set_var(UPDATE_SNAPSHOTS_ENV, "1");
+        let created = tc.eval_snapshot(reject_with_details, &snapshot);
+        std::env::remove_var(UPDATE_SNAPSHOTS_ENV);
+        created.unwrap();
+        assert!(std::path::Path::new(&snapshot).exists());
+        tc.eval_snapshot(reject_with_details, &snapshot).unwrap();
+
+        std::fs::write(&snapshot, r#"{"accepted": false}"#).unwrap();
+        let result = std::panic::catch_unwind(|| tc.eval_snapshot(reject_with_details, &snapshot));
+        assert!(result.is_err());
+    }
+
+    fn allow_alice(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = crate::request::RawValidationRequest::<Settings>::new(payload)?;
+        if req.request["user"] == "alice" {
+            crate::accept_request()
+        } else {
+            crate::reject_request(Some("user not allowed".to_string()), None, None, None)
+        }
+    }
+
+    #[test]
+    fn raw_testcase() {
+        let tc = RawTestcase {
+            name: "alice is allowed".to_string(),
+            request: json!({"user": "alice", "action": "eats"}),
+            expected_validation_result: true,
+            settings: Settings {},
+        };
+        tc.eval(allow_alice).unwrap();
+
+        let fixture = write_fixture("raw", &json!({"user": "bob", "action": "eats"}));
+        let tc =
+            RawTestcase::from_fixture("bob is rejected", &fixture, false, Settings {}).unwrap();
+        let response = tc.eval(allow_alice).unwrap();
+        assert_eq!(response.message.as_deref(), Some("user not allowed"));
+    }
+
+    #[test]
+    fn export_kwctl_reproduction() {
+        let dir = std::env::temp_dir().join(format!("kubewarden-sdk-{}-kwctl", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        let fixture = write_fixture("kwctl", &json!({"uid": "1", "object": {}}));
+
+        let tc = Testcase {
+            name: "Reject Pod".to_string(),
+            fixture_file: fixture,
+            expected_validation_result: false,
+            settings: Settings {},
+        };
+        let command = tc.export_kwctl_command(&dir, "policy.wasm").unwrap();
+        let request_path = format!("{}/reject-pod-request.json", dir);
+        let settings_path = format!("{}/reject-pod-settings.json", dir);
+        assert_eq!(
+            command,
+            format!(
+                "kwctl run --request-path {} --settings-path {} policy.wasm",
+                request_path, settings_path
+            )
+        );
+        let review = read_request_file(&request_path).unwrap();
+        assert_eq!(review["kind"], "AdmissionReview");
+        assert_eq!(review["request"]["uid"], "1");
+        assert_eq!(read_request_file(&settings_path).unwrap(), json!({}));
+
+        let tc = RawTestcase {
+            name: "raw".to_string(),
+            request: json!({"user": "alice"}),
+            expected_validation_result: true,
+            settings: Settings {},
+        };
+        let command = tc.export_kwctl_command(&dir, "policy.wasm").unwrap();
+        assert!(command.starts_with("kwctl run --raw --request-path"));
+        let document = read_request_file(&format!("{}/raw-request.json", dir)).unwrap();
+        assert_eq!(document, json!({"request": {"user": "alice"}}));
+    }
+
+    #[test]
+    fn never_mutates() {
+        let dir = std::env::temp_dir().join(format!("kubewarden-sdk-{}-never", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.json", "b.json"] {
+            std::fs::write(
+                dir.join(name),
+                r#"{"object": {"metadata": {"name": "nginx"}}}"#,
+            )
+            .unwrap();
+        }
+        let glob = dir.join("*.json").to_string_lossy().to_string();
+
+        let accept = |_: &[u8]| crate::accept_request();
+        assert_eq!(
+            assert_never_mutates(accept, &glob, &Settings {}).unwrap(),
+            2
+        );
+
+        let result =
+            std::panic::catch_unwind(|| assert_never_mutates(add_label, &glob, &Settings {}));
+        assert!(result.is_err());
+
+        let missing = dir.join("*.yaml").to_string_lossy().to_string();
+        assert!(assert_never_mutates(accept, &missing, &Settings {}).is_err());
+    }
 }
@@ -0,0 +1,398 @@
+//! Golden payloads of the waPC protocol.
+//!
+//! The Kubewarden protocol is implemented by many parties: the SDKs used to
+//! write policies (Rust, Go,...) and the hosts evaluating them
+//! (policy-server, kwctl). A change of the serialized form of a payload, like
+//! the renaming of an enum variant, silently breaks the other parties.
+//!
+//! This module ships canonical payload and response pairs for every protocol
+//! function exported by the policies and for every host capability operation.
+//! The cases are plain JSON, see [`CASES_JSON`], so that they can be consumed
+//! by the test suites of the other implementations. [`check`] verifies this
+//! SDK against a case:
+//!
+//! * the data produced by the SDK (the payloads of the host capabilities and
+//!   the responses of the protocol functions) must be serialized exactly as
+//!   the golden document
+//! * the data consumed by the SDK (the payloads of the protocol functions and
+//!   the responses of the host capabilities) must be decoded without errors
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::test::conformance;
+//!
+//! for case in conformance::cases() {
+//!     if case.requires_cluster_context() && cfg!(not(feature = "cluster-context")) {
+//!         continue;
+//!     }
+//!     conformance::check(&case).unwrap();
+//! }
+//! ```
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::host_capabilities::{self, ops};
+use crate::metadata::{GuestMetadata, ProtocolVersion};
+use crate::request::ValidationRequest;
+use crate::response::ValidationResponse;
+use crate::settings::SettingsValidationResponse;
+
+/// All the cases, as a JSON list of [`Case`]
+pub const CASES_JSON: &str = include_str!("conformance.json");
+
+/// The function, or host capability operation, exercised by a [`Case`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Target {
+    /// A waPC function exported by the policy (e.g. `validate`)
+    GuestFunction {
+        /// Name of the function
+        function: String,
+    },
+    /// A waPC operation exposed by the host, see [`ops`]
+    HostCapability {
+        /// Namespace of the operation
+        namespace: String,
+        /// Name of the operation
+        operation: String,
+    },
+}
+
+/// A payload, and the response it produces
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Case {
+    /// Unique name of the case
+    pub name: String,
+    /// What the payload is sent to
+    pub target: Target,
+    /// The payload. `None` when the payload is empty
+    #[serde(default)]
+    pub payload: Option<Value>,
+    /// The response. `None` when the response is empty, or ignored
+    #[serde(default)]
+    pub response: Option<Value>,
+}
+
+impl Case {
+    /// Returns `true` when the types exercised by the case are available
+    /// only with the `cluster-context` feature
+    pub fn requires_cluster_context(&self) -> bool {
+        matches!(&self.target, Target::HostCapability { namespace, .. } if namespace == ops::NAMESPACE_KUBERNETES)
+    }
+}
+
+/// All the conformance cases
+pub fn cases() -> Vec<Case> {
+    serde_json::from_str(CASES_JSON).expect("the conformance cases are valid")
+}
+
+/// The cases exercising the given host capability operation
+pub fn host_capability_cases(namespace: &str, operation: &str) -> Vec<Case> {
+    cases()
+        .into_iter()
+        .filter(|case| {
+            matches!(&case.target, Target::HostCapability { namespace: ns, operation: op } if ns == namespace && op == operation)
+        })
+        .collect()
+}
+
+fn required<'a>(value: &'a Option<Value>, what: &str) -> Result<&'a Value> {
+    value
+        .as_ref()
+        .ok_or_else(|| anyhow!("the {} is missing", what))
+}
+
+/// Ensure the document is decoded into `T`
+fn decodes<T: DeserializeOwned>(value: &Option<Value>, what: &str) -> Result<T> {
+    serde_json::from_value(required(value, what)?.clone())
+        .map_err(|e| anyhow!("cannot decode the {}: {}", what, e))
+}
+
+/// Ensure the document is decoded into `T`, and serialized back as it is
+fn round_trips<T: DeserializeOwned + Serialize>(value: &Option<Value>, what: &str) -> Result<()> {
+    let decoded: T = decodes(value, what)?;
+    let encoded = serde_json::to_value(decoded)?;
+    let expected = required(value, what)?;
+    if &encoded != expected {
+        return Err(anyhow!(
+            "the {} is serialized differently:\n{}",
+            what,
+            crate::test::diff::render(expected, &encoded)
+        ));
+    }
+    Ok(())
+}
+
+/// Verify this SDK against the given case
+pub fn check(case: &Case) -> Result<()> {
+    check_target(case).map_err(|e| anyhow!("conformance case '{}': {}", case.name, e))
+}
+
+fn check_target(case: &Case) -> Result<()> {
+    let payload = &case.payload;
+    let response = &case.response;
+    match &case.target {
+        Target::GuestFunction { function } => match function.as_str() {
+            crate::VALIDATE_FUNCTION => {
+                decodes::<ValidationRequest<Value>>(payload, "payload")?;
+                round_trips::<ValidationResponse>(response, "response")
+            }
+            crate::VALIDATE_SETTINGS_FUNCTION => {
+                required(payload, "payload")?;
+                round_trips::<SettingsValidationResponse>(response, "response")
+            }
+            crate::PROTOCOL_VERSION_FUNCTION => {
+                round_trips::<ProtocolVersion>(response, "response")
+            }
+            crate::GUEST_METADATA_FUNCTION => round_trips::<GuestMetadata>(response, "response"),
+            function => Err(anyhow!("unknown guest function '{}'", function)),
+        },
+        Target::HostCapability {
+            namespace,
+            operation,
+        } => check_host_capability(namespace, operation, payload, response),
+    }
+}
+
+fn check_host_capability(
+    namespace: &str,
+    operation: &str,
+    payload: &Option<Value>,
+    response: &Option<Value>,
+) -> Result<()> {
+    use host_capabilities::{crypto, events, net, oci, policy, verification};
+
+    match (namespace, operation) {
+        #[allow(deprecated)]
+        (ops::NAMESPACE_OCI, ops::OCI_V1_VERIFY) => {
+            round_trips::<verification::v1::SigstoreVerificationInput>(payload, "payload")?;
+            decodes::<verification::v1::VerificationResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V2_VERIFY) => {
+            round_trips::<verification::v2::SigstoreVerificationInput>(payload, "payload")?;
+            decodes::<verification::v2::VerificationResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V1_SIGSTORE_TRUST_STORE_STATUS) => {
+            decodes::<verification::v1::TrustStoreStatus>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V1_MANIFEST_DIGEST) => {
+            round_trips::<String>(payload, "payload")?;
+            decodes::<oci::v1::ManifestDigestResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V1_MANIFEST) => {
+            round_trips::<String>(payload, "payload")?;
+            match decodes::<oci::v1::OciManifestResponse>(response, "response")? {
+                oci::v1::OciManifestResponse::Unknown(_) => {
+                    Err(anyhow!("the response is not a known OCI manifest"))
+                }
+                _ => Ok(()),
+            }
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V1_MANIFEST_CONFIG) => {
+            round_trips::<String>(payload, "payload")?;
+            decodes::<oci::v1::OciManifestAndConfigResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_OCI, ops::OCI_V1_SIGSTORE_SIGNATURES) => {
+            round_trips::<String>(payload, "payload")?;
+            decodes::<verification::v1::SignaturesResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_CRYPTO, ops::CRYPTO_V1_IS_CERTIFICATE_TRUSTED) => {
+            round_trips::<crypto::v1::CertificateVerificationRequest>(payload, "payload")?;
+            decodes::<crypto::v1::CertificateVerificationResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_CRYPTO, ops::CRYPTO_V2_IS_CERTIFICATE_TRUSTED) => {
+            round_trips::<crypto::v2::CertificateVerificationRequest>(payload, "payload")?;
+            decodes::<crypto::v2::CertificateVerificationResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_NET, ops::NET_V1_DNS_LOOKUP_HOST) => {
+            round_trips::<String>(payload, "payload")?;
+            decodes::<net::v1::LookupResponse>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_KUBERNETES, operation) => check_kubernetes(operation, payload, response),
+        (ops::NAMESPACE_TRACING, ops::TRACING_LOG) => {
+            let event = required(payload, "payload")?;
+            let level = event["level"].as_str().unwrap_or_default();
+            if !["debug", "info", "warning", "error"].contains(&level) {
+                return Err(anyhow!("invalid log level '{}'", level));
+            }
+            for field in ["message", "file"] {
+                if !event[field].is_string() {
+                    return Err(anyhow!("the '{}' field of the log event is missing", field));
+                }
+            }
+            Ok(())
+        }
+        (ops::NAMESPACE_TIME, ops::TIME_V1_NOW) => {
+            decodes::<String>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_RAND, ops::RAND_V1_BYTES) => {
+            round_trips::<usize>(payload, "payload")?;
+            decodes::<Vec<u8>>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_POLICY, ops::POLICY_V1_INFO) => {
+            decodes::<policy::v1::PolicyInfo>(response, "response").map(drop)
+        }
+        (ops::NAMESPACE_EVENTS, ops::EVENTS_V1_EMIT) => {
+            round_trips::<events::v1::EmitEventRequest>(payload, "payload")
+        }
+        (namespace, operation) => Err(anyhow!(
+            "unknown host capability '{}/{}'",
+            namespace,
+            operation
+        )),
+    }
+}
+
+#[cfg(feature = "cluster-context")]
+fn check_kubernetes(
+    operation: &str,
+    payload: &Option<Value>,
+    response: &Option<Value>,
+) -> Result<()> {
+    use host_capabilities::kubernetes::v1::*;
+    use k8s_openapi::api::core::v1::{ConfigMap, Namespace};
+
+    /// Decode a Kubernetes object, or a list of them, of the kind requested
+    /// by the payload
+    fn decodes_kind(payload: &Option<Value>, response: &Option<Value>, list: bool) -> Result<()> {
+        let payload = required(payload, "payload")?;
+        let kind = payload["kind"]
+            .as_str()
+            .or_else(|| payload["object"]["kind"].as_str())
+            .unwrap_or_default();
+        match (kind, list) {
+            ("ConfigMap", false) => decodes::<ConfigMap>(response, "response").map(drop),
+            ("ConfigMap", true) => {
+                decodes::<k8s_openapi::List<ConfigMap>>(response, "response").map(drop)
+            }
+            ("Namespace", false) => decodes::<Namespace>(response, "response").map(drop),
+            ("Namespace", true) => {
+                decodes::<k8s_openapi::List<Namespace>>(response, "response").map(drop)
+            }
+            (kind, _) => Err(anyhow!("no conformance type for kind '{}'", kind)),
+        }
+    }
+
+    match operation {
+        ops::KUBERNETES_LIST_RESOURCES_BY_NAMESPACE => {
+            round_trips::<ListResourcesByNamespaceRequest>(payload, "payload")?;
+            decodes_kind(payload, response, true)
+        }
+        ops::KUBERNETES_LIST_RESOURCES_ALL => {
+            round_trips::<ListAllResourcesRequest>(payload, "payload")?;
+            decodes_kind(payload, response, true)
+        }
+        ops::KUBERNETES_LIST_RESOURCES_METADATA => {
+            round_trips::<ListMetadataRequest>(payload, "payload")?;
+            decodes::<PartialObjectMetadataList>(response, "response").map(drop)
+        }
+        ops::KUBERNETES_GET_RESOURCE => {
+            round_trips::<GetResourceRequest>(payload, "payload")?;
+            decodes_kind(payload, response, false)
+        }
+        ops::KUBERNETES_DRY_RUN_APPLY => {
+            required(
+                &required(payload, "payload")?.get("object").cloned(),
+                "object",
+            )?;
+            decodes_kind(payload, response, false)
+        }
+        operation => Err(anyhow!(
+            "unknown host capability '{}/{}'",
+            ops::NAMESPACE_KUBERNETES,
+            operation
+        )),
+    }
+}
+
+#[cfg(not(feature = "cluster-context"))]
+fn check_kubernetes(
+    _operation: &str,
+    _payload: &Option<Value>,
+    _response: &Option<Value>,
+) -> Result<()> {
+    Err(anyhow!(
+        "the Kubernetes host capabilities require the `cluster-context` feature"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn all_cases_pass() {
+        for case in cases() {
+            if case.requires_cluster_context() && cfg!(not(feature = "cluster-context")) {
+                assert!(check(&case).is_err());
+                continue;
+            }
+            check(&case).unwrap();
+        }
+    }
+
+    #[test]
+    fn every_operation_is_covered() {
+        for operation in ops::OPERATIONS {
+            assert!(
+                !host_capability_cases(operation.namespace, operation.operation).is_empty(),
+                "no conformance case for {}/{}",
+                operation.namespace,
+                operation.operation
+            );
+        }
+
+        let functions: HashSet<String> = cases()
+            .into_iter()
+            .filter_map(|case| match case.target {
+                Target::GuestFunction { function } => Some(function),
+                _ => None,
+            })
+            .collect();
+        for function in [
+            crate::VALIDATE_FUNCTION,
+            crate::VALIDATE_SETTINGS_FUNCTION,
+            crate::PROTOCOL_VERSION_FUNCTION,
+            crate::GUEST_METADATA_FUNCTION,
+        ] {
+            assert!(
+                functions.contains(function),
+                "no conformance case for {}",
+                function
+            );
+        }
+    }
+
+    #[test]
+    fn unique_names() {
+        let cases = cases();
+        let names: HashSet<&str> = cases.iter().map(|case| case.name.as_str()).collect();
+        assert_eq!(names.len(), cases.len());
+    }
+
+    #[test]
+    fn detect_serialization_drift() {
+        let mut case =
+            host_capability_cases(ops::NAMESPACE_CRYPTO, ops::CRYPTO_V1_IS_CERTIFICATE_TRUSTED)
+                .remove(0);
+        case.payload.as_mut().unwrap()["cert"]["encoding"] = "PEM".into();
+        assert!(check(&case)
+            .unwrap_err()
+            .to_string()
+            .contains("cannot decode the payload"));
+
+        let mut case = cases()
+            .into_iter()
+            .find(|case| case.name == "validate-settings-valid")
+            .unwrap();
+        case.response.as_mut().unwrap()["is_valid"] = true.into();
+        assert!(check(&case)
+            .unwrap_err()
+            .to_string()
+            .contains("serialized differently"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Path-level differences between JSON documents.
+//!
+//! Used by the test helpers to report mutation failures without dumping the
+//! whole serialized objects. Policy authors can use it to debug their
+//! mutations too:
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::test::diff;
+//! use serde_json::json;
+//!
+//! let expected = json!({"metadata": {"labels": {"owner": "team-a"}}});
+//! let actual = json!({"metadata": {"labels": {"owner": "team-b", "env": "prod"}}});
+//!
+//! println!("{}", diff::render(&expected, &actual));
+//! ```
+use serde_json::Value;
+
+/// Environment variable that, when set, disables the ANSI colors produced by
+/// [`render`]. See <https://no-color.org>
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// A difference found between two JSON documents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// The value is present only inside of the actual document
+    Added { path: String, actual: Value },
+    /// The value is present only inside of the expected document
+    Removed { path: String, expected: Value },
+    /// The value is different inside of the two documents
+    Changed {
+        path: String,
+        expected: Value,
+        actual: Value,
+    },
+}
+
+impl Difference {
+    /// JSON Pointer of the value that differs
+    pub fn path(&self) -> &str {
+        match self {
+            Difference::Added { path, .. }
+            | Difference::Removed { path, .. }
+            | Difference::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// Compute the differences between `expected` and `actual`. Objects and
+/// arrays are compared member by member, the other values are compared as a
+/// whole. The differences are sorted by path.
+pub fn diff(expected: &Value, actual: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    walk("", expected, actual, &mut differences);
+    differences.sort_by(|a, b| a.path().cmp(b.path()));
+    differences
+}
+
+fn walk(path: &str, expected: &Value, actual: &Value, differences: &mut Vec<Difference>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let child = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match actual.get(key) {
+                    Some(actual_value) => walk(&child, expected_value, actual_value, differences),
+                    None => differences.push(Difference::Removed {
+                        path: child,
+                        expected: expected_value.clone(),
+                    }),
+                }
+            }
+            for (key, actual_value) in actual
+                .iter()
+                .filter(|(key, _)| !expected.contains_key(*key))
+            {
+                differences.push(Difference::Added {
+                    path: format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1")),
+                    actual: actual_value.clone(),
+                });
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for index in 0..expected.len().max(actual.len()) {
+                let child = format!("{path}/{index}");
+                match (expected.get(index), actual.get(index)) {
+                    (Some(e), Some(a)) => walk(&child, e, a, differences),
+                    (Some(e), None) => differences.push(Difference::Removed {
+                        path: child,
+                        expected: e.clone(),
+                    }),
+                    (None, Some(a)) => differences.push(Difference::Added {
+                        path: child,
+                        actual: a.clone(),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (expected, actual) if expected != actual => differences.push(Difference::Changed {
+            path: path.to_string(),
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Render the differences between `expected` and `actual`, one line per
+/// path. Removed and expected values are prefixed by `-`, added and actual
+/// values by `+`. The output is colored unless the `NO_COLOR` environment
+/// variable is set.
+pub fn render(expected: &Value, actual: &Value) -> String {
+    render_differences(
+        &diff(expected, actual),
+        std::env::var_os(NO_COLOR_ENV).is_none(),
+    )
+}
+
+/// Render a list of differences, optionally using ANSI colors
+pub fn render_differences(differences: &[Difference], colored: bool) -> String {
+    let (red, green, reset) = if colored {
+        (RED, GREEN, RESET)
+    } else {
+        ("", "", "")
+    };
+    let path = |path: &str| {
+        if path.is_empty() {
+            "/".to_string()
+        } else {
+            path.to_string()
+        }
+    };
+
+    differences
+        .iter()
+        .map(|difference| match difference {
+            Difference::Added { path: p, actual } => {
+                format!("{green}+ {}: {actual}{reset}\n", path(p))
+            }
+            Difference::Removed { path: p, expected } => {
+                format!("{red}- {}: {expected}{reset}\n", path(p))
+            }
+            Difference::Changed {
+                path: p,
+                expected,
+                actual,
+            } => format!(
+                "~ {}: {red}{expected}{reset} -> {green}{actual}{reset}\n",
+                path(p)
+            ),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn path_level_differences() {
+        let expected = json!({
+            "metadata": {"name": "nginx", "labels": {"a/b": "1", "gone": "x"}},
+            "spec": {"containers": [{"image": "nginx"}]}
+        });
+        let actual = json!({
+            "metadata": {"name": "nginx", "labels": {"a/b": "2", "new": "y"}},
+            "spec": {"containers": [{"image": "nginx"}, {"image": "sidecar"}]}
+        });
+
+        assert_eq!(
+            diff(&expected, &actual),
+            vec![
+                Difference::Changed {
+                    path: "/metadata/labels/a~1b".to_string(),
+                    expected: json!("1"),
+                    actual: json!("2"),
+                },
+                Difference::Removed {
+                    path: "/metadata/labels/gone".to_string(),
+                    expected: json!("x"),
+                },
+                Difference::Added {
+                    path: "/metadata/labels/new".to_string(),
+                    actual: json!("y"),
+                },
+                Difference::Added {
+                    path: "/spec/containers/1".to_string(),
+                    actual: json!({"image": "sidecar"}),
+                },
+            ]
+        );
+        assert!(diff(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn render_plain_and_colored() {
+        let differences = diff(&json!({"a": 1, "b": true}), &json!({"a": 2, "c": null}));
+        assert_eq!(
+            render_differences(&differences, false),
+            "~ /a: 1 -> 2\n- /b: true\n+ /c: null\n"
+        );
+        assert!(render_differences(&differences, true).contains("\x1b[31m1\x1b[0m"));
+
+        let root = diff(&json!(1), &json!("1"));
+        assert_eq!(render_differences(&root, false), "~ /: 1 -> \"1\"\n");
+    }
+}
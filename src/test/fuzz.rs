@@ -0,0 +1,237 @@
+use arbitrary::{Arbitrary, Unstructured};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+use super::{make_validate_payload_from_request, ValidateFn};
+use crate::request::{
+    GroupVersionKind, GroupVersionResource, KubernetesAdmissionRequest, UserInfo,
+};
+use crate::response::ValidationResponse;
+
+const MAX_DEPTH: usize = 4;
+const MAX_ITEMS: usize = 4;
+
+const OPERATIONS: &[&str] = &["CREATE", "UPDATE", "DELETE", "CONNECT"];
+
+/// (group, version, kind, resource) tuples used to produce plausible requests
+const KINDS: &[(&str, &str, &str, &str)] = &[
+    ("", "v1", "Pod", "pods"),
+    ("", "v1", "Service", "services"),
+    ("", "v1", "ConfigMap", "configmaps"),
+    ("", "v1", "Namespace", "namespaces"),
+    ("apps", "v1", "Deployment", "deployments"),
+    ("apps", "v1", "StatefulSet", "statefulsets"),
+    ("apps", "v1", "DaemonSet", "daemonsets"),
+    ("batch", "v1", "Job", "jobs"),
+    ("batch", "v1", "CronJob", "cronjobs"),
+    ("networking.k8s.io", "v1", "Ingress", "ingresses"),
+];
+
+/// An admission request with a valid envelope and an arbitrary object payload.
+///
+/// The type implements [`arbitrary::Arbitrary`], hence it can be used
+/// directly as input of a `cargo-fuzz` target:
+///
+/// ```ignore
+/// fuzz_target!(|req: FuzzAdmissionRequest| {
+///     kubewarden_policy_sdk::test::fuzz_validate_request(validate, &Settings::default(), req);
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct FuzzAdmissionRequest(pub KubernetesAdmissionRequest);
+
+impl<'a> Arbitrary<'a> for FuzzAdmissionRequest {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        arbitrary_admission_request(u).map(FuzzAdmissionRequest)
+    }
+}
+
+/// Generate an admission request with a valid envelope: a well known kind,
+/// a valid operation, user information and an object whose `metadata` is
+/// well formed while the rest of the document is arbitrary.
+///
+/// DELETE requests have an empty `object` and the generated document inside
+/// of `old_object`, like the Kubernetes API server does.
+pub fn arbitrary_admission_request(
+    u: &mut Unstructured,
+) -> arbitrary::Result<KubernetesAdmissionRequest> {
+    let (group, version, kind, resource) = *u.choose(KINDS)?;
+    let operation = u.choose(OPERATIONS)?.to_string();
+    let name = arbitrary_name(u)?;
+    let namespace = if kind == "Namespace" {
+        String::new()
+    } else {
+        arbitrary_name(u)?
+    };
+
+    let api_version = if group.is_empty() {
+        version.to_string()
+    } else {
+        format!("{group}/{version}")
+    };
+    let object = json!({
+        "apiVersion": api_version,
+        "kind": kind,
+        "metadata": {
+            "name": name,
+            "namespace": namespace,
+            "labels": arbitrary_string_map(u)?,
+            "annotations": arbitrary_string_map(u)?,
+        },
+        "spec": arbitrary_json_value(u, 0)?,
+    });
+    let (object, old_object) = match operation.as_str() {
+        "DELETE" => (Value::Null, object),
+        "UPDATE" => {
+            let mut old_object = object.clone();
+            old_object["spec"] = arbitrary_json_value(u, 0)?;
+            (object, old_object)
+        }
+        _ => (object, Value::Null),
+    };
+
+    let gvk = GroupVersionKind {
+        group: group.to_string(),
+        version: version.to_string(),
+        kind: kind.to_string(),
+    };
+
+    Ok(KubernetesAdmissionRequest {
+        uid: arbitrary_name(u)?,
+        kind: gvk.clone(),
+        resource: GroupVersionResource {
+            group: group.to_string(),
+            version: version.to_string(),
+            kind: resource.to_string(),
+        },
+        request_kind: gvk,
+        name,
+        namespace,
+        operation,
+        user_info: UserInfo {
+            username: String::arbitrary(u)?,
+            uid: String::arbitrary(u)?,
+            groups: HashSet::from(["system:authenticated".to_string()]),
+            extra: HashMap::new(),
+        },
+        object,
+        old_object,
+        dry_run: bool::arbitrary(u)?,
+        ..Default::default()
+    })
+}
+
+fn arbitrary_name(u: &mut Unstructured) -> arbitrary::Result<String> {
+    let len = u.int_in_range(1..=16)?;
+    (0..len)
+        .map(|_| {
+            u.choose(b"abcdefghijklmnopqrstuvwxyz0123456789-")
+                .map(|c| *c as char)
+        })
+        .collect()
+}
+
+fn arbitrary_string_map(u: &mut Unstructured) -> arbitrary::Result<HashMap<String, String>> {
+    let len = u.int_in_range(0..=MAX_ITEMS)?;
+    (0..len)
+        .map(|_| Ok((arbitrary_name(u)?, String::arbitrary(u)?)))
+        .collect()
+}
+
+/// Generate an arbitrary JSON document, nested at most a few levels deep
+pub fn arbitrary_json_value(u: &mut Unstructured, depth: usize) -> arbitrary::Result<Value> {
+    let max_choice = if depth >= MAX_DEPTH { 3 } else { 5 };
+    Ok(match u.int_in_range(0..=max_choice)? {
+        0 => Value::Null,
+        1 => Value::Bool(bool::arbitrary(u)?),
+        2 => json!(i64::arbitrary(u)?),
+        3 => Value::String(String::arbitrary(u)?),
+        4 => {
+            let len = u.int_in_range(0..=MAX_ITEMS)?;
+            Value::Array(
+                (0..len)
+                    .map(|_| arbitrary_json_value(u, depth + 1))
+                    .collect::<arbitrary::Result<_>>()?,
+            )
+        }
+        _ => {
+            let len = u.int_in_range(0..=MAX_ITEMS)?;
+            Value::Object(
+                (0..len)
+                    .map(|_| Ok((arbitrary_name(u)?, arbitrary_json_value(u, depth + 1)?)))
+                    .collect::<arbitrary::Result<_>>()?,
+            )
+        }
+    })
+}
+
+/// Evaluate the policy against the given request. Errors returned by the
+/// policy are fine, panics are not. When the policy returns a response, the
+/// response must be a valid `ValidationResponse`.
+pub fn fuzz_validate_request<T>(validate: ValidateFn, settings: &T, req: FuzzAdmissionRequest)
+where
+    T: Serialize,
+{
+    let req = serde_json::to_value(req.0).expect("cannot serialize admission request");
+    let payload = make_validate_payload_from_request(&req, settings);
+    if let Ok(raw_response) = validate(payload.as_bytes()) {
+        serde_json::from_slice::<ValidationResponse>(&raw_response)
+            .expect("policy returned an invalid ValidationResponse");
+    }
+}
+
+/// Evaluate the policy against a request generated from the raw fuzzer input.
+/// Inputs that are too short to produce a request are ignored.
+pub fn fuzz_validate<T>(validate: ValidateFn, settings: &T, data: &[u8])
+where
+    T: Serialize,
+{
+    let mut u = Unstructured::new(data);
+    if let Ok(req) = FuzzAdmissionRequest::arbitrary(&mut u) {
+        fuzz_validate_request(validate, settings, req);
+    }
+}
+
+/// Build a `cargo-fuzz` compatible target for the given validate function.
+///
+/// ```ignore
+/// fuzz_target!(|data: &[u8]| {
+///     let target = kubewarden_policy_sdk::test::fuzz_target(validate, Settings::default());
+///     target(data)
+/// });
+/// ```
+pub fn fuzz_target<T>(validate: ValidateFn, settings: T) -> impl Fn(&[u8])
+where
+    T: Serialize,
+{
+    move |data: &[u8]| fuzz_validate(validate, &settings, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = crate::request::ValidationRequest::<()>::new(payload)?;
+        assert!(!req.request.kind.kind.is_empty());
+        if req.request.operation == "DELETE" {
+            assert!(req.request.object.is_null());
+            assert!(req.request.old_object.get("metadata").is_some());
+        } else {
+            assert!(req.request.object.get("metadata").is_some());
+        }
+        crate::accept_request()
+    }
+
+    #[test]
+    fn generated_requests_are_plausible() {
+        let target = fuzz_target(validate, ());
+        for seed in 0..64u8 {
+            let data: Vec<u8> = (0..512u32)
+                .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+                .collect();
+            target(&data);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{find_fixtures, make_validate_payload_from_request, read_request_file, ValidateFn};
+use crate::response::ValidationResponse;
+
+/// Name of the sidecar file that declares the expected outcomes of the
+/// fixtures evaluated by [`run_fixture_matrix`]
+pub const FIXTURE_EXPECTATIONS_FILE: &str = "expectations.toml";
+
+/// Settings variant key that applies to all the variants not explicitly
+/// listed inside of the [`FIXTURE_EXPECTATIONS_FILE`]
+pub const ANY_SETTINGS_VARIANT: &str = "*";
+
+/// Outcome of the evaluation of one fixture against one settings variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureOutcome {
+    /// File name of the fixture, relative to the fixtures directory
+    pub fixture: String,
+    /// Name of the settings variant
+    pub variant: String,
+    /// Outcome declared inside of the sidecar file, `None` when missing
+    pub expected: Option<bool>,
+    /// Outcome returned by the policy
+    pub accepted: bool,
+}
+
+impl FixtureOutcome {
+    /// True when the policy returned the declared outcome
+    pub fn is_success(&self) -> bool {
+        self.expected == Some(self.accepted)
+    }
+}
+
+/// The outcomes of all the fixture/settings combinations evaluated by
+/// [`run_fixture_matrix`]. Its `Display` implementation renders a compact
+/// table, with one row per fixture and one column per settings variant.
+#[derive(Debug, Clone, Default)]
+pub struct FixtureMatrixReport {
+    pub variants: Vec<String>,
+    pub outcomes: Vec<FixtureOutcome>,
+}
+
+impl FixtureMatrixReport {
+    /// True when all the combinations returned the declared outcome
+    pub fn is_success(&self) -> bool {
+        self.outcomes.iter().all(FixtureOutcome::is_success)
+    }
+
+    /// The combinations that did not return the declared outcome
+    pub fn failures(&self) -> Vec<&FixtureOutcome> {
+        self.outcomes.iter().filter(|o| !o.is_success()).collect()
+    }
+}
+
+impl std::fmt::Display for FixtureMatrixReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cell = |o: &FixtureOutcome| {
+            let outcome = if o.accepted { "accept" } else { "reject" };
+            match o.expected {
+                Some(expected) if expected == o.accepted => outcome.to_string(),
+                Some(_) => format!("{outcome} (!)"),
+                None => format!("{outcome} (?)"),
+            }
+        };
+
+        let mut rows: Vec<Vec<String>> = vec![std::iter::once("fixture".to_string())
+            .chain(self.variants.iter().cloned())
+            .collect()];
+        for chunk in self.outcomes.chunks(self.variants.len().max(1)) {
+            let mut row = vec![chunk[0].fixture.clone()];
+            row.extend(chunk.iter().map(cell));
+            rows.push(row);
+        }
+
+        let columns = rows[0].len();
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+            .collect();
+        for row in rows {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{value:width$}"))
+                .collect();
+            writeln!(f, "{}", line.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate all the fixtures found inside of `dir` against each one of the
+/// named `settings_variants`.
+///
+/// Every `*.json` file of `dir` is considered a fixture. The expected outcomes
+/// are declared inside of the [`FIXTURE_EXPECTATIONS_FILE`] sidecar, which
+/// maps the fixture file names to the outcome of each settings variant. The
+/// special `"*"` key applies to the variants that are not listed:
+///
+/// ```toml
+/// ["privileged-pod.json"]
+/// strict = false
+/// permissive = true
+///
+/// ["unprivileged-pod.json"]
+/// "*" = true
+/// ```
+///
+/// The function panics, printing the table of outcomes, when one of the
+/// combinations does not return the declared outcome, or when the
+/// expectation is missing. Mismatches are marked with `(!)`, missing
+/// expectations with `(?)`.
+pub fn run_fixture_matrix<T>(
+    validate: ValidateFn,
+    dir: &str,
+    settings_variants: &[(&str, T)],
+) -> anyhow::Result<FixtureMatrixReport>
+where
+    T: Serialize,
+{
+    let dir = Path::new(dir);
+    let expectations_file = dir.join(FIXTURE_EXPECTATIONS_FILE);
+    let expectations: BTreeMap<String, BTreeMap<String, bool>> =
+        toml::from_str(&std::fs::read_to_string(&expectations_file).map_err(|e| {
+            anyhow::anyhow!("cannot read '{}': {}", expectations_file.display(), e)
+        })?)?;
+
+    let fixtures = find_fixtures(&dir.join("*.json").to_string_lossy())?;
+    if fixtures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no fixture file found inside of '{}'",
+            dir.display()
+        ));
+    }
+
+    let mut report = FixtureMatrixReport {
+        variants: settings_variants
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        outcomes: vec![],
+    };
+    for fixture in &fixtures {
+        let name = Path::new(fixture)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| fixture.clone());
+        let declared = expectations.get(&name);
+        let req = read_request_file(fixture)?;
+
+        for (variant, settings) in settings_variants {
+            let payload = make_validate_payload_from_request(&req, settings);
+            let raw_result = validate(payload.as_bytes()).map_err(|e| anyhow::anyhow!("{}", e))?;
+            let response: ValidationResponse = serde_json::from_slice(&raw_result)?;
+            report.outcomes.push(FixtureOutcome {
+                fixture: name.clone(),
+                variant: variant.to_string(),
+                expected: declared.and_then(|d| {
+                    d.get(*variant)
+                        .or_else(|| d.get(ANY_SETTINGS_VARIANT))
+                        .copied()
+                }),
+                accepted: response.accepted,
+            });
+        }
+    }
+
+    assert!(
+        report.is_success(),
+        "Fixture matrix '{}' has unexpected outcomes:\n{}",
+        dir.display(),
+        report,
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::ValidationRequest;
+
+    #[derive(serde::Serialize, serde::Deserialize, Default)]
+    struct StrictSettings {
+        strict: bool,
+    }
+
+    fn reject_privileged_when_strict(payload: &[u8]) -> wapc_guest::CallResult {
+        let req = ValidationRequest::<StrictSettings>::new(payload)?;
+        if req.settings.strict && req.request.object["privileged"] == true {
+            crate::reject_request(Some("privileged".to_string()), None, None, None)
+        } else {
+            crate::accept_request()
+        }
+    }
+
+    #[test]
+    fn fixture_matrix() {
+        let dir =
+            std::env::temp_dir().join(format!("kubewarden-sdk-{}-matrix", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("privileged.json"),
+            r#"{"object": {"privileged": true}}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("plain.json"), r#"{"object": {}}"#).unwrap();
+        std::fs::write(
+            dir.join(FIXTURE_EXPECTATIONS_FILE),
+            r#"
+["privileged.json"]
+strict = false
+permissive = true
+
+["plain.json"]
+"*" = true
+"#,
+        )
+        .unwrap();
+        let variants = [
+            ("strict", StrictSettings { strict: true }),
+            ("permissive", StrictSettings { strict: false }),
+        ];
+        let dir = dir.to_string_lossy().to_string();
+
+        let report = run_fixture_matrix(reject_privileged_when_strict, &dir, &variants).unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.outcomes.len(), 4);
+        assert_eq!(
+            report.to_string(),
+            "fixture          strict  permissive\n\
+             plain.json       accept  accept\n\
+             privileged.json  reject  accept\n"
+        );
+
+        let accept = |_: &[u8]| crate::accept_request();
+        let result = std::panic::catch_unwind(|| run_fixture_matrix(accept, &dir, &variants));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn fixture_matrix_missing_expectation() {
+        let outcome = FixtureOutcome {
+            fixture: "new.json".to_string(),
+            variant: "strict".to_string(),
+            expected: None,
+            accepted: true,
+        };
+        let report = FixtureMatrixReport {
+            variants: vec!["strict".to_string()],
+            outcomes: vec![outcome.clone()],
+        };
+        assert!(!report.is_success());
+        assert_eq!(report.failures(), vec![&outcome]);
+        assert!(report.to_string().contains("accept (?)"));
+
+        let dir =
+            std::env::temp_dir().join(format!("kubewarden-sdk-{}-nomatrix", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let accept = |_: &[u8]| crate::accept_request();
+        let variants = [("strict", StrictSettings { strict: true })];
+        assert!(run_fixture_matrix(accept, &dir.to_string_lossy(), &variants).is_err());
+    }
+}
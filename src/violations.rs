@@ -0,0 +1,235 @@
+//! Accumulate the problems found while validating many items.
+//!
+//! Policies inspecting lists of items (e.g. all the containers of a Pod)
+//! should report all the problems at once, instead of the first one only.
+//! [`Violations`] collects them, and turns them into a single rejection
+//! response that stays within the limits enforced by the API server.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::violations::Violations;
+//!
+//! fn validate(payload: &[u8]) -> wapc_guest::CallResult {
+//!     let mut violations = Violations::new();
+//!     for (index, image) in ["nginx:latest", "busybox"].iter().enumerate() {
+//!         if image.ends_with(":latest") || !image.contains(':') {
+//!             violations.add(
+//!                 &format!("spec.containers[{}].image", index),
+//!                 "the image must be pinned to a tag",
+//!             );
+//!         }
+//!     }
+//!     // accepts the request when no violation has been found
+//!     violations.into_response(5)
+//! }
+//! ```
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::response::ValidationResponse;
+
+/// Key of the audit annotation holding the list of violations
+pub const AUDIT_ANNOTATION_KEY: &str = "violations";
+/// Maximum length of the rejection message, in bytes
+pub const MAX_MESSAGE_LENGTH: usize = 4 * 1024;
+/// Maximum length of the audit annotation value, in bytes
+pub const MAX_AUDIT_ANNOTATION_LENGTH: usize = 32 * 1024;
+
+/// A problem found inside of the object being validated
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Where the problem has been found (e.g. `spec.containers[0].image`)
+    pub path: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+/// Collects the violations found by a policy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Violations {
+    items: Vec<Violation>,
+}
+
+fn truncate(value: &mut String, max_len: usize) {
+    if value.len() <= max_len {
+        return;
+    }
+    let mut end = max_len.saturating_sub(3);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    value.push_str("...");
+}
+
+impl Violations {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a violation found at `path`
+    pub fn add(&mut self, path: &str, message: &str) {
+        self.items.push(Violation {
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Returns `true` when no violation has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The number of violations recorded
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// All the violations recorded
+    pub fn iter(&self) -> impl Iterator<Item = &Violation> {
+        self.items.iter()
+    }
+
+    /// A message describing at most `limit` violations. The message is
+    /// truncated to [`MAX_MESSAGE_LENGTH`]. `None` when there are no violations
+    pub fn message(&self, limit: usize) -> Option<String> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        let mut message = self
+            .items
+            .iter()
+            .take(limit.max(1))
+            .map(|v| format!("{}: {}", v.path, v.message))
+            .collect::<Vec<String>>()
+            .join("; ");
+        let hidden = self.items.len().saturating_sub(limit.max(1));
+        if hidden > 0 {
+            message.push_str(&format!(" (and {} more)", hidden));
+        }
+        truncate(&mut message, MAX_MESSAGE_LENGTH);
+        Some(message)
+    }
+
+    /// All the violations as audit annotations, stored as a JSON list under
+    /// the [`AUDIT_ANNOTATION_KEY`] key. Violations that do not fit into
+    /// [`MAX_AUDIT_ANNOTATION_LENGTH`] are left out
+    pub fn audit_annotations(&self) -> HashMap<String, String> {
+        let mut value = String::from("[");
+        for item in &self.items {
+            let Ok(entry) = serde_json::to_string(item) else {
+                break;
+            };
+            // separator, entry and the closing bracket
+            let separator = usize::from(value.len() > 1);
+            if value.len() + separator + entry.len() + 1 > MAX_AUDIT_ANNOTATION_LENGTH {
+                break;
+            }
+            if separator > 0 {
+                value.push(',');
+            }
+            value.push_str(&entry);
+        }
+        value.push(']');
+        HashMap::from([(AUDIT_ANNOTATION_KEY.to_string(), value)])
+    }
+
+    /// Accept the request when there are no violations, reject it otherwise.
+    /// The rejection message describes at most `limit` violations, while all
+    /// of them are attached as audit annotations
+    pub fn into_response(self, limit: usize) -> wapc_guest::CallResult {
+        self.into_validation_response(limit).into_call_result()
+    }
+
+    /// Same as [`into_response`](Self::into_response), without serializing
+    /// the response
+    pub fn into_validation_response(self, limit: usize) -> ValidationResponse {
+        match self.message(limit) {
+            None => crate::accept_response(),
+            Some(message) => ValidationResponse {
+                accepted: false,
+                message: Some(message),
+                code: None,
+                mutated_object: None,
+                audit_annotations: Some(self.audit_annotations()),
+                warnings: None,
+            },
+        }
+    }
+}
+
+impl Extend<Violation> for Violations {
+    fn extend<I: IntoIterator<Item = Violation>>(&mut self, iter: I) {
+        self.items.extend(iter)
+    }
+}
+
+impl IntoIterator for Violations {
+    type Item = Violation;
+    type IntoIter = std::vec::IntoIter<Violation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(raw: wapc_guest::CallResult) -> ValidationResponse {
+        serde_json::from_slice(&raw.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn no_violations_accepts() {
+        let violations = Violations::new();
+        assert!(violations.is_empty());
+        assert!(response(violations.into_response(3)).accepted);
+    }
+
+    #[test]
+    fn violations_reject() {
+        let mut violations = Violations::new();
+        for i in 0..5 {
+            violations.add(&format!("spec.containers[{}].image", i), "latest tag");
+        }
+        assert_eq!(violations.len(), 5);
+
+        let response = response(violations.into_response(2));
+        assert!(!response.accepted);
+        assert_eq!(
+            response.message.unwrap(),
+            "spec.containers[0].image: latest tag; spec.containers[1].image: latest tag (and 3 more)"
+        );
+        let annotation = &response.audit_annotations.unwrap()[AUDIT_ANNOTATION_KEY];
+        let all: Vec<Violation> = serde_json::from_str(annotation).unwrap();
+        assert_eq!(all.len(), 5);
+    }
+
+    #[test]
+    fn truncate_to_limits() {
+        let mut violations = Violations::new();
+        for i in 0..2000 {
+            violations.add(&format!("items[{}]", i), &"é".repeat(20));
+        }
+
+        let message = violations.message(usize::MAX).unwrap();
+        assert!(message.len() <= MAX_MESSAGE_LENGTH);
+        assert!(message.ends_with("..."));
+
+        let annotation = &violations.audit_annotations()[AUDIT_ANNOTATION_KEY];
+        assert!(annotation.len() <= MAX_AUDIT_ANNOTATION_LENGTH);
+        let included: Vec<Violation> = serde_json::from_str(annotation).unwrap();
+        assert!(!included.is_empty() && included.len() < 2000);
+        assert_eq!(
+            annotation,
+            &serde_json::to_string(&violations.items[..included.len()]).unwrap()
+        );
+        let one_more = serde_json::to_string(&violations.items[..=included.len()]).unwrap();
+        assert!(one_more.len() > MAX_AUDIT_ANNOTATION_LENGTH);
+    }
+}
@@ -0,0 +1,174 @@
+//! Binary wire formats for the payloads exchanged with the host.
+//!
+//! JSON encoding and decoding is the dominant cost of evaluating big objects
+//! inside of Wasm. When the `msgpack` feature is enabled, policies advertise
+//! the formats they understand through the `wire_formats` field of the
+//! [`GuestMetadata`](crate::metadata::GuestMetadata). Hosts opting into it
+//! can then send the validation payloads encoded with
+//! [MessagePack](https://msgpack.org). The policy keeps reporting
+//! [`ProtocolVersion::V1`](crate::metadata::ProtocolVersion::V1), hosts
+//! unaware of `wire_formats` keep exchanging JSON.
+//!
+//! The format of a payload is detected from its first byte: validation
+//! payloads are maps, the MessagePack map markers can never start a JSON
+//! document. The response is encoded using the same format of the latest
+//! request decoded by [`ValidationRequest::new`](crate::request::ValidationRequest::new)
+//! or [`RawValidationRequest::new`](crate::request::RawValidationRequest::new).
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fmt;
+
+thread_local! {
+    static RESPONSE_FORMAT: Cell<WireFormat> = const { Cell::new(WireFormat::Json) };
+}
+
+/// The serialization format of a payload
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WireFormat {
+    /// JSON, the format of [`ProtocolVersion::V1`](crate::metadata::ProtocolVersion::V1)
+    #[serde(rename = "json")]
+    Json,
+    /// MessagePack, with structs encoded as maps
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+impl WireFormat {
+    /// Detect the format of a payload holding a map
+    pub fn detect(payload: &[u8]) -> Self {
+        match payload.first() {
+            // fixmap, map 16 and map 32
+            Some(0x80..=0x8f | 0xde | 0xdf) => WireFormat::MessagePack,
+            _ => WireFormat::Json,
+        }
+    }
+
+    /// Returns `true` when the policy is able to decode payloads using this
+    /// format
+    pub fn is_supported(&self) -> bool {
+        SUPPORTED_WIRE_FORMATS.contains(self)
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The formats understood by the policy, in order of preference
+pub const SUPPORTED_WIRE_FORMATS: &[WireFormat] = &[
+    #[cfg(feature = "msgpack")]
+    WireFormat::MessagePack,
+    WireFormat::Json,
+];
+
+/// The format used to encode the responses
+pub fn response_format() -> WireFormat {
+    RESPONSE_FORMAT.with(Cell::get)
+}
+
+/// Change the format used to encode the responses. This is done by
+/// [`negotiate`], policies should not need to invoke it
+pub fn set_response_format(format: WireFormat) {
+    RESPONSE_FORMAT.with(|current| current.set(format));
+}
+
+/// Detect the format of a payload sent by the host, and use it for the
+/// response too. An error is returned when the format is not supported
+pub fn negotiate(payload: &[u8]) -> Result<WireFormat> {
+    let format = WireFormat::detect(payload);
+    if !format.is_supported() {
+        return Err(anyhow!(
+            "received a {} payload, the policy must be built with the `{}` feature of the SDK",
+            format,
+            format
+        ));
+    }
+    set_response_format(format);
+    Ok(format)
+}
+
+/// Decode a payload encoded using a binary format
+pub fn from_binary_slice<T: DeserializeOwned>(format: WireFormat, payload: &[u8]) -> Result<T> {
+    match format {
+        #[cfg(feature = "msgpack")]
+        WireFormat::MessagePack => rmp_serde::from_slice(payload).map_err(|e| anyhow!("{}", e)),
+        _ => {
+            let _ = payload;
+            Err(anyhow!("{} is not a supported binary format", format))
+        }
+    }
+}
+
+/// Encode `value` using the given format
+pub fn to_vec<T: Serialize>(format: WireFormat, value: &T) -> Result<Vec<u8>> {
+    match format {
+        WireFormat::Json => serde_json::to_vec(value).map_err(|e| anyhow!("{}", e)),
+        #[cfg(feature = "msgpack")]
+        WireFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| anyhow!("{}", e)),
+        #[cfg(not(feature = "msgpack"))]
+        WireFormat::MessagePack => Err(anyhow!("{} is not supported", format)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(WireFormat::detect(b"{}"), WireFormat::Json);
+        assert_eq!(WireFormat::detect(b" {}"), WireFormat::Json);
+        assert_eq!(
+            WireFormat::detect(&[0x81, 0xa1, 0x61]),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            WireFormat::detect(&[0xde, 0x00, 0x10]),
+            WireFormat::MessagePack
+        );
+    }
+
+    #[test]
+    fn negotiate_format() {
+        assert_eq!(negotiate(b"{}").unwrap(), WireFormat::Json);
+        assert_eq!(response_format(), WireFormat::Json);
+
+        let result = negotiate(&[0x80]);
+        assert_eq!(result.is_ok(), cfg!(feature = "msgpack"));
+        if cfg!(feature = "msgpack") {
+            assert_eq!(response_format(), WireFormat::MessagePack);
+        } else {
+            assert_eq!(response_format(), WireFormat::Json);
+        }
+        set_response_format(WireFormat::Json);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        let value = json!({"accepted": true, "warnings": ["a"], "code": 400});
+        let encoded = to_vec(WireFormat::MessagePack, &value).unwrap();
+        assert_eq!(WireFormat::detect(&encoded), WireFormat::MessagePack);
+        let decoded: serde_json::Value =
+            from_binary_slice(WireFormat::MessagePack, &encoded).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn json_is_not_binary() {
+        assert!(from_binary_slice::<serde_json::Value>(WireFormat::Json, b"{}").is_err());
+        assert_eq!(
+            to_vec(WireFormat::Json, &json!({"a": 1})).unwrap(),
+            br#"{"a":1}"#
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Dispatch over the workload resources that embed a pod template.
+//!
+//! Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController,
+//! Job, CronJob and Pod objects all end up creating Pods. [`WorkloadKind`]
+//! gives uniform access to their pod template, which allows policies to
+//! validate or mutate the Pods before they are created by the controllers.
+//!
+//! # Example
+//!
+//! ```rust
+//! use kubewarden_policy_sdk::request::GroupVersionKind;
+//! use kubewarden_policy_sdk::workload::WorkloadKind;
+//! use serde_json::json;
+//!
+//! let kind = WorkloadKind::try_from_gvk(&GroupVersionKind::new("apps", "v1", "Deployment")).unwrap();
+//! let object = json!({"spec": {"selector": {}, "template": {"spec": {"containers": []}}}});
+//!
+//! let mutated = kind
+//!     .visit(object, |template| {
+//!         template
+//!             .metadata
+//!             .get_or_insert_with(Default::default)
+//!             .labels
+//!             .get_or_insert_with(Default::default)
+//!             .insert("owner".to_string(), "team-a".to_string());
+//!     })
+//!     .unwrap();
+//! assert_eq!(mutated["spec"]["template"]["metadata"]["labels"]["owner"], "team-a");
+//! ```
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet};
+use k8s_openapi::api::batch::v1::{CronJob, Job};
+use k8s_openapi::api::core::v1::{Pod, PodTemplateSpec, ReplicationController};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::Resource;
+
+use crate::request::GroupVersionKind;
+
+/// Error message returned when the object is not a workload resource
+pub const UNSUPPORTED_KIND_MESSAGE: &str = "Object should be one of these kinds: Deployment, ReplicaSet, StatefulSet, DaemonSet, ReplicationController, Job, CronJob, Pod";
+
+/// A kind of resource that embeds a pod template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkloadKind {
+    Deployment,
+    ReplicaSet,
+    StatefulSet,
+    DaemonSet,
+    ReplicationController,
+    Job,
+    CronJob,
+    Pod,
+}
+
+impl WorkloadKind {
+    /// All the supported workload kinds
+    pub const ALL: [WorkloadKind; 8] = [
+        WorkloadKind::Deployment,
+        WorkloadKind::ReplicaSet,
+        WorkloadKind::StatefulSet,
+        WorkloadKind::DaemonSet,
+        WorkloadKind::ReplicationController,
+        WorkloadKind::Job,
+        WorkloadKind::CronJob,
+        WorkloadKind::Pod,
+    ];
+
+    /// Find the workload kind of the given GroupVersionKind. Only the kind is
+    /// taken into account, the objects are deserialized using the
+    /// `k8s_openapi` types of the supported API versions.
+    pub fn try_from_gvk(gvk: &GroupVersionKind) -> Result<Self> {
+        Self::try_from_kind(&gvk.kind)
+    }
+
+    /// Find the workload kind named `kind` (e.g. `Deployment`)
+    pub fn try_from_kind(kind: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|workload| workload.kind() == kind)
+            .ok_or_else(|| anyhow!(UNSUPPORTED_KIND_MESSAGE))
+    }
+
+    /// The name of the kind (e.g. `Deployment`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WorkloadKind::Deployment => Deployment::KIND,
+            WorkloadKind::ReplicaSet => ReplicaSet::KIND,
+            WorkloadKind::StatefulSet => StatefulSet::KIND,
+            WorkloadKind::DaemonSet => DaemonSet::KIND,
+            WorkloadKind::ReplicationController => ReplicationController::KIND,
+            WorkloadKind::Job => Job::KIND,
+            WorkloadKind::CronJob => CronJob::KIND,
+            WorkloadKind::Pod => Pod::KIND,
+        }
+    }
+
+    /// The pod template of `object`, `None` when it is not set.
+    ///
+    /// The template of a Pod is made of its spec and of the labels and
+    /// annotations of its metadata.
+    pub fn pod_template(&self, object: &serde_json::Value) -> Result<Option<PodTemplateSpec>> {
+        let object = object.clone();
+        Ok(match self {
+            WorkloadKind::Deployment => serde_json::from_value::<Deployment>(object)?
+                .spec
+                .map(|spec| spec.template),
+            WorkloadKind::ReplicaSet => serde_json::from_value::<ReplicaSet>(object)?
+                .spec
+                .and_then(|spec| spec.template),
+            WorkloadKind::StatefulSet => serde_json::from_value::<StatefulSet>(object)?
+                .spec
+                .map(|spec| spec.template),
+            WorkloadKind::DaemonSet => serde_json::from_value::<DaemonSet>(object)?
+                .spec
+                .map(|spec| spec.template),
+            WorkloadKind::ReplicationController => {
+                serde_json::from_value::<ReplicationController>(object)?
+                    .spec
+                    .and_then(|spec| spec.template)
+            }
+            WorkloadKind::Job => serde_json::from_value::<Job>(object)?
+                .spec
+                .map(|spec| spec.template),
+            WorkloadKind::CronJob => serde_json::from_value::<CronJob>(object)?
+                .spec
+                .and_then(|spec| spec.job_template.spec)
+                .map(|spec| spec.template),
+            WorkloadKind::Pod => {
+                let mut pod = serde_json::from_value::<Pod>(object)?;
+                Some(pod_template_of(&mut pod))
+            }
+        })
+    }
+
+    /// Apply `visitor` to the pod template of `object`, returning the updated
+    /// object. A default template is created when the object has none.
+    ///
+    /// For Pods, only the labels and the annotations of the template
+    /// metadata are copied back into the metadata of the Pod.
+    pub fn visit<F>(&self, object: serde_json::Value, visitor: F) -> Result<serde_json::Value>
+    where
+        F: FnOnce(&mut PodTemplateSpec),
+    {
+        Ok(match self {
+            WorkloadKind::Deployment => {
+                let mut deployment = serde_json::from_value::<Deployment>(object)?;
+                let mut deployment_spec = deployment.spec.unwrap_or_default();
+                visitor(&mut deployment_spec.template);
+                deployment.spec = Some(deployment_spec);
+                serde_json::to_value(deployment)?
+            }
+            WorkloadKind::ReplicaSet => {
+                let mut replicaset = serde_json::from_value::<ReplicaSet>(object)?;
+                let mut replicaset_spec = replicaset.spec.unwrap_or_default();
+                let mut template = replicaset_spec.template.unwrap_or_default();
+                visitor(&mut template);
+                replicaset_spec.template = Some(template);
+                replicaset.spec = Some(replicaset_spec);
+                serde_json::to_value(replicaset)?
+            }
+            WorkloadKind::StatefulSet => {
+                let mut statefulset = serde_json::from_value::<StatefulSet>(object)?;
+                let mut statefulset_spec = statefulset.spec.unwrap_or_default();
+                visitor(&mut statefulset_spec.template);
+                statefulset.spec = Some(statefulset_spec);
+                serde_json::to_value(statefulset)?
+            }
+            WorkloadKind::DaemonSet => {
+                let mut daemonset = serde_json::from_value::<DaemonSet>(object)?;
+                let mut daemonset_spec = daemonset.spec.unwrap_or_default();
+                visitor(&mut daemonset_spec.template);
+                daemonset.spec = Some(daemonset_spec);
+                serde_json::to_value(daemonset)?
+            }
+            WorkloadKind::ReplicationController => {
+                let mut replication_controller =
+                    serde_json::from_value::<ReplicationController>(object)?;
+                let mut replication_controller_spec =
+                    replication_controller.spec.unwrap_or_default();
+                let mut template = replication_controller_spec.template.unwrap_or_default();
+                visitor(&mut template);
+                replication_controller_spec.template = Some(template);
+                replication_controller.spec = Some(replication_controller_spec);
+                serde_json::to_value(replication_controller)?
+            }
+            WorkloadKind::Job => {
+                let mut job = serde_json::from_value::<Job>(object)?;
+                let mut job_spec = job.spec.unwrap_or_default();
+                visitor(&mut job_spec.template);
+                job.spec = Some(job_spec);
+                serde_json::to_value(job)?
+            }
+            WorkloadKind::CronJob => {
+                let mut cronjob = serde_json::from_value::<CronJob>(object)?;
+                let mut cronjob_spec = cronjob.spec.unwrap_or_default();
+                let mut job_template_spec = cronjob_spec.job_template;
+                let mut job_spec = job_template_spec.spec.unwrap_or_default();
+                visitor(&mut job_spec.template);
+                job_template_spec.spec = Some(job_spec);
+                cronjob_spec.job_template = job_template_spec;
+                cronjob.spec = Some(cronjob_spec);
+                serde_json::to_value(cronjob)?
+            }
+            WorkloadKind::Pod => {
+                let mut pod = serde_json::from_value::<Pod>(object)?;
+                let mut template = pod_template_of(&mut pod);
+                visitor(&mut template);
+                let template_metadata = template.metadata.unwrap_or_default();
+                pod.metadata.labels = template_metadata.labels;
+                pod.metadata.annotations = template_metadata.annotations;
+                pod.spec = template.spec;
+                serde_json::to_value(pod)?
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for WorkloadKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.kind())
+    }
+}
+
+/// Move the spec, the labels and the annotations of `pod` into a template
+fn pod_template_of(pod: &mut Pod) -> PodTemplateSpec {
+    PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            labels: pod.metadata.labels.take(),
+            annotations: pod.metadata.annotations.take(),
+            ..Default::default()
+        }),
+        spec: pod.spec.take(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn kind_lookup() {
+        for workload in WorkloadKind::ALL {
+            let gvk = GroupVersionKind::new("", "v1", workload.kind());
+            assert_eq!(WorkloadKind::try_from_gvk(&gvk).unwrap(), workload);
+        }
+        let err = WorkloadKind::try_from_kind("Service").unwrap_err();
+        assert_eq!(err.to_string(), UNSUPPORTED_KIND_MESSAGE);
+        assert_eq!(WorkloadKind::CronJob.to_string(), "CronJob");
+    }
+
+    #[test]
+    fn cronjob_pod_template() {
+        let object = json!({"spec": {"schedule": "* * * * *", "jobTemplate": {"spec": {
+            "template": {"spec": {"containers": [{"name": "job", "image": "busybox"}]}}
+        }}}});
+
+        let template = WorkloadKind::CronJob
+            .pod_template(&object)
+            .unwrap()
+            .unwrap();
+        assert_eq!(template.spec.unwrap().containers[0].name, "job");
+
+        let mutated = WorkloadKind::CronJob
+            .visit(object, |template| {
+                template.spec.as_mut().unwrap().containers[0].image = Some("alpine".to_string())
+            })
+            .unwrap();
+        assert_eq!(
+            mutated["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"][0]["image"],
+            "alpine"
+        );
+    }
+
+    #[test]
+    fn pod_template_of_pod() {
+        let object = json!({
+            "metadata": {"name": "nginx", "labels": {"app": "web"}},
+            "spec": {"containers": [{"name": "nginx"}]}
+        });
+
+        let template = WorkloadKind::Pod.pod_template(&object).unwrap().unwrap();
+        assert_eq!(template.metadata.unwrap().labels.unwrap()["app"], "web");
+
+        let mutated = WorkloadKind::Pod
+            .visit(object, |template| {
+                let metadata = template.metadata.as_mut().unwrap();
+                metadata.name = Some("ignored".to_string());
+                metadata
+                    .labels
+                    .as_mut()
+                    .unwrap()
+                    .insert("tier".to_string(), "front".to_string());
+            })
+            .unwrap();
+        assert_eq!(mutated["metadata"]["name"], "nginx");
+        assert_eq!(
+            mutated["metadata"]["labels"],
+            json!({"app": "web", "tier": "front"})
+        );
+    }
+
+    #[test]
+    fn missing_template() {
+        let object = json!({"metadata": {"name": "rs"}});
+        assert!(WorkloadKind::ReplicaSet
+            .pod_template(&object)
+            .unwrap()
+            .is_none());
+        assert!(WorkloadKind::ReplicaSet
+            .pod_template(&json!({"spec": 1}))
+            .is_err());
+    }
+}